@@ -1,26 +1,156 @@
 use crate::AppState;
-use crate::services::chat_service::{ChatMessage, ChatResponse};
-use crate::commands::validation::{validate_message_content, validate_model_name};
-use tauri::State;
+use crate::services::chat_service::{ChatMessage, ChatResponse, ModelComparison, QueryTiming, SourceSummary};
+use crate::services::usage_stats::UsageStatsSummary;
+use crate::commands::validation::{validate_compared_models, validate_message_content, validate_model_name, validate_temperature};
+use crate::config::AppConfig;
+use crate::errors::{AppError, CommandResult};
+use tauri::{AppHandle, Emitter, State};
 
+/// Answers `message`, emitting `retrieval-started`, `retrieval-complete`, `generation-started`
+/// and `chat-token` events as the answer progresses
 #[tauri::command]
 pub async fn send_message(
+    app: AppHandle,
     state: State<'_, AppState>, 
     message: String,
-    model: Option<String>
-) -> Result<ChatResponse, String> {
+    model: Option<String>,
+    source_filter: Option<Vec<String>>,
+) -> CommandResult<ChatResponse> {
     // Validate message content
-    validate_message_content(&message).map_err(|e| e.to_string())?;
+    validate_message_content(&message)?;
     
-    // Update the model if provided
+    // Update the model if provided, accepting a configured alias in place of the tag
     if let Some(model_name) = model {
-        // Validate model name
-        validate_model_name(&model_name).map_err(|e| e.to_string())?;
-        
         let mut ollama_manager = state.ollama_manager.lock().await;
+        let model_name = ollama_manager.resolve_model(&model_name);
+        validate_model_name(&model_name)?;
         ollama_manager.set_model(model_name);
     }
     
+    // Page URLs or titles the answer's wiki context may come from
+    let source_filter: Vec<String> = source_filter.unwrap_or_default()
+        .into_iter()
+        .map(|source| source.trim().to_string())
+        .filter(|source| !source.is_empty())
+        .collect();
+    
+    let mut chat_service = state.chat_service.lock().await;
+    chat_service.process_message_with_events(&message, &source_filter, move |event| {
+        if let Err(e) = app.emit(event.event_name(), &event) {
+            log::warn!("Failed to emit chat pipeline event: {}", e);
+        }
+    }).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn send_message_with_sources(
+    state: State<'_, AppState>,
+    message: String,
+) -> CommandResult<ChatResponse> {
+    validate_message_content(&message)?;
+    
+    let mut chat_service = state.chat_service.lock().await;
+    chat_service.process_message_with_sources(&message).await.map_err(Into::into)
+}
+
+/// Extends the last reply where it was cut off, emitting `generation-started` and `chat-token`
+/// events for the continuation. The response holds the whole reply.
+#[tauri::command]
+pub async fn continue_response(app: AppHandle, state: State<'_, AppState>) -> CommandResult<ChatResponse> {
+    let mut chat_service = state.chat_service.lock().await;
+    chat_service.continue_response(move |event| {
+        if let Err(e) = app.emit(event.event_name(), &event) {
+            log::warn!("Failed to emit chat pipeline event: {}", e);
+        }
+    }).await.map_err(Into::into)
+}
+
+/// Answers `query` with each of `models` from the same retrieved context, for picking a model
+#[tauri::command]
+pub async fn compare_models(
+    state: State<'_, AppState>,
+    query: String,
+    models: Vec<String>,
+) -> CommandResult<ModelComparison> {
+    validate_message_content(&query)?;
+    
+    // Accept configured aliases, and don't ask the same model twice
+    let models: Vec<String> = {
+        let ollama_manager = state.ollama_manager.lock().await;
+        let mut resolved: Vec<String> = Vec::new();
+        for model in &models {
+            let model = ollama_manager.resolve_model(model);
+            if !resolved.contains(&model) {
+                resolved.push(model);
+            }
+        }
+        resolved
+    };
+    validate_compared_models(&models)?;
+    
+    let chat_service = state.chat_service.lock().await;
+    chat_service.compare_models(&query, &models).await.map_err(Into::into)
+}
+
+/// Asks the model for JSON shaped like `schema` (a JSON Schema or example object), e.g. to
+/// extract a recipe as structured data
+#[tauri::command]
+pub async fn generate_structured(
+    state: State<'_, AppState>,
+    prompt: String,
+    schema: String,
+) -> CommandResult<serde_json::Value> {
+    validate_message_content(&prompt)?;
+    validate_message_content(&schema)?;
+    
+    let chat_service = state.chat_service.lock().await;
+    chat_service.generate_structured(&prompt, &schema).await.map_err(Into::into)
+}
+
+/// Times embedding, retrieval and generation for `query` without adding it to the conversation
+#[tauri::command]
+pub async fn benchmark_query(state: State<'_, AppState>, query: String) -> CommandResult<QueryTiming> {
+    validate_message_content(&query)?;
+    
+    let chat_service = state.chat_service.lock().await;
+    chat_service.benchmark_query(&query).await.map_err(Into::into)
+}
+
+/// Summarizes a scraped wiki page from its stored chunks
+#[tauri::command]
+pub async fn summarize_source(state: State<'_, AppState>, source_url: String) -> CommandResult<SourceSummary> {
+    let source_url = source_url.trim();
+    if source_url.is_empty() {
+        return Err(AppError::ConfigError("Source URL cannot be empty".to_string()).into());
+    }
+    
+    let chat_service = state.chat_service.lock().await;
+    chat_service.summarize_source(source_url).await.map_err(Into::into)
+}
+
+/// Totals of how the assistant has been used, kept only on this machine
+#[tauri::command]
+pub async fn get_usage_stats(state: State<'_, AppState>) -> CommandResult<UsageStatsSummary> {
+    state.usage_stats.summary().map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn reset_usage_stats(state: State<'_, AppState>) -> CommandResult<()> {
+    state.usage_stats.reset().map_err(Into::into)
+}
+
+/// Sets the sampling temperature for chat replies, saving it to the config. Returns the value applied.
+#[tauri::command]
+pub async fn set_temperature(state: State<'_, AppState>, value: f32) -> CommandResult<f32> {
+    validate_temperature(value)?;
+    
     let mut chat_service = state.chat_service.lock().await;
-    chat_service.process_message(&message).await.map_err(|e| e.to_string())
+    
+    let mut config = AppConfig::load()?;
+    config.chat.temperature = value;
+    config.save()?;
+    
+    log::info!("Chat temperature set to {}", value);
+    chat_service.set_temperature(value);
+    Ok(value)
 }
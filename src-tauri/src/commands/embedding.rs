@@ -0,0 +1,266 @@
+use crate::AppState;
+use crate::commands::validation::{validate_embedding_concurrency, validate_embedding_model_name, validate_message_content};
+use crate::config::AppConfig;
+use crate::errors::{AppError, AppResult, CommandResult};
+use crate::services::embedding_service::{ChunkPreview, EmbeddingModelInfo, EmbeddingProgress, EmbeddingRetryReport, TextEmbedding};
+use crate::services::wiki_service::{claim_update, ensure_no_update_running};
+use std::path::Path;
+use tauri::State;
+
+#[tauri::command]
+pub async fn get_embedding_model_info(state: State<'_, AppState>) -> CommandResult<EmbeddingModelInfo> {
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.get_model_info().await?)
+}
+
+/// Switches the embedding model. A model with a different dimension than the stored embeddings
+/// is refused unless `force_reindex` is set, which clears the knowledge base and re-embeds the wiki.
+#[tauri::command]
+pub async fn set_embedding_model(
+    state: State<'_, AppState>,
+    model_name: String,
+    force_reindex: Option<bool>,
+) -> CommandResult<String> {
+    let force_reindex = force_reindex.unwrap_or(false);
+    Ok(switch_embedding_model(&state, &AppConfig::get_config_path(), model_name, force_reindex).await?)
+}
+
+/// `set_embedding_model`, saving the model to the config file at `config_path`
+async fn switch_embedding_model(
+    state: &AppState,
+    config_path: &Path,
+    model_name: String,
+    force_reindex: bool,
+) -> AppResult<String> {
+    // Validate the name and make sure it's a model that can produce embeddings
+    validate_embedding_model_name(&model_name)?;
+    
+    {
+        let ollama_manager = state.ollama_manager.lock().await;
+        let installed = ollama_manager.is_model_installed(&model_name).await?;
+        if !installed {
+            return Err(AppError::OllamaError(
+                format!("Embedding model {} is not installed. Download it first.", model_name)
+            ));
+        }
+    }
+    
+    // Don't clear the knowledge base if the re-embed can't start: everything that can stop it
+    // is checked, and the update claimed, before `switch_model` runs
+    let claim = if force_reindex {
+        let claim = claim_update(&state.wiki_update_in_progress)?;
+        if state.wiki_service.lock().await.is_offline() {
+            return Err(AppError::OfflineMode("can't re-embed the wiki without internet access".to_string()));
+        }
+        Some(claim)
+    } else {
+        None
+    };
+    
+    // Saved even when nothing gets re-embedded: the next start has to load the model the
+    // knowledge base matches, or every search fails on the dimension
+    let mut config = AppConfig::load_from(config_path)?;
+    let previous_model = std::mem::replace(&mut config.embedding.model_name, model_name.clone());
+    config.save_to(config_path)?;
+    
+    let switched = state.embedding_service.lock().await.switch_model(model_name.clone(), force_reindex).await;
+    let (dimension, cleared) = match switched {
+        Ok(switched) => switched,
+        Err(e) => {
+            config.embedding.model_name = previous_model;
+            if let Err(restore_error) = config.save_to(config_path) {
+                log::warn!("Failed to restore the previous embedding model in the config: {}", restore_error);
+            }
+            return Err(e);
+        }
+    };
+    
+    let (true, Some(claim)) = (cleared, claim) else {
+        return Ok(format!("Embedding model set to {} ({} dimensions)", model_name, dimension));
+    };
+    
+    log::info!("Re-embedding wiki content with {}", model_name);
+    let mut wiki_service = state.wiki_service.lock().await;
+    wiki_service.forget_page_records();
+    wiki_service.update_content_claimed(claim).await?;
+    
+    Ok(format!("Embedding model set to {} ({} dimensions) and wiki content re-embedded", model_name, dimension))
+}
+
+#[tauri::command]
+pub async fn preview_chunks(state: State<'_, AppState>, text: String) -> CommandResult<Vec<ChunkPreview>> {
+    if text.trim().is_empty() {
+        return Err(AppError::ConfigError("Text to preview cannot be empty".to_string()).into());
+    }
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.preview_chunks(&text))
+}
+
+/// Embeds `text` with the active model and returns the raw vector, for external tools and for
+/// debugging dimension mismatches. Check `mock`: when Ollama can't embed the text the vector comes
+/// from the development fallback instead.
+#[tauri::command]
+pub async fn embed(state: State<'_, AppState>, text: String) -> CommandResult<TextEmbedding> {
+    validate_message_content(&text)?;
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.embed(&text).await?)
+}
+
+/// Loads the embedding model into Ollama ahead of the first search or wiki update
+#[tauri::command]
+pub async fn warmup_embeddings(state: State<'_, AppState>) -> CommandResult<String> {
+    let embedding_service = state.embedding_service.lock().await;
+    embedding_service.warmup().await?;
+    Ok(format!("Embedding model {} is loaded", embedding_service.get_model_name()))
+}
+
+/// Embeds the chunks earlier wiki updates couldn't, e.g. because Ollama stopped mid-update.
+/// The failures are kept on disk, so this works after a restart too.
+#[tauri::command]
+pub async fn retry_failed_embeddings(state: State<'_, AppState>) -> CommandResult<EmbeddingRetryReport> {
+    // A running update records its own failures as it goes
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let mut embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.retry_failed_embeddings().await?)
+}
+
+/// Polling fallback for the `embedding-progress` event
+#[tauri::command]
+pub async fn get_embedding_progress(state: State<'_, AppState>) -> CommandResult<EmbeddingProgress> {
+    Ok(state.embedding_progress.snapshot())
+}
+
+#[tauri::command]
+pub async fn set_max_concurrent_embeddings(state: State<'_, AppState>, limit: usize) -> CommandResult<usize> {
+    validate_embedding_concurrency(limit)?;
+    
+    let mut embedding_service = state.embedding_service.lock().await;
+    
+    let mut config = AppConfig::load()?;
+    config.embedding.max_concurrent_embeddings = limit;
+    config.save()?;
+    
+    log::info!("Concurrent embedding limit set to {}", limit);
+    embedding_service.set_max_concurrent_embeddings(limit);
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChatConfig, OllamaConfig, WikiConfig, CONFIG_VERSION};
+    use crate::services::cancellation::CancelFlag;
+    use crate::services::chat_service::ChatService;
+    use crate::services::embedding_service::EmbeddingService;
+    use crate::services::ollama_manager::OllamaManager;
+    use crate::services::usage_stats::UsageStats;
+    use crate::services::vector_database::{VectorDatabase, VectorDocument};
+    use crate::services::wiki_service::WikiService;
+    use mockito::{Server, ServerGuard};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    /// App state talking to a mock Ollama that has `mxbai-embed-large` installed and returns
+    /// 3-dimensional embeddings, with one chunk embedded as `stored_embedding` in the knowledge base
+    async fn create_test_state(stored_embedding: Vec<f32>) -> (AppState, Arc<Mutex<VectorDatabase>>, ServerGuard) {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(r#"{"models":[{"name":"mxbai-embed-large:latest","size":1,"digest":"a","details":{"parameter_size":"335M","quantization_level":"F16","family":"bert"}}]}"#)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.3,0.4,0.5]}"#)
+            .create_async()
+            .await;
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        let ollama_config = OllamaConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            ..OllamaConfig::default()
+        };
+        
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_ollama_endpoint(&ollama_config);
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        db.lock().await.insert_documents(vec![VectorDocument {
+            id: "anvil_0".to_string(),
+            content: "The anvil is used for smithing".to_string(),
+            source_url: "https://wiki.vintagestory.at/Anvil".to_string(),
+            source_title: "Anvil".to_string(),
+            embedding: stored_embedding,
+            metadata: HashMap::new(),
+        }]).await.unwrap();
+        embedding_service.set_vector_database(db.clone());
+        let embedding_progress = embedding_service.progress_handle();
+        
+        let wiki_service = WikiService::with_config(WikiConfig::default()).await;
+        let wiki_update_in_progress = wiki_service.update_in_progress_handle();
+        
+        let state = AppState {
+            ollama_manager: Arc::new(Mutex::new(OllamaManager::with_config(ollama_config).await)),
+            wiki_service: Arc::new(Mutex::new(wiki_service)),
+            embedding_service: Arc::new(Mutex::new(embedding_service)),
+            chat_service: Arc::new(Mutex::new(ChatService::with_config(ChatConfig::default()).await)),
+            install_cancel_flag: Arc::new(CancelFlag::default()),
+            download_cancel_flag: Arc::new(CancelFlag::default()),
+            wiki_update_in_progress,
+            import_cancel_flag: Arc::new(CancelFlag::default()),
+            crawl_cancel_flag: Arc::new(CancelFlag::default()),
+            generation_cancel_flag: Arc::new(CancelFlag::default()),
+            embedding_progress,
+            config_recovered: false,
+            usage_stats: Arc::new(UsageStats::new_temporary()),
+        };
+        (state, db, server)
+    }
+
+    fn temp_config_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4())).join("config.json")
+    }
+
+    #[tokio::test]
+    async fn test_switched_embedding_model_is_saved() {
+        let (state, _db, _server) = create_test_state(vec![0.1, 0.2, 0.3]).await;
+        let config_path = temp_config_path();
+        
+        switch_embedding_model(&state, &config_path, "mxbai-embed-large".to_string(), false).await.unwrap();
+        
+        assert_eq!(AppConfig::load_from(&config_path).unwrap().embedding.model_name, "mxbai-embed-large");
+        assert_eq!(state.embedding_service.lock().await.get_model_name(), "mxbai-embed-large");
+        fs::remove_dir_all(config_path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_reindex_that_cant_start_keeps_the_knowledge_base() {
+        // Stored with a different model, so switching has to clear the knowledge base
+        let (state, db, _server) = create_test_state(vec![0.1, 0.2, 0.3, 0.4]).await;
+        
+        // Offline, the wiki can't be re-embedded
+        state.wiki_service.lock().await.set_offline(true);
+        let config_path = temp_config_path();
+        let err = switch_embedding_model(&state, &config_path, "mxbai-embed-large".to_string(), true).await.unwrap_err();
+        assert_eq!(err.code(), "OFFLINE_MODE");
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 1);
+        assert!(!state.wiki_update_in_progress.load(std::sync::atomic::Ordering::SeqCst));
+        fs::remove_dir_all(config_path.parent().unwrap()).ok();
+        
+        // A config from a newer version of the app can't be saved
+        state.wiki_service.lock().await.set_offline(false);
+        let config_path = temp_config_path();
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, format!(r#"{{"version":{}}}"#, CONFIG_VERSION + 1)).unwrap();
+        let err = switch_embedding_model(&state, &config_path, "mxbai-embed-large".to_string(), true).await.unwrap_err();
+        assert_eq!(err.code(), "CONFIG_ERROR");
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 1);
+        fs::remove_dir_all(config_path.parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,210 @@
+use crate::AppState;
+use crate::commands::validation::validate_message_content;
+use crate::errors::{AppError, CommandResult};
+use crate::services::embedding_service::{
+    CoverageReport, KnowledgeSearchResult, PruneCriteria, PruneReport, RetrievalExplanation, SimilarityResult, TextChunk,
+};
+use crate::services::knowledge_base::{KnowledgeBaseHeader, KnowledgeBaseImportSummary};
+use crate::services::vector_database::{CompactionReport, DatabaseMetrics, IndexRebuildReport, OrphanedSource};
+use crate::services::wiki_service::ensure_no_update_running;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter, State};
+use log::info;
+
+fn validate_path(path: &str) -> Result<PathBuf, AppError> {
+    if path.trim().is_empty() {
+        return Err(AppError::ConfigError("File path cannot be empty".to_string()));
+    }
+    Ok(PathBuf::from(path))
+}
+
+#[tauri::command]
+pub async fn export_knowledge_base(state: State<'_, AppState>, path: String) -> CommandResult<KnowledgeBaseHeader> {
+    let path = validate_path(&path)?;
+    info!("Exporting knowledge base to {:?}", path);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.export_knowledge_base(&path).await?)
+}
+
+/// `skip_near_duplicates` leaves out documents whose embeddings nearly match stored ones, e.g. the
+/// same page exported from another install with different chunk ids
+#[tauri::command]
+pub async fn import_knowledge_base(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    skip_near_duplicates: Option<bool>,
+) -> CommandResult<KnowledgeBaseImportSummary> {
+    let path = validate_path(&path)?;
+    if !path.exists() {
+        return Err(AppError::StorageError(format!("File not found: {}", path.display())).into());
+    }
+    info!("Importing knowledge base from {:?}", path);
+    
+    // Counted as running before waiting for the service lock, so a cancel sent meanwhile isn't
+    // cleared when the import starts
+    let _running = state.import_cancel_flag.start();
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.import_knowledge_base(&path, skip_near_duplicates.unwrap_or(false), move |progress| {
+        if let Err(e) = app.emit("import-progress", progress) {
+            log::warn!("Failed to emit import progress: {}", e);
+        }
+    }).await?)
+}
+
+#[tauri::command]
+pub async fn cancel_import(state: State<'_, AppState>) -> CommandResult<String> {
+    // Doesn't take the service lock - a running import is holding it
+    state.import_cancel_flag.cancel();
+    Ok("Knowledge base import cancellation requested".to_string())
+}
+
+#[tauri::command]
+pub async fn compact_database(state: State<'_, AppState>) -> CommandResult<CompactionReport> {
+    info!("Running vector database maintenance");
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.compact_database().await?)
+}
+
+/// Rebuilds the knowledge base's metadata index from the stored chunks, e.g. when filtering by
+/// category or section misses chunks that are there
+#[tauri::command]
+pub async fn rebuild_index(state: State<'_, AppState>) -> CommandResult<IndexRebuildReport> {
+    // A running update writes index entries alongside its chunks
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.rebuild_index().await?)
+}
+
+/// Deletes every chunk of each of `urls` at once, e.g. to clean up after a bad scrape. Returns
+/// how many chunks were removed per URL. The next wiki update fetches those pages again.
+#[tauri::command]
+pub async fn delete_sources(state: State<'_, AppState>, urls: Vec<String>) -> CommandResult<HashMap<String, usize>> {
+    let mut source_urls: Vec<String> = Vec::new();
+    for url in urls.iter().map(|url| url.trim()).filter(|url| !url.is_empty()) {
+        if !source_urls.iter().any(|existing| existing == url) {
+            source_urls.push(url.to_string());
+        }
+    }
+    if source_urls.is_empty() {
+        return Err(AppError::ConfigError("No source URLs given to delete".to_string()).into());
+    }
+    // A running update may be embedding the same pages
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let removed = {
+        let mut embedding_service = state.embedding_service.lock().await;
+        embedding_service.remove_sources(&source_urls).await?
+    };
+    state.wiki_service.lock().await.forget_pages(&source_urls);
+    Ok(removed)
+}
+
+/// Keeps only the pages `keep` selects and deletes every other page's chunks, for a smaller and
+/// faster knowledge base. Pruned pages keep their crawl records, so wiki updates skip them for as
+/// long as the wiki reports them unchanged.
+#[tauri::command]
+pub async fn prune_knowledge_base(state: State<'_, AppState>, keep: PruneCriteria) -> CommandResult<PruneReport> {
+    // A running update may be embedding pages that are about to be pruned
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    let citations = state.usage_stats.source_citations()?;
+    
+    let mut embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.prune_knowledge_base(&keep, &citations).await?)
+}
+
+/// Pages with chunks in the knowledge base that the page store has no record of
+#[tauri::command]
+pub async fn find_orphaned_chunks(state: State<'_, AppState>) -> CommandResult<Vec<OrphanedSource>> {
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.find_orphaned_chunks().await?)
+}
+
+/// Deletes the chunks `find_orphaned_chunks` reports, returning the pages and chunk counts removed
+#[tauri::command]
+pub async fn clean_orphaned_chunks(state: State<'_, AppState>) -> CommandResult<Vec<OrphanedSource>> {
+    // A running update may not have stored its pages yet
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.clean_orphaned_chunks().await?)
+}
+
+/// Writes every chunk stored so far to disk, as a checkpoint during a long update
+#[tauri::command]
+pub async fn flush_database(state: State<'_, AppState>) -> CommandResult<()> {
+    let embedding_service = state.embedding_service.lock().await;
+    embedding_service.flush_database().await?;
+    info!("Vector database flushed");
+    Ok(())
+}
+
+/// Store size and speed numbers for bug reports. `sample_search` also times a few searches,
+/// which scans the whole store each time.
+#[tauri::command]
+pub async fn get_database_metrics(state: State<'_, AppState>, sample_search: Option<bool>) -> CommandResult<DatabaseMetrics> {
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.database_metrics(sample_search.unwrap_or(false)).await?)
+}
+
+#[tauri::command]
+pub async fn search_knowledge(state: State<'_, AppState>, query: String, limit: Option<usize>) -> CommandResult<Vec<KnowledgeSearchResult>> {
+    validate_message_content(&query)?;
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.search_knowledge(&query, limit).await?)
+}
+
+/// `search_knowledge` restricted to pages in one wiki category, e.g. "Tools" or "Mechanics"
+#[tauri::command]
+pub async fn search_by_category(state: State<'_, AppState>, query: String, category: String, limit: Option<usize>) -> CommandResult<Vec<KnowledgeSearchResult>> {
+    validate_message_content(&query)?;
+    if category.trim().is_empty() {
+        return Err(AppError::ConfigError("Category cannot be empty".to_string()).into());
+    }
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.search_by_category(&query, &category, limit).await?)
+}
+
+/// How well the knowledge base covers `topic` (good/partial/none), with the best matching sources
+#[tauri::command]
+pub async fn assess_coverage(state: State<'_, AppState>, topic: String, limit: Option<usize>) -> CommandResult<CoverageReport> {
+    validate_message_content(&topic)?;
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.assess_coverage(&topic, limit).await?)
+}
+
+/// Debug view of retrieval: the chunks chat would use for `query`, with scores and shared terms
+#[tauri::command]
+pub async fn explain_retrieval(state: State<'_, AppState>, query: String, limit: Option<usize>) -> CommandResult<Vec<RetrievalExplanation>> {
+    validate_message_content(&query)?;
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.explain_retrieval(&query, limit).await?)
+}
+
+/// Full content of a single chunk by id; `None` if it doesn't exist (e.g. removed by a re-scrape)
+#[tauri::command]
+pub async fn get_chunk(state: State<'_, AppState>, chunk_id: String) -> CommandResult<Option<TextChunk>> {
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.get_chunk(chunk_id.trim()).await?)
+}
+
+/// Sections from other wiki pages that are most similar to the stored chunk `document_id`
+#[tauri::command]
+pub async fn find_related(state: State<'_, AppState>, document_id: String, limit: Option<usize>) -> CommandResult<Vec<SimilarityResult>> {
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    
+    let embedding_service = state.embedding_service.lock().await;
+    Ok(embedding_service.find_related(document_id.trim(), limit).await?)
+}
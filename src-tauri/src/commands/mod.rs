@@ -2,10 +2,14 @@ pub mod system;
 pub mod ollama;
 pub mod chat;
 pub mod wiki;
+pub mod embedding;
+pub mod knowledge;
 pub mod validation;
 
 pub use system::*;
 pub use ollama::*;
 pub use chat::*;
 pub use wiki::*;
+pub use embedding::*;
+pub use knowledge::*;
 pub use validation::*;
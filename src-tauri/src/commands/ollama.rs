@@ -1,53 +1,210 @@
 use crate::AppState;
-use crate::services::ollama_manager::{OllamaStatus, ModelInfo};
-use crate::commands::validation::validate_model_name;
-use tauri::State;
+use crate::services::ollama_manager::{OllamaStatus, ModelInfo, ModelSetupResult, DEFAULT_CONTEXT_LENGTH};
+use crate::commands::validation::{validate_model_aliases, validate_model_name, validate_ollama_endpoint};
+use crate::config::AppConfig;
+use crate::errors::CommandResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, State};
 
 #[tauri::command]
-pub async fn check_ollama_status(state: State<'_, AppState>) -> Result<OllamaStatus, String> {
+pub async fn check_ollama_status(state: State<'_, AppState>) -> CommandResult<OllamaStatus> {
     let ollama_manager = state.ollama_manager.lock().await;
-    ollama_manager.get_status().await.map_err(|e| e.to_string())
+    ollama_manager.get_status().await.map_err(Into::into)
 }
 
 #[tauri::command]
-pub async fn install_ollama(state: State<'_, AppState>) -> Result<String, String> {
-    let mut ollama_manager = state.ollama_manager.lock().await;
-    ollama_manager.ensure_available().await.map_err(|e| e.to_string())?;
+pub async fn install_ollama(state: State<'_, AppState>) -> CommandResult<String> {
+    // Counted as running before waiting for the manager lock, so a cancel sent meanwhile isn't
+    // cleared when the install starts
+    let _running = state.install_cancel_flag.start();
+    {
+        let mut ollama_manager = state.ollama_manager.lock().await;
+        ollama_manager.ensure_available().await?;
+    }
+    warm_embedding_model(&state);
     Ok("Ollama installed and ready".to_string())
 }
 
+/// Starts preloading the embedding model once Ollama is up, without making the command wait for
+/// the model to load. A failure is only logged, since chat still works and the first search
+/// reports the same error.
+fn warm_embedding_model(state: &AppState) {
+    let embedding_service = state.embedding_service.clone();
+    tokio::spawn(async move {
+        let embedding_service = embedding_service.lock().await;
+        if let Err(e) = embedding_service.warmup().await {
+            log::warn!("Failed to warm up embedding model {}: {}", embedding_service.get_model_name(), e);
+        }
+    });
+}
+
 #[tauri::command]
-pub async fn start_ollama(state: State<'_, AppState>) -> Result<String, String> {
+pub async fn cancel_install(state: State<'_, AppState>) -> CommandResult<String> {
+    // Doesn't take the manager lock - a running install is holding it
+    state.install_cancel_flag.cancel();
+    Ok("Ollama installation cancellation requested".to_string())
+}
+
+#[tauri::command]
+pub async fn start_ollama(state: State<'_, AppState>) -> CommandResult<String> {
     let mut ollama_manager = state.ollama_manager.lock().await;
-    ollama_manager.start_service().await.map_err(|e| e.to_string())?;
+    ollama_manager.start_service().await?;
     Ok("Ollama service started successfully".to_string())
 }
 
 #[tauri::command]
-pub async fn download_model(state: State<'_, AppState>, model_name: String) -> Result<String, String> {
+pub async fn download_model(app: AppHandle, state: State<'_, AppState>, model_name: String) -> CommandResult<String> {
     // Validate model name before attempting download
-    validate_model_name(&model_name).map_err(|e| e.to_string())?;
+    validate_model_name(&model_name)?;
     
     let ollama_manager = state.ollama_manager.lock().await;
-    ollama_manager.download_model(&model_name).await.map_err(|e| e.to_string())?;
+    ollama_manager.download_model_with_progress(&model_name, move |progress| {
+        if let Err(e) = app.emit("model-download-progress", progress) {
+            log::warn!("Failed to emit download progress: {}", e);
+        }
+    }).await?;
     Ok(format!("Model {} downloaded successfully", model_name))
 }
 
+/// First-run setup: downloads `model_names` one after another, by default the configured chat and
+/// embedding models. Installed models are skipped and a failed download doesn't stop the others.
 #[tauri::command]
-pub async fn list_models(state: State<'_, AppState>) -> Result<Vec<ModelInfo>, String> {
+pub async fn setup_models(app: AppHandle, state: State<'_, AppState>, model_names: Option<Vec<String>>) -> CommandResult<Vec<ModelSetupResult>> {
+    let embedding_model = state.embedding_service.lock().await.get_model_name().to_string();
     let ollama_manager = state.ollama_manager.lock().await;
-    ollama_manager.list_models().await.map_err(|e| e.to_string())
+    
+    let requested = model_names.unwrap_or_else(|| vec![ollama_manager.get_model_name().to_string(), embedding_model]);
+    let mut models: Vec<String> = Vec::new();
+    for name in requested {
+        let name = ollama_manager.resolve_model(&name);
+        validate_model_name(&name)?;
+        if !models.contains(&name) {
+            models.push(name);
+        }
+    }
+    
+    Ok(ollama_manager.setup_models(&models, move |progress| {
+        if let Err(e) = app.emit("model-setup-progress", progress) {
+            log::warn!("Failed to emit model setup progress: {}", e);
+        }
+    }).await?)
 }
 
 #[tauri::command]
-pub async fn ensure_ollama_ready(state: State<'_, AppState>) -> Result<OllamaStatus, String> {
+pub async fn list_models(state: State<'_, AppState>) -> CommandResult<Vec<ModelInfo>> {
+    let ollama_manager = state.ollama_manager.lock().await;
+    ollama_manager.list_models().await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn ensure_ollama_ready(state: State<'_, AppState>) -> CommandResult<OllamaStatus> {
+    // May install Ollama; see `install_ollama`
+    let running = state.install_cancel_flag.start();
     let mut ollama_manager = state.ollama_manager.lock().await;
     
     // Try to ensure Ollama is available
-    if let Err(e) = ollama_manager.ensure_available().await {
-        log::error!("Failed to ensure Ollama is ready: {}", e);
-        // Return status anyway so frontend knows what's wrong
+    let ready = match ollama_manager.ensure_available().await {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Failed to ensure Ollama is ready: {}", e);
+            // Return status anyway so frontend knows what's wrong
+            false
+        }
+    };
+    drop(running);
+    if ready {
+        if let Err(e) = ollama_manager.fetch_context_length().await {
+            // Chat budgets context for the default window until this succeeds
+            log::warn!("Failed to look up the model's context length: {}", e);
+        }
+    }
+    
+    let status = ollama_manager.get_status().await?;
+    drop(ollama_manager);
+    if ready {
+        warm_embedding_model(&state);
     }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn set_ollama_port(state: State<'_, AppState>, port: u16, host: Option<String>) -> CommandResult<OllamaStatus> {
+    let mut config = AppConfig::load()?;
+    let host = host.map(|h| h.trim().to_string()).unwrap_or(config.ollama.host.clone());
+    validate_ollama_endpoint(&host, port)?;
     
-    ollama_manager.get_status().await.map_err(|e| e.to_string())
+    config.ollama.host = host.clone();
+    config.ollama.port = port;
+    config.save()?;
+    
+    {
+        let mut embedding_service = state.embedding_service.lock().await;
+        embedding_service.set_ollama_endpoint(&config.ollama);
+    }
+    
+    let mut ollama_manager = state.ollama_manager.lock().await;
+    ollama_manager.set_endpoint(host, port);
+    
+    if let Err(e) = ollama_manager.check_health().await {
+        log::warn!("Ollama is not reachable at the new endpoint: {}", e);
+    }
+    ollama_manager.get_status().await.map_err(Into::into)
+}
+
+/// Turns the check that refuses to pull or load a model too big for the free memory or disk
+/// on or off. Turning it off lets a large model be used anyway, at the risk of freezing the machine.
+#[tauri::command]
+pub async fn set_resource_guard(state: State<'_, AppState>, enabled: bool) -> CommandResult<bool> {
+    let mut config = AppConfig::load()?;
+    config.ollama.check_system_resources = enabled;
+    config.save()?;
+    
+    log::info!("System resource guard {}", if enabled { "enabled" } else { "disabled" });
+    state.ollama_manager.lock().await.set_check_system_resources(enabled);
+    Ok(enabled)
+}
+
+/// The current model's context window, looked up from Ollama, and how much of it is given to wiki context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelContextLength {
+    pub model_name: String,
+    pub context_length: u32,
+    /// False when Ollama didn't report it and `DEFAULT_CONTEXT_LENGTH` is assumed
+    pub known: bool,
+    pub context_token_budget: usize,
+}
+
+#[tauri::command]
+pub async fn get_model_context_length(state: State<'_, AppState>) -> CommandResult<ModelContextLength> {
+    let (model_name, context_length) = {
+        let mut ollama_manager = state.ollama_manager.lock().await;
+        let context_length = ollama_manager.fetch_context_length().await?;
+        (ollama_manager.get_model_name().to_string(), context_length)
+    };
+    
+    let context_token_budget = state.chat_service.lock().await.context_token_budget().await;
+    Ok(ModelContextLength {
+        model_name,
+        context_length: context_length.unwrap_or(DEFAULT_CONTEXT_LENGTH),
+        known: context_length.is_some(),
+        context_token_budget,
+    })
+}
+
+/// Replaces the friendly model names accepted in place of model tags
+#[tauri::command]
+pub async fn set_model_aliases(state: State<'_, AppState>, aliases: HashMap<String, String>) -> CommandResult<HashMap<String, String>> {
+    let aliases: HashMap<String, String> = aliases.into_iter()
+        .map(|(alias, tag)| (alias.trim().to_string(), tag.trim().to_string()))
+        .collect();
+    validate_model_aliases(&aliases)?;
+    
+    let mut config = AppConfig::load()?;
+    config.ollama.model_aliases = aliases.clone();
+    config.save()?;
+    
+    let mut ollama_manager = state.ollama_manager.lock().await;
+    ollama_manager.set_model_aliases(aliases.clone());
+    Ok(aliases)
 }
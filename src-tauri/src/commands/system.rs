@@ -1,5 +1,7 @@
 use crate::AppState;
-use crate::errors::AppResult;
+use crate::config::{config_differences, AppConfig, ConfigDifference, CONFIG_VERSION};
+use crate::errors::CommandResult;
+use crate::services::system_resources::available_disk_space;
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
@@ -11,8 +13,29 @@ pub struct SystemStatus {
     pub memory_usage: u64,
 }
 
+/// Which operations `cancel_all` found running and asked to stop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelReport {
+    pub generation: bool,
+    /// A wiki update or re-parse, including the embedding of its pages
+    pub wiki_update: bool,
+    pub knowledge_import: bool,
+    pub model_download: bool,
+    pub ollama_install: bool,
+}
+
+/// The configuration the running services use, next to the saved config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub effective: AppConfig,
+    /// The config file as saved; `None` if it couldn't be read
+    pub saved: Option<AppConfig>,
+    /// Settings changed at runtime, or in the file since startup, that the two disagree on
+    pub differences: Vec<ConfigDifference>,
+}
+
 #[tauri::command]
-pub async fn get_system_status(state: State<'_, AppState>) -> Result<SystemStatus, String> {
+pub async fn get_system_status(state: State<'_, AppState>) -> CommandResult<SystemStatus> {
     let data_dir = crate::config::AppConfig::get_data_dir();
     
     // Basic system information - in a real implementation, 
@@ -20,9 +43,79 @@ pub async fn get_system_status(state: State<'_, AppState>) -> Result<SystemStatu
     let status = SystemStatus {
         app_version: env!("CARGO_PKG_VERSION").to_string(),
         data_directory: data_dir.to_string_lossy().to_string(),
-        disk_space_available: 0, // TODO: Implement actual disk space check
+        disk_space_available: available_disk_space(&data_dir).unwrap_or(0),
         memory_usage: 0, // TODO: Implement actual memory usage check
     };
     
     Ok(status)
 }
+
+/// Whether the config file couldn't be parsed at startup, in which case it was saved as
+/// `config.json.bak` and the defaults are in use
+#[tauri::command]
+pub async fn get_config_recovered(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.config_recovered)
+}
+
+/// The settings actually in effect, for debugging config issues. Settings changed at runtime,
+/// like the model or endpoint, may not match the saved file; those are listed in `differences`.
+#[tauri::command]
+pub async fn get_effective_config(state: State<'_, AppState>) -> CommandResult<EffectiveConfig> {
+    let (ollama, offline) = {
+        let ollama_manager = state.ollama_manager.lock().await;
+        (ollama_manager.config().clone(), ollama_manager.is_offline())
+    };
+    let wiki = state.wiki_service.lock().await.config().clone();
+    let embedding = state.embedding_service.lock().await.config().clone();
+    let chat = state.chat_service.lock().await.config().clone();
+    
+    let saved = AppConfig::load()
+        .map_err(|e| log::warn!("Failed to read the saved config: {}", e))
+        .ok();
+    let effective = AppConfig {
+        version: CONFIG_VERSION,
+        ollama,
+        wiki,
+        embedding,
+        chat,
+        offline,
+    };
+    let differences = saved.as_ref()
+        .map(|saved| config_differences(saved, &effective))
+        .unwrap_or_default();
+    
+    Ok(EffectiveConfig { effective, saved, differences })
+}
+
+#[tauri::command]
+pub async fn get_offline_mode() -> CommandResult<bool> {
+    Ok(AppConfig::load()?.offline)
+}
+
+/// Turns air-gapped mode on or off, for the running services and on disk
+#[tauri::command]
+pub async fn set_offline_mode(state: State<'_, AppState>, enabled: bool) -> CommandResult<bool> {
+    let mut config = AppConfig::load()?;
+    config.offline = enabled;
+    config.save()?;
+    
+    log::info!("Offline mode {}", if enabled { "enabled" } else { "disabled" });
+    state.ollama_manager.lock().await.set_offline(enabled);
+    state.wiki_service.lock().await.set_offline(enabled);
+    Ok(enabled)
+}
+
+/// Stops everything that's running. Takes no service locks, since the running operations hold
+/// them; each stops at its next check, keeping the work it already finished.
+#[tauri::command]
+pub async fn cancel_all(state: State<'_, AppState>) -> CommandResult<CancelReport> {
+    let report = CancelReport {
+        generation: state.generation_cancel_flag.cancel(),
+        wiki_update: state.crawl_cancel_flag.cancel(),
+        knowledge_import: state.import_cancel_flag.cancel(),
+        model_download: state.download_cancel_flag.cancel(),
+        ollama_install: state.install_cancel_flag.cancel(),
+    };
+    log::info!("Cancel all requested: {:?}", report);
+    Ok(report)
+}
@@ -1,4 +1,10 @@
+use crate::config::{MAX_CONCURRENT_EMBEDDINGS_LIMIT, MAX_TEMPERATURE};
 use crate::errors::{AppError, AppResult};
+use crate::services::chat_service::MAX_COMPARED_MODELS;
+use crate::services::embedding_service::{known_embedding_dimension, KNOWN_EMBEDDING_MODELS};
+use crate::services::ollama_manager::model_names_match;
+use std::collections::HashMap;
+use crate::services::wiki_service::{resolve_entry_point, MAX_CRAWL_DEPTH};
 
 /// Validates a model name for format and character constraints
 /// 
@@ -52,6 +58,260 @@ pub fn validate_model_name(name: &str) -> AppResult<()> {
     Ok(())
 }
 
+/// Validates that a model name is a known embedding-capable model
+/// 
+/// # Arguments
+/// * `name` - The embedding model name to validate, optionally with a tag
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Must pass `validate_model_name`
+/// - The name without its tag must be one of `KNOWN_EMBEDDING_MODELS`
+pub fn validate_embedding_model_name(name: &str) -> AppResult<()> {
+    validate_model_name(name)?;
+    
+    if known_embedding_dimension(name).is_none() {
+        let known: Vec<&str> = KNOWN_EMBEDDING_MODELS.iter().map(|(model, _)| *model).collect();
+        return Err(AppError::ConfigError(
+            format!("'{}' is not a known embedding model. Supported models: {}", name, known.join(", "))
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Validates a list of wiki crawl entry points
+/// 
+/// # Arguments
+/// * `entry_points` - The entry points to validate
+/// * `base_url` - The wiki's base URL that entry points must belong to
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - At least one entry point
+/// - Each must be a path starting with `/` or an http(s) URL on the same host as `base_url`
+pub fn validate_wiki_entry_points(entry_points: &[String], base_url: &str) -> AppResult<()> {
+    if entry_points.is_empty() {
+        return Err(AppError::ConfigError("At least one entry point is required".to_string()));
+    }
+    
+    for entry_point in entry_points {
+        resolve_entry_point(entry_point, base_url)?;
+    }
+    
+    Ok(())
+}
+
+/// Validates the patterns of links the crawl shouldn't follow
+/// 
+/// # Arguments
+/// * `patterns` - Substrings or globs matched against wiki links
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - At most 100 patterns
+/// - Patterns cannot be empty or longer than 200 characters
+/// - A pattern can't consist only of wildcards, since it would exclude every link
+pub fn validate_exclude_patterns(patterns: &[String]) -> AppResult<()> {
+    if patterns.len() > 100 {
+        return Err(AppError::ConfigError("Too many exclude patterns (maximum 100)".to_string()));
+    }
+    
+    for pattern in patterns {
+        if pattern.trim().is_empty() {
+            return Err(AppError::ConfigError("Exclude patterns cannot be empty".to_string()));
+        }
+        
+        if pattern.len() > 200 {
+            return Err(AppError::ConfigError("Exclude pattern too long (maximum 200 characters)".to_string()));
+        }
+        
+        if pattern.chars().all(|c| matches!(c, '*' | '?')) {
+            return Err(AppError::ConfigError(
+                format!("Exclude pattern {} would exclude every page", pattern)
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Validates the wiki crawl depth
+/// 
+/// # Arguments
+/// * `max_depth` - How many links deep the crawl may follow
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Must be within `0..=MAX_CRAWL_DEPTH`
+pub fn validate_crawl_depth(max_depth: u32) -> AppResult<()> {
+    if max_depth > MAX_CRAWL_DEPTH {
+        return Err(AppError::ConfigError(
+            format!("Crawl depth {} is too deep (maximum {})", max_depth, MAX_CRAWL_DEPTH)
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Validates the limit on concurrent embedding requests
+/// 
+/// # Arguments
+/// * `limit` - Most embedding requests allowed in flight at once
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Must be within `1..=MAX_CONCURRENT_EMBEDDINGS_LIMIT`
+pub fn validate_embedding_concurrency(limit: usize) -> AppResult<()> {
+    if !(1..=MAX_CONCURRENT_EMBEDDINGS_LIMIT).contains(&limit) {
+        return Err(AppError::ConfigError(
+            format!("Concurrent embedding limit must be between 1 and {}", MAX_CONCURRENT_EMBEDDINGS_LIMIT)
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Validates the sampling temperature for chat replies
+/// 
+/// # Arguments
+/// * `temperature` - Higher values make replies more varied, 0 makes them repeatable
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Must be within `0.0..=MAX_TEMPERATURE`, which also rules out NaN
+pub fn validate_temperature(temperature: f32) -> AppResult<()> {
+    if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+        return Err(AppError::ConfigError(
+            format!("Temperature must be between 0.0 and {:.1}", MAX_TEMPERATURE)
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Validates the models to compare on one query
+/// 
+/// # Arguments
+/// * `models` - Model tags to generate an answer with
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Between 1 and `MAX_COMPARED_MODELS` models
+/// - Every model must pass `validate_model_name`
+pub fn validate_compared_models(models: &[String]) -> AppResult<()> {
+    if models.is_empty() {
+        return Err(AppError::ConfigError("Choose at least one model to compare".to_string()));
+    }
+    
+    if models.len() > MAX_COMPARED_MODELS {
+        return Err(AppError::ConfigError(
+            format!("At most {} models can be compared at once", MAX_COMPARED_MODELS)
+        ));
+    }
+    
+    for model in models {
+        validate_model_name(model)?;
+    }
+    
+    Ok(())
+}
+
+/// Validates a map of friendly model names to model tags
+/// 
+/// # Arguments
+/// * `aliases` - Alias to model tag
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Aliases cannot be empty or longer than 100 characters
+/// - Every target must pass `validate_model_name`
+/// - Aliases must be unique ignoring case and surrounding whitespace
+/// - An alias can't be the model tag another alias points to, or that tag would become unreachable
+pub fn validate_model_aliases(aliases: &HashMap<String, String>) -> AppResult<()> {
+    let mut seen: Vec<String> = Vec::new();
+    
+    for (alias, target) in aliases {
+        let normalized = alias.trim().to_lowercase();
+        if normalized.is_empty() {
+            return Err(AppError::ConfigError("Model alias cannot be empty".to_string()));
+        }
+        if alias.trim().len() > 100 {
+            return Err(AppError::ConfigError(
+                format!("Model alias '{}' is too long (maximum 100 characters)", alias.trim())
+            ));
+        }
+        validate_model_name(target)?;
+        
+        if seen.contains(&normalized) {
+            return Err(AppError::ConfigError(
+                format!("Model alias '{}' is defined more than once", alias.trim())
+            ));
+        }
+        seen.push(normalized);
+        
+        if let Some((other, shadowed)) = aliases.iter()
+            .find(|(other, tag)| *other != alias && model_names_match(alias, tag)) {
+            return Err(AppError::ConfigError(
+                format!("Model alias '{}' collides with model '{}' used by alias '{}'", alias.trim(), shadowed, other)
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Validates an Ollama host and port
+/// 
+/// # Arguments
+/// * `host` - Hostname or IP address Ollama listens on
+/// * `port` - TCP port Ollama listens on
+/// 
+/// # Returns
+/// * `AppResult<()>` - Ok if valid, Err with specific validation error if invalid
+/// 
+/// # Validation Rules
+/// - Port must be in 1..=65535
+/// - Host cannot be empty or longer than 253 characters
+/// - Host may only contain letters, numbers, dots, and hyphens (no scheme, path, or port)
+pub fn validate_ollama_endpoint(host: &str, port: u16) -> AppResult<()> {
+    if port == 0 {
+        return Err(AppError::ConfigError("Port must be between 1 and 65535".to_string()));
+    }
+    
+    if host.is_empty() {
+        return Err(AppError::ConfigError("Host cannot be empty".to_string()));
+    }
+    
+    if host.len() > 253 {
+        return Err(AppError::ConfigError("Host too long (maximum 253 characters)".to_string()));
+    }
+    
+    if !host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-')) {
+        return Err(AppError::ConfigError(
+            "Host contains invalid characters. Enter just a hostname or IP address, like 127.0.0.1 or localhost".to_string()
+        ));
+    }
+    
+    Ok(())
+}
+
 /// Validates message content for length and basic format constraints
 /// 
 /// # Arguments
@@ -130,6 +390,115 @@ mod tests {
         assert!(validate_model_name("model-").is_err());
     }
 
+    #[test]
+    fn test_validate_embedding_model_name() {
+        assert!(validate_embedding_model_name("nomic-embed-text").is_ok());
+        assert!(validate_embedding_model_name("nomic-embed-text:latest").is_ok());
+        assert!(validate_embedding_model_name("mxbai-embed-large:335m").is_ok());
+        
+        // Chat models are not embedding-capable
+        assert!(validate_embedding_model_name("phi3:mini").is_err());
+        assert!(validate_embedding_model_name("llama3.2").is_err());
+        
+        // Still subject to the general model name rules
+        assert!(validate_embedding_model_name("").is_err());
+        assert!(validate_embedding_model_name("nomic embed").is_err());
+    }
+
+    #[test]
+    fn test_validate_wiki_entry_points() {
+        let base = "https://wiki.vintagestory.at";
+        
+        assert!(validate_wiki_entry_points(&["/index.php?title=Smithing".to_string()], base).is_ok());
+        assert!(validate_wiki_entry_points(&[
+            "/index.php?title=Main_Page".to_string(),
+            "https://wiki.vintagestory.at/index.php?title=Modding".to_string(),
+        ], base).is_ok());
+        
+        assert!(validate_wiki_entry_points(&[], base).is_err());
+        assert!(validate_wiki_entry_points(&[
+            "/index.php?title=Main_Page".to_string(),
+            "https://example.com/wiki".to_string(),
+        ], base).is_err());
+    }
+
+    #[test]
+    fn test_validate_exclude_patterns() {
+        assert!(validate_exclude_patterns(&[]).is_ok());
+        assert!(validate_exclude_patterns(&["talk:".to_string(), "*title=User:*".to_string()]).is_ok());
+        assert!(validate_exclude_patterns(&["  ".to_string()]).is_err());
+        assert!(validate_exclude_patterns(&["**".to_string()]).is_err());
+        assert!(validate_exclude_patterns(&["x".repeat(201)]).is_err());
+    }
+
+    #[test]
+    fn test_validate_crawl_depth() {
+        assert!(validate_crawl_depth(0).is_ok());
+        assert!(validate_crawl_depth(3).is_ok());
+        assert!(validate_crawl_depth(6).is_ok());
+        assert!(validate_crawl_depth(7).is_err());
+    }
+
+    #[test]
+    fn test_validate_embedding_concurrency() {
+        assert!(validate_embedding_concurrency(1).is_ok());
+        assert!(validate_embedding_concurrency(4).is_ok());
+        assert!(validate_embedding_concurrency(32).is_ok());
+        assert!(validate_embedding_concurrency(0).is_err());
+        assert!(validate_embedding_concurrency(33).is_err());
+    }
+
+    #[test]
+    fn test_validate_temperature() {
+        assert!(validate_temperature(0.0).is_ok());
+        assert!(validate_temperature(0.7).is_ok());
+        assert!(validate_temperature(2.0).is_ok());
+        assert!(validate_temperature(-0.1).is_err());
+        assert!(validate_temperature(2.01).is_err());
+        assert!(validate_temperature(f32::NAN).is_err());
+        assert!(validate_temperature(f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_validate_compared_models() {
+        let models = |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        
+        assert!(validate_compared_models(&models(&["phi3:mini", "llama3.2:3b"])).is_ok());
+        assert!(validate_compared_models(&models(&[])).is_err());
+        assert!(validate_compared_models(&models(&["phi3:mini", "not a model!"])).is_err());
+        assert!(validate_compared_models(&models(&["a", "b", "c", "d", "e"])).is_err());
+    }
+
+    #[test]
+    fn test_validate_model_aliases() {
+        let aliases = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(alias, tag)| (alias.to_string(), tag.to_string())).collect()
+        };
+        
+        assert!(validate_model_aliases(&aliases(&[])).is_ok());
+        assert!(validate_model_aliases(&aliases(&[("Fast (Phi-3)", "phi3:mini"), ("Smart", "llama3.2:3b")])).is_ok());
+        // Two friendly names for one model are fine
+        assert!(validate_model_aliases(&aliases(&[("Fast", "phi3:mini"), ("Default", "phi3:mini")])).is_ok());
+        
+        assert!(validate_model_aliases(&aliases(&[("  ", "phi3:mini")])).is_err());
+        assert!(validate_model_aliases(&aliases(&[("Fast", "not a model!")])).is_err());
+        assert!(validate_model_aliases(&aliases(&[("Fast", "phi3:mini"), ("fast ", "llama3.2:3b")])).is_err());
+        assert!(validate_model_aliases(&aliases(&[("phi3:mini", "llama3.2:3b"), ("Fast", "phi3:mini")])).is_err());
+    }
+
+    #[test]
+    fn test_validate_ollama_endpoint() {
+        assert!(validate_ollama_endpoint("127.0.0.1", 11434).is_ok());
+        assert!(validate_ollama_endpoint("localhost", 8080).is_ok());
+        assert!(validate_ollama_endpoint("gpu-box.lan", 65535).is_ok());
+        
+        assert!(validate_ollama_endpoint("127.0.0.1", 0).is_err());
+        assert!(validate_ollama_endpoint("", 11434).is_err());
+        assert!(validate_ollama_endpoint("http://localhost", 11434).is_err());
+        assert!(validate_ollama_endpoint("localhost:11434", 11434).is_err());
+        assert!(validate_ollama_endpoint("local host", 11434).is_err());
+    }
+
     #[test]
     fn test_validate_message_content_valid() {
         // Valid messages
@@ -1,22 +1,50 @@
 use crate::AppState;
-use crate::services::wiki_service::WikiStatus;
-use tauri::State;
-use log::info;
+use crate::commands::validation::{validate_crawl_depth, validate_exclude_patterns, validate_wiki_entry_points};
+use crate::config::AppConfig;
+use crate::services::embedding_service::StorageEstimate;
+use crate::services::page_store::ESTIMATED_BYTES_PER_PAGE;
+use crate::services::system_resources::available_disk_space;
+use crate::services::wiki_service::{claim_update, ensure_no_update_running, resolve_entry_point, CategoryScrapeSummary, ReparseSummary, ScrapeProbe, WikiConfigReport, WikiStatus};
+use crate::errors::{AppError, CommandResult};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use log::{info, warn};
+
+/// Disk space a full scrape is expected to take, next to the space free in the data directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapeSizeEstimate {
+    pub knowledge_base: StorageEstimate,
+    /// Page HTML kept for re-parsing; 0 unless `store_raw_html` is on
+    pub raw_html_bytes: u64,
+    pub total_bytes: u64,
+    /// `None` if the free space couldn't be read
+    pub disk_space_available: Option<u64>,
+    /// Set when the scrape may not fit, or the free space couldn't be checked
+    pub warning: Option<String>,
+}
 
 #[tauri::command]
-pub async fn get_wiki_status(state: State<'_, AppState>) -> Result<WikiStatus, String> {
+pub async fn get_wiki_status(state: State<'_, AppState>) -> CommandResult<WikiStatus> {
     let wiki_service = state.wiki_service.lock().await;
-    wiki_service.get_status().await.map_err(|e| e.to_string())
+    wiki_service.get_status().await.map_err(Into::into)
 }
 
 #[tauri::command]
-pub async fn update_wiki_content(state: State<'_, AppState>) -> Result<String, String> {
+pub async fn update_wiki_content(app: AppHandle, state: State<'_, AppState>) -> CommandResult<String> {
     info!("Starting wiki content update from frontend command");
+    // Claimed before waiting for the service lock, so a second click is rejected instead of queued
+    let claim = claim_update(&state.wiki_update_in_progress)?;
+    
+    state.embedding_progress.set_listener(move |progress| {
+        if let Err(e) = app.emit("embedding-progress", progress) {
+            log::warn!("Failed to emit embedding progress: {}", e);
+        }
+    });
     
     // Start wiki update
     {
         let mut wiki_service = state.wiki_service.lock().await;
-        wiki_service.update_content().await.map_err(|e| e.to_string())?;
+        wiki_service.update_content_claimed(claim).await?;
     }
     
     // TODO: Process scraped content into embeddings
@@ -26,8 +54,222 @@ pub async fn update_wiki_content(state: State<'_, AppState>) -> Result<String, S
     Ok("Wiki content update completed successfully".to_string())
 }
 
+/// Scrapes only the pages in one wiki category and its subcategories, e.g. "Mechanics"
+#[tauri::command]
+pub async fn scrape_category(app: AppHandle, state: State<'_, AppState>, category: String) -> CommandResult<CategoryScrapeSummary> {
+    let claim = claim_update(&state.wiki_update_in_progress)?;
+    
+    state.embedding_progress.set_listener(move |progress| {
+        if let Err(e) = app.emit("embedding-progress", progress) {
+            log::warn!("Failed to emit embedding progress: {}", e);
+        }
+    });
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.scrape_category_claimed(&category, claim).await?)
+}
+
+/// Re-fetches and re-embeds one wiki page, replacing its old chunks. Returns the new chunk count.
+#[tauri::command]
+pub async fn rescrape_page(state: State<'_, AppState>, url: String) -> CommandResult<usize> {
+    let claim = claim_update(&state.wiki_update_in_progress)?;
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.rescrape_page_claimed(url.trim(), claim).await?)
+}
+
+/// Fetches and parses one page without storing it, to check a custom entry point yields content
+#[tauri::command]
+pub async fn test_scrape(state: State<'_, AppState>, url: String) -> CommandResult<ScrapeProbe> {
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.test_scrape(url.trim()).await?)
+}
+
+/// Re-extracts and re-embeds every page from its stored HTML, without fetching anything
+#[tauri::command]
+pub async fn reparse_all(state: State<'_, AppState>) -> CommandResult<ReparseSummary> {
+    let claim = claim_update(&state.wiki_update_in_progress)?;
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.reparse_all_claimed(claim).await?)
+}
+
+/// Checks the wiki config (reachability, entry points, robots.txt, retry settings) so
+/// misconfiguration shows up before a long crawl instead of during it
+#[tauri::command]
+pub async fn validate_wiki_config(state: State<'_, AppState>) -> CommandResult<WikiConfigReport> {
+    ensure_no_update_running(&state.wiki_update_in_progress)?;
+    
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.validate_config().await)
+}
+
+/// Estimates the disk space a full scrape needs, so a disk-full failure doesn't happen halfway
+/// through. Without `estimated_pages` or `embedding_dimension`, errs on the large side.
+#[tauri::command]
+pub async fn estimate_scrape_size(
+    state: State<'_, AppState>,
+    embedding_dimension: Option<usize>,
+    estimated_pages: Option<usize>,
+) -> CommandResult<ScrapeSizeEstimate> {
+    if embedding_dimension == Some(0) || estimated_pages == Some(0) {
+        return Err(AppError::ConfigError("Embedding dimension and page count must be at least 1".to_string()).into());
+    }
+    
+    let stores_raw_html = state.wiki_service.lock().await.stores_raw_html();
+    let knowledge_base = {
+        let embedding_service = state.embedding_service.lock().await;
+        embedding_service.estimate_storage(estimated_pages, embedding_dimension).await?
+    };
+    let raw_html_bytes = if stores_raw_html {
+        knowledge_base.estimated_pages as u64 * ESTIMATED_BYTES_PER_PAGE
+    } else {
+        0
+    };
+    let total_bytes = knowledge_base.estimated_bytes + raw_html_bytes;
+    
+    let disk_space_available = available_disk_space(&AppConfig::get_data_dir());
+    let warning = match disk_space_available {
+        Some(available) if available < total_bytes => Some(format!(
+            "A full scrape needs about {} MB, but only {} MB is free",
+            total_bytes.div_ceil(1_000_000), available / 1_000_000
+        )),
+        Some(_) => None,
+        None => Some("Couldn't check the free disk space".to_string()),
+    };
+    if let Some(warning) = &warning {
+        warn!("Scrape size estimate: {}", warning);
+    }
+    
+    Ok(ScrapeSizeEstimate {
+        knowledge_base,
+        raw_html_bytes,
+        total_bytes,
+        disk_space_available,
+        warning,
+    })
+}
+
+#[tauri::command]
+pub async fn get_wiki_entry_points(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.get_entry_points())
+}
+
+#[tauri::command]
+pub async fn set_wiki_entry_points(state: State<'_, AppState>, entry_points: Vec<String>) -> CommandResult<Vec<String>> {
+    let entry_points: Vec<String> = entry_points.into_iter()
+        .map(|entry| entry.trim().to_string())
+        .collect();
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    validate_wiki_entry_points(&entry_points, wiki_service.base_url())?;
+    
+    // Persist first so the live service never diverges from what's on disk
+    let mut config = AppConfig::load()?;
+    config.wiki.entry_points = entry_points.clone();
+    config.save()?;
+    
+    info!("Wiki entry points updated: {} entries", entry_points.len());
+    wiki_service.set_entry_points(entry_points.clone());
+    Ok(entry_points)
+}
+
+#[tauri::command]
+pub async fn get_wiki_exclude_patterns(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.get_exclude_patterns())
+}
+
+/// Replaces the patterns of links the crawl won't follow (substrings, or globs with `*`/`?`)
+#[tauri::command]
+pub async fn set_wiki_exclude_patterns(state: State<'_, AppState>, patterns: Vec<String>) -> CommandResult<Vec<String>> {
+    let patterns: Vec<String> = patterns.into_iter()
+        .map(|pattern| pattern.trim().to_string())
+        .collect();
+    validate_exclude_patterns(&patterns)?;
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    let mut config = AppConfig::load()?;
+    config.wiki.exclude_patterns = patterns.clone();
+    config.save()?;
+    
+    info!("Wiki exclude patterns updated: {} patterns", patterns.len());
+    wiki_service.set_exclude_patterns(patterns.clone());
+    Ok(patterns)
+}
+
+#[tauri::command]
+pub async fn get_wiki_max_depth(state: State<'_, AppState>) -> CommandResult<u32> {
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.get_max_depth())
+}
+
+#[tauri::command]
+pub async fn set_wiki_max_depth(state: State<'_, AppState>, max_depth: u32) -> CommandResult<u32> {
+    validate_crawl_depth(max_depth)?;
+    
+    let mut wiki_service = state.wiki_service.lock().await;
+    
+    let mut config = AppConfig::load()?;
+    config.wiki.max_depth = max_depth;
+    config.save()?;
+    
+    info!("Wiki crawl depth set to {}", max_depth);
+    wiki_service.set_max_depth(max_depth);
+    Ok(max_depth)
+}
+
+#[tauri::command]
+pub async fn list_pinned(state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    let wiki_service = state.wiki_service.lock().await;
+    Ok(wiki_service.get_pinned_pages())
+}
+
+#[tauri::command]
+pub async fn pin_page(state: State<'_, AppState>, url: String) -> CommandResult<Vec<String>> {
+    let mut wiki_service = state.wiki_service.lock().await;
+    let url = resolve_entry_point(&url, wiki_service.base_url())?;
+    
+    let mut pinned_pages = wiki_service.get_pinned_pages();
+    if !pinned_pages.contains(&url) {
+        pinned_pages.push(url.clone());
+        
+        let mut config = AppConfig::load()?;
+        config.wiki.pinned_pages = pinned_pages.clone();
+        config.save()?;
+        
+        info!("Pinned wiki page {}", url);
+        wiki_service.set_pinned_pages(pinned_pages.clone());
+    }
+    
+    Ok(pinned_pages)
+}
+
+#[tauri::command]
+pub async fn unpin_page(state: State<'_, AppState>, url: String) -> CommandResult<Vec<String>> {
+    let mut wiki_service = state.wiki_service.lock().await;
+    let url = resolve_entry_point(&url, wiki_service.base_url())?;
+    
+    let mut pinned_pages = wiki_service.get_pinned_pages();
+    if pinned_pages.contains(&url) {
+        pinned_pages.retain(|pinned| pinned != &url);
+        
+        let mut config = AppConfig::load()?;
+        config.wiki.pinned_pages = pinned_pages.clone();
+        config.save()?;
+        
+        info!("Unpinned wiki page {}", url);
+        wiki_service.set_pinned_pages(pinned_pages.clone());
+    }
+    
+    Ok(pinned_pages)
+}
+
 #[tauri::command]
-pub async fn process_wiki_embeddings(state: State<'_, AppState>) -> Result<String, String> {
+pub async fn process_wiki_embeddings(state: State<'_, AppState>) -> CommandResult<String> {
     info!("Processing wiki content into embeddings");
     
     // This is a placeholder for processing scraped wiki content into embeddings
@@ -1,58 +1,272 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::fs;
 use dirs;
 
+/// Schema version written to new config files. Bump it when a change needs more than serde
+/// defaults to read older files, and add the upgrade step to `AppConfig::migrate`.
+pub const CONFIG_VERSION: u32 = 1;
+
+// Missing sections and fields fall back to their defaults so older config files keep loading
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
+    /// Schema version of the file; files written before versioning was added have none and read as 0
+    #[serde(default)]
+    pub version: u32,
     pub ollama: OllamaConfig,
     pub wiki: WikiConfig,
     pub embedding: EmbeddingConfig,
     pub chat: ChatConfig,
+    /// Air-gapped mode: never reach the internet. Wiki updates, Ollama installs and model
+    /// downloads fail immediately; chat uses a locally running Ollama and the existing knowledge base.
+    pub offline: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OllamaConfig {
     pub host: String,
     pub port: u16,
     pub model_name: String,
     pub installation_path: Option<PathBuf>,
+    /// Friendly names for model tags, e.g. `"Fast (Phi-3)" -> "phi3:mini"`. Anywhere a model
+    /// name is accepted, an alias works too.
+    pub model_aliases: HashMap<String, String>,
+    /// Consecutive failed generation requests after which Ollama is treated as down
+    pub breaker_failure_threshold: u32,
+    /// How long requests fail fast once Ollama is treated as down, before one is let through to probe it
+    pub breaker_cooldown_secs: u64,
+    /// Refuse to pull or load a model when free memory or disk space is short of what it needs.
+    /// Only applies when Ollama runs on this machine.
+    pub check_system_resources: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WikiConfig {
     pub base_url: String,
     pub update_interval_hours: u64,
     pub last_update: Option<chrono::DateTime<chrono::Utc>>,
+    /// Pages the crawl starts from: paths relative to `base_url` or URLs on the same host
+    pub entry_points: Vec<String>,
+    /// How many links deep to follow from each entry point (0 crawls only the entry points)
+    pub max_depth: u32,
+    /// Absolute URLs that are re-fetched on every update even if the wiki reports them unchanged
+    pub pinned_pages: Vec<String>,
+    /// User-agent sent by the scraper; `{version}` and `{contact}` are filled in
+    pub user_agent: String,
+    /// URL or email the wiki operator can use to reach whoever runs the crawler
+    pub contact: String,
+    /// Times a page fetch is tried before it counts as an error; only network errors and 5xx are retried
+    pub fetch_attempts: u32,
+    /// Delay before the first retry, doubled for each one after that
+    pub retry_backoff_ms: u64,
+    /// Keep each fetched page's HTML so `reparse_all` can re-extract text without re-fetching.
+    /// Wiki pages are mostly markup, so this takes several times the space of the parsed text.
+    pub store_raw_html: bool,
+    /// Links matching any of these aren't followed. A pattern with `*` or `?` is a glob matched
+    /// against the whole link; anything else matches as a substring. Both ignore case.
+    pub exclude_patterns: Vec<String>,
+    /// Idle keep-alive connections to the wiki kept open for reuse. Over HTTP/2, which is used
+    /// whenever the wiki offers it, requests share a single connection anyway.
+    pub max_idle_connections: usize,
+    /// Extra headers sent with every request to the wiki, e.g. an API key a mirror asks for.
+    /// The user-agent is set with `user_agent` instead.
+    pub custom_headers: HashMap<String, String>,
 }
 
+pub const DEFAULT_WIKI_USER_AGENT: &str = "VintageStoryAI/{version} (Educational; +{contact})";
+pub const DEFAULT_WIKI_CONTACT: &str = "https://github.com/rayman546/vintage-story-ai-assistant";
+
+/// Talk pages, user pages and old revisions or diffs of pages
+pub const DEFAULT_WIKI_EXCLUDE_PATTERNS: &[&str] = &["talk:", "User:", "oldid=", "diff="];
+
+/// Entry points crawled when the config doesn't override them
+pub const DEFAULT_WIKI_ENTRY_POINTS: &[&str] = &[
+    "/index.php?title=Main_Page",
+    "/index.php?title=Blocks",
+    "/index.php?title=Items",
+    "/index.php?title=Crafting",
+    "/index.php?title=Getting_started",
+    "/index.php?title=Knapping",
+    "/index.php?title=Clay_forming",
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EmbeddingConfig {
     pub model_name: String,
     pub chunk_size: usize,
     pub chunk_overlap: usize,
     pub batch_size: usize,
+    /// Most embedding requests sent to Ollama at the same time
+    pub max_concurrent_embeddings: usize,
+    /// Chunks shorter than this many characters aren't embedded. Applied after splitting, so it
+    /// drops fragments like a page's short tail or a stub page; text extraction keeps short
+    /// paragraphs and headings so they end up inside a neighbouring chunk instead.
+    pub min_chunk_chars: usize,
+    /// Chunks longer than this many characters are split further, whatever their word count, so
+    /// a huge run of text without spaces can't overflow the embedding model's input
+    pub max_chunk_chars: usize,
+    /// Base time allowed for a single embedding request
+    pub request_timeout_secs: u64,
+    /// Extra time allowed per 1000 characters of text, since long chunks embed slower on CPU
+    pub timeout_secs_per_1000_chars: u64,
+    /// When an import skips near-duplicates, documents whose embedding is at most this far from a
+    /// stored one are skipped. Euclidean distance between unit-length embeddings, from 0 to 2.
+    pub import_duplicate_distance: f32,
+    /// Prepended to search queries before embedding. Some models are trained with task prefixes,
+    /// e.g. `search_query: ` for nomic-embed-text. Changing the prefixes calls for a re-embed.
+    pub query_prefix: String,
+    /// Prepended to wiki chunks before embedding, e.g. `search_document: ` for nomic-embed-text
+    pub document_prefix: String,
+    /// Flush the knowledge base to disk once this many chunks have been written since the last
+    /// flush. A crash loses at most the chunks written since then; ingestion flushes when it ends.
+    pub flush_every_documents: usize,
+    /// Flush on the next write once this many seconds have passed since the last flush
+    pub flush_interval_secs: u64,
+    /// Scale embeddings to unit length before storing them. Unset, it's decided by the model:
+    /// ones known to return unit-length vectors are stored as they are. Changing it calls for a re-embed.
+    pub normalize_embeddings: Option<bool>,
+    /// Threads used to score chunks when searching the in-memory fallback, which only happens
+    /// when the vector database has nothing to search. 0 uses one per CPU core, and more than that
+    /// is capped to it; 1 scores serially.
+    pub memory_search_threads: usize,
+    /// Chunk code blocks on their own, breaking them only between lines, instead of running them
+    /// together with the surrounding prose. Changing it calls for a re-embed.
+    pub separate_code_blocks: bool,
 }
 
+pub const MAX_CONCURRENT_EMBEDDINGS_LIMIT: usize = 32;
+
+/// Highest sampling temperature accepted; Ollama's output is mostly noise well before this
+pub const MAX_TEMPERATURE: f32 = 2.0;
+
+// Missing fields fall back to their defaults so older config files keep loading
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ChatConfig {
     pub max_context_chunks: usize,
     pub temperature: f32,
     pub max_tokens: u32,
+    /// Overall deadline for generating a single reply
+    pub response_timeout_secs: u64,
+    /// Stream tokens from Ollama so partial output survives a timeout
+    pub stream_responses: bool,
+    /// Knowledge bases with fewer chunks than this are treated as empty
+    pub min_knowledge_base_chunks: usize,
+    pub empty_knowledge_base_behavior: EmptyKnowledgeBaseBehavior,
+    /// What to do when a message is limited to certain pages and none of them match it
+    pub empty_source_filter_behavior: EmptySourceFilterBehavior,
+    /// How many recent conversation messages are included in the prompt
+    pub prompt_history_messages: usize,
+    /// Most messages kept in the conversation history; the oldest are dropped first. 0 keeps everything
+    pub max_history_messages: usize,
+    /// Label wiki context as authoritative and history as reference-only
+    pub authoritative_context_framing: bool,
+    /// How each retrieved chunk is written into the prompt. Placeholders: `{title}`, `{url}`,
+    /// `{content}`, and `{section}`, which becomes ` - <heading>` when the chunk's section is known
+    pub context_template: String,
+    /// Replies used when the model can't produce an answer, by failure reason
+    pub fallback_responses: FallbackResponses,
+    /// Installed model to answer with when the chat model isn't installed or fails to load
+    pub fallback_model: Option<String>,
+    /// Search the wiki with just the core of the message, without greetings and pleasantries
+    pub clean_retrieval_query: bool,
+    /// Send the text that adjacent chunks of the same page share through the chunk overlap only once
+    pub trim_overlapping_context: bool,
+}
+
+pub const DEFAULT_CONTEXT_TEMPLATE: &str = "Source: {title}{section}\n{content}";
+
+/// Canned replies for when generation fails. Each list is used in rotation; an empty list
+/// falls back to the built-in messages for that reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackResponses {
+    /// Ollama couldn't be reached
+    pub connection: Vec<String>,
+    /// Ollama is running but the configured model isn't installed
+    pub model_not_found: Vec<String>,
+    /// Any other generation failure
+    pub other: Vec<String>,
+}
+
+pub const DEFAULT_CONNECTION_FALLBACKS: &[&str] = &[
+    "I can't reach the AI service right now. Please check that Ollama is running and try again.",
+    "The AI service is temporarily unavailable. In the meantime, you might want to check the Vintage Story wiki directly.",
+];
+
+pub const DEFAULT_MODEL_NOT_FOUND_FALLBACKS: &[&str] = &[
+    "The AI model isn't installed yet. Please download it from the setup screen and try again.",
+];
+
+pub const DEFAULT_OTHER_FALLBACKS: &[&str] = &[
+    "I'm experiencing some technical difficulties with the AI service. Could you please try again in a moment?",
+    "I apologize, but I'm having trouble processing your request right now. Please try again shortly.",
+];
+
+impl Default for FallbackResponses {
+    fn default() -> Self {
+        let to_strings = |messages: &[&str]| messages.iter().map(|m| m.to_string()).collect();
+        Self {
+            connection: to_strings(DEFAULT_CONNECTION_FALLBACKS),
+            model_not_found: to_strings(DEFAULT_MODEL_NOT_FOUND_FALLBACKS),
+            other: to_strings(DEFAULT_OTHER_FALLBACKS),
+        }
+    }
+}
+
+/// What chat should do when there's no (or almost no) wiki data to ground answers in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyKnowledgeBaseBehavior {
+    /// Answer from the model's general knowledge and say so
+    AnswerFromGeneralKnowledge,
+    /// Don't call the model; tell the user to run a wiki update first
+    RequireWikiUpdate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptySourceFilterBehavior {
+    /// Answer from the model's general knowledge and say the selected pages didn't cover it
+    #[default]
+    AnswerGenerally,
+    /// Don't call the model; say the selected pages don't cover the question
+    Refuse,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             ollama: OllamaConfig::default(),
             wiki: WikiConfig::default(),
             embedding: EmbeddingConfig::default(),
             chat: ChatConfig::default(),
+            offline: false,
         }
     }
 }
 
 
+impl OllamaConfig {
+    /// Root URL of the Ollama HTTP API, e.g. `http://127.0.0.1:11434`
+    pub fn base_url(&self) -> String {
+        format!("http://{}:{}", self.host, self.port)
+    }
+    
+    /// Whether Ollama runs on this machine, so its memory and disk are the ones we can see
+    pub fn is_local(&self) -> bool {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        host.eq_ignore_ascii_case("localhost")
+            || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
 impl Default for OllamaConfig {
     fn default() -> Self {
         Self {
@@ -60,6 +274,10 @@ impl Default for OllamaConfig {
             port: 11434,
             model_name: "phi3:mini".to_string(),
             installation_path: None,
+            model_aliases: HashMap::new(),
+            breaker_failure_threshold: 3,
+            breaker_cooldown_secs: 30,
+            check_system_resources: true,
         }
     }
 }
@@ -70,6 +288,17 @@ impl Default for WikiConfig {
             base_url: "https://wiki.vintagestory.at".to_string(),
             update_interval_hours: 24,
             last_update: None,
+            entry_points: DEFAULT_WIKI_ENTRY_POINTS.iter().map(|p| p.to_string()).collect(),
+            max_depth: 3,
+            pinned_pages: Vec::new(),
+            user_agent: DEFAULT_WIKI_USER_AGENT.to_string(),
+            contact: DEFAULT_WIKI_CONTACT.to_string(),
+            fetch_attempts: 3,
+            retry_backoff_ms: 500,
+            store_raw_html: false,
+            exclude_patterns: DEFAULT_WIKI_EXCLUDE_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            max_idle_connections: 8,
+            custom_headers: HashMap::new(),
         }
     }
 }
@@ -81,6 +310,19 @@ impl Default for EmbeddingConfig {
             chunk_size: 512,
             chunk_overlap: 50,
             batch_size: 10,
+            max_concurrent_embeddings: 4,
+            min_chunk_chars: 50,
+            max_chunk_chars: 6000,
+            request_timeout_secs: 30,
+            timeout_secs_per_1000_chars: 10,
+            import_duplicate_distance: 0.3,
+            query_prefix: String::new(),
+            document_prefix: String::new(),
+            flush_every_documents: 500,
+            flush_interval_secs: 5,
+            normalize_embeddings: None,
+            memory_search_threads: 0,
+            separate_code_blocks: true,
         }
     }
 }
@@ -91,26 +333,91 @@ impl Default for ChatConfig {
             max_context_chunks: 5,
             temperature: 0.7,
             max_tokens: 1024,
+            response_timeout_secs: 120,
+            stream_responses: true,
+            min_knowledge_base_chunks: 10,
+            empty_knowledge_base_behavior: EmptyKnowledgeBaseBehavior::AnswerFromGeneralKnowledge,
+            empty_source_filter_behavior: EmptySourceFilterBehavior::AnswerGenerally,
+            prompt_history_messages: 6,
+            max_history_messages: 100,
+            authoritative_context_framing: true,
+            context_template: DEFAULT_CONTEXT_TEMPLATE.to_string(),
+            fallback_responses: FallbackResponses::default(),
+            fallback_model: None,
+            clean_retrieval_query: false,
+            trim_overlapping_context: true,
+        }
+    }
+}
+
+/// A setting whose value in the running services differs from the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigDifference {
+    /// Dotted path of the setting, e.g. `ollama.model_name`
+    pub setting: String,
+    /// `null` when the file doesn't have the setting
+    pub saved: serde_json::Value,
+    pub effective: serde_json::Value,
+}
+
+/// The settings that differ between the config file and the running services, in path order
+pub fn config_differences(saved: &AppConfig, effective: &AppConfig) -> Vec<ConfigDifference> {
+    fn compare(path: &str, saved: &serde_json::Value, effective: &serde_json::Value, differences: &mut Vec<ConfigDifference>) {
+        if let (serde_json::Value::Object(saved), serde_json::Value::Object(effective)) = (saved, effective) {
+            let keys: std::collections::BTreeSet<&String> = saved.keys().chain(effective.keys()).collect();
+            for key in keys {
+                let setting = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                let missing = serde_json::Value::Null;
+                compare(&setting, saved.get(key).unwrap_or(&missing), effective.get(key).unwrap_or(&missing), differences);
+            }
+        } else if saved != effective {
+            differences.push(ConfigDifference {
+                setting: path.to_string(),
+                saved: saved.clone(),
+                effective: effective.clone(),
+            });
         }
     }
+    
+    let mut differences = Vec::new();
+    match (serde_json::to_value(saved), serde_json::to_value(effective)) {
+        (Ok(saved), Ok(effective)) => compare("", &saved, &effective, &mut differences),
+        (Err(e), _) | (_, Err(e)) => log::warn!("Failed to compare configs: {}", e),
+    }
+    differences
 }
 
 impl AppConfig {
     pub fn load() -> crate::errors::AppResult<Self> {
-        let config_path = Self::get_config_path();
-        
+        Self::load_from(&Self::get_config_path())
+    }
+    
+    /// Loads the config at `config_path`, creating it with defaults if it doesn't exist
+    pub fn load_from(config_path: &Path) -> crate::errors::AppResult<Self> {
+        Self::load_or_recover(config_path).map(|(config, _)| config)
+    }
+    
+    /// Like `load_from`, but also reports whether the file couldn't be parsed. A malformed file is
+    /// moved aside to `<name>.bak` and replaced with defaults, so a bad edit can't stop the app starting.
+    pub fn load_or_recover(config_path: &Path) -> crate::errors::AppResult<(Self, bool)> {
         if config_path.exists() {
-            let content = fs::read_to_string(&config_path)
+            let content = fs::read_to_string(config_path)
                 .map_err(|e| crate::errors::AppError::ConfigError(
                     format!("Failed to read config file: {}", e)
                 ))?;
             
-            let config: AppConfig = serde_json::from_str(&content)
-                .map_err(|e| crate::errors::AppError::ConfigError(
-                    format!("Failed to parse config file: {}", e)
-                ))?;
-            
-            Ok(config)
+            match serde_json::from_str::<AppConfig>(&content) {
+                Ok(mut config) => {
+                    if config.migrate() {
+                        config.save_to(config_path)?;
+                    }
+                    Ok((config, false))
+                }
+                Err(e) => {
+                    log::error!("Failed to parse config file {:?}: {}", config_path, e);
+                    Self::recover_malformed(config_path).map(|config| (config, true))
+                }
+            }
         } else {
             // Create default config and save it
             let default_config = Self::default();
@@ -123,13 +430,66 @@ impl AppConfig {
                     ))?;
             }
             
-            default_config.save()?;
-            Ok(default_config)
+            default_config.save_to(config_path)?;
+            Ok((default_config, false))
+        }
+    }
+    
+    /// Upgrades a config read from an older file to `CONFIG_VERSION`, one version at a time.
+    /// Returns whether anything changed, i.e. whether the file should be saved again.
+    fn migrate(&mut self) -> bool {
+        if self.version > CONFIG_VERSION {
+            log::warn!("Config file is from a newer version of the app (schema {}, expected {}); unknown settings are ignored and changes won't be saved",
+                       self.version, CONFIG_VERSION);
+            return false;
         }
+        
+        let original_version = self.version;
+        while self.version < CONFIG_VERSION {
+            match self.version {
+                // Unversioned files only lack fields that serde fills in with their defaults
+                0 => {}
+                _ => unreachable!("no migration from config version {}", self.version),
+            }
+            self.version += 1;
+        }
+        
+        if self.version != original_version {
+            log::info!("Migrated config from schema version {} to {}", original_version, self.version);
+        }
+        self.version != original_version
+    }
+    
+    /// Backs up an unparseable config file and writes defaults in its place
+    fn recover_malformed(config_path: &Path) -> crate::errors::AppResult<Self> {
+        let mut backup_name = config_path.file_name().unwrap_or_default().to_os_string();
+        backup_name.push(".bak");
+        let backup_path = config_path.with_file_name(backup_name);
+        
+        fs::rename(config_path, &backup_path)
+            .map_err(|e| crate::errors::AppError::ConfigError(
+                format!("Failed to back up malformed config file: {}", e)
+            ))?;
+        log::warn!("Moved malformed config file to {:?} and restored the defaults", backup_path);
+        
+        let default_config = Self::default();
+        default_config.save_to(config_path)?;
+        Ok(default_config)
     }
     
     pub fn save(&self) -> crate::errors::AppResult<()> {
-        let config_path = Self::get_config_path();
+        self.save_to(&Self::get_config_path())
+    }
+    
+    /// Writes the config to `config_path`. Refuses a config loaded from a newer version of the app:
+    /// saving it would drop the settings this version doesn't know about.
+    pub fn save_to(&self, config_path: &Path) -> crate::errors::AppResult<()> {
+        if self.version > CONFIG_VERSION {
+            return Err(crate::errors::AppError::ConfigError(format!(
+                "Config file was written by a newer version of the app (schema {}, this version understands {}); update the app to change settings",
+                self.version, CONFIG_VERSION
+            )));
+        }
         
         // Create directory if it doesn't exist
         if let Some(parent) = config_path.parent() {
@@ -154,7 +514,7 @@ impl AppConfig {
             ))?;
         
         // Atomic rename to final location
-        fs::rename(&temp_path, &config_path)
+        fs::rename(&temp_path, config_path)
             .map_err(|e| crate::errors::AppError::ConfigError(
                 format!("Failed to finalize config file: {}", e)
             ))?;
@@ -168,7 +528,113 @@ impl AppConfig {
             .join("vintage-story-ai-assistant")
     }
     
-    fn get_config_path() -> PathBuf {
+    pub fn get_config_path() -> PathBuf {
         Self::get_data_dir().join("config.json")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_config_is_backed_up_and_replaced_with_defaults() {
+        let dir = std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        let corrupt = r#"{ "ollama": { "host": "localhost", "port": 11434, "#;
+        fs::write(&config_path, corrupt).unwrap();
+        
+        let (config, recovered) = AppConfig::load_or_recover(&config_path).unwrap();
+        
+        assert!(recovered);
+        assert_eq!(config.ollama.port, OllamaConfig::default().port);
+        assert_eq!(fs::read_to_string(dir.join("config.json.bak")).unwrap(), corrupt);
+        // The defaults were written back, so the next start loads cleanly
+        let (_, recovered_again) = AppConfig::load_or_recover(&config_path).unwrap();
+        assert!(!recovered_again);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_unversioned_config_is_migrated_and_resaved() {
+        let dir = std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        // An early config file: no version, no chat section, and only some of the current fields
+        fs::write(&config_path, r#"{
+            "ollama": { "host": "192.168.1.20", "port": 11500, "model_name": "phi3:mini", "installation_path": null },
+            "wiki": { "base_url": "https://wiki.vintagestory.at", "update_interval_hours": 24, "last_update": null },
+            "embedding": { "model_name": "nomic-embed-text", "chunk_size": 800, "chunk_overlap": 100, "batch_size": 10 }
+        }"#).unwrap();
+        
+        let (config, recovered) = AppConfig::load_or_recover(&config_path).unwrap();
+        
+        assert!(!recovered);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.ollama.host, "192.168.1.20");
+        assert_eq!(config.ollama.port, 11500);
+        assert_eq!(config.embedding.chunk_size, 800);
+        assert_eq!(config.chat.max_history_messages, ChatConfig::default().max_history_messages);
+        assert_eq!(config.wiki.max_depth, WikiConfig::default().max_depth);
+        
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(saved["version"], CONFIG_VERSION);
+        assert_eq!(saved["ollama"]["port"], 11500);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_config_from_newer_version_is_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        let newer = format!(r#"{{
+            "version": {},
+            "ollama": {{ "host": "localhost", "port": 11434, "gpu_layers": 20 }},
+            "voice": {{ "enabled": true }}
+        }}"#, CONFIG_VERSION + 1);
+        fs::write(&config_path, &newer).unwrap();
+        
+        // What a settings command does: load, change a field, save
+        let mut config = AppConfig::load_from(&config_path).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION + 1);
+        config.ollama.port = 11500;
+        let err = config.save_to(&config_path).unwrap_err();
+        
+        assert_eq!(err.code(), "CONFIG_ERROR");
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), newer);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_config_differences_name_each_changed_setting() {
+        let saved = AppConfig::default();
+        assert!(config_differences(&saved, &saved).is_empty());
+        
+        let mut effective = saved.clone();
+        effective.ollama.model_name = "llama3.2:3b".to_string();
+        effective.ollama.model_aliases.insert("Fast".to_string(), "phi3:mini".to_string());
+        effective.wiki.max_depth = 4;
+        
+        let differences = config_differences(&saved, &effective);
+        let settings: Vec<&str> = differences.iter().map(|difference| difference.setting.as_str()).collect();
+        assert_eq!(settings, ["ollama.model_aliases.Fast", "ollama.model_name", "wiki.max_depth"]);
+        assert_eq!(differences[0].saved, serde_json::Value::Null);
+        assert_eq!(differences[1].saved, serde_json::json!(saved.ollama.model_name));
+        assert_eq!(differences[1].effective, serde_json::json!("llama3.2:3b"));
+    }
+
+    #[test]
+    fn test_current_config_is_not_rewritten() {
+        let mut config = AppConfig::default();
+        assert!(!config.migrate());
+        
+        config.version = CONFIG_VERSION + 1;
+        assert!(!config.migrate());
+        assert_eq!(config.version, CONFIG_VERSION + 1);
+    }
+}
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,6 +12,15 @@ pub enum AppError {
     #[error("Embedding service error: {0}")]
     EmbeddingError(String),
     
+    /// A query embedding can't be compared with the stored ones, because the embedding model
+    /// changed since the knowledge base was built
+    #[error("Query embedding has {query_dimension} dimensions but the knowledge base contains {stored_dimension}-dimensional embeddings. \
+             The embedding model has changed since the knowledge base was built; re-embed the wiki content to search it.")]
+    DimensionMismatch {
+        query_dimension: usize,
+        stored_dimension: usize,
+    },
+    
     #[error("Database error: {0}")]
     DatabaseError(String),
     
@@ -28,6 +38,167 @@ pub enum AppError {
     
     #[error("Configuration error: {0}")]
     ConfigError(String),
+    
+    #[error("Request timed out: {message}")]
+    Timeout {
+        message: String,
+        partial_response: Option<String>,
+    },
+    
+    /// The model's stream ended before Ollama marked it `done`, e.g. because the connection dropped
+    #[error("Incomplete response: {message}")]
+    IncompleteResponse {
+        message: String,
+        partial_response: Option<String>,
+    },
+    
+    /// The model finished without writing anything; the diagnostic says what it was sent
+    #[error("{} returned an empty response", .0.model)]
+    EmptyResponse(Box<EmptyResponseDiagnostic>),
+    
+    #[error("Unavailable in offline mode: {0}")]
+    OfflineMode(String),
+    
+    #[error("A wiki update is already running")]
+    WikiUpdateInProgress,
+    
+    /// A wiki update finished without fetching a single page, e.g. because the wiki is down or
+    /// its layout changed so no page could be parsed
+    #[error("The wiki update didn't scrape any pages ({errors} errors); the wiki may be unreachable or its page layout may have changed")]
+    NoPagesScraped {
+        errors: u32,
+    },
+    
+    #[error("Ollama installation was cancelled")]
+    InstallCancelled,
+    
+    /// A running operation was stopped with `cancel_all`
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    
+    /// Ollama doesn't have the requested model; it needs to be pulled first
+    #[error("Model not installed: {0}")]
+    ModelNotInstalled(String),
+    
+    /// Free memory or disk space is short of what a model needs; checked before pulling one so
+    /// a large model can't fill the disk or freeze the machine
+    #[error("Insufficient resources: {message}")]
+    InsufficientResources {
+        message: String,
+        shortfall_bytes: u64,
+    },
+    
+    /// Ollama failed repeatedly, so requests are refused without trying until the cooldown ends
+    #[error("Service unavailable: {message}")]
+    ServiceUnavailable {
+        message: String,
+        retry_after_secs: u64,
+    },
+}
+
+impl AppError {
+    /// Stable machine-readable code the frontend can match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::OllamaError(_) => "OLLAMA_ERROR",
+            AppError::WikiError(_) => "WIKI_ERROR",
+            AppError::EmbeddingError(_) => "EMBEDDING_ERROR",
+            AppError::DimensionMismatch { .. } => "DIMENSION_MISMATCH",
+            AppError::DatabaseError(_) => "DATABASE_ERROR",
+            AppError::StorageError(_) => "STORAGE_ERROR",
+            AppError::HttpError(_) => "HTTP_ERROR",
+            AppError::IoError(_) => "IO_ERROR",
+            AppError::JsonError(_) => "JSON_ERROR",
+            AppError::ConfigError(_) => "CONFIG_ERROR",
+            AppError::Timeout { .. } => "TIMEOUT",
+            AppError::IncompleteResponse { .. } => "INCOMPLETE",
+            AppError::EmptyResponse(_) => "EMPTY_RESPONSE",
+            AppError::OfflineMode(_) => "OFFLINE_MODE",
+            AppError::WikiUpdateInProgress => "WIKI_UPDATE_IN_PROGRESS",
+            AppError::NoPagesScraped { .. } => "NO_PAGES_SCRAPED",
+            AppError::InstallCancelled => "INSTALL_CANCELLED",
+            AppError::Cancelled(_) => "CANCELLED",
+            AppError::ModelNotInstalled(_) => "MODEL_NOT_INSTALLED",
+            AppError::InsufficientResources { .. } => "INSUFFICIENT_RESOURCES",
+            AppError::ServiceUnavailable { .. } => "SERVICE_UNAVAILABLE",
+        }
+    }
+    
+    /// Extra structured context for errors that carry more than a message
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::Timeout { partial_response: Some(partial), .. }
+            | AppError::IncompleteResponse { partial_response: Some(partial), .. } => {
+                Some(serde_json::json!({ "partial_response": partial }))
+            }
+            AppError::ServiceUnavailable { retry_after_secs, .. } => {
+                Some(serde_json::json!({ "retry_after_secs": retry_after_secs }))
+            }
+            AppError::DimensionMismatch { query_dimension, stored_dimension } => {
+                Some(serde_json::json!({ "query_dimension": query_dimension, "stored_dimension": stored_dimension }))
+            }
+            AppError::NoPagesScraped { errors } => {
+                Some(serde_json::json!({ "errors": errors }))
+            }
+            AppError::InsufficientResources { shortfall_bytes, .. } => {
+                Some(serde_json::json!({ "shortfall_bytes": shortfall_bytes }))
+            }
+            AppError::EmptyResponse(diagnostic) => {
+                let mut details = serde_json::to_value(diagnostic).ok()?;
+                details["likely_causes"] = serde_json::json!(diagnostic.likely_causes());
+                Some(details)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What was sent when the model finished without writing anything, so the UI can explain the
+/// missing answer instead of showing a bare error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmptyResponseDiagnostic {
+    pub model: String,
+    pub prompt_chars: usize,
+    /// Estimated at about four characters per token
+    pub prompt_tokens: usize,
+    /// Prompt tokens Ollama says it read; fewer than `prompt_tokens` means it cut the prompt short
+    pub prompt_eval_count: Option<u64>,
+    /// The model's context window, if it has been looked up
+    pub context_length: Option<u32>,
+    /// Wiki chunks in the prompt; only known when answering a chat message
+    pub context_chunks: Option<usize>,
+    pub done_reason: Option<String>,
+}
+
+impl EmptyResponseDiagnostic {
+    /// Plain-language reasons the model may have returned nothing, most likely first
+    pub fn likely_causes(&self) -> Vec<String> {
+        let mut causes = Vec::new();
+        if self.done_reason.as_deref() == Some("load") {
+            causes.push(format!("Ollama was still loading {} and didn't generate anything; try again", self.model));
+        }
+        if let Some(context_length) = self.context_length.filter(|&length| self.prompt_tokens >= length as usize) {
+            causes.push(format!(
+                "The prompt (about {} tokens) doesn't fit in the model's {}-token context window",
+                self.prompt_tokens, context_length
+            ));
+        } else if let Some(read) = self.prompt_eval_count.filter(|&read| (read as usize) < self.prompt_tokens / 2) {
+            causes.push(format!(
+                "Ollama only read {} of the prompt's roughly {} tokens; its context window may be set too small",
+                read, self.prompt_tokens
+            ));
+        }
+        if self.done_reason.as_deref() == Some("length") {
+            causes.push("The model reached its token limit before writing any text".to_string());
+        }
+        if self.context_chunks == Some(0) {
+            causes.push("No wiki content matched the question, so the model had nothing to answer from".to_string());
+        }
+        if causes.is_empty() {
+            causes.push("The model may have declined to answer; try rephrasing the question or using another model".to_string());
+        }
+        causes
+    }
 }
 
 // Convert AppError to Tauri's Result type
@@ -37,4 +208,25 @@ impl From<AppError> for tauri::Error {
     }
 }
 
+/// Error returned from Tauri commands, serialized as `{ code, message, details? }`
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandError {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl From<AppError> for CommandError {
+    fn from(err: AppError) -> Self {
+        Self {
+            code: err.code().to_string(),
+            message: err.to_string(),
+            details: err.details(),
+        }
+    }
+}
+
 pub type AppResult<T> = Result<T, AppError>;
+
+pub type CommandResult<T> = Result<T, CommandError>;
@@ -2,6 +2,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use log::{info, warn, error};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -10,12 +11,18 @@ mod services;
 mod config;
 mod errors;
 
+use config::AppConfig;
+
 use services::{
     ollama_manager::OllamaManager,
     wiki_service::WikiService,
     embedding_service::EmbeddingService,
     chat_service::ChatService,
     vector_database::VectorDatabase,
+    page_store::PageStore,
+    usage_stats::UsageStats,
+    embedding_service::EmbeddingProgressTracker,
+    cancellation::CancelFlag,
 };
 
 /// Application state shared across all Tauri commands
@@ -25,6 +32,24 @@ pub struct AppState {
     pub wiki_service: Arc<Mutex<WikiService>>,
     pub embedding_service: Arc<Mutex<EmbeddingService>>,
     pub chat_service: Arc<Mutex<ChatService>>,
+    /// Set to abort an in-progress Ollama install (the install holds the manager lock)
+    pub install_cancel_flag: Arc<CancelFlag>,
+    /// Stops a model download or model setup
+    pub download_cancel_flag: Arc<CancelFlag>,
+    /// Set while a wiki update runs, so a second request is rejected instead of queued
+    pub wiki_update_in_progress: Arc<AtomicBool>,
+    /// Set to stop a knowledge base import (the import holds the embedding service lock)
+    pub import_cancel_flag: Arc<CancelFlag>,
+    /// Stops a running wiki update or re-parse (they hold the wiki service lock)
+    pub crawl_cancel_flag: Arc<CancelFlag>,
+    /// Stops the chat answer being generated (the chat command holds the chat service lock)
+    pub generation_cancel_flag: Arc<CancelFlag>,
+    /// Embedding progress during a wiki update, readable while the update holds the service locks
+    pub embedding_progress: Arc<EmbeddingProgressTracker>,
+    /// The config file was malformed at startup and was replaced with defaults
+    pub config_recovered: bool,
+    /// Local-only counters of chat usage
+    pub usage_stats: Arc<UsageStats>,
 }
 
 #[tokio::main]
@@ -33,19 +58,69 @@ async fn main() {
     env_logger::init();
     info!("Starting Vintage Story AI Assistant");
 
+    // Load configuration, falling back to defaults so the app can still start
+    let (mut app_config, config_recovered) = AppConfig::load_or_recover(&AppConfig::get_config_path())
+        .unwrap_or_else(|e| {
+            warn!("Failed to load configuration, using defaults: {}", e);
+            (AppConfig::default(), false)
+        });
+    
+    if let Err(e) = commands::validation::validate_model_aliases(&app_config.ollama.model_aliases) {
+        warn!("Ignoring configured model aliases: {}", e);
+        app_config.ollama.model_aliases.clear();
+    }
+    
+    if let Some(fallback_model) = &app_config.chat.fallback_model {
+        if let Err(e) = commands::validation::validate_model_name(fallback_model) {
+            warn!("Ignoring configured fallback model: {}", e);
+            app_config.chat.fallback_model = None;
+        }
+    }
+
+    if app_config.offline {
+        info!("Offline mode enabled: wiki updates, Ollama installs and model downloads are disabled");
+    }
+    
     // Initialize services
-    let ollama_manager = Arc::new(Mutex::new(OllamaManager::new().await));
-    let mut wiki_service = WikiService::new().await;
-    let embedding_service = Arc::new(Mutex::new(EmbeddingService::new().await));
+    let mut ollama_manager = OllamaManager::with_config(app_config.ollama.clone()).await;
+    ollama_manager.set_offline(app_config.offline);
+    ollama_manager.set_max_response_tokens(app_config.chat.max_tokens);
+    let install_cancel_flag = ollama_manager.install_cancel_handle();
+    let download_cancel_flag = ollama_manager.download_cancel_handle();
+    let ollama_manager = Arc::new(Mutex::new(ollama_manager));
+    let mut wiki_service = WikiService::with_config(app_config.wiki.clone()).await;
+    wiki_service.set_config_path(AppConfig::get_config_path());
+    wiki_service.set_offline(app_config.offline);
+    if app_config.wiki.store_raw_html {
+        warn!("Storing raw wiki HTML for re-parsing; this takes several times the disk space of the parsed text");
+        match PageStore::new() {
+            Ok(page_store) => wiki_service.set_page_store(Arc::new(page_store)),
+            Err(e) => error!("Failed to open page store, raw HTML won't be kept: {}", e),
+        }
+    }
+    let embedding_service = EmbeddingService::with_config(app_config.embedding.clone(), &app_config.ollama).await;
+    let embedding_progress = embedding_service.progress_handle();
+    let import_cancel_flag = embedding_service.import_cancel_handle();
+    let embedding_service = Arc::new(Mutex::new(embedding_service));
     
     // Connect wiki service to embedding service
     wiki_service.set_embedding_service(embedding_service.clone());
+    wiki_service.set_embedding_progress(embedding_progress.clone());
+    let wiki_update_in_progress = wiki_service.update_in_progress_handle();
+    let crawl_cancel_flag = wiki_service.crawl_cancel_handle();
     let wiki_service = Arc::new(Mutex::new(wiki_service));
     
+    let usage_stats = Arc::new(UsageStats::new().unwrap_or_else(|e| {
+        error!("Failed to open usage stats, keeping them in memory for this session: {}", e);
+        UsageStats::new_temporary()
+    }));
+    
     // Create chat service and give it access to both services
-    let mut chat_service = ChatService::new().await;
+    let mut chat_service = ChatService::with_config(app_config.chat.clone()).await;
     chat_service.set_embedding_service(embedding_service.clone());
     chat_service.set_ollama_manager(ollama_manager.clone());
+    chat_service.set_usage_stats(usage_stats.clone());
+    let generation_cancel_flag = chat_service.generation_cancel_handle();
     let chat_service = Arc::new(Mutex::new(chat_service));
 
     let app_state = AppState {
@@ -53,6 +128,15 @@ async fn main() {
         wiki_service,
         embedding_service,
         chat_service,
+        install_cancel_flag,
+        download_cancel_flag,
+        wiki_update_in_progress,
+        import_cancel_flag,
+        crawl_cancel_flag,
+        generation_cancel_flag,
+        embedding_progress,
+        config_recovered,
+        usage_stats,
     };
 
     // Build and run the Tauri application
@@ -63,16 +147,76 @@ async fn main() {
         .plugin(tauri_plugin_http::init())
         .invoke_handler(tauri::generate_handler![
             commands::system::get_system_status,
+            commands::system::get_config_recovered,
+            commands::system::get_effective_config,
+            commands::system::get_offline_mode,
+            commands::system::set_offline_mode,
+            commands::system::cancel_all,
             commands::ollama::check_ollama_status,
             commands::ollama::ensure_ollama_ready,
             commands::ollama::install_ollama,
+            commands::ollama::cancel_install,
             commands::ollama::start_ollama,
             commands::ollama::download_model,
+            commands::ollama::setup_models,
             commands::ollama::list_models,
+            commands::ollama::set_ollama_port,
+            commands::ollama::set_model_aliases,
+            commands::ollama::set_resource_guard,
+            commands::ollama::get_model_context_length,
             commands::chat::send_message,
+            commands::chat::send_message_with_sources,
+            commands::chat::continue_response,
+            commands::chat::benchmark_query,
+            commands::chat::compare_models,
+            commands::chat::generate_structured,
+            commands::chat::summarize_source,
+            commands::chat::get_usage_stats,
+            commands::chat::reset_usage_stats,
+            commands::chat::set_temperature,
             commands::wiki::update_wiki_content,
             commands::wiki::get_wiki_status,
             commands::wiki::process_wiki_embeddings,
+            commands::wiki::scrape_category,
+            commands::wiki::rescrape_page,
+            commands::wiki::test_scrape,
+            commands::wiki::reparse_all,
+            commands::wiki::validate_wiki_config,
+            commands::wiki::estimate_scrape_size,
+            commands::wiki::get_wiki_entry_points,
+            commands::wiki::set_wiki_entry_points,
+            commands::wiki::get_wiki_max_depth,
+            commands::wiki::set_wiki_max_depth,
+            commands::wiki::get_wiki_exclude_patterns,
+            commands::wiki::set_wiki_exclude_patterns,
+            commands::wiki::pin_page,
+            commands::wiki::unpin_page,
+            commands::wiki::list_pinned,
+            commands::embedding::set_embedding_model,
+            commands::embedding::get_embedding_model_info,
+            commands::embedding::preview_chunks,
+            commands::embedding::get_embedding_progress,
+            commands::embedding::warmup_embeddings,
+            commands::embedding::embed,
+            commands::embedding::retry_failed_embeddings,
+            commands::embedding::set_max_concurrent_embeddings,
+            commands::knowledge::export_knowledge_base,
+            commands::knowledge::import_knowledge_base,
+            commands::knowledge::cancel_import,
+            commands::knowledge::compact_database,
+            commands::knowledge::rebuild_index,
+            commands::knowledge::prune_knowledge_base,
+            commands::knowledge::delete_sources,
+            commands::knowledge::find_orphaned_chunks,
+            commands::knowledge::clean_orphaned_chunks,
+            commands::knowledge::flush_database,
+            commands::knowledge::get_database_metrics,
+            commands::knowledge::search_knowledge,
+            commands::knowledge::search_by_category,
+            commands::knowledge::assess_coverage,
+            commands::knowledge::explain_retrieval,
+            commands::knowledge::get_chunk,
+            commands::knowledge::find_related,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// How often an operation waiting on `cancelled` checks the flag
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancellation for one kind of long-running operation, which also tracks whether one is
+/// running. Operations hold their service's lock while they run, so the flag is shared through
+/// an `Arc` and can be set without that lock.
+#[derive(Debug, Default)]
+pub struct CancelFlag {
+    cancelled: AtomicBool,
+    running: AtomicUsize,
+}
+
+/// Keeps its operation counted as running until dropped
+pub struct RunningOperation(Arc<CancelFlag>);
+
+impl Drop for RunningOperation {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl CancelFlag {
+    /// Marks an operation as running. The outermost of nested operations (a model download
+    /// within model setup) clears any cancellation left over from an earlier run.
+    pub fn start(self: &Arc<Self>) -> RunningOperation {
+        if self.running.fetch_add(1, Ordering::SeqCst) == 0 {
+            self.cancelled.store(false, Ordering::SeqCst);
+        }
+        RunningOperation(self.clone())
+    }
+    
+    /// Asks the running operation to stop, returning whether one was running. Harmless when
+    /// nothing is; the next operation starts with the flag cleared.
+    pub fn cancel(&self) -> bool {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.is_running()
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+    
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst) > 0
+    }
+    
+    /// Resolves once the flag is set, for racing against a step with `tokio::select!`
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            sleep(CANCEL_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_cancel_reports_whether_anything_was_running() {
+        let flag = Arc::new(CancelFlag::default());
+        assert!(!flag.cancel());
+        
+        let running = flag.start();
+        assert!(!flag.is_cancelled(), "starting clears a leftover cancellation");
+        assert!(flag.cancel());
+        assert!(flag.is_cancelled());
+        
+        drop(running);
+        assert!(!flag.is_running());
+    }
+    
+    #[test]
+    fn test_nested_operations_keep_the_cancellation() {
+        let flag = Arc::new(CancelFlag::default());
+        let setup = flag.start();
+        flag.cancel();
+        
+        let download = flag.start();
+        assert!(flag.is_cancelled());
+        
+        drop(download);
+        assert!(flag.is_running());
+        drop(setup);
+        assert!(!flag.is_running());
+    }
+}
@@ -1,10 +1,81 @@
-use crate::errors::AppResult;
-use crate::services::embedding_service::{EmbeddingService, SimilarityResult};
-use crate::services::ollama_manager::OllamaManager;
+use crate::config::{
+    ChatConfig, EmptyKnowledgeBaseBehavior, EmptySourceFilterBehavior, DEFAULT_CONNECTION_FALLBACKS, DEFAULT_MODEL_NOT_FOUND_FALLBACKS,
+    DEFAULT_OTHER_FALLBACKS,
+};
+use crate::errors::{AppError, AppResult};
+use crate::services::cancellation::CancelFlag;
+use crate::services::embedding_service::{
+    estimate_tokens, EmbeddingKind, EmbeddingService, SimilarityResult, GOOD_COVERAGE_SIMILARITY, PARTIAL_COVERAGE_SIMILARITY,
+    TOKEN_COUNT_METADATA_KEY,
+};
+use crate::services::ollama_manager::{is_model_unavailable, model_names_match, Generation, OllamaManager, DEFAULT_CONTEXT_LENGTH};
+use crate::services::usage_stats::{ChatTurnUsage, UsageStats};
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio::time::{timeout, Duration, Instant};
 use log::{info, warn, error};
+use regex::Regex;
+
+pub(crate) const NO_WIKI_DATA_INSTRUCTION: &str = "Note: No Vintage Story wiki data is available yet. Answer from your general knowledge of the game, and clearly tell the user that your answer is not based on the wiki and may be outdated or inaccurate.";
+
+pub(crate) const EMPTY_KNOWLEDGE_BASE_GUIDANCE: &str = "I don't have any Vintage Story wiki data to answer from yet. Please run a wiki update first so I can give you accurate, wiki-based answers.";
+
+pub(crate) const NO_FILTERED_CONTEXT_INSTRUCTION: &str = "Note: The user limited this question to specific wiki pages, and none of them cover it. Answer from your general knowledge of the game, and clearly tell the user that your answer does not come from the pages they selected.";
+
+pub(crate) const NO_FILTERED_CONTEXT_RESPONSE: &str = "None of the pages you limited this question to cover it, so I can't answer from them. Try adding more pages or removing the page filter.";
+
+pub(crate) const AUTHORITATIVE_CONTEXT_HEADER: &str = "Authoritative information from the Vintage Story wiki. Treat it as the source of truth: if anything in the conversation above contradicts it, trust the wiki.";
+
+pub(crate) const HISTORY_REFERENCE_HEADER: &str = "Previous conversation (for reference only - it may contain mistakes and does not override the wiki):";
+
+pub(crate) const INSUFFICIENT_SOURCES_RESPONSE: &str = "I can't answer that from the wiki sources I have. None of the retrieved wiki content covers this question well enough to cite, so I'd rather not guess.";
+
+/// Token the model is told to reply with when the sources don't answer the question
+const INSUFFICIENT_SOURCES_MARKER: &str = "INSUFFICIENT_SOURCES";
+
+/// Tokens set aside for the instructions, conversation history and question around the wiki context
+const PROMPT_OVERHEAD_TOKENS: usize = 768;
+
+/// Fewest words adjacent chunks must share before the later one is trimmed, so a word that just
+/// happens to end one chunk and start the next isn't mistaken for overlap
+const MIN_OVERLAP_WORDS: usize = 3;
+
+/// Smallest context budget used, so even a tiny context window gets about one chunk of context
+const MIN_CONTEXT_TOKEN_BUDGET: usize = 256;
+
+/// Most models `compare_models` answers with at once; each may need to be loaded into memory
+pub const MAX_COMPARED_MODELS: usize = 4;
+
+/// Context chunks at least partly matching the question that an answer needs for full coverage
+/// in `answer_confidence`
+const CONFIDENT_SUPPORTING_CHUNKS: usize = 3;
+
+const UNCITED_ANSWER_NOTE: &str = "Note: this answer doesn't cite any of the wiki sources, so it may not be supported by them.";
+
+/// Why generation failed, which decides the fallback reply the user sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FallbackReason {
+    Connection,
+    ModelNotFound,
+    Other,
+}
+
+impl FallbackReason {
+    pub(crate) fn from_error(error: &AppError) -> Self {
+        let message = error.to_string().to_lowercase();
+        match error {
+            AppError::HttpError(e) if e.is_connect() => FallbackReason::Connection,
+            AppError::ServiceUnavailable { .. } => FallbackReason::Connection,
+            AppError::ModelNotInstalled(_) => FallbackReason::ModelNotFound,
+            // Ollama answers 404 with `model "<name>" not found, try pulling it first`
+            AppError::OllamaError(_) if message.contains("not found") => FallbackReason::ModelNotFound,
+            AppError::OllamaError(_) if message.contains("failed to send request") => FallbackReason::Connection,
+            _ => FallbackReason::Other,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -14,30 +85,150 @@ pub struct ChatMessage {
     pub timestamp: String,
 }
 
+/// Wall-clock time spent in each stage of answering a query, in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryTiming {
+    pub embedding_ms: f64,
+    pub retrieval_ms: f64,
+    pub generation_ms: f64,
+    pub total_ms: f64,
+    /// The query was embedded with the mock fallback because Ollama's embedding API failed
+    pub used_mock_embedding: bool,
+    pub context_chunks: usize,
+    pub response_chars: usize,
+}
+
+/// Milestones of answering a message, reported in order so the UI can show what the
+/// assistant is doing before the answer is complete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum ChatPipelineEvent {
+    RetrievalStarted,
+    /// `sources` are the wiki chunks the answer will draw on, formatted like `context_used`
+    RetrievalComplete { sources: Vec<String> },
+    GenerationStarted,
+    /// A piece of the answer; non-streaming generation sends the whole answer as one token
+    Token { text: String },
+}
+
+impl ChatPipelineEvent {
+    /// Name of the Tauri event this is emitted as
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            Self::RetrievalStarted => "retrieval-started",
+            Self::RetrievalComplete { .. } => "retrieval-complete",
+            Self::GenerationStarted => "generation-started",
+            Self::Token { .. } => "chat-token",
+        }
+    }
+}
+
+/// One model's side of `compare_models`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelAnswer {
+    pub model: String,
+    /// `None` when the model was skipped or failed; `note` says why
+    pub response: Option<String>,
+    pub generation_ms: f64,
+    pub note: Option<String>,
+}
+
+/// Answers from several models to the same query, all given the same wiki context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelComparison {
+    pub query: String,
+    pub context_used: Vec<String>,
+    pub retrieval_ms: f64,
+    pub answers: Vec<ModelAnswer>,
+}
+
+/// Summary of one wiki page, from `summarize_source`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceSummary {
+    pub source_url: String,
+    pub source_title: String,
+    pub summary: String,
+    pub chunks_summarized: usize,
+    /// Rounds of summarization: 1 when the page fit in one prompt, more when parts of it were
+    /// summarized first and those summaries were summarized in turn
+    pub passes: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponse {
     pub message: ChatMessage,
     pub context_used: Vec<String>,
+    /// The model stopped at its token limit mid-answer; `continue_response` picks up from there
+    pub truncated: bool,
+    /// How well the wiki context backs the answer. `None` for continuations, which share the
+    /// confidence of the reply they extend.
+    pub confidence: Option<AnswerConfidence>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceLevel {
+    High,
+    Medium,
+    /// Little or no wiki context matched; a wiki update may help
+    Low,
+}
+
+/// Heuristic trust in an answer, from how closely the retrieved wiki context matched the question
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AnswerConfidence {
+    /// 0.0 - 1.0
+    pub score: f32,
+    pub level: ConfidenceLevel,
+}
+
+/// A reply and the model that wrote it; `model` is `None` for canned fallback replies
+struct GeneratedAnswer {
+    text: String,
+    model: Option<String>,
+    truncated: bool,
 }
 
 pub struct ChatService {
+    config: ChatConfig,
     embedding_service: Arc<Mutex<EmbeddingService>>,
     ollama_manager: Arc<Mutex<OllamaManager>>,
+    usage_stats: Option<Arc<UsageStats>>,
     conversation_history: Vec<ChatMessage>,
+    /// Advances on every fallback reply so repeated failures don't show the same message
+    fallback_counter: AtomicUsize,
+    /// Stops the answer being generated (the chat command holds the service lock meanwhile)
+    generation_cancelled: Arc<CancelFlag>,
 }
 
 impl ChatService {
     pub async fn new() -> Self {
+        Self::with_config(ChatConfig::default()).await
+    }
+    
+    pub async fn with_config(config: ChatConfig) -> Self {
         let embedding_service = Arc::new(Mutex::new(EmbeddingService::new().await));
         let ollama_manager = Arc::new(Mutex::new(OllamaManager::new().await));
         
         Self {
+            config,
             embedding_service,
             ollama_manager,
+            usage_stats: None,
             conversation_history: Vec::new(),
+            fallback_counter: AtomicUsize::new(0),
+            generation_cancelled: Arc::new(CancelFlag::default()),
         }
     }
     
+    pub fn config(&self) -> &ChatConfig {
+        &self.config
+    }
+    
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.config.temperature = temperature;
+    }
+    
     pub fn set_embedding_service(&mut self, embedding_service: Arc<Mutex<EmbeddingService>>) {
         self.embedding_service = embedding_service;
     }
@@ -45,9 +236,34 @@ impl ChatService {
     pub fn set_ollama_manager(&mut self, ollama_manager: Arc<Mutex<OllamaManager>>) {
         self.ollama_manager = ollama_manager;
     }
+    
+    pub fn set_usage_stats(&mut self, usage_stats: Arc<UsageStats>) {
+        self.usage_stats = Some(usage_stats);
+    }
+    
+    /// Flag that stops the answer being generated, settable without the service lock
+    pub fn generation_cancel_handle(&self) -> Arc<CancelFlag> {
+        self.generation_cancelled.clone()
+    }
 
     pub async fn process_message(&mut self, message: &str) -> AppResult<ChatResponse> {
+        self.process_message_filtered(message, &[]).await
+    }
+    
+    /// `process_message` with retrieval limited to chunks from `source_filter` (page URLs or
+    /// titles); an empty filter searches the whole knowledge base
+    pub async fn process_message_filtered(&mut self, message: &str, source_filter: &[String]) -> AppResult<ChatResponse> {
+        self.process_message_with_events(message, source_filter, |_| {}).await
+    }
+    
+    /// `process_message_filtered` reporting each pipeline stage to `on_event` as it starts or
+    /// finishes: retrieval, then generation with the answer's tokens as they arrive
+    pub async fn process_message_with_events<F>(&mut self, message: &str, source_filter: &[String], on_event: F) -> AppResult<ChatResponse>
+    where
+        F: Fn(ChatPipelineEvent) + Send + Sync,
+    {
         info!("Processing user message: {}", message);
+        let started = Instant::now();
         
         // Store user message in history
         let user_message = ChatMessage {
@@ -56,25 +272,59 @@ impl ChatService {
             role: "user".to_string(),
             timestamp: chrono::Utc::now().to_rfc3339(),
         };
-        self.conversation_history.push(user_message);
+        self.record_message(user_message);
         
-        // Search for relevant context using embedding service
-        let context_results = {
+        // Check whether there's enough wiki data to ground an answer in
+        let knowledge_base_size = {
             let embedding_service = self.embedding_service.lock().await;
-            embedding_service.search_similar(message, 5).await.unwrap_or_default()
+            embedding_service.knowledge_base_size().await.unwrap_or(0)
         };
+        let knowledge_base_empty = knowledge_base_size < self.config.min_knowledge_base_chunks;
         
-        // Extract context text and sources
-        let context_texts: Vec<String> = context_results.iter()
-            .map(|result| format!("Source: {}\n{}", result.chunk.source_title, result.chunk.content))
-            .collect();
+        if knowledge_base_empty {
+            warn!("Knowledge base has only {} chunks (minimum {})", 
+                  knowledge_base_size, self.config.min_knowledge_base_chunks);
+            
+            if self.config.empty_knowledge_base_behavior == EmptyKnowledgeBaseBehavior::RequireWikiUpdate {
+                return Ok(self.respond_without_model(EMPTY_KNOWLEDGE_BASE_GUIDANCE.to_string()));
+            }
+        }
+        
+        // Search for relevant context using embedding service
+        on_event(ChatPipelineEvent::RetrievalStarted);
+        let context_results = self.retrieve_context(message, source_filter).await?;
+        let context_results = fit_context_to_budget(context_results, self.context_token_budget().await);
         
         let context_sources: Vec<String> = context_results.iter()
             .map(|result| format!("{} (score: {:.2})", result.chunk.source_title, result.similarity_score))
             .collect();
+        on_event(ChatPipelineEvent::RetrievalComplete { sources: context_sources.clone() });
+        
+        let mut prompt_note = knowledge_base_empty.then_some(NO_WIKI_DATA_INSTRUCTION);
+        if !source_filter.is_empty() && context_results.is_empty() && !knowledge_base_empty {
+            info!("No context found in the {} selected sources", source_filter.len());
+            if self.config.empty_source_filter_behavior == EmptySourceFilterBehavior::Refuse {
+                return Ok(self.respond_without_model(NO_FILTERED_CONTEXT_RESPONSE.to_string()));
+            }
+            prompt_note = Some(NO_FILTERED_CONTEXT_INSTRUCTION);
+        }
+        
+        // Extract context text
+        let context_texts: Vec<String> = context_results.iter()
+            .map(|result| format_context_chunk(&self.config.context_template, result))
+            .collect();
         
         // Generate response using Ollama with context
-        let response_content = self.generate_llm_response(message, &context_texts).await?;
+        let prompt = self.build_prompt_with_note(message, &context_texts, prompt_note);
+        on_event(ChatPipelineEvent::GenerationStarted);
+        let answer = self.generate_with_deadline_streaming(&prompt, &|token: &str| {
+            on_event(ChatPipelineEvent::Token { text: token.to_string() });
+        }).await.map_err(|e| note_context_chunks(e, context_results.len()))?;
+        self.record_usage(started, answer.model, &context_results);
+        let response_content = answer.text;
+        if answer.truncated {
+            info!("Response was cut off at the token limit");
+        }
         
         // Create assistant message
         let assistant_message = ChatMessage {
@@ -85,70 +335,639 @@ impl ChatService {
         };
         
         // Store assistant message in history
-        self.conversation_history.push(assistant_message.clone());
+        self.record_message(assistant_message.clone());
         
         Ok(ChatResponse {
             message: assistant_message,
             context_used: context_sources,
+            truncated: answer.truncated,
+            confidence: Some(answer_confidence(&context_results)),
         })
     }
     
-    async fn generate_llm_response(&self, query: &str, context: &[String]) -> AppResult<String> {
-        // Build prompt with context
-        let prompt = self.build_prompt(query, context);
+    /// Has the model carry on from the last reply, e.g. one cut off at the token limit, and
+    /// appends what it writes to that same message. Returns the whole extended reply; the
+    /// continuation is reported to `on_event` as it arrives.
+    pub async fn continue_response<F>(&mut self, on_event: F) -> AppResult<ChatResponse>
+    where
+        F: Fn(ChatPipelineEvent) + Send + Sync,
+    {
+        let Some(partial) = self.conversation_history.last().filter(|message| message.role == "assistant") else {
+            return Err(AppError::ConfigError("There's no assistant reply to continue".to_string()));
+        };
+        info!("Continuing reply {} ({} chars so far)", partial.id, partial.content.len());
+        let started = Instant::now();
+        
+        let prompt = self.build_continuation_prompt(&partial.content);
+        on_event(ChatPipelineEvent::GenerationStarted);
+        let answer = self.generate_with_deadline_streaming(&prompt, &|token: &str| {
+            on_event(ChatPipelineEvent::Token { text: token.to_string() });
+        }).await?;
+        if answer.model.is_none() {
+            // A canned fallback isn't part of the reply; leave the message as it was
+            return Err(AppError::OllamaError(answer.text));
+        }
+        self.record_usage(started, answer.model, &[]);
+        
+        let message = self.conversation_history.last_mut().expect("checked above");
+        message.content.push_str(&answer.text);
+        Ok(ChatResponse {
+            message: message.clone(),
+            context_used: Vec::new(),
+            truncated: answer.truncated,
+            confidence: None,
+        })
+    }
+    
+    /// Answers strictly from retrieved wiki context, citing each claim as `[source N]` where N
+    /// indexes `context_used`. Refuses instead of guessing when there's no usable context.
+    pub async fn process_message_with_sources(&mut self, message: &str) -> AppResult<ChatResponse> {
+        info!("Processing user message with explicit sources: {}", message);
+        let started = Instant::now();
+        
+        let user_message = ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            content: message.to_string(),
+            role: "user".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        self.record_message(user_message);
+        
+        let context_results = self.retrieve_context(message, &[]).await?;
+        if context_results.is_empty() {
+            info!("No wiki context found, refusing to answer without sources");
+            return Ok(self.respond_without_model(INSUFFICIENT_SOURCES_RESPONSE.to_string()));
+        }
+        
+        let context_sources: Vec<String> = context_results.iter()
+            .enumerate()
+            .map(|(i, result)| format!("[source {}] {} (score: {:.2})", i + 1, result.chunk.source_title, result.similarity_score))
+            .collect();
+        
+        let prompt = self.build_sourced_prompt(message, &context_results);
+        let answer = self.generate_with_deadline_streaming(&prompt, &|_| {}).await
+            .map_err(|e| note_context_chunks(e, context_results.len()))?;
+        self.record_usage(started, answer.model, &context_results);
+        let raw_response = answer.text;
+        
+        let content = if raw_response.contains(INSUFFICIENT_SOURCES_MARKER) {
+            INSUFFICIENT_SOURCES_RESPONSE.to_string()
+        } else {
+            validate_citations(&raw_response, context_results.len())
+        };
+        
+        let assistant_message = ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            content,
+            role: "assistant".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        self.record_message(assistant_message.clone());
+        
+        Ok(ChatResponse {
+            message: assistant_message,
+            context_used: context_sources,
+            truncated: answer.truncated,
+            confidence: Some(answer_confidence(&context_results)),
+        })
+    }
+    
+    /// Asks the model for JSON matching `schema`, a description of the expected shape (a JSON
+    /// Schema or an example object). Not added to the conversation history.
+    pub async fn generate_structured(&self, request: &str, schema: &str) -> AppResult<serde_json::Value> {
+        let prompt = format!(
+            "You are a helpful assistant specializing in the game Vintage Story.\n\n\
+             Respond only with JSON matching this schema:\n{}\n\n\
+             Request: {}",
+            schema, request
+        );
         
-        // Call Ollama to generate response
         let ollama = self.ollama_manager.lock().await;
+        ollama.generate_json(&prompt).await
+    }
+    
+    /// Runs the `process_message` pipeline for `query` with each stage timed. The conversation
+    /// history is used for the prompt but not updated, so benchmarking doesn't show up in chat.
+    pub async fn benchmark_query(&self, query: &str) -> AppResult<QueryTiming> {
+        let started = Instant::now();
         
-        match ollama.generate_response(&prompt).await {
-            Ok(response) => Ok(response),
+        let (context_results, embedding_ms, retrieval_ms, used_mock_embedding, knowledge_base_empty) = {
+            let embedding_service = self.embedding_service.lock().await;
+            let knowledge_base_size = embedding_service.knowledge_base_size().await.unwrap_or(0);
+            
+            let stage = Instant::now();
+            let (query_embedding, used_mock_embedding) = embedding_service.create_embedding_with_source(&self.search_query(query), EmbeddingKind::Query).await?;
+            let embedding_ms = elapsed_ms(stage);
+            
+            let stage = Instant::now();
+            let context_results = match embedding_service.search_by_embedding(query_embedding, 5).await {
+                Ok(results) => results,
+                Err(e @ AppError::DimensionMismatch { .. }) if !used_mock_embedding => return Err(e),
+                Err(e) => {
+                    warn!("Context search failed, benchmarking without wiki context: {}", e);
+                    Vec::new()
+                }
+            };
+            let retrieval_ms = elapsed_ms(stage);
+            
+            (context_results, embedding_ms, retrieval_ms, used_mock_embedding,
+             knowledge_base_size < self.config.min_knowledge_base_chunks)
+        };
+        
+        let context_texts: Vec<String> = context_results.iter()
+            .map(|result| format_context_chunk(&self.config.context_template, result))
+            .collect();
+        
+        let stage = Instant::now();
+        let response = self.generate_llm_response(query, &context_texts, knowledge_base_empty).await?;
+        let generation_ms = elapsed_ms(stage);
+        
+        let timing = QueryTiming {
+            embedding_ms,
+            retrieval_ms,
+            generation_ms,
+            total_ms: elapsed_ms(started),
+            used_mock_embedding,
+            context_chunks: context_results.len(),
+            response_chars: response.chars().count(),
+        };
+        info!("Query benchmark: {:?}", timing);
+        Ok(timing)
+    }
+    
+    /// Retrieves context for `query` once and has each of `models` answer from the same prompt,
+    /// concurrently. Models that aren't installed are skipped with a note rather than failing the
+    /// comparison. Like `benchmark_query`, this doesn't add to the conversation history.
+    pub async fn compare_models(&self, query: &str, models: &[String]) -> AppResult<ModelComparison> {
+        let started = Instant::now();
+        let knowledge_base_empty = {
+            let embedding_service = self.embedding_service.lock().await;
+            embedding_service.knowledge_base_size().await.unwrap_or(0) < self.config.min_knowledge_base_chunks
+        };
+        let context_results = self.retrieve_context(query, &[]).await?;
+        let context_results = fit_context_to_budget(context_results, self.context_token_budget().await);
+        let retrieval_ms = elapsed_ms(started);
+        
+        let context_texts: Vec<String> = context_results.iter()
+            .map(|result| format_context_chunk(&self.config.context_template, result))
+            .collect();
+        let prompt = self.build_prompt(query, &context_texts, knowledge_base_empty);
+        
+        let ollama = self.ollama_manager.lock().await;
+        let installed = ollama.list_models().await?;
+        let deadline = Duration::from_secs(self.config.response_timeout_secs);
+        
+        let answers = futures::future::join_all(models.iter().map(|model| {
+            let ollama = &*ollama;
+            let prompt = &prompt;
+            let is_installed = installed.iter().any(|info| model_names_match(model, &info.name));
+            async move {
+                if !is_installed {
+                    return ModelAnswer {
+                        model: model.clone(),
+                        response: None,
+                        generation_ms: 0.0,
+                        note: Some(format!("{} isn't installed, so it was skipped", model)),
+                    };
+                }
+                
+                let stage = Instant::now();
+                let (response, note) = match timeout(deadline, ollama.generate_response_with_model(model, prompt)).await {
+                    Ok(Ok(response)) => (Some(response), None),
+                    Ok(Err(e)) => (None, Some(format!("Generation failed: {}", e))),
+                    Err(_) => (None, Some(format!("No answer within {} seconds", deadline.as_secs()))),
+                };
+                ModelAnswer {
+                    model: model.clone(),
+                    response,
+                    generation_ms: elapsed_ms(stage),
+                    note,
+                }
+            }
+        })).await;
+        
+        Ok(ModelComparison {
+            query: query.to_string(),
+            context_used: context_results.iter()
+                .map(|result| format!("{} (score: {:.2})", result.chunk.source_title, result.similarity_score))
+                .collect(),
+            retrieval_ms,
+            answers,
+        })
+    }
+    
+    /// Summarizes the stored chunks of the wiki page `source_url`. Pages too long for the
+    /// context window are summarized hierarchically: consecutive parts that fit are summarized on
+    /// their own, then their summaries are combined. Not added to the conversation history.
+    pub async fn summarize_source(&self, source_url: &str) -> AppResult<SourceSummary> {
+        let chunks = {
+            let embedding_service = self.embedding_service.lock().await;
+            embedding_service.chunks_for_source(source_url).await?
+        };
+        let Some(first) = chunks.first() else {
+            return Err(AppError::WikiError(format!(
+                "Nothing is stored for {}; update the wiki or re-scrape the page first", source_url
+            )));
+        };
+        let source_title = first.source_title.clone();
+        let budget = self.context_token_budget().await;
+        
+        let ollama = self.ollama_manager.lock().await;
+        let mut parts: Vec<String> = chunks.iter().map(|chunk| chunk.content.clone()).collect();
+        let mut passes = 1;
+        while parts.iter().map(|part| estimate_tokens(part)).sum::<usize>() > budget {
+            let groups = group_within_budget(&parts, budget);
+            if groups.len() >= parts.len() {
+                // Each part already fills the budget on its own, so another round won't shrink it
+                warn!("Summary of {} still exceeds {} tokens after {} passes, leaving out the end", source_url, budget, passes);
+                parts = truncate_to_budget(parts, budget);
+                break;
+            }
+            
+            info!("Summarizing {} in {} parts (pass {})", source_url, groups.len(), passes);
+            let mut summaries = Vec::with_capacity(groups.len());
+            for (i, group) in groups.iter().enumerate() {
+                let prompt = format!(
+                    "You are summarizing the Vintage Story wiki page \"{}\". Below is part {} of {}.\n\n\
+                     Summarize this part in a short paragraph, keeping specific item names, numbers and recipes.\n\n{}",
+                    source_title, i + 1, groups.len(), group
+                );
+                summaries.push(self.generate_summary(&ollama, &prompt).await?);
+            }
+            parts = summaries;
+            passes += 1;
+        }
+        
+        let prompt = format!(
+            "You are summarizing the Vintage Story wiki page \"{}\" for a player.\n\n\
+             Write a one or two sentence overview, then list the key points as bullet points \
+             (what it is, how to get or make it, and what it's used for, where the text covers them). \
+             Use only the text below.\n\n{}",
+            source_title, parts.join("\n\n")
+        );
+        let summary = self.generate_summary(&ollama, &prompt).await?;
+        
+        info!("Summarized {} ({} chunks, {} passes)", source_url, chunks.len(), passes);
+        Ok(SourceSummary {
+            source_url: source_url.to_string(),
+            source_title,
+            summary,
+            chunks_summarized: chunks.len(),
+            passes,
+        })
+    }
+    
+    async fn generate_summary(&self, ollama: &OllamaManager, prompt: &str) -> AppResult<String> {
+        let deadline = Duration::from_secs(self.config.response_timeout_secs);
+        match timeout(deadline, ollama.generate_response(prompt)).await {
+            Ok(result) => result.map(|summary| summary.trim().to_string()),
+            Err(_) => Err(AppError::Timeout {
+                message: format!("The model did not finish the summary within {} seconds", deadline.as_secs()),
+                partial_response: None,
+            }),
+        }
+    }
+    
+    /// Tokens of wiki context that fit in the model's context window alongside the rest of the
+    /// prompt and the reply, using the context length last fetched from Ollama
+    pub async fn context_token_budget(&self) -> usize {
+        let context_length = self.ollama_manager.lock().await.context_length();
+        context_token_budget(context_length, self.config.max_tokens)
+    }
+    
+    /// What to embed for retrieval: the message itself, or its cleaned-up core when
+    /// `clean_retrieval_query` is on. Generation always sees the full message.
+    fn search_query(&self, message: &str) -> String {
+        if self.config.clean_retrieval_query {
+            let query = retrieval_query(message);
+            info!("Searching the wiki for: {}", query);
+            query
+        } else {
+            message.to_string()
+        }
+    }
+    
+    async fn retrieve_context(&self, message: &str, source_filter: &[String]) -> AppResult<Vec<SimilarityResult>> {
+        let query = self.search_query(message);
+        let embedding_service = self.embedding_service.lock().await;
+        let (query_embedding, used_mock_embedding) = match embedding_service.create_embedding_with_source(&query, EmbeddingKind::Query).await {
+            Ok(embedding) => embedding,
             Err(e) => {
+                warn!("Failed to embed the question, answering without wiki context: {}", e);
+                return Ok(Vec::new());
+            }
+        };
+        match embedding_service.search_by_embedding_filtered(query_embedding, 5, source_filter).await {
+            Ok(results) if self.config.trim_overlapping_context => Ok(trim_overlapping_context(results)),
+            Ok(results) => Ok(results),
+            // The model changed since the wiki was embedded, so every score would be meaningless;
+            // tell the user to re-embed. The mock fallback never fits the store: Ollama is just down.
+            Err(e @ AppError::DimensionMismatch { .. }) if !used_mock_embedding => Err(e),
+            Err(e) => {
+                warn!("Context search failed, answering without wiki context: {}", e);
+                Ok(Vec::new())
+            }
+        }
+    }
+    
+    /// Records and returns an assistant reply that didn't come from the model
+    fn respond_without_model(&mut self, content: String) -> ChatResponse {
+        let assistant_message = ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            content,
+            role: "assistant".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        self.record_message(assistant_message.clone());
+        
+        ChatResponse {
+            message: assistant_message,
+            context_used: Vec::new(),
+            truncated: false,
+            confidence: Some(answer_confidence(&[])),
+        }
+    }
+    
+    async fn generate_llm_response(&self, query: &str, context: &[String], knowledge_base_empty: bool) -> AppResult<String> {
+        // Build prompt with context
+        let prompt = self.build_prompt(query, context, knowledge_base_empty);
+        self.generate_with_deadline(&prompt).await.map_err(|e| note_context_chunks(e, context.len()))
+    }
+    
+    async fn generate_with_deadline(&self, prompt: &str) -> AppResult<String> {
+        self.generate_with_deadline_streaming(prompt, &|_| {}).await.map(|answer| answer.text)
+    }
+    
+    /// `generate_with_deadline` passing the answer to `on_token` as it's generated. Fallback
+    /// replies aren't passed on, since they don't come from the model.
+    async fn generate_with_deadline_streaming(&self, prompt: &str, on_token: &(dyn Fn(&str) + Send + Sync)) -> AppResult<GeneratedAnswer> {
+        // Call Ollama to generate response
+        let ollama = self.ollama_manager.lock().await;
+        let _running = self.generation_cancelled.start();
+        
+        // Tokens received so far, so a stalled stream can still report what it produced
+        let partial_response = Arc::new(std::sync::Mutex::new(String::new()));
+        let deadline = Duration::from_secs(self.config.response_timeout_secs);
+        
+        let generation = async {
+            let primary = ollama.get_model_name().to_string();
+            match self.generate_with_model(&ollama, &primary, prompt, &partial_response, on_token).await {
+                Err(e) if is_model_unavailable(&e) => match self.available_fallback_model(&ollama, &primary).await {
+                    Some(fallback) => {
+                        warn!("{} is unavailable ({}), answering with fallback model {}", primary, e, fallback);
+                        self.generate_with_model(&ollama, &fallback, prompt, &partial_response, on_token).await
+                            .map(|generation| GeneratedAnswer {
+                                text: format!("{}\n\n{}", generation.text, fallback_model_note(&primary, &fallback)),
+                                model: Some(fallback),
+                                truncated: generation.truncated(),
+                            })
+                    }
+                    None => Err(e),
+                },
+                result => result.map(|generation| GeneratedAnswer {
+                    truncated: generation.truncated(),
+                    text: generation.text,
+                    model: Some(primary),
+                }),
+            }
+        };
+        
+        let result = tokio::select! {
+            result = timeout(deadline, generation) => result,
+            _ = self.generation_cancelled.cancelled() => {
+                info!("Response generation cancelled");
+                return Err(AppError::Cancelled("response generation".to_string()));
+            }
+        };
+        
+        match result {
+            Ok(Ok(answer)) => Ok(answer),
+            // Keep the partial answer instead of replacing it with a canned fallback
+            Ok(Err(e @ AppError::IncompleteResponse { .. })) => Err(e),
+            // A canned reply would hide why the model said nothing; the diagnostic explains it
+            Ok(Err(e @ AppError::EmptyResponse(_))) => Err(e),
+            Ok(Err(e)) => {
                 error!("Failed to generate LLM response: {}", e);
                 // Fall back to a simple response if LLM fails
-                Ok(self.generate_fallback_response(query))
+                Ok(GeneratedAnswer {
+                    text: self.generate_fallback_response(FallbackReason::from_error(&e)),
+                    model: None,
+                    truncated: false,
+                })
             }
+            Err(_) => {
+                let partial = partial_response.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                warn!("Response generation exceeded {}s deadline ({} chars received)", 
+                      self.config.response_timeout_secs, partial.len());
+                ollama.record_generation_timeout();
+                
+                Err(AppError::Timeout {
+                    message: format!("The model did not finish responding within {} seconds", 
+                                     self.config.response_timeout_secs),
+                    partial_response: if partial.is_empty() { None } else { Some(partial) },
+                })
+            }
+        }
+    }
+    
+    /// Adds a turn to the usage stats on a blocking thread, so the reply isn't held up by it
+    fn record_usage(&self, started: Instant, model: Option<String>, context_results: &[SimilarityResult]) {
+        if let Some(usage_stats) = self.usage_stats.clone() {
+            let mut sources: Vec<String> = context_results.iter()
+                .map(|result| result.chunk.source_title.clone())
+                .collect();
+            sources.sort();
+            sources.dedup();
+            let turn = ChatTurnUsage {
+                response_ms: started.elapsed().as_millis() as u64,
+                model,
+                sources,
+            };
+            
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = usage_stats.record_turn(&turn) {
+                    warn!("Failed to record usage stats: {}", e);
+                }
+            });
+        }
+    }
+    
+    async fn generate_with_model(
+        &self,
+        ollama: &OllamaManager,
+        model_name: &str,
+        prompt: &str,
+        partial_response: &Arc<std::sync::Mutex<String>>,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> AppResult<Generation> {
+        if self.config.stream_responses {
+            let partial_response = partial_response.clone();
+            ollama.generate_stream_with_model(model_name, prompt, move |token| {
+                partial_response.lock().unwrap_or_else(|e| e.into_inner()).push_str(token);
+                on_token(token);
+            }).await
+        } else {
+            let response = ollama.generate_with_model(model_name, prompt).await;
+            if let Ok(generation) = &response {
+                on_token(&generation.text);
+            }
+            response
         }
     }
     
-    fn build_prompt(&self, query: &str, context: &[String]) -> String {
+    /// The configured fallback model, if it's set, differs from `primary_model` and is installed
+    async fn available_fallback_model(&self, ollama: &OllamaManager, primary_model: &str) -> Option<String> {
+        let fallback = self.config.fallback_model.as_deref()?.trim();
+        if fallback.is_empty() || model_names_match(fallback, primary_model) {
+            return None;
+        }
+        
+        match ollama.is_model_installed(fallback).await {
+            Ok(true) => Some(fallback.to_string()),
+            Ok(false) => {
+                warn!("Fallback model {} isn't installed", fallback);
+                None
+            }
+            Err(e) => {
+                warn!("Couldn't check whether fallback model {} is installed: {}", fallback, e);
+                None
+            }
+        }
+    }
+    
+    fn build_prompt(&self, query: &str, context: &[String], knowledge_base_empty: bool) -> String {
+        self.build_prompt_with_note(query, context, knowledge_base_empty.then_some(NO_WIKI_DATA_INSTRUCTION))
+    }
+    
+    /// Builds the chat prompt, with `note` placed before everything else when there's no wiki
+    /// context to answer from, so the model doesn't present guesses as wiki facts
+    fn build_prompt_with_note(&self, query: &str, context: &[String], note: Option<&str>) -> String {
         let mut prompt = String::from("You are a helpful assistant specializing in the game Vintage Story. You provide accurate, detailed information based on the game's wiki and mechanics.\n\n");
         
-        // Add context if available
-        if !context.is_empty() {
-            prompt.push_str("Here is relevant information from the Vintage Story wiki:\n\n");
-            for (i, ctx) in context.iter().enumerate() {
-                prompt.push_str(&format!("Context {}:\n{}\n\n", i + 1, ctx));
+        if let Some(note) = note {
+            prompt.push_str(note);
+            prompt.push_str("\n\n");
+        }
+        
+        if self.config.authoritative_context_framing {
+            // History first and wiki context last, so the authoritative material sits
+            // right next to the question and the model weighs it over earlier turns
+            self.push_history(&mut prompt, HISTORY_REFERENCE_HEADER);
+            
+            if !context.is_empty() {
+                prompt.push_str(AUTHORITATIVE_CONTEXT_HEADER);
+                prompt.push_str("\n\n");
+                self.push_context(&mut prompt, context);
+            }
+        } else {
+            // Add context if available
+            if !context.is_empty() {
+                prompt.push_str("Here is relevant information from the Vintage Story wiki:\n\n");
+                self.push_context(&mut prompt, context);
+                prompt.push_str("Based on the above context, ");
             }
-            prompt.push_str("Based on the above context, ");
+            
+            // Add conversation history for context
+            self.push_history(&mut prompt, "Previous conversation:");
         }
         
-        // Add conversation history for context
-        if self.conversation_history.len() > 1 {
+        // Add the current query
+        prompt.push_str(&format!("User question: {}\n\n", query));
+        prompt.push_str("Assistant: Please provide a helpful and accurate response. If you have relevant context from the wiki, use it to give specific information. If you don't have specific information, provide general guidance about Vintage Story.");
+        
+        prompt
+    }
+    
+    /// Prompt for picking `partial` up where it stopped: the conversation before it, then the
+    /// reply so far, written so the model's output carries straight on from it
+    fn build_continuation_prompt(&self, partial: &str) -> String {
+        let mut prompt = String::from("You are a helpful assistant specializing in the game Vintage Story.\n\n");
+        
+        // The history ends with the partial reply itself, which is quoted on its own below
+        let earlier = &self.conversation_history[..self.conversation_history.len().saturating_sub(1)];
+        let start = earlier.len().saturating_sub(self.config.prompt_history_messages);
+        if !earlier[start..].is_empty() {
             prompt.push_str("Previous conversation:\n");
-            // Include last 2-3 exchanges for context
-            let start = self.conversation_history.len().saturating_sub(6);
-            for msg in &self.conversation_history[start..] {
+            for msg in &earlier[start..] {
                 prompt.push_str(&format!("{}: {}\n", msg.role, msg.content));
             }
-            prompt.push_str("\n");
+            prompt.push('\n');
+        }
+        
+        prompt.push_str("Your last reply was cut off before it was finished. Continue it from exactly where it stops. ");
+        prompt.push_str("Don't repeat any of it, don't start over and don't comment on the interruption.\n\n");
+        prompt.push_str("Reply so far:\n");
+        prompt.push_str(partial);
+        
+        prompt
+    }
+    
+    fn build_sourced_prompt(&self, query: &str, sources: &[SimilarityResult]) -> String {
+        let mut prompt = String::from("You are a helpful assistant specializing in the game Vintage Story. Answer the question using ONLY the numbered wiki sources below.\n\n");
+        
+        prompt.push_str("Rules:\n");
+        prompt.push_str("- Every claim must be followed by a citation like [source 1] naming the source it comes from.\n");
+        prompt.push_str("- Do not use any knowledge that isn't in the sources, and don't cite sources that aren't listed.\n");
+        prompt.push_str(&format!(
+            "- If the sources don't contain enough information to answer, reply with exactly {} and nothing else.\n\n",
+            INSUFFICIENT_SOURCES_MARKER
+        ));
+        
+        for (i, source) in sources.iter().enumerate() {
+            prompt.push_str(&format!("[source {}] {}\n{}\n\n", i + 1, source.chunk.source_title, source.chunk.content));
         }
         
-        // Add the current query
         prompt.push_str(&format!("User question: {}\n\n", query));
-        prompt.push_str("Assistant: Please provide a helpful and accurate response. If you have relevant context from the wiki, use it to give specific information. If you don't have specific information, provide general guidance about Vintage Story.");
+        prompt.push_str("Assistant:");
         
         prompt
     }
     
-    fn generate_fallback_response(&self, query: &str) -> String {
-        let fallback_responses = vec![
-            "I'm experiencing some technical difficulties connecting to the AI service. Could you please try again in a moment?",
-            "I apologize, but I'm having trouble processing your request right now. Please try again shortly.",
-            "The AI service is temporarily unavailable. In the meantime, you might want to check the Vintage Story wiki directly.",
-        ];
+    fn push_context(&self, prompt: &mut String, context: &[String]) {
+        for (i, ctx) in context.iter().enumerate() {
+            prompt.push_str(&format!("Context {}:\n{}\n\n", i + 1, ctx));
+        }
+    }
+    
+    fn push_history(&self, prompt: &mut String, header: &str) {
+        if self.conversation_history.len() <= 1 || self.config.prompt_history_messages == 0 {
+            return;
+        }
         
-        let index = query.len() % fallback_responses.len();
-        fallback_responses[index].to_string()
+        prompt.push_str(header);
+        prompt.push('\n');
+        let start = self.conversation_history.len().saturating_sub(self.config.prompt_history_messages);
+        for msg in &self.conversation_history[start..] {
+            prompt.push_str(&format!("{}: {}\n", msg.role, msg.content));
+        }
+        prompt.push('\n');
+    }
+    
+    fn generate_fallback_response(&self, reason: FallbackReason) -> String {
+        let fallbacks = &self.config.fallback_responses;
+        let (configured, defaults) = match reason {
+            FallbackReason::Connection => (&fallbacks.connection, DEFAULT_CONNECTION_FALLBACKS),
+            FallbackReason::ModelNotFound => (&fallbacks.model_not_found, DEFAULT_MODEL_NOT_FOUND_FALLBACKS),
+            FallbackReason::Other => (&fallbacks.other, DEFAULT_OTHER_FALLBACKS),
+        };
+        
+        let index = self.fallback_counter.fetch_add(1, Ordering::Relaxed);
+        if configured.is_empty() {
+            defaults[index % defaults.len()].to_string()
+        } else {
+            configured[index % configured.len()].clone()
+        }
+    }
+    
+    /// Appends to the history, dropping the oldest messages beyond `max_history_messages`
+    fn record_message(&mut self, message: ChatMessage) {
+        self.conversation_history.push(message);
+        
+        let max = self.config.max_history_messages;
+        if max > 0 && self.conversation_history.len() > max {
+            let excess = self.conversation_history.len() - max;
+            self.conversation_history.drain(..excess);
+        }
     }
     
     pub fn get_conversation_history(&self) -> &[ChatMessage] {
@@ -159,3 +978,245 @@ impl ChatService {
         self.conversation_history.clear();
     }
 }
+
+/// Appended to answers from the fallback model so the user knows which model answered
+pub(crate) fn fallback_model_note(primary_model: &str, fallback_model: &str) -> String {
+    format!("Note: {} isn't available, so this answer comes from the fallback model {}.", primary_model, fallback_model)
+}
+
+/// Records on an empty-response diagnostic how many wiki chunks were in the prompt
+fn note_context_chunks(error: AppError, context_chunks: usize) -> AppError {
+    match error {
+        AppError::EmptyResponse(mut diagnostic) => {
+            diagnostic.context_chunks = Some(context_chunks);
+            AppError::EmptyResponse(diagnostic)
+        }
+        error => error,
+    }
+}
+
+/// Context tokens available in a `context_length`-token window (`DEFAULT_CONTEXT_LENGTH` when
+/// unknown) after reserving room for a `max_response_tokens` reply and the rest of the prompt
+pub(crate) fn context_token_budget(context_length: Option<u32>, max_response_tokens: u32) -> usize {
+    let context_length = context_length.unwrap_or(DEFAULT_CONTEXT_LENGTH) as usize;
+    context_length
+        .saturating_sub(max_response_tokens as usize)
+        .saturating_sub(PROMPT_OVERHEAD_TOKENS)
+        .max(MIN_CONTEXT_TOKEN_BUDGET)
+}
+
+/// Keeps the best-ranked results whose content fits in `budget_tokens`, dropping the rest
+pub(crate) fn fit_context_to_budget(results: Vec<SimilarityResult>, budget_tokens: usize) -> Vec<SimilarityResult> {
+    let mut used = 0;
+    let total = results.len();
+    let kept: Vec<SimilarityResult> = results.into_iter()
+        .take_while(|result| {
+            used += result.chunk.token_count();
+            used <= budget_tokens
+        })
+        .collect();
+    
+    if kept.len() < total {
+        info!("Using {} of {} context chunks to stay within {} tokens", kept.len(), total, budget_tokens);
+    }
+    kept
+}
+
+/// Removes the words a chunk repeats from the chunk just before it on the same page, when both
+/// were retrieved, so the chunk overlap isn't sent to the model twice. Ranking order is kept, and
+/// a chunk is only dropped when every word of it is already in the previous one.
+pub(crate) fn trim_overlapping_context(results: Vec<SimilarityResult>) -> Vec<SimilarityResult> {
+    let position = |result: &SimilarityResult| {
+        result.chunk.metadata.get("chunk_index").and_then(|index| index.parse::<usize>().ok())
+    };
+    let previous_contents: Vec<Option<String>> = results.iter()
+        .map(|result| {
+            let previous_index = position(result)?.checked_sub(1)?;
+            results.iter()
+                .find(|other| other.chunk.source_url == result.chunk.source_url && position(other) == Some(previous_index))
+                .map(|previous| previous.chunk.content.clone())
+        })
+        .collect();
+    
+    results.into_iter()
+        .zip(previous_contents)
+        .filter_map(|(mut result, previous)| {
+            let Some(previous) = previous else {
+                return Some(result);
+            };
+            let shared = shared_word_count(&previous, &result.chunk.content);
+            if shared < MIN_OVERLAP_WORDS {
+                return Some(result);
+            }
+            let rest = skip_words(&result.chunk.content, shared);
+            if rest.is_empty() {
+                return None;
+            }
+            result.chunk.content = rest.to_string();
+            result.chunk.metadata.insert(TOKEN_COUNT_METADATA_KEY.to_string(), estimate_tokens(&result.chunk.content).to_string());
+            Some(result)
+        })
+        .collect()
+}
+
+/// How many words at the end of `earlier` are repeated at the start of `later`
+fn shared_word_count(earlier: &str, later: &str) -> usize {
+    let earlier: Vec<&str> = earlier.split_whitespace().collect();
+    let later: Vec<&str> = later.split_whitespace().collect();
+    (1..=earlier.len().min(later.len()))
+        .rev()
+        .find(|&count| earlier[earlier.len() - count..] == later[..count])
+        .unwrap_or(0)
+}
+
+/// `text` without its first `count` words, keeping the rest's original spacing
+fn skip_words(text: &str, count: usize) -> &str {
+    let mut rest = text.trim_start();
+    for _ in 0..count {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[word_end..].trim_start();
+    }
+    rest
+}
+
+/// Joins consecutive `parts` into groups of at most `budget_tokens`; a part larger than the
+/// budget gets a group of its own
+pub(crate) fn group_within_budget(parts: &[String], budget_tokens: usize) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for part in parts {
+        if !current.is_empty() && estimate_tokens(&current) + estimate_tokens(part) > budget_tokens {
+            groups.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(part);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+    groups
+}
+
+/// The leading `parts` that fit in `budget_tokens`, always keeping at least the first
+fn truncate_to_budget(parts: Vec<String>, budget_tokens: usize) -> Vec<String> {
+    let mut used = 0;
+    parts.into_iter()
+        .enumerate()
+        .take_while(|(i, part)| {
+            used += estimate_tokens(part);
+            *i == 0 || used <= budget_tokens
+        })
+        .map(|(_, part)| part)
+        .collect()
+}
+
+fn elapsed_ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Renders a retrieved chunk with the configured context template so the model can attribute it.
+/// Templates without `{content}` still get the chunk text appended.
+pub(crate) fn format_context_chunk(template: &str, result: &SimilarityResult) -> String {
+    let chunk = &result.chunk;
+    let section = chunk.metadata.get("section")
+        .map(|heading| heading.trim())
+        .filter(|heading| !heading.is_empty())
+        .map(|heading| format!(" - {}", heading))
+        .unwrap_or_default();
+    
+    let rendered = template
+        .replace("{title}", &chunk.source_title)
+        .replace("{url}", &chunk.source_url)
+        .replace("{section}", &section);
+    
+    if rendered.contains("{content}") {
+        rendered.replace("{content}", &chunk.content)
+    } else {
+        format!("{}\n{}", rendered, chunk.content)
+    }
+}
+
+/// The part of a chat message worth searching the wiki for: greetings, requests like "can you
+/// please tell me" and sign-offs like "thanks" are removed. Falls back to the whole message if
+/// nothing would be left.
+pub(crate) fn retrieval_query(message: &str) -> String {
+    let greeting = Regex::new(r"(?i)^\s*(hi|hey|hello|heya|hiya|yo|greetings|good (morning|afternoon|evening))( there)?\b[\s,.!]*")
+        .expect("valid greeting pattern");
+    let request = Regex::new(
+        r"(?i)^\s*(please|pls|can you|could you|would you|will you|do you know|tell me|explain( to me)?|help me( understand)?|i('d| would) like to know|i want to know|i was wondering|i wonder)\b[\s,]*"
+    ).expect("valid request pattern");
+    let sign_off = Regex::new(r"(?i)[\s,.!?]*\b(thanks|thank you|thx|ty|cheers|please|pls)( (so|very) much| in advance)?[\s,.!?]*$")
+        .expect("valid sign-off pattern");
+    
+    let mut query = greeting.replace(message, "").to_string();
+    // Requests stack up: "can you please tell me ..."
+    while let Some(found) = request.find(&query) {
+        query = query[found.end()..].to_string();
+    }
+    while let Some(found) = sign_off.find(&query) {
+        query.truncate(found.start());
+    }
+    
+    let query = query.trim();
+    if query.is_empty() {
+        message.trim().to_string()
+    } else {
+        query.to_string()
+    }
+}
+
+/// Confidence in an answer given the context it was based on: mostly the best match's similarity,
+/// the rest how many chunks back it up. No context at all means low confidence.
+pub(crate) fn answer_confidence(context: &[SimilarityResult]) -> AnswerConfidence {
+    let best_score = context.iter()
+        .map(|result| result.similarity_score)
+        .fold(0.0f32, f32::max)
+        .clamp(0.0, 1.0);
+    let supporting = context.iter()
+        .filter(|result| result.similarity_score >= PARTIAL_COVERAGE_SIMILARITY)
+        .count();
+    let coverage = supporting.min(CONFIDENT_SUPPORTING_CHUNKS) as f32 / CONFIDENT_SUPPORTING_CHUNKS as f32;
+    
+    let score = 0.7 * best_score + 0.3 * coverage;
+    let level = if score >= GOOD_COVERAGE_SIMILARITY {
+        ConfidenceLevel::High
+    } else if score >= PARTIAL_COVERAGE_SIMILARITY {
+        ConfidenceLevel::Medium
+    } else {
+        ConfidenceLevel::Low
+    };
+    AnswerConfidence { score, level }
+}
+
+/// Drops `[source N]` markers that don't refer to one of the `source_count` sources,
+/// and flags answers that end up citing nothing.
+pub(crate) fn validate_citations(answer: &str, source_count: usize) -> String {
+    let citation = Regex::new(r"(?i)\s?\[source\s*(\d+)\]").expect("valid citation pattern");
+    let mut valid_citations = 0;
+    
+    let cleaned = citation.replace_all(answer, |caps: &regex::Captures| {
+        match caps[1].parse::<usize>() {
+            Ok(n) if (1..=source_count).contains(&n) => {
+                valid_citations += 1;
+                caps[0].to_string()
+            }
+            _ => {
+                warn!("Removing citation to nonexistent source: {}", caps[0].trim());
+                String::new()
+            }
+        }
+    });
+    
+    let cleaned = cleaned.trim().to_string();
+    if valid_citations == 0 {
+        format!("{}\n\n{}", cleaned, UNCITED_ANSWER_NOTE)
+    } else {
+        cleaned
+    }
+}
+
+#[cfg(test)]
+#[path = "chat_service_test.rs"]
+mod tests;
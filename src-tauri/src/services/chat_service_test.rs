@@ -0,0 +1,953 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{
+        ChatConfig, EmptyKnowledgeBaseBehavior, EmptySourceFilterBehavior, FallbackResponses, OllamaConfig, DEFAULT_CONNECTION_FALLBACKS,
+        DEFAULT_CONTEXT_TEMPLATE, DEFAULT_MODEL_NOT_FOUND_FALLBACKS, DEFAULT_OTHER_FALLBACKS,
+    };
+    use crate::errors::AppError;
+    use crate::services::chat_service::{
+        answer_confidence, context_token_budget, fallback_model_note, fit_context_to_budget, format_context_chunk, retrieval_query, trim_overlapping_context, validate_citations, ChatMessage, ChatPipelineEvent, ChatService, ConfidenceLevel, FallbackReason, AUTHORITATIVE_CONTEXT_HEADER,
+        EMPTY_KNOWLEDGE_BASE_GUIDANCE, HISTORY_REFERENCE_HEADER, INSUFFICIENT_SOURCES_RESPONSE,
+        NO_FILTERED_CONTEXT_RESPONSE, NO_WIKI_DATA_INSTRUCTION,
+    };
+    use crate::services::embedding_service::{estimate_tokens, EmbeddingService, SimilarityResult, TextChunk};
+    use crate::services::ollama_manager::{BreakerState, OllamaManager};
+    use crate::services::vector_database::{VectorDatabase, VectorDocument};
+    use mockito::Server;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    /// Starts a fake Ollama server that streams the given NDJSON lines and then
+    /// stalls forever without ever sending `done: true`.
+    async fn start_stalling_server(lines: Vec<&'static str>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let lines = lines.clone();
+                tokio::spawn(async move {
+                    let mut request = [0u8; 8192];
+                    let _ = socket.read(&mut request).await;
+                    if !request.starts_with(b"POST /api/generate") {
+                        let _ = socket.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+                        return;
+                    }
+                    
+                    let _ = socket.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\n\r\n"
+                    ).await;
+                    for line in lines {
+                        let data = format!("{}\n", line);
+                        let _ = socket.write_all(format!("{:x}\r\n{}\r\n", data.len(), data).as_bytes()).await;
+                    }
+                    let _ = socket.flush().await;
+                    
+                    // Hold the connection open without finishing the stream
+                    tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+                });
+            }
+        });
+        
+        addr
+    }
+
+    async fn create_test_service(addr: SocketAddr, config: ChatConfig) -> ChatService {
+        let mut chat_service = ChatService::with_config(config).await;
+        let ollama_config = OllamaConfig {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            ..OllamaConfig::default()
+        };
+        chat_service.set_ollama_manager(Arc::new(Mutex::new(OllamaManager::with_config(ollama_config).await)));
+        chat_service
+    }
+
+    async fn create_empty_embedding_service() -> Arc<Mutex<EmbeddingService>> {
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        Arc::new(Mutex::new(embedding_service))
+    }
+
+    #[tokio::test]
+    async fn test_response_deadline_fires_when_generation_stalls() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            response_timeout_secs: 1,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        
+        let started = std::time::Instant::now();
+        let result = chat_service.process_message("How do I make a pickaxe?").await;
+        
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        match result {
+            Err(AppError::Timeout { partial_response, .. }) => assert!(partial_response.is_none()),
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_includes_partial_streamed_text() {
+        let addr = start_stalling_server(vec![
+            r#"{"response":"Knap a ","done":false}"#,
+            r#"{"response":"flint stone","done":false}"#,
+        ]).await;
+        let config = ChatConfig {
+            response_timeout_secs: 1,
+            stream_responses: true,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        
+        let err = chat_service.process_message("How do I make a knife?").await.unwrap_err();
+        
+        assert_eq!(err.code(), "TIMEOUT");
+        match err {
+            AppError::Timeout { partial_response, .. } => {
+                assert_eq!(partial_response.as_deref(), Some("Knap a flint stone"));
+            }
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_stalled_generation() {
+        let addr = start_stalling_server(vec![r#"{"response":"Knap a ","done":false}"#]).await;
+        let config = ChatConfig {
+            response_timeout_secs: 600,
+            stream_responses: true,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        let cancel_flag = chat_service.generation_cancel_handle();
+        
+        let cancel = async {
+            while !cancel_flag.is_running() {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            assert!(cancel_flag.cancel(), "generation should be reported as running");
+        };
+        
+        let (result, _) = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            async { tokio::join!(chat_service.process_message("How do I make a knife?"), cancel) },
+        ).await.expect("cancellation should end the stalled generation");
+        
+        assert_eq!(result.unwrap_err().code(), "CANCELLED");
+        assert!(!cancel_flag.is_running());
+        assert!(!cancel_flag.cancel(), "nothing is left running to cancel");
+    }
+
+    #[tokio::test]
+    async fn test_empty_knowledge_base_requires_wiki_update() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            response_timeout_secs: 1,
+            empty_knowledge_base_behavior: EmptyKnowledgeBaseBehavior::RequireWikiUpdate,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        // The model is never called, so the stalling server can't cause a timeout
+        let response = chat_service.process_message("How do I tame a wolf?").await.unwrap();
+        
+        assert_eq!(response.message.content, EMPTY_KNOWLEDGE_BASE_GUIDANCE);
+        assert_eq!(response.message.role, "assistant");
+        assert!(response.context_used.is_empty());
+        assert_eq!(response.confidence.unwrap().level, ConfidenceLevel::Low);
+        assert_eq!(chat_service.get_conversation_history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_empty_knowledge_base_prompt_mentions_general_knowledge() {
+        let addr = start_stalling_server(vec![]).await;
+        let chat_service = create_test_service(addr, ChatConfig::default()).await;
+        
+        let prompt = chat_service.build_prompt("How do I tame a wolf?", &[], true);
+        assert!(prompt.contains(NO_WIKI_DATA_INSTRUCTION));
+        
+        let context = vec!["Source: Wolf\nWolves can't be tamed.".to_string()];
+        let prompt = chat_service.build_prompt("How do I tame a wolf?", &context, false);
+        assert!(!prompt.contains(NO_WIKI_DATA_INSTRUCTION));
+    }
+
+    fn history_message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            content: content.to_string(),
+            role: role.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_frames_wiki_context_as_authoritative() {
+        let addr = start_stalling_server(vec![]).await;
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        chat_service.conversation_history.push(history_message("user", "Copper melts at 500 degrees, right?"));
+        chat_service.conversation_history.push(history_message("assistant", "I'm not sure."));
+        chat_service.conversation_history.push(history_message("user", "What temperature does copper melt at?"));
+        
+        let context = vec!["Source: Copper\nCopper melts at 1084 degrees.".to_string()];
+        let prompt = chat_service.build_prompt("What temperature does copper melt at?", &context, false);
+        
+        assert!(prompt.contains(AUTHORITATIVE_CONTEXT_HEADER));
+        assert!(prompt.contains(HISTORY_REFERENCE_HEADER));
+        // Wiki context comes after the history so it's closest to the question
+        let history_pos = prompt.find("Copper melts at 500").unwrap();
+        let context_pos = prompt.find("Copper melts at 1084").unwrap();
+        assert!(history_pos < context_pos);
+        
+        // No authoritative header without context to attach it to
+        let prompt = chat_service.build_prompt("What temperature does copper melt at?", &[], false);
+        assert!(!prompt.contains(AUTHORITATIVE_CONTEXT_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_framing_can_be_disabled() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            authoritative_context_framing: false,
+            prompt_history_messages: 1,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        chat_service.conversation_history.push(history_message("user", "First question"));
+        chat_service.conversation_history.push(history_message("user", "Second question"));
+        
+        let context = vec!["Source: Copper\nCopper melts at 1084 degrees.".to_string()];
+        let prompt = chat_service.build_prompt("Second question", &context, false);
+        
+        assert!(!prompt.contains(AUTHORITATIVE_CONTEXT_HEADER));
+        assert!(!prompt.contains(HISTORY_REFERENCE_HEADER));
+        assert!(prompt.contains("Here is relevant information from the Vintage Story wiki"));
+        // Only the configured number of history messages is included
+        assert!(!prompt.contains("First question"));
+    }
+
+    #[tokio::test]
+    async fn test_sourced_answer_refuses_without_context() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            response_timeout_secs: 1,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        // No context means no model call, so the stalling server can't cause a timeout
+        let response = chat_service.process_message_with_sources("How do I tame a wolf?").await.unwrap();
+        
+        assert_eq!(response.message.content, INSUFFICIENT_SOURCES_RESPONSE);
+        assert!(response.context_used.is_empty());
+        assert_eq!(chat_service.get_conversation_history().len(), 2);
+    }
+
+    #[test]
+    fn test_validate_citations_keeps_real_sources() {
+        let answer = "Copper is smelted in a crucible [source 1]. Bronze needs tin [Source 2].";
+        assert_eq!(validate_citations(answer, 2), answer);
+    }
+
+    #[test]
+    fn test_validate_citations_drops_unknown_sources() {
+        let answer = "Copper is smelted in a crucible [source 1]. Iron needs a bloomery [source 7].";
+        assert_eq!(
+            validate_citations(answer, 2),
+            "Copper is smelted in a crucible [source 1]. Iron needs a bloomery."
+        );
+    }
+
+    #[test]
+    fn test_validate_citations_flags_uncited_answers() {
+        let cleaned = validate_citations("Iron needs a bloomery [source 0].", 3);
+        assert!(cleaned.starts_with("Iron needs a bloomery."));
+        assert!(cleaned.contains("doesn't cite any of the wiki sources"));
+    }
+
+    fn similarity_result(section: Option<&str>) -> SimilarityResult {
+        let mut metadata = std::collections::HashMap::new();
+        if let Some(section) = section {
+            metadata.insert("section".to_string(), section.to_string());
+        }
+        SimilarityResult {
+            chunk: TextChunk {
+                id: "bloomery_0".to_string(),
+                content: "A bloomery smelts iron ore.".to_string(),
+                source_url: "https://wiki.vintagestory.at/index.php?title=Bloomery".to_string(),
+                source_title: "Bloomery".to_string(),
+                embedding: None,
+                metadata,
+            },
+            similarity_score: 0.9,
+        }
+    }
+
+    #[test]
+    fn test_default_context_template() {
+        assert_eq!(
+            format_context_chunk(DEFAULT_CONTEXT_TEMPLATE, &similarity_result(None)),
+            "Source: Bloomery\nA bloomery smelts iron ore."
+        );
+        assert_eq!(
+            format_context_chunk(DEFAULT_CONTEXT_TEMPLATE, &similarity_result(Some("Usage"))),
+            "Source: Bloomery - Usage\nA bloomery smelts iron ore."
+        );
+    }
+
+    #[test]
+    fn test_custom_context_template_is_applied() {
+        let template = "[{title}{section}]({url})\n> {content}";
+        assert_eq!(
+            format_context_chunk(template, &similarity_result(Some("Usage"))),
+            "[Bloomery - Usage](https://wiki.vintagestory.at/index.php?title=Bloomery)\n> A bloomery smelts iron ore."
+        );
+        
+        // Content is never dropped, even if the template forgets it
+        assert_eq!(
+            format_context_chunk("From {title}:", &similarity_result(None)),
+            "From Bloomery:\nA bloomery smelts iron ore."
+        );
+    }
+
+    async fn fallback_for_status(status: usize, body: &str) -> String {
+        let mut server = Server::new_async().await;
+        let _mock = server.mock("POST", "/api/generate")
+            .with_status(status)
+            .with_body(body)
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let chat_service = create_test_service(addr, ChatConfig::default()).await;
+        
+        chat_service.generate_with_deadline("How do I make a pickaxe?").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fallback_when_ollama_unreachable() {
+        // Bind and drop a listener so the port is known to be closed
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let chat_service = create_test_service(addr, ChatConfig::default()).await;
+        
+        let response = chat_service.generate_with_deadline("How do I make a pickaxe?").await.unwrap();
+        assert!(DEFAULT_CONNECTION_FALLBACKS.contains(&response.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_when_model_not_found() {
+        let response = fallback_for_status(404, r#"{"error":"model \"llama3.2:3b\" not found, try pulling it first"}"#).await;
+        assert!(DEFAULT_MODEL_NOT_FOUND_FALLBACKS.contains(&response.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_for_other_errors() {
+        let response = fallback_for_status(500, r#"{"error":"out of memory"}"#).await;
+        assert!(DEFAULT_OTHER_FALLBACKS.contains(&response.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_responses_are_configurable_and_rotate() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            fallback_responses: FallbackResponses {
+                connection: vec!["Ollama is down.".to_string(), "Still down.".to_string()],
+                model_not_found: Vec::new(),
+                ..FallbackResponses::default()
+            },
+            ..ChatConfig::default()
+        };
+        let chat_service = create_test_service(addr, config).await;
+        
+        // Same reason, consecutive failures: the configured messages take turns
+        assert_eq!(chat_service.generate_fallback_response(FallbackReason::Connection), "Ollama is down.");
+        assert_eq!(chat_service.generate_fallback_response(FallbackReason::Connection), "Still down.");
+        assert_eq!(chat_service.generate_fallback_response(FallbackReason::Connection), "Ollama is down.");
+        
+        // An emptied list falls back to the built-in messages
+        assert_eq!(
+            chat_service.generate_fallback_response(FallbackReason::ModelNotFound),
+            DEFAULT_MODEL_NOT_FOUND_FALLBACKS[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_query_times_each_stage() {
+        let mut server = Server::new_async().await;
+        // Embedding fails, so the mock embedding fallback is timed instead
+        let _embed_mock = server.mock("POST", "/api/embeddings")
+            .with_status(500)
+            .create_async()
+            .await;
+        let _generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body("{\"response\":\"Use a bloomery.\",\"done\":false}\n{\"response\":\"\",\"done\":true}\n")
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        let embedding_service = create_empty_embedding_service().await;
+        embedding_service.lock().await.set_ollama_endpoint(&OllamaConfig {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            ..OllamaConfig::default()
+        });
+        chat_service.set_embedding_service(embedding_service);
+        
+        let timing = chat_service.benchmark_query("How do I smelt iron?").await.unwrap();
+        
+        assert!(timing.used_mock_embedding);
+        assert_eq!(timing.context_chunks, 0);
+        assert_eq!(timing.response_chars, "Use a bloomery.".len());
+        assert!(timing.embedding_ms > 0.0);
+        assert!(timing.total_ms >= timing.embedding_ms + timing.retrieval_ms + timing.generation_ms);
+        // Benchmarks don't become part of the conversation
+        assert!(chat_service.get_conversation_history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_is_capped_to_newest_messages() {
+        let addr = start_stalling_server(vec![]).await;
+        let config = ChatConfig {
+            empty_knowledge_base_behavior: EmptyKnowledgeBaseBehavior::RequireWikiUpdate,
+            max_history_messages: 5,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        for i in 0..20 {
+            chat_service.process_message(&format!("Question {}", i)).await.unwrap();
+            assert!(chat_service.get_conversation_history().len() <= 5);
+        }
+        
+        let history = chat_service.get_conversation_history();
+        assert_eq!(history.len(), 5);
+        // Oldest messages go first: the last question and reply are still there
+        assert_eq!(history[3].content, "Question 19");
+        assert_eq!(history[4].role, "assistant");
+    }
+
+    /// A chat service backed by a mock Ollama whose knowledge base holds chunks from the Anvil and
+    /// Bloomery pages, all embedded close to the query
+    async fn create_two_source_service(server: &mut mockito::ServerGuard, config: ChatConfig) -> ChatService {
+        use crate::services::vector_database::VectorDocument;
+        use std::collections::HashMap;
+        
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.5,0.5,0.1]}"#)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body("{\"response\":\"Build a bloomery from clay.\",\"done\":false}\n{\"response\":\"\",\"done\":true}\n")
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        
+        let db = VectorDatabase::new_fallback();
+        let documents = ["Anvil", "Bloomery"].iter()
+            .flat_map(|title| (0..3).map(move |i| VectorDocument {
+                id: format!("{}_{}", title.to_lowercase(), i),
+                content: format!("{} chunk {}", title, i),
+                source_url: format!("https://wiki.vintagestory.at/index.php?title={}", title),
+                source_title: title.to_string(),
+                embedding: vec![0.5, 0.5 - i as f32 * 0.01, 0.1],
+                metadata: HashMap::new(),
+            }))
+            .collect();
+        db.insert_documents(documents).await.unwrap();
+        
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_vector_database(Arc::new(Mutex::new(db)));
+        embedding_service.set_ollama_endpoint(&OllamaConfig {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            ..OllamaConfig::default()
+        });
+        
+        let mut chat_service = create_test_service(addr, ChatConfig { min_knowledge_base_chunks: 1, ..config }).await;
+        chat_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        chat_service
+    }
+
+    #[tokio::test]
+    async fn test_source_filter_limits_context_to_selected_pages() {
+        let mut server = Server::new_async().await;
+        let mut chat_service = create_two_source_service(&mut server, ChatConfig::default()).await;
+        
+        let filter = vec!["https://wiki.vintagestory.at/index.php?title=Bloomery".to_string()];
+        let response = chat_service.process_message_filtered("How do I smelt iron?", &filter).await.unwrap();
+        
+        assert_eq!(response.context_used.len(), 3);
+        assert!(response.context_used.iter().all(|source| source.starts_with("Bloomery ")));
+        
+        // Titles work as well as URLs
+        let response = chat_service.process_message_filtered("How do I smelt iron?", &["anvil".to_string()]).await.unwrap();
+        assert!(response.context_used.iter().all(|source| source.starts_with("Anvil ")));
+    }
+
+    #[tokio::test]
+    async fn test_source_filter_without_matches_can_refuse() {
+        let mut server = Server::new_async().await;
+        let config = ChatConfig {
+            empty_source_filter_behavior: EmptySourceFilterBehavior::Refuse,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_two_source_service(&mut server, config).await;
+        
+        let response = chat_service.process_message_filtered("How do I smelt iron?", &["Quern".to_string()]).await.unwrap();
+        
+        assert_eq!(response.message.content, NO_FILTERED_CONTEXT_RESPONSE);
+        assert!(response.context_used.is_empty());
+    }
+
+    #[test]
+    fn test_known_context_length_adjusts_context_budget() {
+        let small_budget = context_token_budget(Some(2048), 1024);
+        let default_budget = context_token_budget(None, 1024);
+        let large_budget = context_token_budget(Some(8192), 1024);
+        assert_eq!(large_budget, 8192 - 1024 - 768);
+        assert!(small_budget < default_budget && default_budget < large_budget);
+        
+        // A reply allowance bigger than the window still leaves room for some context
+        assert_eq!(context_token_budget(Some(1024), 4096), 256);
+        
+        let chunk = |id: &str| SimilarityResult {
+            chunk: TextChunk {
+                id: id.to_string(),
+                content: "x".repeat(1000),
+                source_url: String::new(),
+                source_title: id.to_string(),
+                embedding: None,
+                metadata: Default::default(),
+            },
+            similarity_score: 0.9,
+        };
+        let results = || (0..5).map(|i| chunk(&format!("chunk{}", i))).collect::<Vec<_>>();
+        
+        // 250 tokens per chunk: the small window fits only the best-ranked chunk, the large one all five
+        let kept = fit_context_to_budget(results(), small_budget);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].chunk.id, "chunk0");
+        assert_eq!(fit_context_to_budget(results(), large_budget).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_events_are_emitted_in_order() {
+        let mut server = Server::new_async().await;
+        let config = ChatConfig {
+            stream_responses: true,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_two_source_service(&mut server, config).await;
+        
+        let events = std::sync::Mutex::new(Vec::new());
+        let response = chat_service.process_message_with_events("How do I smelt iron?", &[], |event| {
+            events.lock().unwrap().push(event);
+        }).await.unwrap();
+        
+        let events = events.into_inner().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], ChatPipelineEvent::RetrievalStarted);
+        assert_eq!(events[1], ChatPipelineEvent::RetrievalComplete { sources: response.context_used.clone() });
+        assert!(!response.context_used.is_empty());
+        assert_eq!(events[2], ChatPipelineEvent::GenerationStarted);
+        assert_eq!(events[3], ChatPipelineEvent::Token { text: "Build a bloomery from clay.".to_string() });
+        assert_eq!(events[3].event_name(), "chat-token");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_model_answers_when_primary_is_missing() {
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"phi3:mini"}"#.to_string()))
+            .with_status(404)
+            .with_body(r#"{"error":"model \"phi3:mini\" not found, try pulling it first"}"#)
+            .create_async()
+            .await;
+        let fallback_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"llama3.2:1b"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"response":"Knap flint into a pickaxe head.","done":true}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(r#"{"models":[{"name":"llama3.2:1b","size":1300000000,"digest":"def456","details":{"parameter_size":"1B","quantization_level":"Q4_0","family":"llama"}}]}"#)
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let config = ChatConfig {
+            stream_responses: false,
+            fallback_model: Some("llama3.2:1b".to_string()),
+            ..ChatConfig::default()
+        };
+        let chat_service = create_test_service(addr, config).await;
+        
+        let response = chat_service.generate_with_deadline("How do I make a pickaxe?").await.unwrap();
+        
+        fallback_mock.assert_async().await;
+        assert!(response.starts_with("Knap flint into a pickaxe head."));
+        assert!(response.ends_with(&fallback_model_note("phi3:mini", "llama3.2:1b")));
+    }
+
+    #[tokio::test]
+    async fn test_compare_models_answers_with_each_installed_model() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(r#"{"models":[{"name":"phi3:mini","size":2200000000,"digest":"abc123","details":{"parameter_size":"3.8B","quantization_level":"Q4_0","family":"phi3"}}]}"#)
+            .create_async()
+            .await;
+        let generate_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"phi3:mini"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"response":"Knap flint into a pickaxe head.","done":true}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        let models = vec!["phi3:mini".to_string(), "llama3.2:3b".to_string()];
+        let comparison = chat_service.compare_models("How do I make a pickaxe?", &models).await.unwrap();
+        
+        generate_mock.assert_async().await;
+        assert_eq!(comparison.answers.len(), 2);
+        assert_eq!(comparison.answers[0].model, "phi3:mini");
+        assert_eq!(comparison.answers[0].response.as_deref(), Some("Knap flint into a pickaxe head."));
+        assert!(comparison.answers[0].note.is_none());
+        assert_eq!(comparison.answers[1].model, "llama3.2:3b");
+        assert!(comparison.answers[1].response.is_none());
+        assert!(comparison.answers[1].note.as_deref().unwrap().contains("isn't installed"));
+        assert!(chat_service.get_conversation_history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_summarize_source_summarizes_long_pages_in_parts() {
+        use crate::services::vector_database::VectorDocument;
+        use std::collections::HashMap;
+        
+        let mut server = Server::new_async().await;
+        let part_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::Regex("part [12] of 2".to_string()))
+            .with_status(200)
+            .with_body(r#"{"response":"Anvils shape hot metal.","done":true}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let final_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::Regex("bullet points".to_string()))
+            .with_status(200)
+            .with_body(r#"{"response":"An anvil is a smithing station.\n- Shapes hot metal","done":true}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        // A tiny context budget, so the page has to be summarized in two parts
+        let config = ChatConfig {
+            max_tokens: 100_000,
+            ..ChatConfig::default()
+        };
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let mut chat_service = create_test_service(addr, config).await;
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents((0..10).rev().map(|i| VectorDocument {
+            id: format!("anvil_{}", i),
+            content: format!("Anvil section {}: {}", i, "Smithing on an anvil shapes hot metal. ".repeat(4)),
+            source_url: "https://wiki.vintagestory.at/Anvil".to_string(),
+            source_title: "Anvil".to_string(),
+            embedding: vec![1.0, 0.0, i as f32],
+            metadata: HashMap::from([("chunk_index".to_string(), i.to_string())]),
+        }).collect()).await.unwrap();
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_vector_database(Arc::new(Mutex::new(db)));
+        chat_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        let summary = chat_service.summarize_source("https://wiki.vintagestory.at/Anvil").await.unwrap();
+        
+        part_mock.assert_async().await;
+        final_mock.assert_async().await;
+        assert_eq!(summary.source_title, "Anvil");
+        assert_eq!(summary.chunks_summarized, 10);
+        assert_eq!(summary.passes, 2);
+        assert!(summary.summary.starts_with("An anvil is a smithing station."));
+        
+        let missing = chat_service.summarize_source("https://wiki.vintagestory.at/Bloomery").await;
+        assert!(matches!(missing, Err(AppError::WikiError(_))));
+    }
+
+    #[test]
+    fn test_retrieval_query_strips_chat_fluff() {
+        assert_eq!(retrieval_query("hey can you please tell me how do I craft a pickaxe thanks"), "how do I craft a pickaxe");
+        assert_eq!(retrieval_query("Hi there! Could you explain the bloomery? Thank you so much!"), "the bloomery");
+        assert_eq!(retrieval_query("How do I smelt iron?"), "How do I smelt iron?");
+        // Nothing but pleasantries: search with the message as it is
+        assert_eq!(retrieval_query("thanks!"), "thanks!");
+    }
+
+    #[tokio::test]
+    async fn test_cleaned_query_is_used_for_retrieval_only() {
+        let mut server = Server::new_async().await;
+        // Created first so it answers the cleaned query before the catch-all embedding mock
+        let cleaned_mock = server.mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"prompt":"how do I craft a pickaxe"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.5,0.5,0.1]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let generate_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::Regex("hey can you please tell me how do I craft a pickaxe thanks".to_string()))
+            .with_status(200)
+            .with_body("{\"response\":\"Knap a flint head first.\",\"done\":false}\n{\"response\":\"\",\"done\":true}\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let config = ChatConfig { clean_retrieval_query: true, ..ChatConfig::default() };
+        let mut chat_service = create_two_source_service(&mut server, config).await;
+        
+        let response = chat_service.process_message("hey can you please tell me how do I craft a pickaxe thanks").await.unwrap();
+        
+        cleaned_mock.assert_async().await;
+        generate_mock.assert_async().await;
+        assert_eq!(response.message.content, "Knap a flint head first.");
+        assert!(!response.context_used.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_response_can_be_continued() {
+        let mut server = Server::new_async().await;
+        let first_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::Regex("User question: How do I make a stone axe".to_string()))
+            .with_status(200)
+            .with_body("{\"response\":\"First, knap a \",\"done\":false}\n{\"response\":\"\",\"done\":true,\"done_reason\":\"length\"}\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let continue_mock = server.mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::Regex(r"cut off.*Reply so far:\\nFirst, knap a ".to_string()))
+            .with_status(200)
+            .with_body("{\"response\":\"flint axe head.\",\"done\":false}\n{\"response\":\"\",\"done\":true,\"done_reason\":\"stop\"}\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        let response = chat_service.process_message("How do I make a stone axe?").await.unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.message.content, "First, knap a ");
+        
+        let continued = chat_service.continue_response(|_| {}).await.unwrap();
+        first_mock.assert_async().await;
+        continue_mock.assert_async().await;
+        assert!(!continued.truncated);
+        assert_eq!(continued.message.id, response.message.id);
+        assert_eq!(continued.message.content, "First, knap a flint axe head.");
+        
+        // The continuation extends the same message rather than adding one
+        let history = chat_service.get_conversation_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].content, "First, knap a flint axe head.");
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_continue_without_a_reply() {
+        let addr = start_stalling_server(vec![]).await;
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        assert!(matches!(chat_service.continue_response(|_| {}).await, Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_confidence_is_low_without_good_context() {
+        let with_scores = |scores: &[f32]| -> Vec<SimilarityResult> {
+            scores.iter().map(|&score| SimilarityResult { similarity_score: score, ..similarity_result(None) }).collect()
+        };
+        
+        let none = answer_confidence(&[]);
+        assert_eq!(none.score, 0.0);
+        assert_eq!(none.level, ConfidenceLevel::Low);
+        assert_eq!(answer_confidence(&with_scores(&[0.35, 0.3, 0.2])).level, ConfidenceLevel::Low);
+        
+        // One decent match isn't as convincing as several
+        let single = answer_confidence(&with_scores(&[0.72, 0.3]));
+        assert_eq!(single.level, ConfidenceLevel::Medium);
+        let strong = answer_confidence(&with_scores(&[0.9, 0.85, 0.8, 0.4]));
+        assert_eq!(strong.level, ConfidenceLevel::High);
+        assert!(strong.score > single.score && strong.score <= 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_well_matched_answer_is_confident() {
+        let mut server = Server::new_async().await;
+        let mut chat_service = create_two_source_service(&mut server, ChatConfig::default()).await;
+        
+        let response = chat_service.process_message("How do I smelt iron?").await.unwrap();
+        
+        assert_eq!(response.confidence.unwrap().level, ConfidenceLevel::High);
+    }
+
+    #[tokio::test]
+    async fn test_empty_response_yields_diagnostic() {
+        let mut server = Server::new_async().await;
+        let _embed_mock = server.mock("POST", "/api/embed")
+            .with_status(500)
+            .create_async()
+            .await;
+        let _generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body("{\"response\":\"\",\"done\":false}\n{\"response\":\" \",\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":12}\n")
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        let config = ChatConfig {
+            stream_responses: true,
+            ..ChatConfig::default()
+        };
+        let mut chat_service = create_test_service(addr, config).await;
+        chat_service.set_embedding_service(create_empty_embedding_service().await);
+        
+        // No canned fallback reply; the error says what the model was sent
+        let err = chat_service.process_message("How do I smelt iron?").await.unwrap_err();
+        assert_eq!(err.code(), "EMPTY_RESPONSE");
+        let diagnostic = match &err {
+            AppError::EmptyResponse(diagnostic) => diagnostic.clone(),
+            other => panic!("Expected an empty response error, got {:?}", other),
+        };
+        assert_eq!(diagnostic.model, OllamaConfig::default().model_name);
+        assert_eq!(diagnostic.context_chunks, Some(0));
+        assert_eq!(diagnostic.prompt_eval_count, Some(12));
+        assert_eq!(diagnostic.done_reason.as_deref(), Some("stop"));
+        assert!(diagnostic.prompt_chars > "How do I smelt iron?".len());
+        
+        // Ollama read far less of the prompt than was sent, and there was no wiki context
+        let causes = diagnostic.likely_causes();
+        assert!(causes[0].contains("only read 12"));
+        assert!(causes.iter().any(|cause| cause.contains("No wiki content")));
+        
+        let command_error = crate::errors::CommandError::from(err);
+        let details = command_error.details.unwrap();
+        assert_eq!(details["context_chunks"], 0);
+        assert_eq!(details["likely_causes"].as_array().unwrap().len(), causes.len());
+    }
+    
+    #[test]
+    fn test_overlap_between_adjacent_chunks_is_sent_once() {
+        let chunk = |index: usize, source: &str, content: &str, score: f32| SimilarityResult {
+            chunk: TextChunk {
+                id: format!("{}-{}", source, index),
+                content: content.to_string(),
+                source_url: source.to_string(),
+                source_title: source.to_string(),
+                embedding: None,
+                metadata: [("chunk_index".to_string(), index.to_string())].into_iter().collect(),
+            },
+            similarity_score: score,
+        };
+        let shared = "Clay is formed into pots on a flat surface";
+        let results = vec![
+            // The later chunk ranks higher; ranking order must survive the trim
+            chunk(1, "Clay", &format!("{} and then fired in a pit kiln.", shared), 0.9),
+            chunk(0, "Clay", &format!("Clay is dug from deposits near water.\n{}", shared), 0.8),
+            // Same words, but a different page: nothing is shared with it
+            chunk(1, "Pottery", &format!("{} by hand.", shared), 0.7),
+        ];
+        
+        let trimmed = trim_overlapping_context(results);
+        let ids: Vec<&str> = trimmed.iter().map(|result| result.chunk.id.as_str()).collect();
+        assert_eq!(ids, ["Clay-1", "Clay-0", "Pottery-1"]);
+        assert_eq!(trimmed[0].chunk.content, "and then fired in a pit kiln.");
+        assert_eq!(trimmed[1].chunk.content, format!("Clay is dug from deposits near water.\n{}", shared));
+        assert_eq!(trimmed[2].chunk.content, format!("{} by hand.", shared));
+        
+        let clay_context: String = trimmed[..2].iter().map(|result| result.chunk.content.as_str()).collect();
+        assert_eq!(clay_context.matches(shared).count(), 1);
+        assert_eq!(trimmed[0].chunk.token_count(), estimate_tokens("and then fired in a pit kiln."));
+    }
+
+    #[tokio::test]
+    async fn test_dimension_mismatch_fails_only_for_real_query_embeddings() {
+        let mut server = Server::new_async().await;
+        let embed_down = server.mock("POST", "/api/embeddings")
+            .with_status(500)
+            .create_async()
+            .await;
+        let _generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body("{\"response\":\"Use a bloomery.\",\"done\":true}\n")
+            .create_async()
+            .await;
+        let addr: SocketAddr = server.host_with_port().parse().unwrap();
+        
+        let mut chat_service = create_test_service(addr, ChatConfig::default()).await;
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_ollama_endpoint(&OllamaConfig {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            ..OllamaConfig::default()
+        });
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![VectorDocument {
+            id: "bloomery_0".to_string(),
+            content: "A bloomery smelts iron ore.".to_string(),
+            source_url: "https://wiki.vintagestory.at/index.php?title=Bloomery".to_string(),
+            source_title: "Bloomery".to_string(),
+            embedding: vec![0.1; 768],
+            metadata: std::collections::HashMap::new(),
+        }]).await.unwrap();
+        embedding_service.set_vector_database(Arc::new(Mutex::new(db)));
+        chat_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        // Ollama can't embed the question, and the mock fallback never fits the store
+        let response = chat_service.process_message("How do I smelt iron?").await.unwrap();
+        assert!(response.context_used.is_empty());
+        let timing = chat_service.benchmark_query("How do I smelt iron?").await.unwrap();
+        assert_eq!(timing.context_chunks, 0);
+        
+        // A real embedding of another dimension means the wiki has to be re-embedded
+        embed_down.remove_async().await;
+        let _embed_mock = server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.1,0.2,0.3]}"#)
+            .create_async()
+            .await;
+        let err = chat_service.process_message("How do I smelt iron?").await.unwrap_err();
+        assert_eq!(err.code(), "DIMENSION_MISMATCH");
+        let err = chat_service.benchmark_query("How do I smelt iron?").await.unwrap_err();
+        assert_eq!(err.code(), "DIMENSION_MISMATCH");
+    }
+
+    #[tokio::test]
+    async fn test_generation_deadline_counts_against_the_circuit_breaker() {
+        let addr = start_stalling_server(vec![]).await;
+        let mut chat_service = ChatService::with_config(ChatConfig {
+            response_timeout_secs: 1,
+            ..ChatConfig::default()
+        }).await;
+        let ollama_manager = Arc::new(Mutex::new(OllamaManager::with_config(OllamaConfig {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            breaker_failure_threshold: 1,
+            breaker_cooldown_secs: 600,
+            ..OllamaConfig::default()
+        }).await));
+        chat_service.set_ollama_manager(ollama_manager.clone());
+        
+        let err = chat_service.process_message("How do I make a pickaxe?").await.unwrap_err();
+        assert_eq!(err.code(), "TIMEOUT");
+        
+        let status = ollama_manager.lock().await.get_status().await.unwrap();
+        assert_eq!(status.circuit_breaker.state, BreakerState::Open);
+    }
+}
@@ -1,13 +1,100 @@
-use crate::config::EmbeddingConfig;
+use crate::config::{EmbeddingConfig, OllamaConfig};
 use crate::errors::{AppError, AppResult};
-use crate::services::vector_database::{VectorDatabase, VectorDocument};
+use crate::services::cancellation::CancelFlag;
+use crate::services::highlight::{highlight_snippet, term_overlap, HighlightedSnippet};
+use crate::services::knowledge_base::{self, ImportProgress, KnowledgeBaseHeader, KnowledgeBaseImportSummary};
+use crate::services::vector_database::{
+    estimate_document_bytes, normalize_embedding, CompactionReport, DatabaseMetrics, FailedEmbedding, FlushPolicy, IndexRebuildReport, OrphanedSource, StoredSource,
+    VectorDatabase, VectorDocument,
+    METADATA_LIST_SEPARATOR, scraped_time,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore};
 use log::{info, warn, error};
 use reqwest::Client;
 
+/// Embedding-capable models we know how to use, with their output dimension.
+/// Matched against the model name without its tag (`nomic-embed-text:latest` -> `nomic-embed-text`).
+pub const KNOWN_EMBEDDING_MODELS: &[(&str, usize)] = &[
+    ("nomic-embed-text", 768),
+    ("mxbai-embed-large", 1024),
+    ("all-minilm", 384),
+    ("snowflake-arctic-embed", 1024),
+    ("bge-m3", 1024),
+    ("bge-large", 1024),
+    ("paraphrase-multilingual", 768),
+    ("granite-embedding", 384),
+];
+
+/// Embedding models that already return unit-length vectors, matched like `KNOWN_EMBEDDING_MODELS`
+pub const UNIT_LENGTH_EMBEDDING_MODELS: &[&str] = &[
+    "mxbai-embed-large",
+    "all-minilm",
+    "snowflake-arctic-embed",
+    "bge-m3",
+    "bge-large",
+];
+
+/// Pages assumed for a full scrape when there's nothing better to go on; more than the
+/// English wiki has, so the estimate errs on the large side
+pub const DEFAULT_ESTIMATED_WIKI_PAGES: usize = 4000;
+
+/// Chunks per page assumed before any page has been embedded
+const DEFAULT_ESTIMATED_CHUNKS_PER_PAGE: f64 = 12.0;
+
+/// Embedding size assumed when the model's isn't known, as large as common embedding models get
+const DEFAULT_ESTIMATED_DIMENSION: usize = 1024;
+
+/// Characters per word, counting the following space, that wiki text stays under on average.
+/// `chunk_size` is in words, so this bounds a chunk's length in characters.
+const MAX_AVERAGE_WORD_CHARS: usize = 8;
+
+/// In-memory searches over fewer chunks than this stay on one thread, as they finish in about a
+/// millisecond anyway (`test_memory_search_benchmark`)
+const PARALLEL_SEARCH_MIN_CHUNKS: usize = 1024;
+
+/// Projected knowledge base size for a scrape, from `estimate_storage`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageEstimate {
+    pub estimated_pages: usize,
+    /// From the pages already embedded, or a default before there are any
+    pub chunks_per_page: f64,
+    pub estimated_chunks: usize,
+    pub embedding_dimension: usize,
+    pub bytes_per_chunk: u64,
+    pub estimated_bytes: u64,
+}
+
+/// Returns the expected dimension for a known embedding model, or `None` if the model isn't known.
+pub fn known_embedding_dimension(model_name: &str) -> Option<usize> {
+    let base_name = model_name.split(':').next().unwrap_or(model_name);
+    KNOWN_EMBEDDING_MODELS
+        .iter()
+        .find(|(name, _)| *name == base_name)
+        .map(|(_, dimension)| *dimension)
+}
+
+/// Whether `model_name` is known to return unit-length embeddings
+pub fn returns_unit_length_embeddings(model_name: &str) -> bool {
+    let base_name = model_name.split(':').next().unwrap_or(model_name);
+    UNIT_LENGTH_EMBEDDING_MODELS.contains(&base_name)
+}
+
+/// Whether embeddings are normalized before storing: `normalize_embeddings` if set, otherwise
+/// only for models that aren't known to return unit-length vectors
+pub fn normalizes_embeddings(config: &EmbeddingConfig) -> bool {
+    config.normalize_embeddings.unwrap_or_else(|| !returns_unit_length_embeddings(&config.model_name))
+}
+
+/// Whether every embedding stored with `config` has unit length, so searches can use a dot product
+fn stores_unit_length_embeddings(config: &EmbeddingConfig) -> bool {
+    normalizes_embeddings(config) || returns_unit_length_embeddings(&config.model_name)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextChunk {
     pub id: String,
@@ -18,31 +105,558 @@ pub struct TextChunk {
     pub metadata: HashMap<String, String>,
 }
 
+impl TextChunk {
+    /// Estimated tokens in `content`, as recorded when the chunk was stored. Falls back to
+    /// estimating for chunks stored before counts were recorded.
+    pub fn token_count(&self) -> usize {
+        self.metadata.get(TOKEN_COUNT_METADATA_KEY)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or_else(|| estimate_tokens(&self.content))
+    }
+}
+
+/// Chunk metadata holding `estimate_tokens` of the chunk's content
+pub const TOKEN_COUNT_METADATA_KEY: &str = "token_count";
+
+/// Rough token count for budgeting: about four characters per token for English text. Used
+/// both for the counts stored with chunks and for budgeting prompts, so they always agree.
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimilarityResult {
     pub chunk: TextChunk,
     pub similarity_score: f32,
 }
 
+/// A search hit prepared for the sources panel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeSearchResult {
+    pub chunk_id: String,
+    pub source_title: String,
+    pub source_url: String,
+    pub similarity_score: f32,
+    pub snippet: HighlightedSnippet,
+    /// When the page was fetched from the wiki (RFC 3339); unknown for chunks stored before this was recorded
+    pub scraped_at: Option<String>,
+}
+
+/// Why a chunk was retrieved for a query: its similarity score plus which query terms it shares.
+/// Retrieval is purely semantic, so a high score with no shared terms is expected for paraphrases.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalExplanation {
+    pub rank: usize,
+    pub chunk_id: String,
+    pub source_title: String,
+    pub source_url: String,
+    pub similarity_score: f32,
+    pub matched_terms: Vec<String>,
+    pub missing_terms: Vec<String>,
+    /// Fraction of the query's terms that appear in the chunk
+    pub term_overlap: f32,
+    pub content: String,
+}
+
+/// How well the knowledge base covers a topic, judged from the similarity of its best matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverageLevel {
+    Good,
+    Partial,
+    None,
+}
+
+/// Result of `assess_coverage`: similarity of the best and average match, and the matches themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub topic: String,
+    pub level: CoverageLevel,
+    pub best_score: f32,
+    pub mean_score: f32,
+    pub top_sources: Vec<KnowledgeSearchResult>,
+}
+
+/// Similarity a chunk needs to count as covering a topic well, or at least partly
+pub(crate) const GOOD_COVERAGE_SIMILARITY: f32 = 0.7;
+pub(crate) const PARTIAL_COVERAGE_SIMILARITY: f32 = 0.5;
+
+/// Good coverage needs a strong best match backed by reasonable matches around it;
+/// a single decent match is only partial
+pub(crate) fn coverage_level(best_score: f32, mean_score: f32) -> CoverageLevel {
+    if best_score >= GOOD_COVERAGE_SIMILARITY && mean_score >= PARTIAL_COVERAGE_SIMILARITY {
+        CoverageLevel::Good
+    } else if best_score >= PARTIAL_COVERAGE_SIMILARITY {
+        CoverageLevel::Partial
+    } else {
+        CoverageLevel::None
+    }
+}
+
+/// Which pages `prune_knowledge_base` keeps; every other page's chunks are deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum PruneCriteria {
+    /// The `count` pages cited most often in chat answers, going by the usage stats
+    MostCited { count: usize },
+    /// The `count` most recently scraped pages
+    Newest { count: usize },
+    /// The pages listed, by URL or by title ignoring case
+    Allowlist { sources: Vec<String> },
+}
+
+/// What `prune_knowledge_base` deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub sources_kept: usize,
+    pub chunks_removed: usize,
+    /// Sorted by URL
+    pub removed: Vec<StoredSource>,
+}
+
+/// Outcome of `retry_failed_embeddings`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingRetryReport {
+    pub retried: usize,
+    /// Embedded and stored this time
+    pub recovered: usize,
+    /// Still recorded as failed, with their latest error
+    pub still_failing: usize,
+}
+
+/// URLs of the `sources` that `criteria` keeps. `citations` counts chat citations by page title.
+/// Errors rather than keeping nothing, since that would empty the knowledge base.
+pub(crate) fn sources_to_keep(sources: &[StoredSource], criteria: &PruneCriteria, citations: &HashMap<String, u64>) -> AppResult<HashSet<String>> {
+    let mut ranked: Vec<&StoredSource> = sources.iter().collect();
+    let count = match criteria {
+        PruneCriteria::MostCited { count } => {
+            if !sources.iter().any(|source| citations.contains_key(&source.source_title)) {
+                return Err(AppError::ConfigError("None of the stored pages have been cited in chat yet".to_string()));
+            }
+            let cited = |source: &StoredSource| citations.get(&source.source_title).copied().unwrap_or(0);
+            ranked.sort_by(|a, b| cited(b).cmp(&cited(a)).then_with(|| a.source_url.cmp(&b.source_url)));
+            *count
+        }
+        PruneCriteria::Newest { count } => {
+            ranked.sort_by(|a, b| {
+                scraped_time(b.scraped_at.as_deref()).cmp(&scraped_time(a.scraped_at.as_deref()))
+                    .then_with(|| a.source_url.cmp(&b.source_url))
+            });
+            *count
+        }
+        PruneCriteria::Allowlist { sources: allowed } => {
+            let allowed: Vec<&str> = allowed.iter().map(|source| source.trim()).filter(|source| !source.is_empty()).collect();
+            if allowed.is_empty() {
+                return Err(AppError::ConfigError("No sources given to keep".to_string()));
+            }
+            let keep: HashSet<String> = sources.iter()
+                .filter(|source| allowed.iter().any(|allowed| *allowed == source.source_url || allowed.eq_ignore_ascii_case(&source.source_title)))
+                .map(|source| source.source_url.clone())
+                .collect();
+            if keep.is_empty() && !sources.is_empty() {
+                return Err(AppError::ConfigError("None of the listed sources are in the knowledge base".to_string()));
+            }
+            return Ok(keep);
+        }
+    };
+    
+    if count == 0 {
+        return Err(AppError::ConfigError("At least one source has to be kept".to_string()));
+    }
+    Ok(ranked.into_iter().take(count).map(|source| source.source_url.clone()).collect())
+}
+
+/// Characters of chunk text shown around the best match in search snippets
+const SNIPPET_WINDOW_CHARS: usize = 240;
+
+/// The active embedding model and whether the stored knowledge base was built with a compatible one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingModelInfo {
+    pub model_name: String,
+    /// Dimension of the active model, once it has been probed
+    pub dimension: Option<usize>,
+    /// Dimension of the embeddings already in the knowledge base, if it isn't empty
+    pub stored_dimension: Option<usize>,
+    pub chunk_count: usize,
+    /// The knowledge base can't be searched with the active model until it's re-embedded
+    pub reindex_required: bool,
+}
+
+/// A raw embedding of arbitrary text, for external tooling and debugging
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextEmbedding {
+    pub model_name: String,
+    pub dimension: usize,
+    /// Dimension the model is expected to return: probed if it has been, otherwise its published one
+    pub expected_dimension: Option<usize>,
+    /// Made by the development fallback because Ollama couldn't embed the text; it doesn't come
+    /// from the model and can't be compared with real embeddings
+    pub mock: bool,
+    pub embedding: Vec<f32>,
+}
+
+/// How a single chunk would look after splitting, without embedding or storing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkPreview {
+    pub index: usize,
+    pub content: String,
+    pub word_count: usize,
+}
+
+/// Embedding progress across a wiki update. The total is an estimate: it grows as the crawl
+/// discovers pages and is refined with the average chunk count of pages embedded so far.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingProgress {
+    pub is_running: bool,
+    pub pages_discovered: usize,
+    /// Pages whose chunk count is known, or that turned out not to need embedding
+    pub pages_accounted: usize,
+    pub chunks_processed: usize,
+    pub chunks_estimated: usize,
+    /// 0.0 - 1.0
+    pub fraction: f32,
+}
+
+type ProgressListener = Arc<dyn Fn(&EmbeddingProgress) + Send + Sync>;
+
+/// Shared progress counter, updated by the wiki crawl and the embedding service and read
+/// without taking either service's lock
+#[derive(Default)]
+pub struct EmbeddingProgressTracker {
+    state: std::sync::Mutex<ProgressState>,
+    listener: std::sync::Mutex<Option<ProgressListener>>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    progress: EmbeddingProgress,
+    /// Chunks in the pages accounted so far, and how many of those pages had any
+    chunks_known: usize,
+    pages_with_chunks: usize,
+}
+
+impl EmbeddingProgressTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Called with every progress change
+    pub fn set_listener<F>(&self, listener: F)
+    where
+        F: Fn(&EmbeddingProgress) + Send + Sync + 'static,
+    {
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(listener));
+    }
+    
+    pub fn snapshot(&self) -> EmbeddingProgress {
+        self.lock_state().progress.clone()
+    }
+    
+    /// A listener that panicked mustn't break progress reporting for every later update
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, ProgressState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    
+    /// Resets the counters for a new update that starts from `initial_pages` pages
+    pub fn start(&self, initial_pages: usize) {
+        self.update(|state| {
+            *state = ProgressState::default();
+            state.progress.is_running = true;
+            state.progress.pages_discovered = initial_pages;
+        });
+    }
+    
+    /// The crawl queued more pages
+    pub fn pages_discovered(&self, count: usize) {
+        self.update(|state| state.progress.pages_discovered += count);
+    }
+    
+    /// A queued page won't be embedded (unchanged, already visited, failed or low-value)
+    pub fn page_skipped(&self) {
+        self.update(|state| state.progress.pages_accounted += 1);
+    }
+    
+    /// A page was split and is about to be embedded
+    pub fn page_started(&self, chunk_count: usize) {
+        self.update(|state| {
+            state.progress.pages_accounted += 1;
+            state.chunks_known += chunk_count;
+            if chunk_count > 0 {
+                state.pages_with_chunks += 1;
+            }
+        });
+    }
+    
+    pub fn chunk_processed(&self) {
+        self.update(|state| state.progress.chunks_processed += 1);
+    }
+    
+    pub fn finish(&self) {
+        self.update(|state| {
+            state.progress.is_running = false;
+            state.progress.pages_discovered = state.progress.pages_accounted;
+        });
+    }
+    
+    fn update(&self, change: impl FnOnce(&mut ProgressState)) {
+        let progress = {
+            let mut state = self.lock_state();
+            change(&mut state);
+            
+            let remaining_pages = state.progress.pages_discovered.saturating_sub(state.progress.pages_accounted);
+            let average_chunks = if state.pages_with_chunks == 0 {
+                0.0
+            } else {
+                state.chunks_known as f32 / state.pages_with_chunks as f32
+            };
+            let estimated = state.chunks_known + (remaining_pages as f32 * average_chunks).round() as usize;
+            state.progress.chunks_estimated = estimated.max(state.progress.chunks_processed);
+            
+            state.progress.fraction = if !state.progress.is_running {
+                1.0
+            } else if state.progress.chunks_estimated == 0 {
+                0.0
+            } else {
+                state.progress.chunks_processed as f32 / state.progress.chunks_estimated as f32
+            };
+            state.progress.clone()
+        };
+        
+        // Called without the lock held, so the listener may replace itself
+        let listener = self.listener.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(listener) = listener {
+            listener(&progress);
+        }
+    }
+}
+
+/// Prepares search hits for the sources panel, highlighting `query` in each
+fn to_search_results(query: &str, results: Vec<SimilarityResult>) -> Vec<KnowledgeSearchResult> {
+    results.into_iter()
+        .map(|result| KnowledgeSearchResult {
+            snippet: highlight_snippet(query, &result.chunk.content, SNIPPET_WINDOW_CHARS),
+            scraped_at: result.chunk.metadata.get("scraped_at").cloned(),
+            chunk_id: result.chunk.id,
+            source_title: result.chunk.source_title,
+            source_url: result.chunk.source_url,
+            similarity_score: result.similarity_score,
+        })
+        .collect()
+}
+
+/// Normalizes a category name the way MediaWiki does: no `Category:` prefix, underscores as
+/// spaces and an uppercase first letter, so "tools" and "Category:Tools" both find "Tools"
+pub(crate) fn normalize_category(name: &str) -> String {
+    let name = name.trim();
+    let name = name.strip_prefix("Category:").unwrap_or(name).trim().replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Marks the lines around a code block in page content, as `extract_clean_text` writes them
+pub(crate) const CODE_FENCE: &str = "```";
+
+/// The `## ` and `### ` headings `extract_clean_text` writes into page content, each with the
+/// index of its first word, counting words the way the chunker does. Lines in code blocks are
+/// never headings, e.g. a `## ` comment in a script.
+pub(crate) fn section_headings(content: &str) -> Vec<(usize, String)> {
+    let mut headings = Vec::new();
+    let mut word_index = 0;
+    let mut in_code = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with(CODE_FENCE) {
+            in_code = !in_code;
+        }
+        let heading = line.strip_prefix("## ").or_else(|| line.strip_prefix("### ")).filter(|_| !in_code);
+        if let Some(heading) = heading.map(str::trim).filter(|heading| !heading.is_empty()) {
+            headings.push((word_index, heading.to_string()));
+        }
+        word_index += line.split_whitespace().count();
+    }
+    headings
+}
+
+/// Threads to score in-memory chunks with: `configured`, or one per CPU core when it's 0. Capped
+/// at the core count, since every search spawns them afresh from inside a tokio worker.
+pub(crate) fn memory_search_thread_count(configured: usize) -> usize {
+    let cores = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    match configured {
+        0 => cores,
+        threads => threads.min(cores),
+    }
+}
+
+/// `chunk` in pieces of at most `max_chars` characters. Each piece ends at the last whitespace in
+/// its second half, or mid-word when there's none there, e.g. in a long URL or base64 blob.
+/// A fenced code block stays fenced: every piece gets the block's opening and closing fence lines,
+/// unless `max_chars` leaves no room for code between them.
+fn split_oversized_chunk(chunk: &str, max_chars: usize) -> Vec<String> {
+    let chunk = chunk.trim();
+    if chunk.chars().count() > max_chars {
+        let fenced = chunk.split_once('\n').filter(|(opening_fence, _)| opening_fence.starts_with(CODE_FENCE));
+        let fence_chars = fenced.map_or(0, |(opening_fence, _)| opening_fence.chars().count() + CODE_FENCE.len() + 2);
+        if let Some((opening_fence, code)) = fenced.filter(|_| fence_chars < max_chars) {
+            let code = code.trim_end();
+            let code = code.strip_suffix(CODE_FENCE).unwrap_or(code).trim_end();
+            return split_oversized_chunk(code, max_chars - fence_chars)
+                .into_iter()
+                .map(|piece| format!("{}\n{}\n{}", opening_fence, piece, CODE_FENCE))
+                .collect();
+        }
+    }
+    
+    let byte_offset = |text: &str, chars: usize| text.char_indices().nth(chars).map_or(text.len(), |(i, _)| i);
+    
+    let mut pieces = Vec::new();
+    let mut rest = chunk;
+    while rest.chars().count() > max_chars {
+        let limit = byte_offset(rest, max_chars);
+        let second_half = byte_offset(rest, max_chars / 2);
+        let cut = rest[second_half..limit].rfind(char::is_whitespace)
+            .map(|i| second_half + i)
+            .filter(|&cut| cut > 0)
+            .unwrap_or(limit);
+        pieces.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest.to_string());
+    }
+    pieces
+}
+
+/// Splits `content` into prose and fenced code blocks, in order. Each code block includes its
+/// fence lines; one that's never closed runs to the end of the content.
+fn code_block_segments(content: &str) -> Vec<(&str, bool)> {
+    let mut segments = Vec::new();
+    let mut segment_start = 0;
+    let mut line_start = 0;
+    let mut in_code = false;
+    for line in content.split_inclusive('\n') {
+        let line_end = line_start + line.len();
+        if line.trim_start().starts_with(CODE_FENCE) {
+            if in_code {
+                segments.push((&content[segment_start..line_end], true));
+                segment_start = line_end;
+            } else {
+                segments.push((&content[segment_start..line_start], false));
+                segment_start = line_start;
+            }
+            in_code = !in_code;
+        }
+        line_start = line_end;
+    }
+    segments.push((&content[segment_start..], in_code));
+    segments.retain(|(segment, _)| !segment.trim().is_empty());
+    segments
+}
+
+/// Id of a chunk derived from its page URL and content rather than its position, so
+/// unchanged text keeps its id when the page is re-scraped and upserts over itself. Uses
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+pub(crate) fn stable_chunk_id(title_prefix: &str, source_url: &str, content: &str) -> String {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    
+    let bytes = source_url.bytes().chain(std::iter::once(0)).chain(content.bytes());
+    let hash = bytes.fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME));
+    format!("{}_{:016x}", title_prefix, hash)
+}
+
+/// What a text is embedded as, which decides the task prefix it gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingKind {
+    /// A search query or question
+    Query,
+    /// A chunk stored in the knowledge base
+    Document,
+}
+
+/// Ollama's embedding endpoints. `/api/embed` (Ollama 0.3.4 and later) takes `input` and returns
+/// `embeddings`; the older `/api/embeddings` takes `prompt` and returns `embedding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbedEndpoint {
+    Embed,
+    Legacy,
+}
+
+impl EmbedEndpoint {
+    fn path(self) -> &'static str {
+        match self {
+            EmbedEndpoint::Embed => "/api/embed",
+            EmbedEndpoint::Legacy => "/api/embeddings",
+        }
+    }
+    
+    fn payload(self, model: &str, text: &str) -> serde_json::Value {
+        match self {
+            EmbedEndpoint::Embed => serde_json::json!({ "model": model, "input": text }),
+            EmbedEndpoint::Legacy => serde_json::json!({ "model": model, "prompt": text }),
+        }
+    }
+}
+
+/// The embedding in a response from either endpoint: the first of `embeddings`, or `embedding`
+pub(crate) fn parse_embedding_response(response: &serde_json::Value) -> Vec<f32> {
+    let values = response["embeddings"].get(0).unwrap_or(&response["embedding"]);
+    values.as_array()
+        .map(|values| values.iter().filter_map(|v| v.as_f64().map(|f| f as f32)).collect())
+        .unwrap_or_default()
+}
+
+/// Converts a stored document back into a chunk, leaving out the embedding callers don't need
+fn chunk_from_document(doc: VectorDocument) -> TextChunk {
+    TextChunk {
+        id: doc.id,
+        content: doc.content,
+        source_url: doc.source_url,
+        source_title: doc.source_title,
+        embedding: None,
+        metadata: doc.metadata,
+    }
+}
+
 pub struct EmbeddingService {
     config: EmbeddingConfig,
     chunks: Vec<TextChunk>,
     client: Client,
     vector_db: Arc<Mutex<VectorDatabase>>,
+    ollama_base_url: String,
+    embedding_dimension: Option<usize>,
+    progress: Arc<EmbeddingProgressTracker>,
+    /// Caps how many embedding requests are in flight to Ollama at once
+    embedding_permits: Arc<Semaphore>,
+    /// Set to stop a running knowledge base import (the import holds the service lock)
+    import_cancelled: Arc<CancelFlag>,
+    /// The embedding endpoint the Ollama server answered on, once one has
+    embed_endpoint: OnceLock<EmbedEndpoint>,
 }
 
 impl EmbeddingService {
     pub async fn new() -> Self {
-        let config = EmbeddingConfig::default();
+        Self::with_config(EmbeddingConfig::default(), &OllamaConfig::default()).await
+    }
+    
+    pub async fn with_config(config: EmbeddingConfig, ollama_config: &OllamaConfig) -> Self {
         let client = Client::new();
+        let ollama_base_url = ollama_config.base_url();
         
         // Initialize vector database
         let vector_db = match VectorDatabase::new().await {
-            Ok(db) => {
+            Ok(mut db) => {
+                db.set_flush_policy(FlushPolicy {
+                    every_documents: config.flush_every_documents.max(1),
+                    interval: Duration::from_secs(config.flush_interval_secs),
+                });
                 // Initialize the database tables
                 if let Err(e) = db.initialize().await {
                     error!("Failed to initialize vector database: {}", e);
                 }
+                db.set_unit_length_embeddings(stores_unit_length_embeddings(&config)).await;
                 Arc::new(Mutex::new(db))
             }
             Err(e) => {
@@ -55,64 +669,343 @@ impl EmbeddingService {
         };
         
         Self {
-            config,
             chunks: Vec::new(),
             client,
             vector_db,
+            ollama_base_url,
+            embedding_dimension: None,
+            progress: Arc::new(EmbeddingProgressTracker::new()),
+            embedding_permits: Arc::new(Semaphore::new(config.max_concurrent_embeddings.max(1))),
+            import_cancelled: Arc::new(CancelFlag::default()),
+            embed_endpoint: OnceLock::new(),
+            config,
         }
     }
     
-    pub async fn process_wiki_page(&mut self, title: &str, url: &str, content: &str) -> AppResult<()> {
+    /// Progress counter shared with the wiki crawl, readable while embedding is running
+    pub fn progress_handle(&self) -> Arc<EmbeddingProgressTracker> {
+        self.progress.clone()
+    }
+    
+    /// Flag that stops a running import, settable without the service lock
+    pub fn import_cancel_handle(&self) -> Arc<CancelFlag> {
+        self.import_cancelled.clone()
+    }
+    
+    /// Sends embedding requests to the Ollama instance described by `ollama_config`
+    pub fn set_ollama_endpoint(&mut self, ollama_config: &OllamaConfig) {
+        self.ollama_base_url = ollama_config.base_url();
+        self.embed_endpoint = OnceLock::new();
+    }
+    
+    /// The settings in use, including model and concurrency changes made since startup
+    pub fn config(&self) -> &EmbeddingConfig {
+        &self.config
+    }
+    
+    /// Requests already in flight finish under the old limit
+    pub fn set_max_concurrent_embeddings(&mut self, limit: usize) {
+        self.config.max_concurrent_embeddings = limit;
+        self.embedding_permits = Arc::new(Semaphore::new(limit.max(1)));
+    }
+    
+    pub fn set_vector_database(&mut self, vector_db: Arc<Mutex<VectorDatabase>>) {
+        self.vector_db = vector_db;
+    }
+    
+    pub async fn database_metrics(&self, sample_search: bool) -> AppResult<DatabaseMetrics> {
+        let db = self.vector_db.lock().await;
+        db.metrics(sample_search).await
+    }
+    
+    /// Flushes the vector database so sled can reclaim the space of deleted chunks
+    pub async fn compact_database(&self) -> AppResult<CompactionReport> {
+        let db = self.vector_db.lock().await;
+        db.compact().await
+    }
+    
+    pub async fn rebuild_index(&self) -> AppResult<IndexRebuildReport> {
+        let db = self.vector_db.lock().await;
+        db.rebuild_index().await
+    }
+    
+    /// Estimates the knowledge base size after a scrape of `pages` pages (by default, the larger of
+    /// the pages already stored and `DEFAULT_ESTIMATED_WIKI_PAGES`) with `dimension`-long
+    /// embeddings (by default, the configured model's)
+    pub async fn estimate_storage(&self, pages: Option<usize>, dimension: Option<usize>) -> AppResult<StorageEstimate> {
+        let db = self.vector_db.lock().await;
+        let stored_chunks = db.count_documents().await?;
+        let stored_pages = db.count_sources().await?;
+        
+        let estimated_pages = pages.unwrap_or(stored_pages.max(DEFAULT_ESTIMATED_WIKI_PAGES));
+        let chunks_per_page = if stored_pages > 0 {
+            stored_chunks as f64 / stored_pages as f64
+        } else {
+            DEFAULT_ESTIMATED_CHUNKS_PER_PAGE
+        };
+        let embedding_dimension = match dimension {
+            Some(dimension) => dimension,
+            None => match self.embedding_dimension.or_else(|| known_embedding_dimension(&self.config.model_name)) {
+                Some(dimension) => dimension,
+                None => db.stored_dimension().await.unwrap_or(DEFAULT_ESTIMATED_DIMENSION),
+            },
+        };
+        
+        let estimated_chunks = (estimated_pages as f64 * chunks_per_page).ceil() as usize;
+        let bytes_per_chunk = estimate_document_bytes(embedding_dimension, self.config.chunk_size * MAX_AVERAGE_WORD_CHARS);
+        Ok(StorageEstimate {
+            estimated_pages,
+            chunks_per_page,
+            estimated_chunks,
+            embedding_dimension,
+            bytes_per_chunk,
+            estimated_bytes: bytes_per_chunk * estimated_chunks as u64,
+        })
+    }
+    
+    /// Writes chunks the flush policy is still holding in memory to disk
+    pub async fn flush_database(&self) -> AppResult<()> {
+        let db = self.vector_db.lock().await;
+        db.flush().await
+    }
+    
+    /// Number of chunks available for retrieval, counting the in-memory fallback store
+    pub async fn knowledge_base_size(&self) -> AppResult<usize> {
+        let db = self.vector_db.lock().await;
+        let stored = db.count_documents().await?;
+        Ok(stored.max(self.chunks.len()))
+    }
+    
+    /// Writes the whole knowledge base to a portable file tagged with the embedding model
+    pub async fn export_knowledge_base(&self, path: &Path) -> AppResult<KnowledgeBaseHeader> {
+        let db = self.vector_db.lock().await;
+        knowledge_base::export_knowledge_base(&db, path, &self.config.model_name).await
+    }
+    
+    /// Loads a shared knowledge base if it was built with the configured embedding model. With
+    /// `skip_near_duplicates`, documents within `import_duplicate_distance` of stored ones are left out.
+    pub async fn import_knowledge_base<F>(&self, path: &Path, skip_near_duplicates: bool, on_progress: F) -> AppResult<KnowledgeBaseImportSummary>
+    where
+        F: FnMut(ImportProgress),
+    {
+        // Clears any cancellation left over from a previous import. Started before waiting for
+        // the database, so a cancel sent meanwhile still stops this one.
+        let _running = self.import_cancelled.start();
+        let db = self.vector_db.lock().await;
+        
+        // Imported vectors must be comparable with what's already stored, or with the probed model
+        let expected_dimension = match db.stored_dimension().await {
+            Some(dimension) => Some(dimension),
+            None => self.embedding_dimension,
+        };
+        
+        knowledge_base::import_knowledge_base(
+            &db,
+            path,
+            &self.config.model_name,
+            expected_dimension,
+            skip_near_duplicates.then_some(self.config.import_duplicate_distance),
+            &self.import_cancelled,
+            on_progress,
+        ).await
+    }
+    
+    pub fn get_model_name(&self) -> &str {
+        &self.config.model_name
+    }
+    
+    /// Dimension reported by the last successful probe of the embedding model, if any.
+    pub fn get_embedding_dimension(&self) -> Option<usize> {
+        self.embedding_dimension
+    }
+    
+    pub fn normalizes_embeddings(&self) -> bool {
+        normalizes_embeddings(&self.config)
+    }
+    
+    pub fn set_model(&mut self, model_name: String) {
+        info!("Switching embedding model to: {}", model_name);
+        self.config.model_name = model_name;
+        self.embedding_dimension = None;
+    }
+    
+    pub async fn get_model_info(&self) -> AppResult<EmbeddingModelInfo> {
+        let db = self.vector_db.lock().await;
+        let stored_dimension = db.stored_dimension().await;
+        
+        Ok(EmbeddingModelInfo {
+            model_name: self.config.model_name.clone(),
+            dimension: self.embedding_dimension,
+            stored_dimension,
+            chunk_count: db.count_documents().await?,
+            reindex_required: matches!((self.embedding_dimension, stored_dimension), (Some(a), Some(b)) if a != b),
+        })
+    }
+    
+    /// Switches to `model_name` after probing its dimension. If the knowledge base holds
+    /// embeddings of a different dimension the switch is refused, because none of them could be
+    /// searched any more, unless `force_reindex` is set - then the knowledge base is cleared so it
+    /// can be re-embedded. On failure the previous model stays active.
+    /// Returns the new dimension and whether the knowledge base was cleared.
+    pub async fn switch_model(&mut self, model_name: String, force_reindex: bool) -> AppResult<(usize, bool)> {
+        let previous_model = self.config.model_name.clone();
+        let previous_dimension = self.embedding_dimension;
+        
+        self.set_model(model_name.clone());
+        let dimension = match self.probe_dimension().await {
+            Ok(dimension) => dimension,
+            Err(e) => {
+                self.config.model_name = previous_model;
+                self.embedding_dimension = previous_dimension;
+                return Err(e);
+            }
+        };
+        
+        let stored_dimension = self.vector_db.lock().await.stored_dimension().await;
+        match stored_dimension {
+            Some(stored) if stored != dimension && !force_reindex => {
+                self.config.model_name = previous_model;
+                self.embedding_dimension = previous_dimension;
+                Err(AppError::EmbeddingError(format!(
+                    "{} produces {}-dimensional embeddings but the knowledge base has {}-dimensional ones. \
+                     Switching requires re-embedding the wiki; pass force_reindex to clear the knowledge base and re-embed.",
+                    model_name, dimension, stored
+                )))
+            }
+            Some(stored) if stored != dimension => {
+                warn!("Clearing {}-dimensional knowledge base to re-embed with {}", stored, model_name);
+                let db = self.vector_db.lock().await;
+                db.clear().await?;
+                db.set_unit_length_embeddings(stores_unit_length_embeddings(&self.config)).await;
+                self.chunks.clear();
+                Ok((dimension, true))
+            }
+            _ => {
+                let db = self.vector_db.lock().await;
+                db.set_unit_length_embeddings(stores_unit_length_embeddings(&self.config)).await;
+                Ok((dimension, false))
+            }
+        }
+    }
+    
+    /// Embeds a short probe string with the configured model and records the resulting dimension.
+    /// Unlike regular embedding calls this never falls back to mock embeddings.
+    pub async fn probe_dimension(&mut self) -> AppResult<usize> {
+        let embedding = self.request_embedding("dimension probe").await?;
+        let dimension = embedding.len();
+        
+        if let Some(expected) = known_embedding_dimension(&self.config.model_name) {
+            if expected != dimension {
+                warn!("Embedding model {} returned dimension {}, expected {}", 
+                      self.config.model_name, dimension, expected);
+            }
+        }
+        
+        info!("Embedding model {} has dimension {}", self.config.model_name, dimension);
+        self.embedding_dimension = Some(dimension);
+        Ok(dimension)
+    }
+    
+    /// Embeds a tiny string so Ollama loads the embedding model before the first search or wiki
+    /// update needs it. Fails with `ModelNotInstalled` if the model hasn't been pulled; like
+    /// `probe_dimension` it never falls back to mock embeddings.
+    pub async fn warmup(&self) -> AppResult<()> {
+        let started = std::time::Instant::now();
+        self.request_embedding("warmup").await?;
+        info!("Embedding model {} loaded in {:?}", self.config.model_name, started.elapsed());
+        Ok(())
+    }
+    
+    /// Splits, embeds and stores a page. Its `categories` are recorded on every chunk so
+    /// searches can be scoped to a category.
+    pub async fn process_wiki_page(&mut self, title: &str, url: &str, content: &str, categories: &[String]) -> AppResult<()> {
+        let scraped_at = chrono::Utc::now().to_rfc3339();
+        self.process_wiki_page_scraped_at(title, url, content, categories, &scraped_at).await
+    }
+    
+    /// `process_wiki_page` for a page fetched at `scraped_at` (RFC 3339), which every chunk
+    /// records so citations can say how old their information is
+    pub async fn process_wiki_page_scraped_at(&mut self, title: &str, url: &str, content: &str, categories: &[String], scraped_at: &str) -> AppResult<()> {
         info!("Processing wiki page for embeddings: {}", title);
         
+        let categories: Vec<String> = categories.iter()
+            .map(|category| normalize_category(category))
+            .filter(|category| !category.is_empty())
+            .collect();
+        
         // Split content into chunks
-        let chunks = self.split_into_chunks(content);
+        let chunks = self.split_into_sectioned_chunks(content);
         let total_chunks = chunks.len();
+        self.progress.page_started(total_chunks);
         
         // Process chunks in batches for efficiency
         let batch_size = self.config.batch_size;
         let mut processed = 0;
+        let mut failures = Vec::new();
+        let mut page_chunks = Vec::new();
         
         for batch_start in (0..chunks.len()).step_by(batch_size) {
             let batch_end = std::cmp::min(batch_start + batch_size, chunks.len());
             let batch = &chunks[batch_start..batch_end];
             
-            // Generate embeddings for batch
-            let mut batch_chunks = Vec::new();
-            for (i, chunk_content) in batch.iter().enumerate() {
-                if chunk_content.trim().len() < 50 {
-                    continue; // Skip very short chunks
+            // Generate embeddings for batch; the request limiter bounds how many run at once
+            let service = &*self;
+            let embeddings = futures::future::join_all(batch.iter().map(|(chunk_content, _)| async move {
+                if chunk_content.trim().chars().count() < service.config.min_chunk_chars {
+                    return None; // Too short to be worth retrieving on its own
                 }
-                
+                Some(service.embed_document(chunk_content).await)
+            })).await;
+            
+            let mut batch_chunks = Vec::new();
+            for (i, ((chunk_content, section), embedding)) in batch.iter().zip(embeddings).enumerate() {
+                self.progress.chunk_processed();
                 let chunk_index = batch_start + i;
-                let chunk_id = format!("{}_{}", self.sanitize_title(title), chunk_index);
                 
-                match self.create_embedding(chunk_content).await {
+                let Some(embedding) = embedding else {
+                    continue;
+                };
+                let chunk_id = stable_chunk_id(&self.sanitize_title(title), url, chunk_content);
+                let mut metadata = HashMap::new();
+                metadata.insert("source_type".to_string(), "wiki".to_string());
+                metadata.insert("chunk_index".to_string(), chunk_index.to_string());
+                metadata.insert("scraped_at".to_string(), scraped_at.to_string());
+                metadata.insert(TOKEN_COUNT_METADATA_KEY.to_string(), estimate_tokens(chunk_content).to_string());
+                if !categories.is_empty() {
+                    metadata.insert("categories".to_string(), categories.join(&METADATA_LIST_SEPARATOR.to_string()));
+                }
+                if let Some(section) = section {
+                    metadata.insert("section".to_string(), section.clone());
+                }
+                
+                match embedding {
                     Ok(embedding) => {
-                        let mut metadata = HashMap::new();
-                        metadata.insert("source_type".to_string(), "wiki".to_string());
-                        metadata.insert("chunk_index".to_string(), chunk_index.to_string());
-                        
-                        let chunk = TextChunk {
+                        batch_chunks.push(TextChunk {
                             id: chunk_id,
                             content: chunk_content.clone(),
                             source_url: url.to_string(),
                             source_title: title.to_string(),
                             embedding: Some(embedding),
                             metadata,
-                        };
-                        
-                        batch_chunks.push(chunk);
+                        });
                         processed += 1;
                     }
                     Err(e) => {
+                        // Recorded so `retry_failed_embeddings` can add it once the model works again
                         warn!("Failed to create embedding for chunk {}: {}", chunk_index, e);
+                        failures.push(FailedEmbedding {
+                            id: chunk_id,
+                            content: chunk_content.clone(),
+                            source_url: url.to_string(),
+                            source_title: title.to_string(),
+                            metadata,
+                            error: e.to_string(),
+                        });
                     }
                 }
             }
             
-            // Add batch to chunks
-            self.chunks.extend(batch_chunks);
+            page_chunks.extend(batch_chunks);
             
             info!("Processed {}/{} chunks for page: {}", processed, total_chunks, title);
             
@@ -122,6 +1015,15 @@ impl EmbeddingService {
             }
         }
         
+        // Chunks of an earlier version of the page are only dropped once every new chunk
+        // embedded, so a failing embedding model doesn't wipe the page
+        let replace_previous = failures.is_empty();
+        let keep_ids: HashSet<String> = page_chunks.iter().map(|chunk| chunk.id.clone()).collect();
+        if replace_previous {
+            self.chunks.retain(|chunk| chunk.source_url != url);
+        }
+        self.chunks.extend(page_chunks);
+        
         // Save all processed chunks to the database
         if !self.chunks.is_empty() {
             info!("Saving {} chunks to vector database", self.chunks.len());
@@ -137,7 +1039,7 @@ impl EmbeddingService {
                             source_url: chunk.source_url.clone(),
                             source_title: chunk.source_title.clone(),
                             embedding: embedding.clone(),
-                            metadata: serde_json::to_string(&chunk.metadata).unwrap_or_default(),
+                            metadata: chunk.metadata.clone(),
                         })
                     } else {
                         None
@@ -147,24 +1049,187 @@ impl EmbeddingService {
             
             // Save to database
             let db = self.vector_db.lock().await;
-            if let Err(e) = db.insert_documents(documents).await {
-                error!("Failed to save chunks to database: {}", e);
-            } else {
-                info!("Successfully saved chunks to database");
+            match db.insert_documents(documents).await {
+                Ok(summary) if summary.failed.is_empty() => info!("Successfully saved chunks to database"),
+                Ok(summary) => {
+                    for (id, reason) in &summary.failed {
+                        warn!("Chunk {} from {} was not saved: {}", id, title, reason);
+                    }
+                    warn!("Saved {} chunks to database, {} failed", summary.inserted, summary.failed.len());
+                }
+                Err(e) => error!("Failed to save chunks to database: {}", e),
             }
         }
         
-        info!("Created {} embeddings from {} chunks for page: {}", processed, total_chunks, title);
+        let failed = failures.len();
+        if let Err(e) = self.vector_db.lock().await.replace_failed_embeddings(url, &failures) {
+            warn!("Failed to record the {} chunks of {} that couldn't be embedded: {}", failed, title, e);
+        }
+        
+        if replace_previous {
+            let db = self.vector_db.lock().await;
+            match db.delete_by_source_except(url, &keep_ids).await {
+                Ok(0) => {}
+                Ok(removed) => info!("Removed {} outdated chunks of page: {}", removed, title),
+                Err(e) => warn!("Failed to remove outdated chunks of {}: {}", title, e),
+            }
+        }
+        
+        info!("Created {} embeddings from {} chunks for page: {} ({} failed)", processed, total_chunks, title, failed);
         Ok(())
     }
     
+    /// Embeds the chunks a wiki update couldn't embed again and stores the ones that work now.
+    /// Chunks that still fail stay recorded, with their latest error, for the next retry.
+    pub async fn retry_failed_embeddings(&mut self) -> AppResult<EmbeddingRetryReport> {
+        let failures = self.vector_db.lock().await.failed_embeddings()?;
+        let retried = failures.len();
+        if failures.is_empty() {
+            return Ok(EmbeddingRetryReport { retried: 0, recovered: 0, still_failing: 0 });
+        }
+        info!("Retrying {} chunks that failed to embed", retried);
+        
+        let service = &*self;
+        let embeddings = futures::future::join_all(failures.iter().map(|failure| service.embed_document(&failure.content))).await;
+        let mut recovered = Vec::new();
+        let mut still_failing: Vec<FailedEmbedding> = Vec::new();
+        for (mut failure, embedding) in failures.into_iter().zip(embeddings) {
+            match embedding {
+                Ok(embedding) => recovered.push(TextChunk {
+                    id: failure.id,
+                    content: failure.content,
+                    source_url: failure.source_url,
+                    source_title: failure.source_title,
+                    embedding: Some(embedding),
+                    metadata: failure.metadata,
+                }),
+                Err(e) => {
+                    failure.error = e.to_string();
+                    still_failing.push(failure);
+                }
+            }
+        }
+        
+        let db = self.vector_db.lock().await;
+        let documents: Vec<VectorDocument> = recovered.iter()
+            .filter_map(|chunk| Some(VectorDocument {
+                id: chunk.id.clone(),
+                content: chunk.content.clone(),
+                source_url: chunk.source_url.clone(),
+                source_title: chunk.source_title.clone(),
+                embedding: chunk.embedding.clone()?,
+                metadata: chunk.metadata.clone(),
+            }))
+            .collect();
+        let summary = db.insert_documents(documents).await?;
+        // A chunk the store rejected isn't recovered either
+        for (id, reason) in summary.failed {
+            if let Some(position) = recovered.iter().position(|chunk| chunk.id == id) {
+                let chunk = recovered.remove(position);
+                still_failing.push(FailedEmbedding {
+                    id: chunk.id,
+                    content: chunk.content,
+                    source_url: chunk.source_url,
+                    source_title: chunk.source_title,
+                    metadata: chunk.metadata,
+                    error: reason,
+                });
+            }
+        }
+        db.flush().await?;
+        
+        let sources: HashSet<&str> = recovered.iter().map(|chunk| chunk.source_url.as_str())
+            .chain(still_failing.iter().map(|failure| failure.source_url.as_str()))
+            .collect();
+        let mut current_ids: HashMap<String, HashSet<String>> = HashMap::new();
+        for source_url in sources {
+            let remaining: Vec<FailedEmbedding> = still_failing.iter()
+                .filter(|failure| failure.source_url == source_url)
+                .cloned()
+                .collect();
+            db.replace_failed_embeddings(source_url, &remaining)?;
+            
+            // With the whole page embedded at last, chunks of its earlier version can go, as they
+            // would have if nothing had failed. The current version's chunks share its scrape time.
+            let scraped_at = recovered.iter()
+                .find(|chunk| chunk.source_url == source_url)
+                .and_then(|chunk| chunk.metadata.get("scraped_at"));
+            if let Some(scraped_at) = scraped_at.filter(|_| remaining.is_empty()) {
+                let keep_ids: HashSet<String> = db.documents_for_source(source_url).await?.into_iter()
+                    .filter(|doc| doc.metadata.get("scraped_at") == Some(scraped_at))
+                    .map(|doc| doc.id)
+                    .collect();
+                match db.delete_by_source_except(source_url, &keep_ids).await {
+                    Ok(0) => {}
+                    Ok(removed) => info!("Removed {} outdated chunks of {}", removed, source_url),
+                    Err(e) => warn!("Failed to remove outdated chunks of {}: {}", source_url, e),
+                }
+                current_ids.insert(source_url.to_string(), keep_ids);
+            }
+        }
+        drop(db);
+        self.chunks.retain(|chunk| current_ids.get(&chunk.source_url).is_none_or(|keep_ids| keep_ids.contains(&chunk.id)));
+        
+        info!("Recovered {} of {} chunks that failed to embed", recovered.len(), retried);
+        let report = EmbeddingRetryReport {
+            retried,
+            recovered: recovered.len(),
+            still_failing: still_failing.len(),
+        };
+        self.chunks.extend(recovered);
+        Ok(report)
+    }
+    
     pub async fn embed_text(&self, text: &str) -> AppResult<Vec<f32>> {
-        self.create_embedding(text).await
+        self.create_embedding(text, EmbeddingKind::Query).await
+    }
+    
+    /// `embed_text` with the details needed to use the vector outside the app, including whether
+    /// it is a mock embedding
+    pub async fn embed(&self, text: &str) -> AppResult<TextEmbedding> {
+        let (embedding, mock) = self.create_embedding_with_source(text, EmbeddingKind::Query).await?;
+        if mock {
+            warn!("Returning a mock embedding; Ollama couldn't embed the text with {}", self.config.model_name);
+        }
+        Ok(TextEmbedding {
+            model_name: self.config.model_name.clone(),
+            dimension: embedding.len(),
+            expected_dimension: self.embedding_dimension.or_else(|| known_embedding_dimension(&self.config.model_name)),
+            mock,
+            embedding,
+        })
     }
     
     pub async fn search_similar(&self, query: &str, limit: usize) -> AppResult<Vec<SimilarityResult>> {
-        let query_embedding = self.create_embedding(query).await?;
+        let query_embedding = self.create_embedding(query, EmbeddingKind::Query).await?;
+        self.search_by_embedding(query_embedding, limit).await
+    }
+    
+    /// `search_similar` restricted to chunks from `sources` (page URLs or titles). An empty
+    /// `sources` searches everything.
+    pub async fn search_similar_filtered(&self, query: &str, limit: usize, sources: &[String]) -> AppResult<Vec<SimilarityResult>> {
+        let query_embedding = self.create_embedding(query, EmbeddingKind::Query).await?;
+        self.search_by_embedding_filtered(query_embedding, limit, sources).await
+    }
+    
+    /// Retrieval half of `search_similar_filtered`, for callers that already have the query embedding
+    pub async fn search_by_embedding_filtered(&self, query_embedding: Vec<f32>, limit: usize, sources: &[String]) -> AppResult<Vec<SimilarityResult>> {
+        if sources.is_empty() {
+            return self.search_by_embedding(query_embedding, limit).await;
+        }
         
+        let db = self.vector_db.lock().await;
+        Ok(db.search_similar_filtered(query_embedding, limit, sources).await?
+            .into_iter()
+            .map(|(doc, score)| SimilarityResult {
+                chunk: chunk_from_document(doc),
+                similarity_score: score,
+            })
+            .collect())
+    }
+    
+    /// Retrieval half of `search_similar`, for callers that already have the query embedding
+    pub async fn search_by_embedding(&self, query_embedding: Vec<f32>, limit: usize) -> AppResult<Vec<SimilarityResult>> {
         // Search in vector database
         let db = self.vector_db.lock().await;
         let db_results = db.search_similar(query_embedding.clone(), limit).await?;
@@ -172,17 +1237,8 @@ impl EmbeddingService {
         // Convert database results to SimilarityResult
         let mut results = Vec::new();
         for (doc, score) in db_results {
-            let chunk = TextChunk {
-                id: doc.id,
-                content: doc.content,
-                source_url: doc.source_url,
-                source_title: doc.source_title,
-                embedding: None, // Don't need to return embeddings
-                metadata: serde_json::from_str(&doc.metadata).unwrap_or_default(),
-            };
-            
             results.push(SimilarityResult {
-                chunk,
+                chunk: chunk_from_document(doc),
                 similarity_score: score,
             });
         }
@@ -190,80 +1246,198 @@ impl EmbeddingService {
         // If no results from database, fall back to in-memory search
         if results.is_empty() && !self.chunks.is_empty() {
             warn!("No results from database, falling back to in-memory search");
-            let mut memory_results: Vec<SimilarityResult> = self.chunks
-                .iter()
-                .filter_map(|chunk| {
-                    if let Some(ref embedding) = chunk.embedding {
-                        let similarity = self.cosine_similarity(&query_embedding, embedding);
-                        Some(SimilarityResult {
-                            chunk: chunk.clone(),
-                            similarity_score: similarity,
-                        })
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            
-            // Sort by similarity score (highest first)
-            memory_results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
-            
-            // Return top results
-            memory_results.truncate(limit);
-            return Ok(memory_results);
+            return Ok(self.search_memory_chunks(&query_embedding, limit));
         }
         
         Ok(results)
     }
     
-    async fn create_embedding(&self, text: &str) -> AppResult<Vec<f32>> {
+    /// Scores the in-memory chunks against `query_embedding`. Large sets are split across
+    /// `memory_search_threads` threads; the slices are scored in order and the sort is stable,
+    /// so ties come out the same as with a serial scan.
+    fn search_memory_chunks(&self, query_embedding: &[f32], limit: usize) -> Vec<SimilarityResult> {
+        // Only scores are collected; the few chunks returned are cloned at the end
+        let score = |offset: usize, chunks: &[TextChunk]| -> Vec<(usize, f32)> {
+            chunks.iter().enumerate()
+                .filter_map(|(i, chunk)| {
+                    let embedding = chunk.embedding.as_ref()?;
+                    Some((offset + i, self.cosine_similarity(query_embedding, embedding)))
+                })
+                .collect()
+        };
+        
+        let threads = memory_search_thread_count(self.config.memory_search_threads);
+        let mut scores = if threads > 1 && self.chunks.len() >= PARALLEL_SEARCH_MIN_CHUNKS {
+            let slice_len = self.chunks.len().div_ceil(threads);
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = self.chunks.chunks(slice_len).enumerate()
+                    .map(|(slice, chunks)| scope.spawn(move || score(slice * slice_len, chunks)))
+                    .collect();
+                handles.into_iter()
+                    .flat_map(|handle| handle.join().expect("In-memory search thread panicked"))
+                    .collect::<Vec<_>>()
+            })
+        } else {
+            score(0, &self.chunks)
+        };
+        
+        // Sort by similarity score (highest first)
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores.into_iter()
+            .take(limit)
+            .map(|(index, similarity_score)| SimilarityResult {
+                chunk: self.chunks[index].clone(),
+                similarity_score,
+            })
+            .collect()
+    }
+    
+    /// Full stored content of one chunk, e.g. to open a cited chunk from its id
+    pub async fn get_chunk(&self, chunk_id: &str) -> AppResult<Option<TextChunk>> {
+        let db = self.vector_db.lock().await;
+        Ok(db.get_document(chunk_id).await?.map(chunk_from_document))
+    }
+    
+    /// Chunks from other pages that are closest to the stored chunk `chunk_id`
+    pub async fn find_related(&self, chunk_id: &str, limit: usize) -> AppResult<Vec<SimilarityResult>> {
+        let db = self.vector_db.lock().await;
+        Ok(db.find_related(chunk_id, limit).await?
+            .into_iter()
+            .map(|(doc, score)| SimilarityResult {
+                chunk: chunk_from_document(doc),
+                similarity_score: score,
+            })
+            .collect())
+    }
+    
+    async fn create_embedding(&self, text: &str, kind: EmbeddingKind) -> AppResult<Vec<f32>> {
+        self.create_embedding_with_source(text, kind).await.map(|(embedding, _)| embedding)
+    }
+    
+    /// Like `embed_text`, but also reports whether the mock fallback produced the embedding.
+    /// `text` gets the configured query or document prefix for `kind`.
+    pub async fn create_embedding_with_source(&self, text: &str, kind: EmbeddingKind) -> AppResult<(Vec<f32>, bool)> {
+        let prefix = match kind {
+            EmbeddingKind::Query => &self.config.query_prefix,
+            EmbeddingKind::Document => &self.config.document_prefix,
+        };
+        let text = format!("{}{}", prefix, text);
+        
         // Try to call Ollama's embedding API first
-        let url = "http://localhost:11434/api/embeddings";
+        match self.request_embedding(&text).await {
+            Ok(embedding) => return Ok((embedding, false)),
+            Err(e) => warn!("{}", e),
+        }
         
-        let payload = serde_json::json!({
-            "model": "nomic-embed-text",
-            "prompt": text
-        });
+        // Fall back to mock embeddings for development
+        info!("Using mock embeddings for development (Ollama not available)");
+        self.create_mock_embedding(&text).map(|embedding| (embedding, true))
+    }
+    
+    /// Embeds a chunk to be stored. Once the model's dimension is known, an embedding of any
+    /// other dimension - the mock fallback's, when Ollama fails mid-update - couldn't be
+    /// searched alongside the rest, so it counts as a failure.
+    async fn embed_document(&self, text: &str) -> AppResult<Vec<f32>> {
+        let mut embedding = self.create_embedding(text, EmbeddingKind::Document).await?;
+        if let Some(dimension) = self.embedding_dimension.filter(|dimension| *dimension != embedding.len()) {
+            return Err(AppError::EmbeddingError(format!(
+                "Got an embedding of dimension {}, but {} produces {}", embedding.len(), self.config.model_name, dimension
+            )));
+        }
+        if self.normalizes_embeddings() {
+            normalize_embedding(&mut embedding);
+        }
+        Ok(embedding)
+    }
+    
+    /// Time allowed to embed `text`: the base timeout plus an allowance that grows with its length
+    fn embedding_timeout(&self, text: &str) -> std::time::Duration {
+        let extra_ms = text.chars().count() as u64 * self.config.timeout_secs_per_1000_chars;
+        std::time::Duration::from_secs(self.config.request_timeout_secs) + std::time::Duration::from_millis(extra_ms)
+    }
+    
+    /// Maps a failed embedding request to a timeout, which is worth retrying, or an embedding error
+    fn embedding_request_error(&self, context: &str, error: reqwest::Error, timeout: std::time::Duration) -> AppError {
+        if error.is_timeout() {
+            AppError::Timeout {
+                message: format!("Ollama embedding request timed out after {:.1}s", timeout.as_secs_f64()),
+                partial_response: None,
+            }
+        } else {
+            AppError::EmbeddingError(format!("{}: {}", context, error))
+        }
+    }
+    
+    /// Embeds `text` with the newer `/api/embed` endpoint, falling back to `/api/embeddings` on
+    /// Ollama versions without it. The endpoint that works is remembered for later requests.
+    async fn request_embedding(&self, text: &str) -> AppResult<Vec<f32>> {
+        let _permit = self.embedding_permits.acquire().await
+            .map_err(|e| AppError::EmbeddingError(format!("Embedding request limiter closed: {}", e)))?;
         
-        match self.client
-            .post(url)
+        let known_endpoint = self.embed_endpoint.get().copied();
+        if known_endpoint != Some(EmbedEndpoint::Legacy) {
+            match self.send_embedding_request(EmbedEndpoint::Embed, text).await? {
+                Some(embedding) => {
+                    let _ = self.embed_endpoint.set(EmbedEndpoint::Embed);
+                    return Ok(embedding);
+                }
+                None if known_endpoint.is_none() => {
+                    info!("Ollama doesn't support /api/embed, using /api/embeddings");
+                }
+                None => return Err(AppError::EmbeddingError("Ollama no longer accepts /api/embed requests".to_string())),
+            }
+        }
+        
+        match self.send_embedding_request(EmbedEndpoint::Legacy, text).await? {
+            Some(embedding) => {
+                let _ = self.embed_endpoint.set(EmbedEndpoint::Legacy);
+                Ok(embedding)
+            }
+            None => Err(AppError::EmbeddingError("Ollama has no embedding endpoint; it may be too old".to_string())),
+        }
+    }
+    
+    /// Sends one embedding request to `endpoint`. `Ok(None)` means the server doesn't have the endpoint.
+    async fn send_embedding_request(&self, endpoint: EmbedEndpoint, text: &str) -> AppResult<Option<Vec<f32>>> {
+        let url = format!("{}{}", self.ollama_base_url, endpoint.path());
+        let payload = endpoint.payload(&self.config.model_name, text);
+        
+        let timeout = self.embedding_timeout(text);
+        let response = self.client
+            .post(&url)
             .json(&payload)
-            .timeout(std::time::Duration::from_secs(30))
+            .timeout(timeout)
             .send()
             .await
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.json::<serde_json::Value>().await {
-                        Ok(result) => {
-                            // Extract embedding from response
-                            if let Some(embedding_array) = result["embedding"].as_array() {
-                                let embedding: Vec<f32> = embedding_array
-                                    .iter()
-                                    .filter_map(|v| v.as_f64().map(|f| f as f32))
-                                    .collect();
-                                
-                                if !embedding.is_empty() {
-                                    return Ok(embedding);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse Ollama embedding response: {}", e);
-                        }
-                    }
-                } else {
-                    warn!("Ollama embedding API returned status: {}", response.status());
-                }
+            .map_err(|e| self.embedding_request_error("Failed to call Ollama embedding API", e, timeout))?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            // A missing model is also a 404, but with a JSON error naming the model
+            let unknown_route = matches!(status.as_u16(), 404 | 405 | 501) && !body.contains("model");
+            if unknown_route {
+                return Ok(None);
             }
-            Err(e) => {
-                warn!("Failed to call Ollama embedding API: {}", e);
+            if status == reqwest::StatusCode::NOT_FOUND && body.contains("not found") {
+                return Err(AppError::ModelNotInstalled(self.config.model_name.clone()));
             }
+            return Err(AppError::EmbeddingError(
+                format!("Ollama embedding API returned status: {}", status)
+            ));
         }
         
-        // Fall back to mock embeddings for development
-        info!("Using mock embeddings for development (Ollama not available)");
-        self.create_mock_embedding(text)
+        let result: serde_json::Value = response.json().await
+            .map_err(|e| self.embedding_request_error("Failed to parse Ollama embedding response", e, timeout))?;
+        
+        let embedding = parse_embedding_response(&result);
+        if embedding.is_empty() {
+            return Err(AppError::EmbeddingError(
+                format!("Ollama returned no embedding for model {}", self.config.model_name)
+            ));
+        }
+        
+        Ok(Some(embedding))
     }
     
     fn create_mock_embedding(&self, text: &str) -> AppResult<Vec<f32>> {
@@ -325,14 +1499,65 @@ impl EmbeddingService {
     }
     
     pub fn split_into_chunks(&self, content: &str) -> Vec<String> {
+        self.split_into_sectioned_chunks(content)
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect()
+    }
+    
+    /// `split_into_chunks` pairing each chunk with the heading of the section its first word is
+    /// in, or `None` for text before the page's first heading. With `separate_code_blocks`, code
+    /// blocks become chunks of their own and keep their line breaks.
+    pub fn split_into_sectioned_chunks(&self, content: &str) -> Vec<(String, Option<String>)> {
+        let headings = section_headings(content);
+        let section_at = |start: usize| {
+            headings.iter()
+                .take_while(|(first_word, _)| *first_word <= start)
+                .last()
+                .map(|(_, heading)| heading.clone())
+        };
+        
+        let chunks = if !self.config.separate_code_blocks || !content.contains(CODE_FENCE) {
+            self.split_prose(content, 0, &section_at)
+        } else {
+            let mut chunks = Vec::new();
+            let mut first_word = 0;
+            for (segment, is_code) in code_block_segments(content) {
+                if is_code {
+                    let section = section_at(first_word);
+                    chunks.extend(self.split_code_block(segment).into_iter().map(|chunk| (chunk, section.clone())));
+                } else {
+                    chunks.extend(self.split_prose(segment.trim(), first_word, &section_at));
+                }
+                first_word += segment.split_whitespace().count();
+            }
+            chunks
+        };
+        
+        let max_chars = self.config.max_chunk_chars.max(1);
+        let oversized = chunks.iter().filter(|(chunk, _)| chunk.chars().count() > max_chars).count();
+        if oversized == 0 {
+            return chunks;
+        }
+        warn!("Force-splitting {} chunks longer than {} characters", oversized, max_chars);
+        chunks.into_iter()
+            .flat_map(|(chunk, section)| {
+                split_oversized_chunk(&chunk, max_chars).into_iter().map(move |piece| (piece, section.clone()))
+            })
+            .collect()
+    }
+    
+    /// Overlapping windows of `chunk_size` words. `first_word` is where `text` starts in the page,
+    /// for looking up sections.
+    fn split_prose(&self, text: &str, first_word: usize, section_at: &dyn Fn(usize) -> Option<String>) -> Vec<(String, Option<String>)> {
         let chunk_size = self.config.chunk_size;
         let overlap = self.config.chunk_overlap;
         
-        let words: Vec<&str> = content.split_whitespace().collect();
+        let words: Vec<&str> = text.split_whitespace().collect();
         let mut chunks = Vec::new();
         
         if words.len() <= chunk_size {
-            chunks.push(content.to_string());
+            chunks.push((text.to_string(), section_at(first_word)));
             return chunks;
         }
         
@@ -342,7 +1567,7 @@ impl EmbeddingService {
             let chunk = words[start..end].join(" ");
             
             if !chunk.trim().is_empty() {
-                chunks.push(chunk);
+                chunks.push((chunk, section_at(first_word + start)));
             }
             
             if end >= words.len() {
@@ -355,6 +1580,119 @@ impl EmbeddingService {
         chunks
     }
     
+    /// A fenced code block as a single chunk, or if it's over `chunk_size` words, as consecutive
+    /// runs of whole lines, each fenced again. A line is never split, however long.
+    fn split_code_block(&self, block: &str) -> Vec<String> {
+        let block = block.trim();
+        if block.split_whitespace().count() <= self.config.chunk_size {
+            return vec![block.to_string()];
+        }
+        
+        let mut lines = block.lines();
+        let opening_fence = lines.next().unwrap_or(CODE_FENCE);
+        let code_lines: Vec<&str> = lines.filter(|line| line.trim() != CODE_FENCE).collect();
+        
+        let mut chunks = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        let mut current_words = 0;
+        for line in code_lines {
+            let line_words = line.split_whitespace().count();
+            if !current.is_empty() && current_words + line_words > self.config.chunk_size {
+                chunks.push(format!("{}\n{}\n{}", opening_fence, current.join("\n"), CODE_FENCE));
+                current.clear();
+                current_words = 0;
+            }
+            current.push(line);
+            current_words += line_words;
+        }
+        if !current.is_empty() {
+            chunks.push(format!("{}\n{}\n{}", opening_fence, current.join("\n"), CODE_FENCE));
+        }
+        chunks
+    }
+    
+    /// Searches the knowledge base and returns each hit with the query terms highlighted
+    pub async fn search_knowledge(&self, query: &str, limit: usize) -> AppResult<Vec<KnowledgeSearchResult>> {
+        let results = self.search_similar(query, limit).await?;
+        Ok(to_search_results(query, results))
+    }
+    
+    /// Like `search_knowledge`, but only returns chunks from pages in `category`
+    pub async fn search_by_category(&self, query: &str, category: &str, limit: usize) -> AppResult<Vec<KnowledgeSearchResult>> {
+        let query_embedding = self.create_embedding(query, EmbeddingKind::Query).await?;
+        let category = normalize_category(category);
+        
+        let db = self.vector_db.lock().await;
+        let results = db.search_similar_where(query_embedding, limit, "categories", &category).await?
+            .into_iter()
+            .map(|(doc, score)| SimilarityResult {
+                chunk: chunk_from_document(doc),
+                similarity_score: score,
+            })
+            .collect();
+        Ok(to_search_results(query, results))
+    }
+    
+    /// Searches for `topic` and summarizes how well the results cover it, so users know
+    /// whether to trust answers about it
+    pub async fn assess_coverage(&self, topic: &str, limit: usize) -> AppResult<CoverageReport> {
+        let top_sources = self.search_knowledge(topic, limit).await?;
+        
+        let best_score = top_sources.iter().map(|result| result.similarity_score).fold(0.0, f32::max);
+        let mean_score = if top_sources.is_empty() {
+            0.0
+        } else {
+            top_sources.iter().map(|result| result.similarity_score).sum::<f32>() / top_sources.len() as f32
+        };
+        
+        Ok(CoverageReport {
+            topic: topic.to_string(),
+            level: coverage_level(best_score, mean_score),
+            best_score,
+            mean_score,
+            top_sources,
+        })
+    }
+    
+    /// Diagnostic for surprising retrieval results: runs the same search chat uses and reports
+    /// how each top chunk relates to the query
+    pub async fn explain_retrieval(&self, query: &str, limit: usize) -> AppResult<Vec<RetrievalExplanation>> {
+        let results = self.search_similar(query, limit).await?;
+        
+        Ok(results.into_iter()
+            .enumerate()
+            .map(|(index, result)| {
+                let (matched_terms, missing_terms) = term_overlap(query, &result.chunk.content);
+                let total_terms = matched_terms.len() + missing_terms.len();
+                RetrievalExplanation {
+                    rank: index + 1,
+                    term_overlap: if total_terms == 0 { 0.0 } else { matched_terms.len() as f32 / total_terms as f32 },
+                    matched_terms,
+                    missing_terms,
+                    chunk_id: result.chunk.id,
+                    source_title: result.chunk.source_title,
+                    source_url: result.chunk.source_url,
+                    similarity_score: result.similarity_score,
+                    content: result.chunk.content,
+                }
+            })
+            .collect())
+    }
+    
+    /// Runs the chunker over `content` using the current chunk settings and reports the result.
+    /// Doesn't touch Ollama or the vector database.
+    pub fn preview_chunks(&self, content: &str) -> Vec<ChunkPreview> {
+        self.split_into_chunks(content)
+            .into_iter()
+            .enumerate()
+            .map(|(index, content)| ChunkPreview {
+                index,
+                word_count: content.split_whitespace().count(),
+                content,
+            })
+            .collect()
+    }
+    
     fn sanitize_title(&self, title: &str) -> String {
         title.chars()
             .filter(|c| c.is_alphanumeric() || c.is_whitespace())
@@ -367,9 +1705,65 @@ impl EmbeddingService {
         self.chunks.len()
     }
     
+    /// Deletes every chunk of `source_url`, from the knowledge base and the in-memory store
+    pub async fn remove_source(&mut self, source_url: &str) -> AppResult<()> {
+        self.chunks.retain(|chunk| chunk.source_url != source_url);
+        let db = self.vector_db.lock().await;
+        db.delete_by_source(source_url).await
+    }
+    
+    /// `remove_source` for many pages at once, returning how many chunks each had stored
+    pub async fn remove_sources(&mut self, source_urls: &[String]) -> AppResult<HashMap<String, usize>> {
+        let urls: HashSet<&str> = source_urls.iter().map(String::as_str).collect();
+        self.chunks.retain(|chunk| !urls.contains(chunk.source_url.as_str()));
+        let db = self.vector_db.lock().await;
+        db.delete_sources(source_urls).await
+    }
+    
+    /// Deletes the chunks of every page `criteria` doesn't keep. `citations` counts how often
+    /// each page title was cited in chat, for `PruneCriteria::MostCited`.
+    pub async fn prune_knowledge_base(&mut self, criteria: &PruneCriteria, citations: &HashMap<String, u64>) -> AppResult<PruneReport> {
+        let sources = {
+            let db = self.vector_db.lock().await;
+            db.stored_sources().await?
+        };
+        let keep = sources_to_keep(&sources, criteria, citations)?;
+        let (kept, removed): (Vec<StoredSource>, Vec<StoredSource>) = sources.into_iter()
+            .partition(|source| keep.contains(&source.source_url));
+        
+        if !removed.is_empty() {
+            let urls: Vec<String> = removed.iter().map(|source| source.source_url.clone()).collect();
+            self.remove_sources(&urls).await?;
+        }
+        let chunks_removed = removed.iter().map(|source| source.chunk_count).sum();
+        info!("Pruned {} pages ({} chunks), kept {}", removed.len(), chunks_removed, kept.len());
+        
+        Ok(PruneReport {
+            sources_kept: kept.len(),
+            chunks_removed,
+            removed,
+        })
+    }
+    
+    /// Pages in the knowledge base that aren't in `known_sources`
+    pub async fn orphaned_sources(&self, known_sources: &HashSet<String>) -> AppResult<Vec<OrphanedSource>> {
+        let db = self.vector_db.lock().await;
+        db.sources_not_in(known_sources).await
+    }
+    
     pub fn get_chunks_for_source(&self, source_url: &str) -> Vec<&TextChunk> {
         self.chunks.iter().filter(|chunk| chunk.source_url == source_url).collect()
     }
+    
+    /// The stored chunks of `source_url` in page order, falling back to the in-memory store
+    pub async fn chunks_for_source(&self, source_url: &str) -> AppResult<Vec<TextChunk>> {
+        let db = self.vector_db.lock().await;
+        let documents = db.documents_for_source(source_url).await?;
+        if documents.is_empty() {
+            return Ok(self.get_chunks_for_source(source_url).into_iter().cloned().collect());
+        }
+        Ok(documents.into_iter().map(chunk_from_document).collect())
+    }
 }
 
 #[cfg(test)]
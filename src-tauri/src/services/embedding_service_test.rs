@@ -2,17 +2,27 @@
 mod tests {
     use super::*;
     use crate::config::EmbeddingConfig;
-    use crate::services::embedding_service::EmbeddingService;
+    use crate::services::embedding_service::{
+        coverage_level, known_embedding_dimension, memory_search_thread_count, normalize_category, section_headings, stable_chunk_id, CoverageLevel, EmbeddingProgressTracker, EmbeddingService,
+        estimate_tokens, parse_embedding_response, EmbeddingKind, SimilarityResult, TextChunk, DEFAULT_ESTIMATED_WIKI_PAGES, PARALLEL_SEARCH_MIN_CHUNKS,
+        TOKEN_COUNT_METADATA_KEY,
+    };
+    use crate::services::vector_database::{VectorDatabase, VectorDocument};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
     use mockito::{Server, ServerGuard, Matcher};
     use serde_json::json;
 
     async fn create_test_service() -> (EmbeddingService, ServerGuard) {
-        let mut server = Server::new();
+        let server = Server::new_async().await;
         let mut service = EmbeddingService::new().await;
         
-        // Override the config to use mockito server
-        let url = server.url();
-        let parts: Vec<&str> = url.trim_start_matches("http://").split(':').collect();
+        // Override the endpoint to use mockito server
+        service.ollama_base_url = server.url();
         
         (service, server)
     }
@@ -52,4 +62,1081 @@ mod tests {
         assert_eq!(service.sanitize_title("Test@#$123"), "test123");
         assert_eq!(service.sanitize_title("Multiple   Spaces"), "multiple___spaces");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_embedding_request_uses_configured_model() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_model("mxbai-embed-large".to_string());
+        
+        let mock = server.mock("POST", "/api/embeddings")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "mxbai-embed-large",
+                "prompt": "How do I make a pickaxe?"
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding":[0.1,0.2,0.3]}"#)
+            .create_async()
+            .await;
+        
+        let embedding = service.embed_text("How do I make a pickaxe?").await.unwrap();
+        
+        mock.assert_async().await;
+        assert_eq!(embedding, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[tokio::test]
+    async fn test_probe_dimension_records_dimension() {
+        let (mut service, mut server) = create_test_service().await;
+        
+        let _mock = server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding":[0.1,0.2,0.3,0.4]}"#)
+            .create_async()
+            .await;
+        
+        assert_eq!(service.probe_dimension().await.unwrap(), 4);
+        assert_eq!(service.get_embedding_dimension(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_preview_chunks_reports_index_and_word_count() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.chunk_size = 10;
+        service.config.chunk_overlap = 2;
+        
+        let content = (1..=25).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+        let previews = service.preview_chunks(&content);
+        
+        assert_eq!(previews.len(), 3);
+        for (i, preview) in previews.iter().enumerate() {
+            assert_eq!(preview.index, i);
+            assert_eq!(preview.word_count, preview.content.split_whitespace().count());
+        }
+        assert_eq!(previews[0].word_count, 10);
+        assert!(previews[1].content.starts_with("word9 word10"));
+        assert_eq!(previews[2].word_count, 9);
+    }
+
+    #[tokio::test]
+    async fn test_preview_chunks_short_text_is_single_chunk() {
+        let (service, _server) = create_test_service().await;
+        
+        let previews = service.preview_chunks("A short note about clay.");
+        
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].index, 0);
+        assert_eq!(previews[0].word_count, 5);
+    }
+
+    #[tokio::test]
+    async fn test_set_ollama_endpoint_redirects_embedding_requests() {
+        let (mut service, _old_server) = create_test_service().await;
+        let mut server = Server::new_async().await;
+        
+        let mock = server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding":[0.5,0.5]}"#)
+            .create_async()
+            .await;
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        service.set_ollama_endpoint(&crate::config::OllamaConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            ..crate::config::OllamaConfig::default()
+        });
+        
+        assert_eq!(service.embed_text("Where do I find copper?").await.unwrap(), vec![0.5, 0.5]);
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_progress_estimate_follows_discovered_pages() {
+        let tracker = EmbeddingProgressTracker::new();
+        tracker.start(2);
+        assert_eq!(tracker.snapshot().chunks_estimated, 0);
+        
+        // First page has 10 chunks, so the other discovered page is assumed to have 10 too
+        tracker.page_started(10);
+        for _ in 0..5 {
+            tracker.chunk_processed();
+        }
+        let progress = tracker.snapshot();
+        assert_eq!(progress.chunks_estimated, 20);
+        assert_eq!(progress.fraction, 0.25);
+        
+        // The crawl found two more pages; a skipped page no longer counts toward the total
+        tracker.pages_discovered(2);
+        tracker.page_skipped();
+        assert_eq!(tracker.snapshot().chunks_estimated, 30);
+        
+        tracker.finish();
+        let progress = tracker.snapshot();
+        assert!(!progress.is_running);
+        assert_eq!(progress.fraction, 1.0);
+    }
+
+    #[test]
+    fn test_progress_listener_is_notified() {
+        let tracker = EmbeddingProgressTracker::new();
+        let updates = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        tracker.set_listener(move |progress| recorded.lock().unwrap().push(progress.chunks_processed));
+        
+        tracker.start(1);
+        tracker.page_started(2);
+        tracker.chunk_processed();
+        tracker.chunk_processed();
+        
+        assert_eq!(*updates.lock().unwrap(), vec![0, 0, 1, 2]);
+    }
+
+    /// Fake embedding endpoint that records the most requests it was handling at once
+    async fn start_counting_server(in_flight: Arc<AtomicUsize>, peak: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    // Read the headers and the JSON body before answering
+                    let mut request = Vec::new();
+                    let mut buffer = [0u8; 4096];
+                    loop {
+                        let read = socket.read(&mut buffer).await.unwrap_or(0);
+                        request.extend_from_slice(&buffer[..read]);
+                        let text = String::from_utf8_lossy(&request);
+                        if read == 0 || text.trim_end().ends_with('}') {
+                            break;
+                        }
+                    }
+                    
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    
+                    let body = r#"{"embedding":[0.1,0.2,0.3]}"#;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(), body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_embeddings_respect_limit() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        
+        let (mut service, _server) = create_test_service().await;
+        service.ollama_base_url = start_counting_server(in_flight.clone(), peak.clone()).await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.config.chunk_size = 20;
+        service.config.chunk_overlap = 0;
+        service.set_max_concurrent_embeddings(2);
+        
+        // 8 chunks of 20 words, all in one batch
+        let content = "Clay can be shaped into pots and molds on a forming surface. ".repeat(14);
+        service.process_wiki_page("Clay forming", "https://wiki.vintagestory.at/Clay_forming", &content, &[]).await.unwrap();
+        
+        assert_eq!(service.get_chunk_count(), 8);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    /// Service whose knowledge base holds one 3-dimensional document and whose Ollama
+    /// answers embedding requests with `dimension`-sized vectors
+    async fn create_service_with_stored_embeddings(dimension: usize) -> (EmbeddingService, ServerGuard) {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.vector_db.lock().await.insert_documents(vec![VectorDocument {
+            id: "anvil_0".to_string(),
+            content: "Anvils are used for smithing.".to_string(),
+            source_url: "test://anvil".to_string(),
+            source_title: "Anvil".to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            metadata: HashMap::new(),
+        }]).await.unwrap();
+        
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": vec![0.5; dimension] }).to_string())
+            .create_async()
+            .await;
+        (service, server)
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_same_dimension_model_is_allowed() {
+        let (mut service, _server) = create_service_with_stored_embeddings(3).await;
+        
+        let (dimension, cleared) = service.switch_model("all-minilm".to_string(), false).await.unwrap();
+        
+        assert_eq!(dimension, 3);
+        assert!(!cleared);
+        let info = service.get_model_info().await.unwrap();
+        assert_eq!(info.model_name, "all-minilm");
+        assert_eq!(info.chunk_count, 1);
+        assert!(!info.reindex_required);
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_different_dimension_requires_force() {
+        let (mut service, _server) = create_service_with_stored_embeddings(1024).await;
+        let previous_model = service.get_model_name().to_string();
+        
+        let err = service.switch_model("mxbai-embed-large".to_string(), false).await.unwrap_err();
+        assert!(err.to_string().contains("force_reindex"));
+        // Refused switches leave the old model and the knowledge base alone
+        assert_eq!(service.get_model_name(), previous_model);
+        assert_eq!(service.get_model_info().await.unwrap().chunk_count, 1);
+        
+        let (dimension, cleared) = service.switch_model("mxbai-embed-large".to_string(), true).await.unwrap();
+        assert_eq!(dimension, 1024);
+        assert!(cleared);
+        let info = service.get_model_info().await.unwrap();
+        assert_eq!(info.model_name, "mxbai-embed-large");
+        assert_eq!(info.chunk_count, 0);
+    }
+
+    #[test]
+    fn test_coverage_level_thresholds() {
+        assert_eq!(coverage_level(0.85, 0.65), CoverageLevel::Good);
+        // One strong match surrounded by weak ones is only partial
+        assert_eq!(coverage_level(0.85, 0.3), CoverageLevel::Partial);
+        assert_eq!(coverage_level(0.55, 0.5), CoverageLevel::Partial);
+        assert_eq!(coverage_level(0.3, 0.2), CoverageLevel::None);
+    }
+
+    #[tokio::test]
+    async fn test_assess_coverage_summarizes_matches() {
+        let (service, _server) = create_service_with_stored_embeddings(3).await;
+        
+        let report = service.assess_coverage("smithing", 5).await.unwrap();
+        
+        assert_eq!(report.topic, "smithing");
+        assert_eq!(report.top_sources.len(), 1);
+        assert_eq!(report.top_sources[0].source_title, "Anvil");
+        assert!((report.best_score - report.mean_score).abs() < 1e-6);
+        assert!(report.best_score > 0.9);
+        assert_eq!(report.level, CoverageLevel::Good);
+    }
+
+    #[tokio::test]
+    async fn test_assess_coverage_of_empty_knowledge_base() {
+        let (mut service, _server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        
+        let report = service.assess_coverage("mechanical power", 5).await.unwrap();
+        
+        assert!(report.top_sources.is_empty());
+        assert_eq!(report.best_score, 0.0);
+        assert_eq!(report.level, CoverageLevel::None);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_timeout_is_configurable() {
+        let (mut service, mut server) = create_test_service().await;
+        service.config.request_timeout_secs = 1;
+        service.config.timeout_secs_per_1000_chars = 0;
+        
+        let _mock = server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_chunked_body(|writer| {
+                std::thread::sleep(std::time::Duration::from_secs(3));
+                writer.write_all(json!({ "embedding": [0.1, 0.2, 0.3] }).to_string().as_bytes())
+            })
+            .create_async()
+            .await;
+        
+        let started = std::time::Instant::now();
+        let result = service.request_embedding("Slow chunk").await;
+        
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        assert!(matches!(result, Err(crate::errors::AppError::Timeout { partial_response: None, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_embedding_timeout_scales_with_text_length() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.request_timeout_secs = 30;
+        service.config.timeout_secs_per_1000_chars = 10;
+        
+        assert_eq!(service.embedding_timeout(""), std::time::Duration::from_secs(30));
+        assert_eq!(service.embedding_timeout(&"a".repeat(1500)), std::time::Duration::from_secs(45));
+    }
+
+    #[test]
+    fn test_normalize_category() {
+        assert_eq!(normalize_category("Tools"), "Tools");
+        assert_eq!(normalize_category(" tools "), "Tools");
+        assert_eq!(normalize_category("Category:Mechanical_power"), "Mechanical power");
+        assert_eq!(normalize_category(""), "");
+    }
+
+    #[tokio::test]
+    async fn test_search_by_category_only_returns_that_category() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .create_async()
+            .await;
+        
+        let page = |subject: &str| format!("The {} is an essential piece of equipment for every settler in Vintage Story.", subject);
+        service.process_wiki_page("Pickaxe", "test://pickaxe", &page("pickaxe"), &["Tools".to_string()]).await.unwrap();
+        service.process_wiki_page("Windmill", "test://windmill", &page("windmill"), &["Mechanics".to_string()]).await.unwrap();
+        service.process_wiki_page("Saw", "test://saw", &page("saw"), &["Tools".to_string(), "Mechanics".to_string()]).await.unwrap();
+        
+        let tools = service.search_by_category("What equipment do I need?", "tools", 10).await.unwrap();
+        let mut titles: Vec<&str> = tools.iter().map(|result| result.source_title.as_str()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Pickaxe", "Saw"]);
+        
+        let mechanics = service.search_by_category("What equipment do I need?", "Category:Mechanics", 10).await.unwrap();
+        assert_eq!(mechanics.len(), 2);
+        assert!(mechanics.iter().all(|result| result.source_title != "Pickaxe"));
+        
+        assert!(service.search_by_category("What equipment do I need?", "Creatures", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chunks_below_min_chunk_chars_are_not_embedded() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.config.chunk_size = 10;
+        service.config.chunk_overlap = 0;
+        service.config.min_chunk_chars = 30;
+        let mock = server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        
+        // A full 10-word chunk and a two-word tail
+        let content = "Bloomeries smelt iron ore into blooms using charcoal as fuel. Done here.";
+        service.process_wiki_page("Bloomery", "test://bloomery", content, &[]).await.unwrap();
+        
+        assert_eq!(service.get_chunk_count(), 1);
+        assert!(service.get_chunks_for_source("test://bloomery")[0].content.starts_with("Bloomeries"));
+        mock.assert_async().await;
+        
+        // Raising the minimum above the full chunk drops the whole page
+        service.config.min_chunk_chars = 1000;
+        service.process_wiki_page("Anvil", "test://anvil", "Anvils are used for smithing metal tools and plates.", &[]).await.unwrap();
+        assert!(service.get_chunks_for_source("test://anvil").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chunks_record_the_section_they_start_in() {
+        let (mut service, mut server) = create_test_service().await;
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        service.set_vector_database(db.clone());
+        service.config.chunk_size = 10;
+        service.config.chunk_overlap = 0;
+        service.config.min_chunk_chars = 0;
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .create_async()
+            .await;
+        
+        let content = "Iron is a metal found deep underground in ore veins.\n\
+                       ## Smelting\n\
+                       Ore must be heated in a bloomery fed with charcoal.\n\
+                       ### Bloomery\n\
+                       The bloomery is built from fire bricks and needs air.";
+        assert_eq!(section_headings(content), vec![(10, "Smelting".to_string()), (22, "Bloomery".to_string())]);
+        
+        service.process_wiki_page("Iron", "test://iron", content, &[]).await.unwrap();
+        
+        let mut chunks = service.get_chunks_for_source("test://iron");
+        chunks.sort_by_key(|chunk| chunk.metadata["chunk_index"].parse::<usize>().unwrap());
+        let sections: Vec<Option<&str>> = chunks.iter().map(|chunk| chunk.metadata.get("section").map(String::as_str)).collect();
+        // The third chunk starts before the Bloomery heading, so it's still part of Smelting
+        assert_eq!(sections, vec![None, Some("Smelting"), Some("Smelting"), Some("Bloomery")]);
+        
+        let stored = db.lock().await.iter_documents()
+            .find(|doc| doc.content.starts_with("## Smelting"))
+            .unwrap();
+        assert_eq!(stored.metadata.get("section").map(String::as_str), Some("Smelting"));
+    }
+
+    #[tokio::test]
+    async fn test_stable_chunk_id_depends_on_source_and_content() {
+        let id = stable_chunk_id("anvil", "test://anvil", "Anvils are used for smithing.");
+        
+        assert!(id.starts_with("anvil_"));
+        assert_eq!(id, stable_chunk_id("anvil", "test://anvil", "Anvils are used for smithing."));
+        assert_ne!(id, stable_chunk_id("anvil", "test://anvil", "Anvils are used for smelting."));
+        assert_ne!(id, stable_chunk_id("anvil", "test://other", "Anvils are used for smithing."));
+    }
+
+    #[tokio::test]
+    async fn test_reembedding_a_changed_page_leaves_no_orphaned_chunks() {
+        let (mut service, mut server) = create_test_service().await;
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        service.set_vector_database(db.clone());
+        service.config.chunk_size = 10;
+        service.config.chunk_overlap = 0;
+        service.config.min_chunk_chars = 0;
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .create_async()
+            .await;
+        
+        let intro = "Anvils are used to smith metal into tools and plates.";
+        let middle = "A stone anvil is the first one players will craft.";
+        let outro = "Iron anvils need a working bloomery and plenty of charcoal.";
+        let ids = |db: &VectorDatabase| {
+            let mut ids: Vec<String> = db.iter_documents().map(|doc| doc.id).collect();
+            ids.sort();
+            ids
+        };
+        
+        service.process_wiki_page("Anvil", "test://anvil", &format!("{} {} {}", intro, middle, outro), &[]).await.unwrap();
+        let before = ids(&*db.lock().await);
+        assert_eq!(before.len(), 3);
+        
+        // Dropping the middle paragraph shifts the last chunk's index but not its content
+        service.process_wiki_page("Anvil", "test://anvil", &format!("{} {}", intro, outro), &[]).await.unwrap();
+        let after = ids(&*db.lock().await);
+        
+        let mut expected = vec![
+            stable_chunk_id("anvil", "test://anvil", intro),
+            stable_chunk_id("anvil", "test://anvil", outro),
+        ];
+        expected.sort();
+        assert_eq!(after, expected);
+        assert!(after.iter().all(|id| before.contains(id)));
+        assert_eq!(service.get_chunks_for_source("test://anvil").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_and_document_prefixes_are_applied_per_call_site() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.config.query_prefix = "search_query: ".to_string();
+        service.config.document_prefix = "search_document: ".to_string();
+        let content = "Anvils are used to smith metal into tools and plates.";
+        let document_mock = server.mock("POST", "/api/embeddings")
+            .match_body(Matcher::PartialJson(json!({ "prompt": format!("search_document: {}", content) })))
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let query_mock = server.mock("POST", "/api/embeddings")
+            .match_body(Matcher::PartialJson(json!({ "prompt": "search_query: What is an anvil for?" })))
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .expect(2)
+            .create_async()
+            .await;
+        
+        service.process_wiki_page("Anvil", "test://anvil", content, &[]).await.unwrap();
+        let results = service.search_similar("What is an anvil for?", 5).await.unwrap();
+        service.embed_text("What is an anvil for?").await.unwrap();
+        
+        document_mock.assert_async().await;
+        query_mock.assert_async().await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk.content, content);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_storage_uses_stored_pages() {
+        let mut service = EmbeddingService::new().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        
+        // Nothing stored yet: the default page count and chunks per page, and the model's dimension
+        let empty = service.estimate_storage(None, None).await.unwrap();
+        assert_eq!(empty.estimated_pages, DEFAULT_ESTIMATED_WIKI_PAGES);
+        assert_eq!(empty.embedding_dimension, 768);
+        assert_eq!(empty.estimated_chunks, DEFAULT_ESTIMATED_WIKI_PAGES * 12);
+        assert_eq!(empty.estimated_bytes, empty.bytes_per_chunk * empty.estimated_chunks as u64);
+        
+        let documents: Vec<VectorDocument> = ["Anvil", "Bloomery"].iter()
+            .flat_map(|title| (0..3).map(move |i| VectorDocument {
+                id: format!("{}_{}", title, i),
+                content: format!("{} chunk {}", title, i),
+                source_url: format!("https://wiki.vintagestory.at/{}", title),
+                source_title: title.to_string(),
+                embedding: vec![0.1; 768],
+                metadata: HashMap::new(),
+            }))
+            .collect();
+        service.vector_db.lock().await.insert_documents(documents).await.unwrap();
+        
+        let estimate = service.estimate_storage(Some(100), Some(1024)).await.unwrap();
+        assert_eq!(estimate.estimated_pages, 100);
+        assert_eq!(estimate.chunks_per_page, 3.0);
+        assert_eq!(estimate.estimated_chunks, 300);
+        assert_eq!(estimate.embedding_dimension, 1024);
+        assert!(estimate.bytes_per_chunk > empty.bytes_per_chunk);
+    }
+
+    #[test]
+    fn test_parse_embedding_response_accepts_both_shapes() {
+        assert_eq!(parse_embedding_response(&json!({"embeddings": [[0.1, 0.2], [0.3, 0.4]]})), vec![0.1, 0.2]);
+        assert_eq!(parse_embedding_response(&json!({"embedding": [0.5, 0.6]})), vec![0.5, 0.6]);
+        assert!(parse_embedding_response(&json!({"embeddings": []})).is_empty());
+        assert!(parse_embedding_response(&json!({})).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_embed_endpoint_is_used_when_available() {
+        let (service, mut server) = create_test_service().await;
+        let embed_mock = server.mock("POST", "/api/embed")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "nomic-embed-text",
+                "input": "How do I make a pickaxe?"
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2,0.3]]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let legacy_mock = server.mock("POST", "/api/embeddings")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        assert_eq!(service.embed_text("How do I make a pickaxe?").await.unwrap(), vec![0.1, 0.2, 0.3]);
+        assert_eq!(service.embed_text("How do I make a pickaxe?").await.unwrap(), vec![0.1, 0.2, 0.3]);
+        
+        embed_mock.assert_async().await;
+        legacy_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_older_ollama_falls_back_to_embeddings_endpoint() {
+        let (service, mut server) = create_test_service().await;
+        // Older Ollama versions answer unknown routes with Go's plain-text 404
+        let embed_mock = server.mock("POST", "/api/embed")
+            .with_status(404)
+            .with_body("404 page not found")
+            .expect(1)
+            .create_async()
+            .await;
+        let legacy_mock = server.mock("POST", "/api/embeddings")
+            .match_body(Matcher::PartialJson(json!({"prompt": "Where is copper found?"})))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding":[0.4,0.5,0.6]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        
+        assert_eq!(service.embed_text("Where is copper found?").await.unwrap(), vec![0.4, 0.5, 0.6]);
+        // The fallback is remembered, so /api/embed isn't tried again
+        assert_eq!(service.embed_text("Where is copper found?").await.unwrap(), vec![0.4, 0.5, 0.6]);
+        
+        embed_mock.assert_async().await;
+        legacy_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_on_embed_endpoint_is_not_treated_as_old_ollama() {
+        let (service, mut server) = create_test_service().await;
+        let _embed_mock = server.mock("POST", "/api/embed")
+            .with_status(404)
+            .with_body(r#"{"error":"model \"nomic-embed-text\" not found, try pulling it first"}"#)
+            .create_async()
+            .await;
+        let legacy_mock = server.mock("POST", "/api/embeddings")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let (_, used_mock_embedding) = service.create_embedding_with_source("Where is copper found?", EmbeddingKind::Query).await.unwrap();
+        
+        assert!(used_mock_embedding);
+        legacy_mock.assert_async().await;
+    }
+
+    /// The embedding stored for a one-chunk page when Ollama returns `[3, 4, 0]` for it
+    async fn stored_embedding(model_name: &str, normalize_embeddings: Option<bool>) -> Vec<f32> {
+        let (mut service, mut server) = create_test_service().await;
+        let _embed_mock = server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_body(r#"{"embeddings":[[3.0,4.0,0.0]]}"#)
+            .create_async()
+            .await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.config.model_name = model_name.to_string();
+        service.config.normalize_embeddings = normalize_embeddings;
+        
+        let content = "Bloomeries smelt iron ore into blooms that are then worked on an anvil.";
+        service.process_wiki_page("Bloomery", "test://bloomery", content, &[]).await.unwrap();
+        
+        let db = service.vector_db.lock().await;
+        db.documents_for_source("test://bloomery").await.unwrap().remove(0).embedding
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_normalized_per_setting() {
+        assert_eq!(stored_embedding("nomic-embed-text", Some(true)).await, vec![0.6, 0.8, 0.0]);
+        assert_eq!(stored_embedding("nomic-embed-text", Some(false)).await, vec![3.0, 4.0, 0.0]);
+        
+        // Unset, only models not known to return unit-length vectors are normalized
+        assert_eq!(stored_embedding("nomic-embed-text", None).await, vec![0.6, 0.8, 0.0]);
+        assert_eq!(stored_embedding("mxbai-embed-large:latest", None).await, vec![3.0, 4.0, 0.0]);
+    }
+
+    #[tokio::test]
+    async fn test_search_results_say_when_their_page_was_scraped() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .create_async()
+            .await;
+        let content = "Quern stones grind grain into flour and crush ore into crushed ore.";
+        
+        let before = chrono::Utc::now();
+        service.process_wiki_page("Quern", "test://quern", content, &[]).await.unwrap();
+        let results = service.search_knowledge("How do I make flour?", 5).await.unwrap();
+        let scraped_at = chrono::DateTime::parse_from_rfc3339(results[0].scraped_at.as_deref().unwrap()).unwrap();
+        assert!(scraped_at >= before && scraped_at <= chrono::Utc::now());
+        
+        // A page re-parsed from stored HTML keeps the time it was fetched
+        service.process_wiki_page_scraped_at("Quern", "test://quern", content, &[], "2026-07-01T12:00:00+00:00").await.unwrap();
+        let results = service.search_knowledge("How do I make flour?", 5).await.unwrap();
+        assert_eq!(results[0].scraped_at.as_deref(), Some("2026-07-01T12:00:00+00:00"));
+    }
+
+    #[tokio::test]
+    async fn test_chunks_store_their_token_count() {
+        let (mut service, mut server) = create_test_service().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(json!({ "embedding": [0.3, 0.4, 0.5] }).to_string())
+            .create_async()
+            .await;
+        let content = "A bloomery smelts iron ore into blooms, which are then worked on an anvil. \
+                       Fill it with ore and charcoal, light it and wait for the smelting to finish. \
+                       Bronze tools are made by casting molten metal into tool molds. ".repeat(20);
+        
+        service.process_wiki_page("Bloomery", "test://bloomery", &content, &[]).await.unwrap();
+        let chunks = service.chunks_for_source("test://bloomery").await.unwrap();
+        
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            let stored: usize = chunk.metadata[TOKEN_COUNT_METADATA_KEY].parse().unwrap();
+            assert_eq!(stored, estimate_tokens(&chunk.content));
+            assert_eq!(chunk.token_count(), stored);
+            // Tokenizers split English prose into about 1.3 tokens per word
+            let words = chunk.content.split_whitespace().count();
+            assert!(stored >= words && stored <= words * 2, "{} tokens for {} words", stored, words);
+        }
+        
+        // Chunks stored before counts were recorded are estimated on the fly
+        let mut legacy = chunks[0].clone();
+        legacy.metadata.remove(TOKEN_COUNT_METADATA_KEY);
+        assert_eq!(legacy.token_count(), estimate_tokens(&legacy.content));
+    }
+
+    async fn memory_search_service(chunk_count: usize, dimension: usize, threads: usize) -> EmbeddingService {
+        let mut service = EmbeddingService::new().await;
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.config.memory_search_threads = threads;
+        service.chunks = (0..chunk_count).map(|i| TextChunk {
+            id: format!("page{}_{}", i / 10, i % 10),
+            content: format!("Chunk {}", i),
+            source_url: format!("test://page{}", i / 10),
+            source_title: format!("Page {}", i / 10),
+            // Every tenth chunk repeats an earlier embedding, so ties have to be broken consistently
+            embedding: Some((0..dimension).map(|d| (((i % 97) * 31 + d * 7) % 23) as f32 - 11.0).collect()),
+            metadata: HashMap::new(),
+        }).collect();
+        service
+    }
+
+    #[tokio::test]
+    async fn test_parallel_memory_search_matches_serial() {
+        let query: Vec<f32> = (0..16).map(|d| (d % 5) as f32 - 2.0).collect();
+        let serial = memory_search_service(PARALLEL_SEARCH_MIN_CHUNKS * 2, 16, 1).await;
+        let parallel = memory_search_service(PARALLEL_SEARCH_MIN_CHUNKS * 2, 16, 4).await;
+        
+        let expected = serial.search_by_embedding(query.clone(), 50).await.unwrap();
+        let actual = parallel.search_by_embedding(query, 50).await.unwrap();
+        assert_eq!(expected.len(), 50);
+        let ids = |results: &[SimilarityResult]| results.iter()
+            .map(|result| (result.chunk.id.clone(), result.similarity_score))
+            .collect::<Vec<_>>();
+        assert_eq!(ids(&actual), ids(&expected));
+    }
+
+    #[test]
+    fn test_memory_search_threads_are_capped_at_the_core_count() {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        assert_eq!(memory_search_thread_count(0), cores);
+        assert_eq!(memory_search_thread_count(1), 1);
+        assert_eq!(memory_search_thread_count(100_000), cores);
+    }
+
+    /// In-memory fallback search on one thread versus one per core.
+    /// Run with `cargo test memory_search_benchmark -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_memory_search_benchmark() {
+        let query: Vec<f32> = (0..768).map(|d| (d % 5) as f32 - 2.0).collect();
+        for chunk_count in [1_000, 4_000, 20_000, 100_000] {
+            for threads in [1, 0] {
+                let service = memory_search_service(chunk_count, 768, threads).await;
+                let started = std::time::Instant::now();
+                for _ in 0..10 {
+                    service.search_by_embedding(query.clone(), 5).await.unwrap();
+                }
+                println!("{} chunks, {}: {:?} per search", chunk_count,
+                         if threads == 1 { "serial" } else { "parallel" }, started.elapsed() / 10);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warmup_issues_one_embedding_request() {
+        let (service, mut server) = create_test_service().await;
+        let embed_mock = server.mock("POST", "/api/embed")
+            .match_body(Matcher::PartialJson(json!({"model": service.get_model_name()})))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embeddings":[[0.1,0.2,0.3]]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        service.warmup().await.unwrap();
+        
+        embed_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_warmup_reports_missing_embedding_model() {
+        let (service, mut server) = create_test_service().await;
+        let _embed_mock = server.mock("POST", "/api/embed")
+            .with_status(404)
+            .with_body(r#"{"error":"model \"nomic-embed-text\" not found, try pulling it first"}"#)
+            .create_async()
+            .await;
+        
+        // Unlike a search, warmup doesn't fall back to a mock embedding
+        let err = service.warmup().await.unwrap_err();
+        assert_eq!(err.code(), "MODEL_NOT_INSTALLED");
+        assert!(err.to_string().contains(service.get_model_name()));
+    }
+
+    #[tokio::test]
+    async fn test_embed_returns_vector_of_model_dimension() {
+        let (service, mut server) = create_test_service().await;
+        let dimension = known_embedding_dimension(service.get_model_name()).unwrap();
+        let vector: Vec<f32> = (0..dimension).map(|i| i as f32 / dimension as f32).collect();
+        let _embed_mock = server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({"embeddings": [vector]}).to_string())
+            .create_async()
+            .await;
+        
+        let result = service.embed("How do I make a clay pot?").await.unwrap();
+        
+        assert!(!result.mock);
+        assert_eq!(result.model_name, service.get_model_name());
+        assert_eq!(result.embedding.len(), dimension);
+        assert_eq!(result.dimension, dimension);
+        assert_eq!(result.expected_dimension, Some(dimension));
+    }
+
+    #[tokio::test]
+    async fn test_failed_embeddings_are_kept_and_retried() {
+        let (mut service, mut server) = create_test_service().await;
+        let db_path = std::env::temp_dir().join(format!("failed-embeddings-{}", uuid::Uuid::new_v4()));
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::open_at(&db_path).await.unwrap())));
+        let embed_response = r#"{"embeddings":[[0.3,0.4,0.5]]}"#;
+        let embed_mock = server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(embed_response)
+            .create_async()
+            .await;
+        assert_eq!(service.probe_dimension().await.unwrap(), 3);
+        
+        // Ollama stops answering mid-update; mock embeddings can't be stored with the model's
+        embed_mock.remove_async().await;
+        let content = "A bloomery smelts iron ore into blooms, which are then worked on an anvil. \
+                       Fill it with ore and charcoal, light it and wait for the smelting to finish. ".repeat(20);
+        service.process_wiki_page("Bloomery", "test://bloomery", &content, &[]).await.unwrap();
+        assert!(service.chunks_for_source("test://bloomery").await.unwrap().is_empty());
+        
+        // The failures outlive a restart
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::open_at(&db_path).await.unwrap())));
+        let failed = service.vector_db.lock().await.failed_embeddings().unwrap();
+        assert!(failed.len() > 1);
+        assert!(failed.iter().all(|failure| failure.source_url == "test://bloomery" && failure.error.contains("dimension")));
+        
+        server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(embed_response)
+            .create_async()
+            .await;
+        let report = service.retry_failed_embeddings().await.unwrap();
+        
+        assert_eq!(report.retried, failed.len());
+        assert_eq!(report.recovered, failed.len());
+        assert_eq!(report.still_failing, 0);
+        let chunks = service.chunks_for_source("test://bloomery").await.unwrap();
+        assert_eq!(chunks.len(), failed.len());
+        assert!(chunks.iter().all(|chunk| chunk.metadata.contains_key("chunk_index")));
+        assert!(service.vector_db.lock().await.failed_embeddings().unwrap().is_empty());
+        
+        service.set_vector_database(Arc::new(Mutex::new(VectorDatabase::new_fallback())));
+        let _ = std::fs::remove_dir_all(&db_path);
+    }
+
+    #[tokio::test]
+    async fn test_code_blocks_are_not_split() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.chunk_size = 20;
+        service.config.chunk_overlap = 5;
+        let prose = "Mods are written in C# and loaded by the game when it starts up. ".repeat(3);
+        let code = "```\npublic class BloomeryMod : ModSystem\n{\n    public override void Start(ICoreAPI api)\n    {\n        api.RegisterBlockClass(\"Bloomery\", typeof(BlockBloomery));\n    }\n}\n```";
+        let content = format!("{}\n\n{}\n\n{}", prose, code, prose);
+        
+        let chunks = service.split_into_chunks(&content);
+        
+        // The block is one chunk with its line breaks, and no prose chunk holds any of it
+        assert_eq!(chunks.iter().filter(|chunk| chunk.as_str() == code).count(), 1);
+        assert!(chunks.iter().filter(|chunk| chunk.as_str() != code).all(|chunk| !chunk.contains("ModSystem") && !chunk.contains("```")));
+        assert!(chunks.len() > 3);
+        
+        // A block too long for one chunk is only broken between lines
+        service.config.chunk_size = 6;
+        let chunks = service.split_into_chunks(code);
+        assert!(chunks.len() > 1);
+        let code_lines: Vec<&str> = code.lines().filter(|line| *line != "```").collect();
+        let chunked_lines: Vec<&str> = chunks.iter()
+            .inspect(|chunk| assert!(chunk.starts_with("```\n") && chunk.ends_with("\n```")))
+            .flat_map(|chunk| chunk.lines().filter(|line| *line != "```"))
+            .collect();
+        assert_eq!(chunked_lines, code_lines);
+    }
+
+    #[tokio::test]
+    async fn test_huge_token_is_split_into_bounded_chunks() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.max_chunk_chars = 6000;
+        let token = "ab".repeat(50_000);
+        
+        // A single "word", so the word-based chunking alone would keep it whole
+        let chunks = service.split_into_chunks(&token);
+        
+        assert_eq!(chunks.len(), 17);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 6000));
+        assert_eq!(chunks.concat(), token);
+        
+        // Text with spaces is split between words rather than mid-word
+        let text = format!("{} {}", "word ".repeat(1000).trim_end(), token);
+        let chunks = service.split_into_chunks(&text);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 6000));
+        assert!(chunks[0].split(' ').all(|word| word == "word"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_code_block_pieces_stay_fenced() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.max_chunk_chars = 1000;
+        // One line, so splitting the block on line boundaries can't shorten it
+        let code = "block.SetTemperature(1200);".repeat(200);
+        
+        let chunks = service.split_into_chunks(&format!("```csharp\n{}\n```", code));
+        
+        assert!(chunks.len() > 1);
+        let mut rejoined = String::new();
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 1000);
+            let inner = chunk.strip_prefix("```csharp\n").and_then(|chunk| chunk.strip_suffix("\n```")).unwrap();
+            rejoined.push_str(inner);
+        }
+        assert_eq!(rejoined, code);
+    }
+
+    #[test]
+    fn test_progress_survives_a_panicking_listener() {
+        let tracker = Arc::new(EmbeddingProgressTracker::new());
+        tracker.set_listener(|progress| {
+            if progress.chunks_processed == 1 {
+                panic!("listener failed");
+            }
+        });
+        tracker.start(1);
+        tracker.page_started(2);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tracker.chunk_processed())).is_err());
+        
+        // A listener may swap itself out without deadlocking
+        let replaced = Arc::new(AtomicUsize::new(0));
+        let (inner_tracker, inner_replaced) = (tracker.clone(), replaced.clone());
+        tracker.set_listener(move |_| {
+            let replaced = inner_replaced.clone();
+            inner_tracker.set_listener(move |_| {
+                replaced.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+        tracker.chunk_processed();
+        tracker.chunk_processed();
+        
+        assert_eq!(tracker.snapshot().chunks_processed, 3);
+        assert_eq!(replaced.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_cancelled_while_waiting_for_the_database_stops() {
+        let source = VectorDatabase::new_fallback();
+        source.insert_documents(vec![VectorDocument {
+            id: "copper_0".to_string(),
+            content: "Copper is smelted in a crucible".to_string(),
+            source_url: "test://copper".to_string(),
+            source_title: "Copper".to_string(),
+            embedding: vec![1.0, 0.0, 0.0],
+            metadata: HashMap::new(),
+        }]).await.unwrap();
+        let path = std::env::temp_dir().join(format!("kb-export-{}.jsonl", uuid::Uuid::new_v4()));
+        crate::services::knowledge_base::export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        
+        let (mut service, _server) = create_test_service().await;
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        service.set_vector_database(db.clone());
+        let cancel_flag = service.import_cancel_handle();
+        let service = Arc::new(Mutex::new(service));
+        
+        // Something else holds the database, so the import waits for it
+        let held = db.lock().await;
+        let import = {
+            let service = service.clone();
+            let path = path.clone();
+            tokio::spawn(async move { service.lock().await.import_knowledge_base(&path, false, |_| {}).await })
+        };
+        while !cancel_flag.is_running() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(cancel_flag.cancel());
+        drop(held);
+        
+        let summary = import.await.unwrap().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(summary.cancelled);
+        assert_eq!(summary.imported, 0);
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_code_block_pieces_fit_even_when_the_fences_dont() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.max_chunk_chars = 8;
+        
+        let chunks = service.split_into_chunks("```csharp\nblock.SetTemperature(1200);\n```");
+        
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 8), "{:?}", chunks);
+    }
+
+    #[tokio::test]
+    async fn test_unclosed_code_block_pieces_are_fenced_once() {
+        let (mut service, _server) = create_test_service().await;
+        service.config.max_chunk_chars = 1000;
+        let code = "block.SetTemperature(1200);".repeat(200);
+        
+        // The block runs to the end of the page without a closing fence
+        let chunks = service.split_into_chunks(&format!("```csharp\n{}\n\n", code));
+        
+        assert!(chunks.len() > 1);
+        let mut rejoined = String::new();
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 1000);
+            let inner = chunk.strip_prefix("```csharp\n").and_then(|chunk| chunk.strip_suffix("\n```")).unwrap();
+            assert!(!inner.ends_with(char::is_whitespace));
+            rejoined.push_str(inner);
+        }
+        assert_eq!(rejoined, code);
+    }
+
+    #[tokio::test]
+    async fn test_retried_page_drops_its_earlier_chunks() {
+        let (mut service, mut server) = create_test_service().await;
+        let embed_response = r#"{"embeddings":[[0.3,0.4,0.5]]}"#;
+        let embed_mock = server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(embed_response)
+            .create_async()
+            .await;
+        assert_eq!(service.probe_dimension().await.unwrap(), 3);
+        service.vector_db.lock().await.insert_documents(vec![VectorDocument {
+            id: "bloomery_old".to_string(),
+            content: "An earlier version of the bloomery page".to_string(),
+            source_url: "test://bloomery".to_string(),
+            source_title: "Bloomery".to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            metadata: HashMap::from([("scraped_at".to_string(), "2024-01-01T00:00:00Z".to_string())]),
+        }]).await.unwrap();
+        
+        // Failing chunks keep the earlier version around
+        embed_mock.remove_async().await;
+        let content = "A bloomery smelts iron ore into blooms, which are then worked on an anvil. \
+                       Fill it with ore and charcoal, light it and wait for the smelting to finish. ".repeat(20);
+        service.process_wiki_page("Bloomery", "test://bloomery", &content, &[]).await.unwrap();
+        assert!(service.vector_db.lock().await.get_document("bloomery_old").await.unwrap().is_some());
+        
+        server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(embed_response)
+            .create_async()
+            .await;
+        let report = service.retry_failed_embeddings().await.unwrap();
+        
+        assert_eq!(report.still_failing, 0);
+        let chunks = service.chunks_for_source("test://bloomery").await.unwrap();
+        assert_eq!(chunks.len(), report.recovered);
+        assert!(chunks.iter().all(|chunk| chunk.id != "bloomery_old"));
+    }
+
+    #[tokio::test]
+    async fn test_removed_page_forgets_its_failed_chunks() {
+        let (mut service, mut server) = create_test_service().await;
+        let embed_mock = server.mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embeddings":[[0.3,0.4,0.5]]}"#)
+            .create_async()
+            .await;
+        service.probe_dimension().await.unwrap();
+        embed_mock.remove_async().await;
+        let content = "A bloomery smelts iron ore into blooms, which are then worked on an anvil. ".repeat(40);
+        service.process_wiki_page("Bloomery", "test://bloomery", &content, &[]).await.unwrap();
+        assert!(!service.vector_db.lock().await.failed_embeddings().unwrap().is_empty());
+        
+        service.remove_source("test://bloomery").await.unwrap();
+        
+        assert!(service.vector_db.lock().await.failed_embeddings().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+pub const HIGHLIGHT_START: &str = "<mark>";
+pub const HIGHLIGHT_END: &str = "</mark>";
+const ELLIPSIS: &str = "…";
+
+/// A window of chunk text with the query terms wrapped in `<mark>` tags.
+/// The rest of the text is HTML-escaped, so the snippet can be rendered as HTML directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightedSnippet {
+    pub text: String,
+    pub match_count: usize,
+}
+
+/// Splits a query into lowercase search terms, dropping single characters and duplicates
+fn query_terms(query: &str) -> Vec<Vec<char>> {
+    let mut terms: Vec<Vec<char>> = Vec::new();
+    for word in query.split(|c: char| !c.is_alphanumeric()) {
+        let term: Vec<char> = word.chars().map(lowercase_char).collect();
+        if term.len() > 1 && !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Lowercases one char to one char, so positions line up with the original text
+fn lowercase_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Finds every case-insensitive occurrence of the terms as char ranges, merging overlaps
+fn find_matches(content: &[char], terms: &[Vec<char>]) -> Vec<(usize, usize)> {
+    let lowered: Vec<char> = content.iter().map(|&c| lowercase_char(c)).collect();
+    let mut spans = Vec::new();
+
+    for start in 0..lowered.len() {
+        for term in terms {
+            if lowered[start..].starts_with(term) {
+                spans.push((start, start + term.len()));
+            }
+        }
+    }
+
+    spans.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        match merged.last_mut() {
+            // Overlapping or touching terms ("iron" + "ironwork") become one highlight
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Picks the window start that puts the densest cluster of matches in view
+fn best_window_start(spans: &[(usize, usize)], content_len: usize, window_chars: usize) -> usize {
+    let best = spans.iter()
+        .max_by_key(|(center, _)| {
+            let from = center.saturating_sub(window_chars / 2);
+            let to = center + window_chars / 2;
+            // Ties go to the earliest cluster
+            (spans.iter().filter(|(start, _)| (from..to).contains(start)).count(), usize::MAX - center)
+        })
+        .map(|(start, _)| *start)
+        .unwrap_or(0);
+
+    best.saturating_sub(window_chars / 2)
+        .min(content_len.saturating_sub(window_chars))
+}
+
+/// Splits the query's search terms into those that occur in `content` and those that don't,
+/// both lowercased and in query order
+pub fn term_overlap(query: &str, content: &str) -> (Vec<String>, Vec<String>) {
+    let chars: Vec<char> = content.chars().collect();
+    let mut matched = Vec::new();
+    let mut missing = Vec::new();
+
+    for term in query_terms(query) {
+        let found = !find_matches(&chars, std::slice::from_ref(&term)).is_empty();
+        let term: String = term.into_iter().collect();
+        if found {
+            matched.push(term);
+        } else {
+            missing.push(term);
+        }
+    }
+    (matched, missing)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Highlights the query terms in `content` and trims it to about `window_chars` characters
+/// centered on the best match, cutting at word boundaries and marking cuts with an ellipsis.
+pub fn highlight_snippet(query: &str, content: &str, window_chars: usize) -> HighlightedSnippet {
+    let chars: Vec<char> = content.chars().collect();
+    let spans = find_matches(&chars, &query_terms(query));
+
+    let mut start = best_window_start(&spans, chars.len(), window_chars);
+    let mut end = (start + window_chars).min(chars.len());
+
+    // Don't cut words in half, unless that would cut into a highlight
+    let first_span_start = spans.iter().map(|(s, _)| *s).find(|s| *s >= start).unwrap_or(end);
+    if start > 0 {
+        if let Some(offset) = chars[start..first_span_start].iter().position(|c| c.is_whitespace()) {
+            start += offset + 1;
+        }
+    }
+    if end < chars.len() {
+        let last_span_end = spans.iter().map(|(_, e)| *e).filter(|e| *e <= end).max().unwrap_or(start);
+        if let Some(offset) = chars[last_span_end.max(start)..end].iter().rposition(|c| c.is_whitespace()) {
+            end = last_span_end.max(start) + offset;
+        }
+    }
+
+    let visible: Vec<(usize, usize)> = spans.iter()
+        .filter(|(s, e)| *e > start && *s < end)
+        .map(|(s, e)| ((*s).max(start), (*e).min(end)))
+        .collect();
+
+    let mut text = String::new();
+    if start > 0 {
+        text.push_str(ELLIPSIS);
+    }
+    let mut cursor = start;
+    for (span_start, span_end) in &visible {
+        text.push_str(&escape_html(&chars[cursor..*span_start].iter().collect::<String>()));
+        text.push_str(HIGHLIGHT_START);
+        text.push_str(&escape_html(&chars[*span_start..*span_end].iter().collect::<String>()));
+        text.push_str(HIGHLIGHT_END);
+        cursor = *span_end;
+    }
+    text.push_str(&escape_html(&chars[cursor..end].iter().collect::<String>()));
+    if end < chars.len() {
+        text.push_str(ELLIPSIS);
+    }
+
+    HighlightedSnippet {
+        text: text.trim().to_string(),
+        match_count: spans.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlights_are_case_insensitive() {
+        let snippet = highlight_snippet("copper ORE", "Copper ore is found near the surface.", 200);
+        assert_eq!(snippet.text, "<mark>Copper</mark> <mark>ore</mark> is found near the surface.");
+        assert_eq!(snippet.match_count, 2);
+    }
+
+    #[test]
+    fn test_overlapping_terms_are_merged() {
+        let snippet = highlight_snippet("iron ironwork", "Ironwork needs iron bars.", 200);
+        assert_eq!(snippet.text, "<mark>Ironwork</mark> needs <mark>iron</mark> bars.");
+    }
+
+    #[test]
+    fn test_window_is_centered_on_best_match() {
+        let content = format!("{} The bloomery smelts iron ore into blooms. {}", "filler ".repeat(40), "padding ".repeat(40));
+        let snippet = highlight_snippet("bloomery", &content, 60);
+
+        assert!(snippet.text.starts_with(ELLIPSIS));
+        assert!(snippet.text.ends_with(ELLIPSIS));
+        assert!(snippet.text.contains("<mark>bloomery</mark>"));
+        // Cut at word boundaries, so no partial "filler"/"padding" words
+        let inner = snippet.text.trim_matches(|c| c == '…').trim();
+        assert!(inner.split_whitespace().all(|word| !["fil", "ler", "pad", "ding"].contains(&word)));
+        let visible = snippet.text.replace(HIGHLIGHT_START, "").replace(HIGHLIGHT_END, "");
+        assert!(visible.chars().count() <= 62);
+    }
+
+    #[test]
+    fn test_no_match_returns_leading_window() {
+        let snippet = highlight_snippet("anvil", "Clay is shaped on a clay forming surface. More text follows here.", 30);
+        assert_eq!(snippet.match_count, 0);
+        assert!(snippet.text.starts_with("Clay is shaped"));
+        assert!(snippet.text.ends_with(ELLIPSIS));
+    }
+
+    #[test]
+    fn test_term_overlap_splits_matched_and_missing_terms() {
+        let (matched, missing) = term_overlap("Smelt IRON with a bloomery", "The bloomery turns iron ore into blooms.");
+        assert_eq!(matched, vec!["iron", "bloomery"]);
+        assert_eq!(missing, vec!["smelt", "with"]);
+    }
+
+    #[test]
+    fn test_content_is_html_escaped() {
+        let snippet = highlight_snippet("tongs", "Use <tongs> & a hammer", 200);
+        assert_eq!(snippet.text, "Use &lt;<mark>tongs</mark>&gt; &amp; a hammer");
+    }
+}
@@ -0,0 +1,479 @@
+use crate::errors::{AppError, AppResult};
+use crate::services::cancellation::CancelFlag;
+use crate::services::ollama_manager::model_names_match;
+use crate::services::vector_database::{normalized_euclidean_distance, LegacyVectorDocument, VectorDatabase, VectorDocument};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// Bumped whenever the export layout changes incompatibly
+pub const KNOWLEDGE_BASE_FORMAT_VERSION: u32 = 2;
+
+/// Version 1 exports stored document metadata as a JSON-encoded string; they're still importable
+const LEGACY_METADATA_FORMAT_VERSION: u32 = 1;
+
+/// Documents are written to the store in batches of this size during import
+const IMPORT_BATCH_SIZE: usize = 256;
+
+/// First line of an exported knowledge base. The remaining lines are one
+/// JSON-encoded `VectorDocument` each, so exports and imports can be streamed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBaseHeader {
+    pub format_version: u32,
+    pub embedding_model: String,
+    pub embedding_dimension: usize,
+    pub document_count: usize,
+    pub exported_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBaseImportSummary {
+    pub embedding_model: String,
+    pub embedding_dimension: usize,
+    pub imported: usize,
+    pub skipped: usize,
+    /// The import was stopped early; documents imported before that are kept
+    pub cancelled: bool,
+    /// Documents left out because they were too close to one already stored
+    pub near_duplicates_skipped: usize,
+    /// Source URL of each stored document that imported near-duplicates matched, with how many matched it
+    pub near_duplicate_sources: BTreeMap<String, usize>,
+}
+
+/// Reported after each batch of an import is written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub documents_processed: usize,
+    pub documents_total: usize,
+}
+
+/// Writes every stored document to `path`, one at a time. The header counts the documents
+/// actually written, so they go to a temporary file first and are copied in after it.
+pub async fn export_knowledge_base(db: &VectorDatabase, path: &Path, embedding_model: &str) -> AppResult<KnowledgeBaseHeader> {
+    info!("Exporting knowledge base to {:?}", path);
+    
+    let mut body_path = path.as_os_str().to_owned();
+    body_path.push(".partial");
+    let body_path = std::path::PathBuf::from(body_path);
+    let result = write_export(db, path, &body_path, embedding_model).await;
+    tokio::fs::remove_file(&body_path).await.ok();
+    
+    let header = result?;
+    info!("Knowledge base exported to {:?} ({} documents)", path, header.document_count);
+    Ok(header)
+}
+
+async fn write_export(db: &VectorDatabase, path: &Path, body_path: &Path, embedding_model: &str) -> AppResult<KnowledgeBaseHeader> {
+    let write_error = |e: std::io::Error| AppError::StorageError(format!("Failed to write export file: {}", e));
+    
+    let body = tokio::fs::File::create(body_path).await
+        .map_err(|e| AppError::StorageError(format!("Failed to create export file: {}", e)))?;
+    let mut body = tokio::io::BufWriter::new(body);
+    let mut line = Vec::new();
+    let mut document_count = 0;
+    for doc in db.iter_documents() {
+        line.clear();
+        write_line(&mut line, &doc)?;
+        body.write_all(&line).await.map_err(write_error)?;
+        document_count += 1;
+    }
+    body.flush().await.map_err(write_error)?;
+    
+    let header = KnowledgeBaseHeader {
+        format_version: KNOWLEDGE_BASE_FORMAT_VERSION,
+        embedding_model: embedding_model.to_string(),
+        embedding_dimension: db.stored_dimension().await.unwrap_or(0),
+        document_count,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let file = tokio::fs::File::create(path).await
+        .map_err(|e| AppError::StorageError(format!("Failed to create export file: {}", e)))?;
+    let mut writer = tokio::io::BufWriter::new(file);
+    line.clear();
+    write_line(&mut line, &header)?;
+    writer.write_all(&line).await.map_err(write_error)?;
+    let mut body = tokio::fs::File::open(body_path).await.map_err(write_error)?;
+    tokio::io::copy(&mut body, &mut writer).await.map_err(write_error)?;
+    writer.flush().await.map_err(write_error)?;
+    
+    Ok(header)
+}
+
+/// Loads an exported knowledge base after checking it was built with a compatible embedding model.
+/// With `near_duplicate_distance`, documents within that distance of one already stored, or of one
+/// kept earlier in the file, are skipped, which catches reworded copies that have different ids.
+/// Setting `cancel` stops the import after the current batch, keeping what was already written.
+pub async fn import_knowledge_base<F>(
+    db: &VectorDatabase,
+    path: &Path,
+    embedding_model: &str,
+    expected_dimension: Option<usize>,
+    near_duplicate_distance: Option<f32>,
+    cancel: &CancelFlag,
+    mut on_progress: F,
+) -> AppResult<KnowledgeBaseImportSummary>
+where
+    F: FnMut(ImportProgress),
+{
+    let file = File::open(path)
+        .map_err(|e| AppError::StorageError(format!("Failed to open knowledge base file: {}", e)))?;
+    let mut lines = BufReader::new(file).lines();
+    
+    let header_line = lines.next()
+        .ok_or_else(|| AppError::StorageError("Knowledge base file is empty".to_string()))?
+        .map_err(|e| AppError::StorageError(format!("Failed to read knowledge base file: {}", e)))?;
+    let header: KnowledgeBaseHeader = serde_json::from_str(&header_line)
+        .map_err(|e| AppError::StorageError(format!("Invalid knowledge base header: {}", e)))?;
+    
+    validate_header(&header, embedding_model, expected_dimension)?;
+    
+    info!("Importing {} documents from {:?}", header.document_count, path);
+    
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut near_duplicate_sources = BTreeMap::new();
+    let mut cancelled = false;
+    
+    for (line_number, line) in lines.enumerate() {
+        if cancel.is_cancelled() {
+            info!("Knowledge base import cancelled");
+            cancelled = true;
+            break;
+        }
+        
+        let line = line
+            .map_err(|e| AppError::StorageError(format!("Failed to read knowledge base file: {}", e)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let parsed = if header.format_version == LEGACY_METADATA_FORMAT_VERSION {
+            serde_json::from_str::<LegacyVectorDocument>(&line).map(VectorDocument::from)
+        } else {
+            serde_json::from_str::<VectorDocument>(&line)
+        };
+        match parsed {
+            Ok(doc) if doc.embedding.len() == header.embedding_dimension => batch.push(doc),
+            Ok(doc) => {
+                warn!("Skipping document {} with dimension {} (expected {})", 
+                      doc.id, doc.embedding.len(), header.embedding_dimension);
+                skipped += 1;
+            }
+            Err(e) => {
+                warn!("Skipping malformed document on line {}: {}", line_number + 2, e);
+                skipped += 1;
+            }
+        }
+        
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            let batch = drop_near_duplicates(db, std::mem::take(&mut batch), near_duplicate_distance, &mut near_duplicate_sources).await?;
+            let summary = db.insert_documents(batch).await?;
+            imported += summary.inserted;
+            skipped += summary.failed.len();
+            on_progress(ImportProgress {
+                documents_processed: imported + skipped + near_duplicate_sources.values().sum::<usize>(),
+                documents_total: header.document_count,
+            });
+        }
+    }
+    
+    let batch = drop_near_duplicates(db, batch, near_duplicate_distance, &mut near_duplicate_sources).await?;
+    let summary = db.insert_documents(batch).await?;
+    db.flush().await?;
+    imported += summary.inserted;
+    skipped += summary.failed.len();
+    let near_duplicates_skipped = near_duplicate_sources.values().sum();
+    on_progress(ImportProgress {
+        documents_processed: imported + skipped + near_duplicates_skipped,
+        documents_total: header.document_count,
+    });
+    
+    info!("Imported {} documents ({} skipped, {} near-duplicates{})",
+          imported, skipped, near_duplicates_skipped, if cancelled { ", cancelled" } else { "" });
+    Ok(KnowledgeBaseImportSummary {
+        embedding_model: header.embedding_model,
+        embedding_dimension: header.embedding_dimension,
+        imported,
+        skipped,
+        cancelled,
+        near_duplicates_skipped,
+        near_duplicate_sources,
+    })
+}
+
+/// Removes documents within `max_distance` of a stored document, or of one kept earlier in `batch`,
+/// from `batch`, counting each match against the matched document's source. Earlier batches of an
+/// import are stored by then, so they're covered too. Returns `batch` unchanged when `max_distance` is `None`.
+async fn drop_near_duplicates(
+    db: &VectorDatabase,
+    batch: Vec<VectorDocument>,
+    max_distance: Option<f32>,
+    matched_sources: &mut BTreeMap<String, usize>,
+) -> AppResult<Vec<VectorDocument>> {
+    let Some(max_distance) = max_distance else {
+        return Ok(batch);
+    };
+    
+    let embeddings: Vec<Vec<f32>> = batch.iter().map(|doc| doc.embedding.clone()).collect();
+    let nearest = db.nearest_by_distance(&embeddings).await?;
+    
+    let mut kept: Vec<VectorDocument> = Vec::with_capacity(batch.len());
+    for (doc, nearest) in batch.into_iter().zip(nearest) {
+        let nearest_kept = kept.iter()
+            .map(|other| (other, normalized_euclidean_distance(&doc.embedding, &other.embedding)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let (matched_id, matched_source, distance) = match (nearest, nearest_kept) {
+            (Some((existing, distance)), _) if distance <= max_distance => (existing.id, existing.source_url, distance),
+            (_, Some((other, distance))) if distance <= max_distance => (other.id.clone(), other.source_url.clone(), distance),
+            _ => {
+                kept.push(doc);
+                continue;
+            }
+        };
+        info!("Skipping {} as a near-duplicate of {} (distance {:.3})", doc.id, matched_id, distance);
+        *matched_sources.entry(matched_source).or_insert(0) += 1;
+    }
+    Ok(kept)
+}
+
+fn validate_header(header: &KnowledgeBaseHeader, embedding_model: &str, expected_dimension: Option<usize>) -> AppResult<()> {
+    if !(LEGACY_METADATA_FORMAT_VERSION..=KNOWLEDGE_BASE_FORMAT_VERSION).contains(&header.format_version) {
+        return Err(AppError::StorageError(format!(
+            "Unsupported knowledge base format version {} (expected at most {})",
+            header.format_version, KNOWLEDGE_BASE_FORMAT_VERSION
+        )));
+    }
+    
+    if !model_names_match(&header.embedding_model, embedding_model) {
+        return Err(AppError::EmbeddingError(format!(
+            "Knowledge base was built with embedding model '{}' but '{}' is configured",
+            header.embedding_model, embedding_model
+        )));
+    }
+    
+    if let Some(expected) = expected_dimension {
+        if header.embedding_dimension != expected {
+            return Err(AppError::EmbeddingError(format!(
+                "Knowledge base embeddings have dimension {} but the current store uses {}",
+                header.embedding_dimension, expected
+            )));
+        }
+    }
+    
+    Ok(())
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, value: &T) -> AppResult<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")
+        .map_err(|e| AppError::StorageError(format!("Failed to write export file: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn test_document(id: &str, embedding: Vec<f32>) -> VectorDocument {
+        VectorDocument {
+            id: id.to_string(),
+            content: format!("Content of {}", id),
+            source_url: format!("test://wiki/{}", id),
+            source_title: id.to_string(),
+            embedding,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn temp_export_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kb-export-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let source = VectorDatabase::new_fallback();
+        source.insert_documents(vec![
+            test_document("copper", vec![1.0, 0.0, 0.0]),
+            test_document("tin", vec![0.0, 1.0, 0.0]),
+        ]).await.unwrap();
+        
+        let path = temp_export_path();
+        let header = export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        assert_eq!(header.document_count, 2);
+        assert_eq!(header.embedding_dimension, 3);
+        
+        let target = VectorDatabase::new_fallback();
+        let summary = import_knowledge_base(&target, &path, "nomic-embed-text:latest", Some(3), None, &CancelFlag::default(), |_| {}).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        
+        assert_eq!(summary.imported, 2);
+        assert_eq!(summary.skipped, 0);
+        assert_eq!(target.count_documents().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_header_counts_the_documents_written() {
+        let db_path = std::env::temp_dir().join(format!("kb-export-db-{}", uuid::Uuid::new_v4()));
+        {
+            // A record that can't be decoded is left out of the export
+            let raw = sled::open(&db_path).unwrap();
+            raw.insert("copper", bincode::serialize(&test_document("copper", vec![1.0, 0.0, 0.0])).unwrap()).unwrap();
+            raw.insert("corrupt", &b"not a document"[..]).unwrap();
+            raw.flush().unwrap();
+        }
+        let source = VectorDatabase::open_at(&db_path).await.unwrap();
+        
+        let path = temp_export_path();
+        let header = export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        let exported = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        drop(source);
+        std::fs::remove_dir_all(&db_path).ok();
+        
+        assert_eq!(header.document_count, 1);
+        assert_eq!(exported.lines().count(), 2);
+        let written: KnowledgeBaseHeader = serde_json::from_str(exported.lines().next().unwrap()).unwrap();
+        assert_eq!(written.document_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_incompatible_embeddings() {
+        let source = VectorDatabase::new_fallback();
+        source.insert_documents(vec![test_document("copper", vec![1.0, 0.0, 0.0])]).await.unwrap();
+        
+        let path = temp_export_path();
+        export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        
+        let target = VectorDatabase::new_fallback();
+        let wrong_model = import_knowledge_base(&target, &path, "mxbai-embed-large", None, None, &CancelFlag::default(), |_| {}).await;
+        let wrong_dimension = import_knowledge_base(&target, &path, "nomic-embed-text", Some(768), None, &CancelFlag::default(), |_| {}).await;
+        std::fs::remove_file(&path).ok();
+        
+        assert!(matches!(wrong_model, Err(AppError::EmbeddingError(_))));
+        assert!(matches!(wrong_dimension, Err(AppError::EmbeddingError(_))));
+        assert_eq!(target.count_documents().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_import_converts_version_one_metadata() {
+        let path = temp_export_path();
+        let header = KnowledgeBaseHeader {
+            format_version: 1,
+            embedding_model: "nomic-embed-text".to_string(),
+            embedding_dimension: 3,
+            document_count: 1,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let legacy = LegacyVectorDocument {
+            id: "copper".to_string(),
+            content: "copper content".to_string(),
+            source_url: "test://copper".to_string(),
+            source_title: "copper".to_string(),
+            embedding: vec![1.0, 0.0, 0.0],
+            metadata: r#"{"source_type":"wiki"}"#.to_string(),
+        };
+        let mut file = File::create(&path).unwrap();
+        write_line(&mut file, &header).unwrap();
+        write_line(&mut file, &legacy).unwrap();
+        drop(file);
+        
+        let target = VectorDatabase::new_fallback();
+        let summary = import_knowledge_base(&target, &path, "nomic-embed-text", Some(3), None, &CancelFlag::default(), |_| {}).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        
+        assert_eq!(summary.imported, 1);
+        let doc = target.get_document("copper").await.unwrap().unwrap();
+        assert_eq!(doc.metadata.get("source_type").map(String::as_str), Some("wiki"));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_import_keeps_written_batches() {
+        let source = VectorDatabase::new_fallback();
+        let documents = (0..IMPORT_BATCH_SIZE * 3)
+            .map(|i| test_document(&format!("doc{}", i), vec![1.0, i as f32, 0.0]))
+            .collect();
+        source.insert_documents(documents).await.unwrap();
+        let path = temp_export_path();
+        export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        
+        // Cancel as soon as the first batch has been written
+        let cancel = CancelFlag::default();
+        let mut reports = Vec::new();
+        let target = VectorDatabase::new_fallback();
+        let summary = import_knowledge_base(&target, &path, "nomic-embed-text", Some(3), None, &cancel, |progress| {
+            cancel.cancel();
+            reports.push(progress);
+        }).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        
+        assert!(summary.cancelled);
+        assert_eq!(summary.imported, IMPORT_BATCH_SIZE);
+        assert_eq!(target.count_documents().await.unwrap(), IMPORT_BATCH_SIZE);
+        assert_eq!(reports[0].documents_processed, IMPORT_BATCH_SIZE);
+        assert_eq!(reports[0].documents_total, IMPORT_BATCH_SIZE * 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_paraphrased_near_duplicates() {
+        let existing = VectorDatabase::new_fallback();
+        existing.insert_documents(vec![VectorDocument {
+            content: "Knap flint on a flat stone surface to make a knife blade".to_string(),
+            ..test_document("knapping", vec![0.9, 0.1, 0.0])
+        }]).await.unwrap();
+        
+        // A reworded copy of the knapping chunk from another wiki mirror embeds almost identically
+        let source = VectorDatabase::new_fallback();
+        source.insert_documents(vec![
+            VectorDocument {
+                content: "A knife blade is made by knapping flint on a flat stone".to_string(),
+                source_url: "test://mirror/Knapping".to_string(),
+                ..test_document("mirror-knapping", vec![0.88, 0.12, 0.01])
+            },
+            test_document("pottery", vec![0.0, 0.2, 0.9]),
+        ]).await.unwrap();
+        let path = temp_export_path();
+        export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        
+        let summary = import_knowledge_base(&existing, &path, "nomic-embed-text", Some(3), Some(0.1), &CancelFlag::default(), |_| {}).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.near_duplicates_skipped, 1);
+        assert_eq!(summary.near_duplicate_sources.get("test://wiki/knapping"), Some(&1));
+        assert!(existing.get_document("mirror-knapping").await.unwrap().is_none());
+        assert!(existing.get_document("pottery").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_near_duplicates_within_the_file() {
+        // Two mirrors of the same page in one export, and nothing stored yet
+        let source = VectorDatabase::new_fallback();
+        source.insert_documents(vec![
+            VectorDocument {
+                source_url: "test://mirror-a/Knapping".to_string(),
+                ..test_document("a-knapping", vec![0.9, 0.1, 0.0])
+            },
+            VectorDocument {
+                source_url: "test://mirror-b/Knapping".to_string(),
+                ..test_document("b-knapping", vec![0.88, 0.12, 0.01])
+            },
+            test_document("pottery", vec![0.0, 0.2, 0.9]),
+        ]).await.unwrap();
+        let path = temp_export_path();
+        export_knowledge_base(&source, &path, "nomic-embed-text").await.unwrap();
+        
+        let target = VectorDatabase::new_fallback();
+        let summary = import_knowledge_base(&target, &path, "nomic-embed-text", Some(3), Some(0.1), &CancelFlag::default(), |_| {}).await.unwrap();
+        std::fs::remove_file(&path).ok();
+        
+        assert_eq!(summary.imported, 2);
+        assert_eq!(summary.near_duplicates_skipped, 1);
+        assert_eq!(summary.near_duplicate_sources.get("test://mirror-a/Knapping"), Some(&1));
+        assert!(target.get_document("b-knapping").await.unwrap().is_none());
+    }
+}
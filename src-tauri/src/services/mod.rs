@@ -3,6 +3,13 @@ pub mod wiki_service;
 pub mod embedding_service;
 pub mod chat_service;
 pub mod vector_database;
+pub mod ndjson;
+pub mod knowledge_base;
+pub mod highlight;
+pub mod page_store;
+pub mod usage_stats;
+pub mod cancellation;
+pub mod system_resources;
 
 #[cfg(test)]
 #[path = "rag_integration_test.rs"]
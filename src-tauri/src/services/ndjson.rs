@@ -0,0 +1,156 @@
+use log::warn;
+
+/// Incremental decoder for newline-delimited JSON streams.
+///
+/// Ollama streams progress and tokens as one JSON object per line, but HTTP chunk
+/// boundaries can fall anywhere - in the middle of a line or inside a multi-byte
+/// UTF-8 sequence. The decoder buffers raw bytes and only yields lines once their
+/// terminating newline has arrived, so callers always see complete JSON documents.
+#[derive(Debug, Default)]
+pub struct NdjsonDecoder {
+    buffer: Vec<u8>,
+    /// Bytes at the start of `buffer` already searched for a newline, so a long line arriving
+    /// in many chunks isn't rescanned from its start each time
+    scanned: usize,
+}
+
+impl NdjsonDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Feeds a chunk of bytes and returns every line completed by it.
+    /// Blank lines are skipped and surrounding whitespace (including `\r`) is trimmed.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        let mut search_from = self.scanned;
+        while let Some(offset) = self.buffer[search_from..].iter().position(|&b| b == b'\n') {
+            let newline_pos = search_from + offset;
+            if let Some(line) = Self::decode_line(&self.buffer[line_start..newline_pos]) {
+                lines.push(line);
+            }
+            line_start = newline_pos + 1;
+            search_from = line_start;
+        }
+        self.buffer.drain(..line_start);
+        self.scanned = self.buffer.len();
+        
+        lines
+    }
+    
+    /// Returns whatever is left in the buffer as a final line, for streams
+    /// that don't terminate their last object with a newline.
+    pub fn finish(&mut self) -> Option<String> {
+        let remaining = std::mem::take(&mut self.buffer);
+        self.scanned = 0;
+        Self::decode_line(&remaining)
+    }
+    
+    /// Number of bytes buffered while waiting for the rest of a line.
+    #[cfg(test)]
+    fn pending_len(&self) -> usize {
+        self.buffer.len()
+    }
+    
+    fn decode_line(bytes: &[u8]) -> Option<String> {
+        // A newline byte can never appear inside a multi-byte UTF-8 sequence, so a
+        // complete line is only invalid if the server actually sent bad data.
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(e) => {
+                warn!("Streaming response line is not valid UTF-8: {}", e);
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+        };
+        
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            None
+        } else {
+            Some(trimmed.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: &str = "{\"status\":\"pulling manifest\"}\n{\"status\":\"downloading ⛏ café\",\"completed\":10,\"total\":100}\r\n\n{\"status\":\"success\"}\n";
+
+    fn expected_lines() -> Vec<String> {
+        vec![
+            r#"{"status":"pulling manifest"}"#.to_string(),
+            r#"{"status":"downloading ⛏ café","completed":10,"total":100}"#.to_string(),
+            r#"{"status":"success"}"#.to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_single_chunk() {
+        let mut decoder = NdjsonDecoder::new();
+        assert_eq!(decoder.push(STREAM.as_bytes()), expected_lines());
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_byte_at_a_time() {
+        let mut decoder = NdjsonDecoder::new();
+        let mut lines = Vec::new();
+        
+        // Feeding one byte at a time splits every multi-byte character
+        for byte in STREAM.as_bytes() {
+            lines.extend(decoder.push(&[*byte]));
+        }
+        
+        assert_eq!(lines, expected_lines());
+        assert_eq!(decoder.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_every_split_point() {
+        let bytes = STREAM.as_bytes();
+        
+        for split in 0..=bytes.len() {
+            let mut decoder = NdjsonDecoder::new();
+            let mut lines = decoder.push(&bytes[..split]);
+            lines.extend(decoder.push(&bytes[split..]));
+            
+            assert_eq!(lines, expected_lines(), "split at byte {}", split);
+            
+            for line in &lines {
+                assert!(serde_json::from_str::<serde_json::Value>(line).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_line_is_held_back() {
+        let mut decoder = NdjsonDecoder::new();
+        
+        assert!(decoder.push(br#"{"status":"down"#).is_empty());
+        assert!(decoder.pending_len() > 0);
+        assert_eq!(decoder.push(b"loading\"}\n"), vec![r#"{"status":"downloading"}"#.to_string()]);
+    }
+
+    #[test]
+    fn test_finish_returns_unterminated_line() {
+        let mut decoder = NdjsonDecoder::new();
+        
+        assert!(decoder.push(br#"{"done":true}"#).is_empty());
+        assert_eq!(decoder.finish(), Some(r#"{"done":true}"#.to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_decoded_lossily() {
+        let mut decoder = NdjsonDecoder::new();
+        let lines = decoder.push(b"{\"status\":\"\xff\"}\n");
+        
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains('\u{FFFD}'));
+    }
+}
@@ -1,10 +1,19 @@
 use crate::config::OllamaConfig;
-use crate::errors::{AppError, AppResult};
+use crate::errors::{AppError, AppResult, EmptyResponseDiagnostic};
+use crate::services::cancellation::CancelFlag;
+use crate::services::embedding_service::estimate_tokens;
+use crate::services::ndjson::NdjsonDecoder;
+use crate::services::system_resources::{
+    ensure_enough_resources, model_requirement, model_tag_size, ollama_models_dir, parse_parameter_size, ModelRequirement, SystemResources,
+};
 use log::{info, warn, error};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::Path;
 use std::process::{Child, Command, Stdio};
-use tokio::time::{sleep, Duration};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration, Instant};
 use reqwest::Client;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +22,9 @@ pub struct ModelInfo {
     pub size: u64,
     pub digest: String,
     pub details: ModelDetails,
+    /// Friendly name configured for this model, if any
+    #[serde(default)]
+    pub alias: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,18 +34,454 @@ pub struct ModelDetails {
     pub family: String,
 }
 
+/// A single progress update while pulling a model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadProgress {
+    /// Completed fraction of the current layer, 0.0 to 1.0
+    pub fraction: f32,
+    pub status: String,
+    pub completed_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    /// Smoothed download rate; `None` until there's enough data for a stable estimate
+    pub bytes_per_second: Option<f64>,
+    pub eta_seconds: Option<u64>,
+}
+
+/// Progress across every model in a `setup_models` run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSetupProgress {
+    pub model_name: String,
+    /// Position of `model_name` in the list being set up, starting at 0
+    pub model_index: usize,
+    pub model_count: usize,
+    /// Combined progress over all models, 0.0 to 1.0
+    pub overall_fraction: f32,
+    /// Progress of the current model's download
+    pub download: DownloadProgress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSetupOutcome {
+    Downloaded,
+    AlreadyInstalled,
+    Failed,
+    /// Setup was cancelled before this model finished downloading
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSetupResult {
+    pub model_name: String,
+    pub outcome: ModelSetupOutcome,
+    /// Why the download failed, for `Failed`
+    pub error: Option<String>,
+}
+
+/// Context window assumed when a model's context length can't be determined; the `num_ctx` recent Ollama
+/// releases use unless a model sets its own
+pub const DEFAULT_CONTEXT_LENGTH: u32 = 4096;
+
+/// Tries at getting parseable output from JSON-mode generation
+const JSON_GENERATION_ATTEMPTS: u32 = 2;
+
+/// How long looking up a model's size may hold up generation before the resource check is skipped
+const RESOURCE_LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long Ollama keeps a model loaded after a request, by default; a model used again within
+/// this is assumed to still be loaded
+const OLLAMA_KEEP_ALIVE: Duration = Duration::from_secs(5 * 60);
+
+/// The context window Ollama runs a model with, from an `/api/show` response. That's a `num_ctx`
+/// parameter set in the Modelfile, or else `DEFAULT_CONTEXT_LENGTH`: requests don't send their
+/// own `num_ctx`, so a longer trained length (`model_info`) only matters when it's shorter.
+pub(crate) fn parse_context_length(show: &serde_json::Value) -> Option<u32> {
+    let num_ctx = show["parameters"].as_str().and_then(|parameters| {
+        parameters.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                _ => None,
+            }
+        })
+    });
+    
+    num_ctx.or_else(|| {
+        show["model_info"].as_object()?
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .map(|length| length.min(DEFAULT_CONTEXT_LENGTH as u64) as u32)
+    })
+}
+
+/// Times the Windows installer download is tried before giving up on a transient failure
+const INSTALLER_DOWNLOAD_ATTEMPTS: u32 = 4;
+const INSTALLER_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const INSTALLER_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// A failed installer download, split by whether another attempt could succeed
+#[derive(Debug)]
+enum InstallerDownloadFailure {
+    /// Network errors, server errors and truncated downloads
+    Transient(AppError),
+    /// Missing files, other client errors and installers that fail verification
+    Permanent(AppError),
+}
+
+impl InstallerDownloadFailure {
+    fn error(&self) -> &AppError {
+        match self {
+            InstallerDownloadFailure::Transient(e) | InstallerDownloadFailure::Permanent(e) => e,
+        }
+    }
+}
+
+/// Whether an installer download that got `status` is worth trying again
+pub(crate) fn is_retryable_installer_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+}
+
+/// Wait before retrying after failed attempt number `attempt` (starting at 1): exponential backoff
+/// capped at `INSTALLER_BACKOFF_MAX`, scaled into its upper half by `jitter` (0.0 to 1.0) so
+/// clients that failed together don't all retry at the same moment
+pub(crate) fn installer_backoff(attempt: u32, jitter: f64) -> Duration {
+    let exponential = INSTALLER_BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(INSTALLER_BACKOFF_MAX);
+    exponential.mul_f64(0.5 + 0.5 * jitter.clamp(0.0, 1.0))
+}
+
+/// A value in `0.0..1.0` that differs between calls, for retry jitter
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (random % 1_000_000) as f64 / 1_000_000.0
+}
+
+/// How far back the download rate is averaged over
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Rates measured over less time than this are too noisy to report
+const MIN_RATE_SPAN: Duration = Duration::from_secs(2);
+
+/// Computes a moving-average download rate from `(time, completed bytes)` samples.
+/// Ollama reports progress per layer, so the window restarts whenever the layer changes.
+struct DownloadRateTracker {
+    digest: Option<String>,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl DownloadRateTracker {
+    fn new() -> Self {
+        Self {
+            digest: None,
+            samples: VecDeque::new(),
+        }
+    }
+    
+    /// Records a sample and returns the smoothed rate and estimated seconds remaining
+    fn record(&mut self, digest: Option<&str>, completed: u64, total: u64, now: Instant) -> (Option<f64>, Option<u64>) {
+        let went_backwards = self.samples.back().is_some_and(|&(_, last)| completed < last);
+        if digest != self.digest.as_deref() || went_backwards {
+            self.digest = digest.map(str::to_string);
+            self.samples.clear();
+        }
+        
+        self.samples.push_back((now, completed));
+        
+        // Drop samples older than the window, keeping one so the window stays fully covered
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= RATE_WINDOW {
+            self.samples.pop_front();
+        }
+        
+        let (oldest_time, oldest_completed) = self.samples[0];
+        let span = now.duration_since(oldest_time);
+        if span < MIN_RATE_SPAN || completed <= oldest_completed {
+            return (None, None);
+        }
+        
+        let rate = (completed - oldest_completed) as f64 / span.as_secs_f64();
+        let remaining = total.saturating_sub(completed);
+        let eta = (remaining as f64 / rate).ceil() as u64;
+        (Some(rate), Some(eta))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OllamaStatus {
     pub is_running: bool,
     pub is_installed: bool,
     pub version: Option<String>,
+    /// Whether `version` meets `MIN_OLLAMA_VERSION`; `None` when the version couldn't be determined
+    pub compatible: Option<bool>,
     pub models: Vec<ModelInfo>,
+    pub circuit_breaker: CircuitBreakerStatus,
+}
+
+/// Generated text and why the model stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generation {
+    pub text: String,
+    /// Ollama's `done_reason`: `stop` when the model finished, `length` when it ran out of tokens.
+    /// Older Ollama versions don't report it.
+    pub done_reason: Option<String>,
+}
+
+impl Generation {
+    /// Whether the reply was cut off at the token limit rather than finished
+    pub fn truncated(&self) -> bool {
+        self.done_reason.as_deref() == Some("length")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests go through normally
+    Closed,
+    /// Requests fail immediately until the cooldown ends
+    Open,
+    /// The cooldown has ended; the next request probes whether Ollama is back
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Seconds until a probe is allowed, while open
+    pub retry_after_secs: Option<u64>,
+}
+
+/// Stops generation requests from each waiting out a timeout while Ollama is down. After
+/// `threshold` consecutive failures it opens and refuses requests for `cooldown`; then it lets
+/// one through, closing again if that succeeds and reopening if it fails. Other requests fail
+/// fast while that probe is out, unless it hasn't reported back within another `cooldown`.
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: std::sync::Mutex<BreakerInner>,
+}
+
+#[derive(Default)]
+struct BreakerInner {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// When the request probing a half-open breaker was let through
+    probe_started: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: std::sync::Mutex::new(BreakerInner::default()),
+        }
+    }
+    
+    /// A panic elsewhere while holding the lock mustn't disable every later Ollama call
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerInner> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    
+    /// Fails fast while open; when closed lets the request through, and when half-open only
+    /// the one that probes Ollama
+    fn check(&self) -> AppResult<()> {
+        let mut state = self.lock();
+        let Some(open_until) = state.open_until else {
+            return Ok(());
+        };
+        
+        let now = Instant::now();
+        if now < open_until {
+            let retry_after_secs = (open_until - now).as_secs_f64().ceil() as u64;
+            return Err(AppError::ServiceUnavailable {
+                message: format!("Ollama failed {} times in a row; not retrying for {}s", self.threshold, retry_after_secs),
+                retry_after_secs,
+            });
+        }
+        
+        // A probe that never reports back, e.g. a cancelled request, is given up on eventually
+        if state.probe_started.is_some_and(|started| now.duration_since(started) < self.cooldown) {
+            return Err(AppError::ServiceUnavailable {
+                message: "Checking whether Ollama is back after repeated failures; try again shortly".to_string(),
+                retry_after_secs: 1,
+            });
+        }
+        state.probe_started = Some(now);
+        Ok(())
+    }
+    
+    fn record_success(&self) {
+        let mut state = self.lock();
+        state.consecutive_failures = 0;
+        state.probe_started = None;
+        if state.open_until.take().is_some() {
+            info!("Ollama is responding again, closing circuit breaker");
+        }
+    }
+    
+    fn record_failure(&self) {
+        let mut state = self.lock();
+        state.consecutive_failures += 1;
+        state.probe_started = None;
+        if state.consecutive_failures >= self.threshold {
+            warn!("Ollama failed {} consecutive requests, failing fast for {:?}", state.consecutive_failures, self.cooldown);
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+    
+    /// Forgets every recorded failure, closing the breaker
+    fn reset(&self) {
+        *self.lock() = BreakerInner::default();
+    }
+    
+    fn status(&self) -> CircuitBreakerStatus {
+        let state = self.lock();
+        let (breaker_state, retry_after_secs) = match state.open_until {
+            None => (BreakerState::Closed, None),
+            Some(open_until) => match open_until.checked_duration_since(Instant::now()) {
+                Some(remaining) => (BreakerState::Open, Some(remaining.as_secs_f64().ceil() as u64)),
+                None => (BreakerState::HalfOpen, None),
+            },
+        };
+        CircuitBreakerStatus { state: breaker_state, consecutive_failures: state.consecutive_failures, retry_after_secs }
+    }
+}
+
+/// Oldest Ollama release the app works with (`/api/chat` and streaming embeddings landed in 0.1.14)
+pub const MIN_OLLAMA_VERSION: &str = "0.1.14";
+
+/// A parsed `major.minor.patch` version plus whether it carried a pre-release suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OllamaVersion {
+    core: (u64, u64, u64),
+    prerelease: bool,
+}
+
+/// Parses versions like `0.1.32`, `v0.3.0-rc1`, `0.5.7-0-ga420a45-dirty` or `0.2`.
+/// Returns `None` when there's no leading numeric version at all.
+fn parse_ollama_version(version: &str) -> Option<OllamaVersion> {
+    let version = version.trim();
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    let (core, suffix) = match version.find(['-', '+', ' ']) {
+        Some(index) => (&version[..index], &version[index..]),
+        None => (version, ""),
+    };
+    
+    let mut parts = [0u64; 3];
+    let mut parsed_any = false;
+    for (slot, part) in parts.iter_mut().zip(core.split('.')) {
+        let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if digits.is_empty() {
+            break;
+        }
+        *slot = digits.parse().ok()?;
+        parsed_any = true;
+    }
+    
+    parsed_any.then_some(OllamaVersion {
+        core: (parts[0], parts[1], parts[2]),
+        prerelease: suffix.starts_with('-'),
+    })
+}
+
+/// Checks a reported Ollama version against `MIN_OLLAMA_VERSION`.
+/// Returns `None` if the version can't be parsed. Source builds report `0.0.0` and are assumed current.
+pub fn ollama_version_compatible(version: &str) -> Option<bool> {
+    let version = parse_ollama_version(version)?;
+    let minimum = parse_ollama_version(MIN_OLLAMA_VERSION).expect("valid minimum version");
+    
+    if version.core == (0, 0, 0) {
+        return Some(true);
+    }
+    
+    // A pre-release of the minimum version predates the release itself
+    Some(version.core > minimum.core || (version.core == minimum.core && !version.prerelease))
+}
+
+/// Splits a model reference into its base name and tag, treating a missing tag as `latest`
+fn split_model_tag(name: &str) -> (String, String) {
+    let name = name.trim().to_lowercase();
+    match name.rsplit_once(':') {
+        Some((base, tag)) if !tag.is_empty() => (base.to_string(), tag.to_string()),
+        Some((base, _)) => (base.to_string(), "latest".to_string()),
+        None => (name, "latest".to_string()),
+    }
+}
+
+/// Tag-aware model name comparison: `phi3` matches `phi3:latest` but not `phi3:mini`,
+/// and `llama3.2:3b` doesn't match `llama3.2`.
+pub fn model_names_match(requested: &str, installed: &str) -> bool {
+    split_model_tag(requested) == split_model_tag(installed)
+}
+
+/// Error for a failed generate request. Ollama answers 404 with `model "<name>" not found, try
+/// pulling it first` when the model isn't installed.
+fn generate_error(model_name: &str, status: reqwest::StatusCode, error_text: String) -> AppError {
+    if status == reqwest::StatusCode::NOT_FOUND && error_text.contains("not found") {
+        return AppError::ModelNotInstalled(model_name.to_string());
+    }
+    AppError::OllamaError(format!("Ollama API error ({}): {}", status, error_text))
+}
+
+/// Whether `error` means the model itself can't be used (not installed or failing to load),
+/// as opposed to Ollama being unreachable, so another model might still work
+pub fn is_model_unavailable(error: &AppError) -> bool {
+    match error {
+        AppError::ModelNotInstalled(_) => true,
+        AppError::OllamaError(message) => {
+            let message = message.to_lowercase();
+            message.contains("error loading model") || message.contains("failed to load model")
+        }
+        _ => false,
+    }
+}
+
+/// Resolves `name` to a model tag if it's a configured alias (ignoring case and surrounding
+/// whitespace); anything else is returned unchanged
+pub fn resolve_model_alias(aliases: &HashMap<String, String>, name: &str) -> String {
+    let name = name.trim();
+    aliases.iter()
+        .find(|(alias, _)| alias.trim().eq_ignore_ascii_case(name))
+        .map(|(_, tag)| tag.clone())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// The alias configured for an installed model, matching tags the way `model_names_match` does
+pub fn alias_for_model(aliases: &HashMap<String, String>, model_name: &str) -> Option<String> {
+    let mut matches: Vec<&String> = aliases.iter()
+        .filter(|(_, tag)| model_names_match(tag, model_name))
+        .map(|(alias, _)| alias)
+        .collect();
+    // Several aliases may point at one model; pick one consistently
+    matches.sort();
+    matches.first().map(|alias| alias.to_string())
 }
 
 pub struct OllamaManager {
     config: OllamaConfig,
     client: Client,
     process: Option<Child>,
+    install_cancelled: Arc<CancelFlag>,
+    /// Stops model downloads, including the rest of `setup_models`
+    download_cancelled: Arc<CancelFlag>,
+    /// Air-gapped mode: assume Ollama and its models are already present locally
+    offline: bool,
+    breaker: CircuitBreaker,
+    /// Context window of `config.model_name`, once looked up with `fetch_context_length`
+    context_length: Option<u32>,
+    /// Sent as `num_predict`, so a reply stops there with `done_reason: "length"`
+    max_response_tokens: Option<u32>,
+    /// Reads free memory and disk space before a model is pulled or loaded
+    resource_probe: fn(&Path) -> SystemResources,
+    /// Memory needed to load installed models, by name, as worked out from their details
+    load_requirements: std::sync::Mutex<HashMap<String, ModelRequirement>>,
+    /// The model generation last used, and when
+    last_model_use: std::sync::Mutex<Option<(String, Instant)>>,
 }
 
 impl Drop for OllamaManager {
@@ -57,16 +505,65 @@ impl Drop for OllamaManager {
 
 impl OllamaManager {
     pub async fn new() -> Self {
-        let config = OllamaConfig::default();
+        Self::with_config(OllamaConfig::default()).await
+    }
+    
+    pub async fn with_config(config: OllamaConfig) -> Self {
         let client = Client::new();
+        let breaker = CircuitBreaker::new(
+            config.breaker_failure_threshold,
+            Duration::from_secs(config.breaker_cooldown_secs),
+        );
         
         Self {
+            breaker,
             config,
             client,
             process: None,
+            install_cancelled: Arc::new(CancelFlag::default()),
+            download_cancelled: Arc::new(CancelFlag::default()),
+            offline: false,
+            context_length: None,
+            max_response_tokens: None,
+            resource_probe: SystemResources::probe,
+            load_requirements: std::sync::Mutex::new(HashMap::new()),
+            last_model_use: std::sync::Mutex::new(None),
         }
     }
     
+    /// In offline mode nothing is downloaded: installs and model pulls fail immediately
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+    
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+    
+    /// The settings in use, including endpoint and model changes made since startup
+    pub fn config(&self) -> &OllamaConfig {
+        &self.config
+    }
+    
+    fn ensure_online(&self, action: &str) -> AppResult<()> {
+        if self.offline {
+            warn!("Refusing to {} in offline mode", action);
+            return Err(AppError::OfflineMode(format!("can't {} without internet access", action)));
+        }
+        Ok(())
+    }
+    
+    /// Flag that aborts an in-progress installation when set. Shared outside the
+    /// manager's mutex because the install holds that lock while it runs.
+    pub fn install_cancel_handle(&self) -> Arc<CancelFlag> {
+        self.install_cancelled.clone()
+    }
+    
+    /// Flag that stops an in-progress model download, shared for the same reason
+    pub fn download_cancel_handle(&self) -> Arc<CancelFlag> {
+        self.download_cancelled.clone()
+    }
+    
     pub async fn get_status(&self) -> AppResult<OllamaStatus> {
         let is_installed = self.check_installation().await;
         let is_running = self.check_health().await.is_ok();
@@ -81,11 +578,15 @@ impl OllamaManager {
             Vec::new()
         };
         
+        let compatible = version.as_deref().and_then(ollama_version_compatible);
+        
         Ok(OllamaStatus {
             is_running,
             is_installed,
             version,
+            compatible,
             models,
+            circuit_breaker: self.breaker.status(),
         })
     }
 
@@ -98,7 +599,7 @@ impl OllamaManager {
     }
     
     pub async fn check_health(&self) -> AppResult<()> {
-        let url = format!("http://{}:{}/api/tags", self.config.host, self.config.port);
+        let url = self.api_url("tags");
         
         match self.client.get(&url).send().await {
             Ok(response) if response.status().is_success() => Ok(()),
@@ -121,6 +622,8 @@ impl OllamaManager {
         
         let mut cmd = Command::new("ollama");
         cmd.arg("serve")
+           // Make the server listen where we're going to look for it
+           .env("OLLAMA_HOST", format!("{}:{}", self.config.host, self.config.port))
            .stdout(Stdio::null())
            .stderr(Stdio::null());
         
@@ -144,7 +647,7 @@ impl OllamaManager {
     }
     
     async fn get_version(&self) -> AppResult<String> {
-        let url = format!("http://{}:{}/api/version", self.config.host, self.config.port);
+        let url = self.api_url("version");
         
         let response = self.client.get(&url).send().await?;
         let version_info: serde_json::Value = response.json().await?;
@@ -156,7 +659,7 @@ impl OllamaManager {
     }
     
     pub async fn list_models(&self) -> AppResult<Vec<ModelInfo>> {
-        let url = format!("http://{}:{}/api/tags", self.config.host, self.config.port);
+        let url = self.api_url("tags");
         
         let response = self.client.get(&url).send().await?;
         let models_response: serde_json::Value = response.json().await?;
@@ -167,7 +670,8 @@ impl OllamaManager {
         
         let mut model_list = Vec::new();
         for model in models {
-            if let Ok(model_info) = serde_json::from_value::<ModelInfo>(model.clone()) {
+            if let Ok(mut model_info) = serde_json::from_value::<ModelInfo>(model.clone()) {
+                model_info.alias = alias_for_model(&self.config.model_aliases, &model_info.name);
                 model_list.push(model_info);
             }
         }
@@ -176,9 +680,11 @@ impl OllamaManager {
     }
     
     pub async fn download_model(&self, model_name: &str) -> AppResult<()> {
+        self.ensure_online(&format!("download model {}", model_name))?;
+        self.ensure_resources_for(model_name, true).await?;
         info!("Downloading model: {}", model_name);
         
-        let url = format!("http://{}:{}/api/pull", self.config.host, self.config.port);
+        let url = self.api_url("pull");
         let payload = serde_json::json!({
             "name": model_name
         });
@@ -199,35 +705,255 @@ impl OllamaManager {
         }
     }
     
+    /// Refuses to pull (`downloading`) `model_name` when the machine doesn't have the memory or disk
+    /// space it needs. Before loading a model that isn't loaded yet a shortfall is only logged:
+    /// Ollama may put the model in GPU memory, which free RAM doesn't account for. Skipped when
+    /// disabled in the config, when Ollama runs elsewhere, and when the model's size can't be told.
+    async fn ensure_resources_for(&self, model_name: &str, downloading: bool) -> AppResult<()> {
+        if !self.config.check_system_resources || !self.config.is_local() {
+            return Ok(());
+        }
+        if !downloading && self.used_recently(model_name) {
+            return Ok(());
+        }
+        let requirement = if downloading {
+            match model_tag_size(model_name) {
+                (Some(parameters), quantization) => Some(model_requirement(parameters, quantization)),
+                (None, _) => None,
+            }
+        } else {
+            self.requirement_to_load(model_name).await
+        };
+        let Some(requirement) = requirement else {
+            return Ok(());
+        };
+        
+        let probe = self.resource_probe;
+        let resources = tokio::task::spawn_blocking(move || probe(&ollama_models_dir())).await
+            .map_err(|e| AppError::OllamaError(format!("Failed to check system resources: {}", e)))?;
+        let result = ensure_enough_resources(model_name, requirement, resources, downloading);
+        if downloading {
+            return result.inspect_err(|e| warn!("Refusing to download {}: {}", model_name, e));
+        }
+        if let Err(e) = result {
+            warn!("Loading {} anyway, it may not fit in memory: {}", model_name, e);
+        }
+        Ok(())
+    }
+    
+    /// Records a generation with `model_name` and says whether the previous one used the same
+    /// model recently enough that Ollama still has it loaded
+    fn used_recently(&self, model_name: &str) -> bool {
+        let mut last_use = self.last_model_use.lock().unwrap_or_else(|e| e.into_inner());
+        let recent = last_use.as_ref()
+            .is_some_and(|(model, used_at)| model == model_name && used_at.elapsed() < OLLAMA_KEEP_ALIVE);
+        *last_use = Some((model_name.to_string(), Instant::now()));
+        recent
+    }
+    
+    /// Memory needed to load an installed model, or `None` if it's already loaded (its memory is
+    /// in use already) or Ollama can't say
+    async fn requirement_to_load(&self, model_name: &str) -> Option<ModelRequirement> {
+        let running: serde_json::Value = self.client.get(self.api_url("ps"))
+            .timeout(RESOURCE_LOOKUP_TIMEOUT)
+            .send().await.ok()?
+            .error_for_status().ok()?
+            .json().await.ok()?;
+        let loaded = running["models"].as_array()?.iter()
+            .filter_map(|model| model["name"].as_str())
+            .any(|name| model_names_match(model_name, name));
+        if loaded {
+            return None;
+        }
+        
+        if let Some(requirement) = self.load_requirements.lock().unwrap_or_else(|e| e.into_inner()).get(model_name) {
+            return Some(*requirement);
+        }
+        let models = self.list_models().await.ok()?;
+        let details = &models.iter().find(|m| model_names_match(model_name, &m.name))?.details;
+        let parameters = parse_parameter_size(&details.parameter_size)?;
+        let requirement = model_requirement(parameters, Some(&details.quantization_level));
+        self.load_requirements.lock().unwrap_or_else(|e| e.into_inner()).insert(model_name.to_string(), requirement);
+        Some(requirement)
+    }
+    
+    pub async fn is_model_installed(&self, model_name: &str) -> AppResult<bool> {
+        let models = self.list_models().await?;
+        Ok(models.iter().any(|m| model_names_match(model_name, &m.name)))
+    }
+    
+    pub fn get_model_name(&self) -> &str {
+        &self.config.model_name
+    }
+    
+    fn api_url(&self, endpoint: &str) -> String {
+        format!("{}/api/{}", self.config.base_url(), endpoint)
+    }
+    
+    /// Points the manager at a different Ollama host/port; every API call uses the new endpoint
+    pub fn set_endpoint(&mut self, host: String, port: u16) {
+        info!("Switching Ollama endpoint to {}:{}", host, port);
+        if host != self.config.host || port != self.config.port {
+            // Failures, the context window and the models' sizes were learned from the old server
+            self.breaker.reset();
+            self.context_length = None;
+            self.load_requirements.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            *self.last_model_use.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+        self.config.host = host;
+        self.config.port = port;
+    }
+    
+    /// Switches the chat model; `model_name` may be a tag or a configured alias
     pub fn set_model(&mut self, model_name: String) {
+        let model_name = self.resolve_model(&model_name);
+        if model_name != self.config.model_name {
+            self.context_length = None;
+        }
         info!("Switching to model: {}", model_name);
         self.config.model_name = model_name;
     }
     
-    pub async fn generate_response(&self, prompt: &str) -> AppResult<String> {
-        info!("Generating response with model: {}", self.config.model_name);
+    /// Asks Ollama for the current model's context window and remembers it. `None` when the
+    /// response doesn't say; callers should assume `DEFAULT_CONTEXT_LENGTH`.
+    pub async fn fetch_context_length(&mut self) -> AppResult<Option<u32>> {
+        let response = self.client
+            .post(self.api_url("show"))
+            .json(&serde_json::json!({ "name": self.config.model_name }))
+            .send()
+            .await?;
         
-        let url = format!("http://{}:{}/api/generate", self.config.host, self.config.port);
-        let payload = serde_json::json!({
-            "model": self.config.model_name,
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(AppError::OllamaError(format!("Ollama API error ({}): {}", status, error_text)));
+        }
+        
+        let show: serde_json::Value = response.json().await?;
+        self.context_length = parse_context_length(&show);
+        info!("Context length of {}: {:?}", self.config.model_name, self.context_length);
+        Ok(self.context_length)
+    }
+    
+    /// The current model's context window as last fetched, without asking Ollama
+    pub fn context_length(&self) -> Option<u32> {
+        self.context_length
+    }
+    
+    /// The model tag `name` refers to, resolving configured aliases
+    pub fn resolve_model(&self, name: &str) -> String {
+        resolve_model_alias(&self.config.model_aliases, name)
+    }
+    
+    pub fn set_model_aliases(&mut self, aliases: HashMap<String, String>) {
+        self.config.model_aliases = aliases;
+    }
+    
+    /// Turns the free memory and disk check before pulling or loading a model on or off
+    pub fn set_check_system_resources(&mut self, enabled: bool) {
+        self.config.check_system_resources = enabled;
+    }
+    
+    /// Caps the length of every generated reply (the chat's `max_tokens`)
+    pub fn set_max_response_tokens(&mut self, max_tokens: u32) {
+        self.max_response_tokens = Some(max_tokens);
+    }
+    
+    /// Body of an `/api/generate` request
+    fn generate_payload(&self, model_name: &str, prompt: &str, stream: bool) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "model": model_name,
             "prompt": prompt,
-            "stream": false
+            "stream": stream
         });
+        if let Some(max_tokens) = self.max_response_tokens {
+            payload["options"] = serde_json::json!({ "num_predict": max_tokens });
+        }
+        payload
+    }
+    
+    /// Counts a generation the caller gave up waiting for against the circuit breaker, so an
+    /// Ollama that accepts requests but never finishes them trips it too
+    pub fn record_generation_timeout(&self) {
+        self.breaker.record_failure();
+    }
+    
+    /// Sends a generation request through the circuit breaker. Connection failures and 5xx
+    /// responses count against it; any other response shows Ollama is up.
+    async fn send_generate_request(&self, request: reqwest::RequestBuilder) -> AppResult<reqwest::Response> {
+        self.breaker.check()?;
+        
+        match request.send().await {
+            Ok(response) => {
+                if response.status().is_server_error() {
+                    self.breaker.record_failure();
+                } else {
+                    self.breaker.record_success();
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                self.breaker.record_failure();
+                Err(AppError::OllamaError(format!("Failed to send request to Ollama: {}", e)))
+            }
+        }
+    }
+    
+    pub async fn generate_response(&self, prompt: &str) -> AppResult<String> {
+        self.generate_response_with_model(&self.config.model_name, prompt).await
+    }
+    
+    /// `generate_response` with `model_name` instead of the configured chat model
+    pub async fn generate_response_with_model(&self, model_name: &str, prompt: &str) -> AppResult<String> {
+        self.generate_with_format(model_name, prompt, None).await.map(|generation| generation.text)
+    }
+    
+    /// `generate_response_with_model`, also reporting whether the reply was cut off
+    pub async fn generate_with_model(&self, model_name: &str, prompt: &str) -> AppResult<Generation> {
+        self.generate_with_format(model_name, prompt, None).await
+    }
+    
+    /// Generates with Ollama's JSON mode and parses the result. JSON mode doesn't always stop a
+    /// model from producing something unparseable, so that is retried before giving up.
+    pub async fn generate_json(&self, prompt: &str) -> AppResult<serde_json::Value> {
+        let mut last_error = String::new();
+        for attempt in 1..=JSON_GENERATION_ATTEMPTS {
+            let text = self.generate_with_format(&self.config.model_name, prompt, Some("json")).await?.text;
+            match serde_json::from_str(text.trim()) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    warn!("JSON generation attempt {}/{} returned invalid JSON: {}", attempt, JSON_GENERATION_ATTEMPTS, e);
+                    last_error = e.to_string();
+                }
+            }
+        }
+        
+        Err(AppError::OllamaError(format!("The model didn't return valid JSON: {}", last_error)))
+    }
+    
+    /// Non-streaming generation; `format` is passed through as Ollama's output format (`"json"`)
+    async fn generate_with_format(&self, model_name: &str, prompt: &str, format: Option<&str>) -> AppResult<Generation> {
+        info!("Generating response with model: {}", model_name);
+        self.ensure_resources_for(model_name, false).await?;
+        
+        let url = self.api_url("generate");
+        let mut payload = self.generate_payload(model_name, prompt, false);
+        if let Some(format) = format {
+            payload["format"] = serde_json::Value::from(format);
+        }
         
         info!("Sending request to Ollama: {}", url);
         
-        let response = self.client
+        let response = self.send_generate_request(self.client
             .post(&url)
             .json(&payload)
             .timeout(Duration::from_secs(60)) // Add timeout
-            .send()
-            .await
-            .map_err(|e| AppError::OllamaError(format!("Failed to send request to Ollama: {}", e)))?;
+        ).await?;
         
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(AppError::OllamaError(format!("Ollama API error ({}): {}", status, error_text)));
+            return Err(generate_error(model_name, status, error_text));
         }
         
         let result: serde_json::Value = response.json().await
@@ -238,18 +964,154 @@ impl OllamaManager {
             return Err(AppError::OllamaError(format!("Ollama returned error: {}", error)));
         }
         
-        let response_text = result["response"]
-            .as_str()
-            .unwrap_or("No response generated")
-            .to_string();
+        let response_text = result["response"].as_str().unwrap_or_default().to_string();
         
-        if response_text.is_empty() || response_text == "No response generated" {
-            warn!("Empty or default response from Ollama. Full response: {:?}", result);
-            return Err(AppError::OllamaError("Ollama returned empty response".to_string()));
+        if response_text.trim().is_empty() {
+            warn!("Empty response from Ollama. Full response: {:?}", result);
+            return Err(self.empty_response(model_name, prompt, &result));
         }
         
         info!("Successfully generated response ({} chars)", response_text.len());
-        Ok(response_text)
+        Ok(Generation {
+            text: response_text,
+            done_reason: result["done_reason"].as_str().map(str::to_string),
+        })
+    }
+    
+    /// Generates a response with `stream: true`, invoking `on_token` for each piece of text
+    /// as it arrives. Returns the full concatenated response once Ollama reports `done`.
+    pub async fn generate_response_stream<F>(&self, prompt: &str, on_token: F) -> AppResult<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        self.generate_response_stream_with_model(&self.config.model_name, prompt, on_token).await
+    }
+    
+    /// `generate_response_stream` with `model_name` instead of the configured chat model
+    pub async fn generate_response_stream_with_model<F>(&self, model_name: &str, prompt: &str, on_token: F) -> AppResult<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        self.generate_stream_with_model(model_name, prompt, on_token).await.map(|generation| generation.text)
+    }
+    
+    /// `generate_response_stream_with_model`, also reporting whether the reply was cut off
+    pub async fn generate_stream_with_model<F>(&self, model_name: &str, prompt: &str, mut on_token: F) -> AppResult<Generation>
+    where
+        F: FnMut(&str) + Send,
+    {
+        info!("Generating streaming response with model: {}", model_name);
+        self.ensure_resources_for(model_name, false).await?;
+        
+        let url = self.api_url("generate");
+        let payload = self.generate_payload(model_name, prompt, true);
+        
+        // No overall request timeout here - long generations are bounded by the caller's deadline
+        let mut response = self.send_generate_request(self.client.post(&url).json(&payload)).await?;
+        
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(generate_error(model_name, status, error_text));
+        }
+        
+        let mut decoder = NdjsonDecoder::new();
+        let mut response_text = String::new();
+        let mut final_line = None;
+        
+        while final_line.is_none() {
+            let chunk = match response.chunk().await {
+                Ok(chunk) => chunk,
+                Err(e) if !response_text.is_empty() => {
+                    return Err(Self::incomplete_response(format!("Connection lost during generation: {}", e), response_text));
+                }
+                Err(e) => return Err(AppError::OllamaError(format!("Network error during generation: {}", e))),
+            };
+            
+            match chunk {
+                Some(bytes) => {
+                    for line in decoder.push(&bytes) {
+                        if let Some(last) = Self::handle_generate_line(&line, &mut response_text, &mut on_token)? {
+                            final_line = Some(last);
+                        }
+                    }
+                }
+                None => {
+                    // Stream closed; the last object may not have had a trailing newline
+                    if let Some(line) = decoder.finish() {
+                        final_line = Self::handle_generate_line(&line, &mut response_text, &mut on_token)?;
+                    }
+                    break;
+                }
+            }
+        }
+        
+        let Some(final_line) = final_line else {
+            // Without the final `done` object we can't tell a finished answer from a cut-off one
+            return Err(Self::incomplete_response("Ollama closed the stream before finishing".to_string(), response_text));
+        };
+        
+        if response_text.trim().is_empty() {
+            warn!("Empty streaming response from Ollama");
+            return Err(self.empty_response(model_name, prompt, &final_line));
+        }
+        
+        info!("Successfully generated streaming response ({} chars)", response_text.len());
+        Ok(Generation {
+            text: response_text,
+            done_reason: final_line["done_reason"].as_str().map(str::to_string),
+        })
+    }
+    
+    /// Error for a generation that finished without any text; `done` is Ollama's final response object
+    fn empty_response(&self, model_name: &str, prompt: &str, done: &serde_json::Value) -> AppError {
+        let diagnostic = EmptyResponseDiagnostic {
+            model: model_name.to_string(),
+            prompt_chars: prompt.chars().count(),
+            prompt_tokens: estimate_tokens(prompt),
+            prompt_eval_count: done["prompt_eval_count"].as_u64(),
+            // Only the configured chat model's window is looked up
+            context_length: self.context_length.filter(|_| model_names_match(model_name, &self.config.model_name)),
+            context_chunks: None,
+            done_reason: done["done_reason"].as_str().map(str::to_string),
+        };
+        AppError::EmptyResponse(Box::new(diagnostic))
+    }
+    
+    fn incomplete_response(message: String, partial: String) -> AppError {
+        warn!("{} ({} chars received)", message, partial.len());
+        AppError::IncompleteResponse {
+            message,
+            partial_response: if partial.is_empty() { None } else { Some(partial) },
+        }
+    }
+    
+    /// Applies one line of a streaming generate response. Returns the line if it was the final one,
+    /// which carries `done_reason` and the token counts.
+    fn handle_generate_line<F>(line: &str, response_text: &mut String, on_token: &mut F) -> AppResult<Option<serde_json::Value>>
+    where
+        F: FnMut(&str),
+    {
+        let json = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to parse streaming response line: '{}' - Error: {}", line, e);
+                return Ok(None);
+            }
+        };
+        
+        if let Some(error) = json["error"].as_str() {
+            return Err(AppError::OllamaError(format!("Ollama returned error: {}", error)));
+        }
+        
+        if let Some(token) = json["response"].as_str() {
+            if !token.is_empty() {
+                response_text.push_str(token);
+                on_token(token);
+            }
+        }
+        
+        Ok(json["done"].as_bool().unwrap_or(false).then_some(json))
     }
     
     pub async fn ensure_available(&mut self) -> AppResult<()> {
@@ -258,7 +1120,7 @@ impl OllamaManager {
         // Check if Ollama is already running
         if self.check_health().await.is_ok() {
             info!("Ollama is already running");
-            return Ok(());
+            return self.check_version_compatibility().await;
         }
         
         // Check if Ollama is installed
@@ -269,6 +1131,7 @@ impl OllamaManager {
         
         // Start Ollama service
         self.start_service().await?;
+        self.check_version_compatibility().await?;
         
         // Ensure we have at least one model
         self.ensure_model_available().await?;
@@ -276,6 +1139,33 @@ impl OllamaManager {
         Ok(())
     }
     
+    /// Errors if the running Ollama is older than `MIN_OLLAMA_VERSION`.
+    /// An unknown or unparseable version only logs a warning, since it may still work.
+    pub async fn check_version_compatibility(&self) -> AppResult<()> {
+        let version = match self.get_version().await {
+            Ok(version) => version,
+            Err(e) => {
+                warn!("Could not determine Ollama version: {}", e);
+                return Ok(());
+            }
+        };
+        
+        match ollama_version_compatible(&version) {
+            Some(true) => {
+                info!("Ollama version {} is compatible", version);
+                Ok(())
+            }
+            Some(false) => Err(AppError::OllamaError(format!(
+                "Ollama {} is too old; version {} or newer is required. Please update Ollama from https://ollama.com/download",
+                version, MIN_OLLAMA_VERSION
+            ))),
+            None => {
+                warn!("Unrecognized Ollama version '{}', assuming it is compatible", version);
+                Ok(())
+            }
+        }
+    }
+    
     pub fn shutdown(&mut self) -> AppResult<()> {
         if let Some(mut child) = self.process.take() {
             info!("Shutting down Ollama process");
@@ -306,8 +1196,12 @@ impl OllamaManager {
     }
     
     async fn install_ollama(&self) -> AppResult<()> {
+        self.ensure_online("install Ollama")?;
         info!("Installing Ollama for platform: {}", std::env::consts::OS);
         
+        // Clears any cancellation left over from a previous attempt
+        let _running = self.install_cancelled.start();
+        
         match std::env::consts::OS {
             "windows" => self.install_windows().await,
             "macos" => self.install_macos().await,
@@ -316,9 +1210,33 @@ impl OllamaManager {
                 format!("Unsupported platform: {}", std::env::consts::OS)
             )),
         }
-    }    
+    }
+    
+    fn check_install_cancelled(&self) -> AppResult<()> {
+        if self.install_cancelled.is_cancelled() {
+            info!("Ollama installation cancelled");
+            Err(AppError::InstallCancelled)
+        } else {
+            Ok(())
+        }
+    }
+    
+    /// Runs an install step, abandoning it as soon as the cancel flag is set.
+    /// Dropping the step's future also kills any installer process it spawned.
+    async fn run_cancellable<T, F>(&self, step: F) -> AppResult<T>
+    where
+        F: Future<Output = AppResult<T>>,
+    {
+        tokio::select! {
+            result = step => result,
+            _ = self.install_cancelled.cancelled() => {
+                info!("Ollama installation cancelled");
+                Err(AppError::InstallCancelled)
+            }
+        }
+    }
+    
     async fn install_windows(&self) -> AppResult<()> {
-        use std::process::Command;
         use std::env;
         
         info!("Installing Ollama on Windows");
@@ -328,39 +1246,65 @@ impl OllamaManager {
         let temp_dir = env::temp_dir();
         let installer_path = temp_dir.join("OllamaSetup.exe");
         
-        // Retry mechanism for downloads
-        const MAX_RETRIES: usize = 3;
-        let mut last_error = None;
+        let result = self.download_and_run_windows_installer(download_url, &installer_path).await;
         
-        for attempt in 1..=MAX_RETRIES {
-            info!("Downloading Ollama installer from: {} (attempt {}/{})", download_url, attempt, MAX_RETRIES);
-            
-            match self.download_installer_with_verification(download_url, &installer_path).await {
-                Ok(_) => break,
-                Err(e) => {
-                    warn!("Download attempt {} failed: {}", attempt, e);
-                    last_error = Some(e);
-                    
-                    if attempt < MAX_RETRIES {
-                        info!("Retrying download in 2 seconds...");
-                        sleep(Duration::from_secs(2)).await;
-                    }
-                }
+        if matches!(result, Err(AppError::InstallCancelled)) && installer_path.exists() {
+            // Don't leave a partial or unverified installer behind
+            match std::fs::remove_file(&installer_path) {
+                Ok(_) => info!("Removed installer after cancellation: {:?}", installer_path),
+                Err(e) => warn!("Failed to remove installer after cancellation: {}", e),
             }
         }
         
-        // If all retries failed, return the last error
-        if let Some(error) = last_error {
-            return Err(error);
+        result
+    }
+    
+    async fn download_and_run_windows_installer(&self, download_url: &str, installer_path: &std::path::Path) -> AppResult<()> {
+        // What went wrong on each attempt, reported together if the download never succeeds
+        let mut failures = Vec::new();
+        
+        for attempt in 1..=INSTALLER_DOWNLOAD_ATTEMPTS {
+            self.check_install_cancelled()?;
+            info!("Downloading Ollama installer from: {} (attempt {}/{})", download_url, attempt, INSTALLER_DOWNLOAD_ATTEMPTS);
+            
+            let failure = match self.run_cancellable(async {
+                Ok(self.download_installer_with_verification(download_url, installer_path).await)
+            }).await? {
+                Ok(()) => break,
+                Err(failure) => failure,
+            };
+            warn!("Download attempt {} failed: {}", attempt, failure.error());
+            failures.push(format!("attempt {}: {}", attempt, failure.error()));
+            
+            let retryable = matches!(failure, InstallerDownloadFailure::Transient(_));
+            if !retryable || attempt == INSTALLER_DOWNLOAD_ATTEMPTS {
+                return Err(AppError::OllamaError(format!(
+                    "Failed to download the Ollama installer{}: {}",
+                    if retryable { format!(" after {} attempts", attempt) } else { String::new() },
+                    failures.join("; ")
+                )));
+            }
+            
+            let delay = installer_backoff(attempt, random_fraction());
+            info!("Retrying download in {:?}", delay);
+            self.run_cancellable(async {
+                sleep(delay).await;
+                Ok(())
+            }).await?;
         }
         
+        self.check_install_cancelled()?;
         info!("Running Ollama installer");
         
         // Run the installer silently
-        let output = Command::new(&installer_path)
-            .args(&["/S"]) // Silent install
-            .output()
-            .map_err(|e| AppError::OllamaError(format!("Failed to run installer: {}", e)))?;
+        let output = self.run_cancellable(async {
+            tokio::process::Command::new(installer_path)
+                .args(["/S"]) // Silent install
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| AppError::OllamaError(format!("Failed to run installer: {}", e)))
+        }).await?;
         
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -370,7 +1314,7 @@ impl OllamaManager {
         }
         
         // Clean up installer
-        let _ = std::fs::remove_file(&installer_path);
+        let _ = std::fs::remove_file(installer_path);
         
         info!("Ollama installed successfully on Windows");
         
@@ -380,18 +1324,20 @@ impl OllamaManager {
         Ok(())
     }
     
-    async fn download_installer_with_verification(&self, url: &str, path: &std::path::Path) -> AppResult<()> {
+    async fn download_installer_with_verification(&self, url: &str, path: &std::path::Path) -> Result<(), InstallerDownloadFailure> {
+        use InstallerDownloadFailure::{Permanent, Transient};
+        
         // Download the installer
         let response = self.client
             .get(url)
             .send()
             .await
-            .map_err(|e| AppError::OllamaError(format!("Failed to download installer: {}", e)))?;
+            .map_err(|e| Transient(AppError::OllamaError(format!("Failed to download installer: {}", e))))?;
         
-        if !response.status().is_success() {
-            return Err(AppError::OllamaError(
-                format!("Failed to download installer: HTTP {}", response.status())
-            ));
+        let status = response.status();
+        if !status.is_success() {
+            let error = AppError::OllamaError(format!("Failed to download installer: HTTP {}", status));
+            return Err(if is_retryable_installer_status(status) { Transient(error) } else { Permanent(error) });
         }
         
         // Get content length for verification
@@ -399,17 +1345,26 @@ impl OllamaManager {
         
         // Read installer bytes
         let installer_bytes = response.bytes().await
-            .map_err(|e| AppError::OllamaError(format!("Failed to read installer: {}", e)))?;
-        
-        // Verify download integrity
-        self.verify_installer_integrity(&installer_bytes, content_length)?;
+            .map_err(|e| Transient(AppError::OllamaError(format!("Failed to read installer: {}", e))))?;
+        
+        // A body shorter than advertised was cut off in transit; anything else wrong with the file
+        // would be the same on every download
+        if let Some(expected) = content_length {
+            if installer_bytes.len() as u64 != expected {
+                return Err(Transient(AppError::OllamaError(format!(
+                    "Downloaded installer size mismatch: got {} bytes, expected {} bytes",
+                    installer_bytes.len(), expected
+                ))));
+            }
+        }
+        self.verify_installer_integrity(&installer_bytes, content_length).map_err(Permanent)?;
         
         // Store length before moving bytes
         let bytes_len = installer_bytes.len();
         
         // Save installer to temp file
         std::fs::write(path, installer_bytes)
-            .map_err(|e| AppError::OllamaError(format!("Failed to save installer: {}", e)))?;
+            .map_err(|e| Permanent(AppError::OllamaError(format!("Failed to save installer: {}", e))))?;
         
         info!("Installer downloaded and verified successfully ({} bytes)", bytes_len);
         Ok(())
@@ -455,16 +1410,18 @@ impl OllamaManager {
         Ok(())
     }    
     async fn install_macos(&self) -> AppResult<()> {
-        use std::process::Command;
-        
         info!("Installing Ollama on macOS");
         
         // Use curl to download and run the install script
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg("curl -fsSL https://ollama.ai/install.sh | sh")
-            .output()
-            .map_err(|e| AppError::OllamaError(format!("Failed to run install script: {}", e)))?;
+        let output = self.run_cancellable(async {
+            tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg("curl -fsSL https://ollama.ai/install.sh | sh")
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| AppError::OllamaError(format!("Failed to run install script: {}", e)))
+        }).await?;
         
         if !output.status.success() {
             let error_msg = String::from_utf8_lossy(&output.stderr);
@@ -480,16 +1437,18 @@ impl OllamaManager {
     }
     
     async fn install_linux(&self) -> AppResult<()> {
-        use std::process::Command;
-        
         info!("Installing Ollama on Linux");
         
         // Use the official install script
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg("curl -fsSL https://ollama.ai/install.sh | sh")
-            .output()
-            .map_err(|e| AppError::OllamaError(format!("Failed to run install script: {}", e)))?;
+        let output = self.run_cancellable(async {
+            tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg("curl -fsSL https://ollama.ai/install.sh | sh")
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| AppError::OllamaError(format!("Failed to run install script: {}", e)))
+        }).await?;
         
         if !output.status.success() {
             let error_msg = String::from_utf8_lossy(&output.stderr);
@@ -506,11 +1465,8 @@ impl OllamaManager {
     async fn ensure_model_available(&self) -> AppResult<()> {
         info!("Checking for available models");
         
-        let models = self.list_models().await?;
-        
         // Check if our configured model is available
-        let model_available = models.iter()
-            .any(|m| m.name.starts_with(&self.config.model_name));
+        let model_available = self.is_model_installed(&self.config.model_name).await?;
         
         if !model_available {
             info!("Model {} not found, downloading...", self.config.model_name);
@@ -524,11 +1480,14 @@ impl OllamaManager {
     
     pub async fn download_model_with_progress<F>(&self, model_name: &str, progress_callback: F) -> AppResult<()>
     where
-        F: Fn(f32, String) + Send + 'static,
+        F: Fn(DownloadProgress) + Send + 'static,
     {
+        self.ensure_online(&format!("download model {}", model_name))?;
+        self.ensure_resources_for(model_name, true).await?;
         info!("Downloading model with progress: {}", model_name);
+        let _running = self.download_cancelled.start();
         
-        let url = format!("http://{}:{}/api/pull", self.config.host, self.config.port);
+        let url = self.api_url("pull");
         let payload = serde_json::json!({
             "name": model_name,
             "stream": true
@@ -546,75 +1505,172 @@ impl OllamaManager {
             ));
         }
         
-        // Process streaming response with robust error handling
+        // Process streaming response, reassembling lines split across chunks
+        let mut decoder = NdjsonDecoder::new();
         let mut parse_errors = 0;
-        const MAX_PARSE_ERRORS: usize = 10;
+        let mut rate_tracker = DownloadRateTracker::new();
+        
+        loop {
+            let chunk_result = tokio::select! {
+                chunk = response.chunk() => chunk,
+                _ = self.download_cancelled.cancelled() => {
+                    info!("Download of model {} cancelled", model_name);
+                    return Err(AppError::Cancelled(format!("download of model {}", model_name)));
+                }
+            };
+            let Some(chunk_bytes) = chunk_result.transpose() else {
+                break;
+            };
+            let chunk_bytes = chunk_bytes.map_err(|e| {
+                error!("Error reading response chunk: {}", e);
+                AppError::OllamaError(format!("Network error during download: {}", e))
+            })?;
+            
+            for line in decoder.push(&chunk_bytes) {
+                Self::handle_pull_progress_line(&line, &progress_callback, &mut parse_errors, &mut rate_tracker)?;
+            }
+        }
         
-        while let Some(chunk_result) = response.chunk().await.transpose() {
-            match chunk_result {
-                Ok(chunk_bytes) => {
-                    match std::str::from_utf8(&chunk_bytes) {
-                        Ok(text) => {
-                            // Parse each line as JSON with error recovery
-                            for line in text.lines() {
-                                let line = line.trim();
-                                if line.is_empty() {
-                                    continue;
-                                }
-                                
-                                match serde_json::from_str::<serde_json::Value>(line) {
-                                    Ok(json) => {
-                                        // Reset parse error counter on successful parse
-                                        parse_errors = 0;
-                                        
-                                        if let Some(status) = json["status"].as_str() {
-                                            let total = json["total"].as_u64().unwrap_or(100) as f32;
-                                            let completed = json["completed"].as_u64().unwrap_or(0) as f32;
-                                            let progress = if total > 0.0 { completed / total } else { 0.0 };
-                                            progress_callback(progress.clamp(0.0, 1.0), status.to_string());
-                                        }
-                                        
-                                        // Check for error in the JSON response
-                                        if let Some(error) = json["error"].as_str() {
-                                            return Err(AppError::OllamaError(
-                                                format!("Ollama download error: {}", error)
-                                            ));
-                                        }
-                                    }
-                                    Err(e) => {
-                                        parse_errors += 1;
-                                        warn!("Failed to parse streaming response line: '{}' - Error: {}", line, e);
-                                        
-                                        // If we get too many parse errors, something is seriously wrong
-                                        if parse_errors >= MAX_PARSE_ERRORS {
-                                            return Err(AppError::OllamaError(
-                                                format!("Too many JSON parse errors ({}), aborting download", parse_errors)
-                                            ));
-                                        }
-                                        
-                                        // Continue processing other lines
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to decode chunk as UTF-8: {}", e);
-                            // Continue processing, this might be a partial UTF-8 sequence
-                            continue;
-                        }
+        if let Some(line) = decoder.finish() {
+            Self::handle_pull_progress_line(&line, &progress_callback, &mut parse_errors, &mut rate_tracker)?;
+        }
+        
+        info!("Model {} downloaded successfully", model_name);
+        Ok(())
+    }
+    
+    /// Downloads each of `model_names` in turn, skipping ones already installed. A failed download
+    /// doesn't stop the rest; each model's outcome is reported in the same order as `model_names`.
+    /// Fails only if the installed models can't be listed, i.e. Ollama isn't reachable.
+    pub async fn setup_models<F>(&self, model_names: &[String], progress_callback: F) -> AppResult<Vec<ModelSetupResult>>
+    where
+        F: Fn(ModelSetupProgress) + Send + Sync + 'static,
+    {
+        let installed = self.list_models().await?;
+        let _running = self.download_cancelled.start();
+        let progress_callback = Arc::new(progress_callback);
+        let model_count = model_names.len();
+        let mut results = Vec::with_capacity(model_count);
+        
+        for (model_index, model_name) in model_names.iter().enumerate() {
+            if installed.iter().any(|m| model_names_match(model_name, &m.name)) {
+                info!("Model {} is already installed, skipping", model_name);
+                results.push(ModelSetupResult {
+                    model_name: model_name.clone(),
+                    outcome: ModelSetupOutcome::AlreadyInstalled,
+                    error: None,
+                });
+                continue;
+            }
+            
+            if self.download_cancelled.is_cancelled() {
+                results.push(ModelSetupResult {
+                    model_name: model_name.clone(),
+                    outcome: ModelSetupOutcome::Cancelled,
+                    error: None,
+                });
+                continue;
+            }
+            
+            let callback = progress_callback.clone();
+            let name = model_name.clone();
+            let download = self.download_model_with_progress(model_name, move |download| {
+                callback(ModelSetupProgress {
+                    model_name: name.clone(),
+                    model_index,
+                    model_count,
+                    overall_fraction: (model_index as f32 + download.fraction.clamp(0.0, 1.0)) / model_count as f32,
+                    download,
+                });
+            }).await;
+            
+            results.push(match download {
+                Ok(()) => ModelSetupResult {
+                    model_name: model_name.clone(),
+                    outcome: ModelSetupOutcome::Downloaded,
+                    error: None,
+                },
+                Err(AppError::Cancelled(_)) => ModelSetupResult {
+                    model_name: model_name.clone(),
+                    outcome: ModelSetupOutcome::Cancelled,
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Failed to download model {}: {}", model_name, e);
+                    ModelSetupResult {
+                        model_name: model_name.clone(),
+                        outcome: ModelSetupOutcome::Failed,
+                        error: Some(e.to_string()),
                     }
                 }
-                Err(e) => {
-                    error!("Error reading response chunk: {}", e);
+            });
+        }
+        
+        Ok(results)
+    }
+    
+    fn handle_pull_progress_line<F>(
+        line: &str,
+        progress_callback: &F,
+        parse_errors: &mut usize,
+        rate_tracker: &mut DownloadRateTracker,
+    ) -> AppResult<()>
+    where
+        F: Fn(DownloadProgress),
+    {
+        const MAX_PARSE_ERRORS: usize = 10;
+        
+        let json = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(json) => json,
+            Err(e) => {
+                // Lines are always complete here, so a parse failure means the server sent bad data
+                *parse_errors += 1;
+                warn!("Failed to parse streaming response line: '{}' - Error: {}", line, e);
+                
+                // If we get too many parse errors, something is seriously wrong
+                if *parse_errors >= MAX_PARSE_ERRORS {
                     return Err(AppError::OllamaError(
-                        format!("Network error during download: {}", e)
+                        format!("Too many JSON parse errors ({}), aborting download", parse_errors)
                     ));
                 }
+                return Ok(());
             }
+        };
+        
+        // Reset parse error counter on successful parse
+        *parse_errors = 0;
+        
+        // Check for error in the JSON response
+        if let Some(error) = json["error"].as_str() {
+            return Err(AppError::OllamaError(
+                format!("Ollama download error: {}", error)
+            ));
+        }
+        
+        if let Some(status) = json["status"].as_str() {
+            let total_bytes = json["total"].as_u64();
+            let completed_bytes = json["completed"].as_u64();
+            let total = total_bytes.unwrap_or(100) as f32;
+            let completed = completed_bytes.unwrap_or(0) as f32;
+            let progress = if total > 0.0 { completed / total } else { 0.0 };
+            
+            let (bytes_per_second, eta_seconds) = match (completed_bytes, total_bytes) {
+                (Some(completed), Some(total)) => {
+                    rate_tracker.record(json["digest"].as_str(), completed, total, Instant::now())
+                }
+                _ => (None, None),
+            };
+            
+            progress_callback(DownloadProgress {
+                fraction: progress.clamp(0.0, 1.0),
+                status: status.to_string(),
+                completed_bytes,
+                total_bytes,
+                bytes_per_second,
+                eta_seconds,
+            });
         }
         
-        info!("Model {} downloaded successfully", model_name);
         Ok(())
     }
 }
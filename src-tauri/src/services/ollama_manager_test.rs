@@ -3,12 +3,15 @@ mod tests {
     use super::*;
     use crate::config::OllamaConfig;
     use crate::errors::{AppError, AppResult};
-    use crate::services::ollama_manager::OllamaManager;
+    use crate::services::ollama_manager::{
+        alias_for_model, installer_backoff, parse_context_length, is_retryable_installer_status, BreakerState, CircuitBreaker, ModelSetupOutcome, model_names_match, ollama_version_compatible, resolve_model_alias, parse_ollama_version, DownloadRateTracker, OllamaManager, DEFAULT_CONTEXT_LENGTH,
+    };
     use mockito::{Server, ServerGuard, Matcher};
+    use std::collections::HashMap;
     use serde_json::json;
 
     async fn create_test_manager() -> (OllamaManager, ServerGuard) {
-        let mut server = Server::new();
+        let server = Server::new_async().await;
         let mut manager = OllamaManager::new().await;
         
         // Override the config to use mockito server
@@ -88,6 +91,7 @@ mod tests {
         
         assert!(status.is_running);
         assert_eq!(status.version, Some("0.1.0".to_string()));
+        assert_eq!(status.compatible, Some(false));
         assert_eq!(status.models.len(), 1);
         assert_eq!(status.models[0].name, "llama3.2:3b");
     }
@@ -181,4 +185,776 @@ mod tests {
         
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_download_model_with_progress_handles_split_lines() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let _mock = server.mock("POST", "/api/pull")
+            .with_status(200)
+            .with_chunked_body(|w| {
+                // Break lines mid-object and mid-character to mimic arbitrary chunk boundaries
+                w.write_all(b"{\"status\":\"pulling manifest\"}\n{\"status\":\"downl")?;
+                w.flush()?;
+                w.write_all(b"oading \xe2\x9b")?;
+                w.flush()?;
+                w.write_all(b"\x8f\",\"completed\":50,\"total\":100}\n{\"status\":\"success\"}")
+            })
+            .create_async()
+            .await;
+        
+        let updates = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let updates_clone = updates.clone();
+        
+        let result = manager.download_model_with_progress("phi3:mini", move |progress| {
+            updates_clone.lock().unwrap().push((progress.fraction, progress.status));
+        }).await;
+        
+        assert!(result.is_ok());
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[1].1, "downloading \u{26CF}");
+        assert!((updates[1].0 - 0.5).abs() < f32::EPSILON);
+        assert_eq!(updates[2].1, "success");
+    }
+
+    #[tokio::test]
+    async fn test_install_step_aborts_when_cancelled() {
+        let (manager, _server) = create_test_manager().await;
+        let cancel_flag = manager.install_cancel_handle();
+        
+        let cancel = async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            cancel_flag.cancel();
+        };
+        
+        // A step that would otherwise never finish, like a stalled download
+        let stalled_step = manager.run_cancellable(std::future::pending::<AppResult<()>>());
+        
+        let (result, _) = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            async { tokio::join!(stalled_step, cancel) },
+        ).await.expect("cancellation should end the stalled step");
+        
+        assert!(matches!(result, Err(AppError::InstallCancelled)));
+        assert!(manager.check_install_cancelled().is_err());
+    }
+
+    #[test]
+    fn test_model_names_match_exact() {
+        assert!(model_names_match("phi3:mini", "phi3:mini"));
+        assert!(model_names_match("llama3.2:3b", "llama3.2:3b"));
+        assert!(model_names_match("Phi3:Mini", "phi3:mini"));
+    }
+
+    #[test]
+    fn test_model_names_match_missing_tag_is_latest() {
+        assert!(model_names_match("phi3", "phi3:latest"));
+        assert!(model_names_match("nomic-embed-text:latest", "nomic-embed-text"));
+        assert!(model_names_match("phi3", "phi3"));
+    }
+
+    #[test]
+    fn test_model_names_match_different_tags() {
+        // A bare name must not match a specific variant, and vice versa
+        assert!(!model_names_match("phi3", "phi3:mini"));
+        assert!(!model_names_match("phi3:mini", "phi3"));
+        assert!(!model_names_match("llama3.2:3b", "llama3.2"));
+        assert!(!model_names_match("llama3.2", "llama3.2:3b"));
+        // Prefixes of the base name don't count either
+        assert!(!model_names_match("phi3:mini", "phi3:mini-128k"));
+        assert!(!model_names_match("llama3", "llama3.2:latest"));
+    }
+
+    #[tokio::test]
+    async fn test_is_model_installed_uses_tag_matching() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let _mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({
+                "models": [{
+                    "name": "phi3:mini",
+                    "size": 1500000000,
+                    "digest": "def456",
+                    "details": { "parameter_size": "3.8B", "quantization_level": "Q4_K_M", "family": "phi" }
+                }]
+            }).to_string())
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        
+        assert!(manager.is_model_installed("phi3:mini").await.unwrap());
+        assert!(!manager.is_model_installed("phi3").await.unwrap());
+    }
+
+    #[test]
+    fn test_download_rate_waits_for_enough_data() {
+        let mut tracker = DownloadRateTracker::new();
+        let start = tokio::time::Instant::now();
+        
+        assert_eq!(tracker.record(Some("sha256:a"), 0, 1_000_000, start), (None, None));
+        // A burst right at the start shouldn't produce an estimate
+        let (rate, eta) = tracker.record(Some("sha256:a"), 200_000, 1_000_000, start + std::time::Duration::from_millis(500));
+        assert!(rate.is_none());
+        assert!(eta.is_none());
+    }
+
+    #[test]
+    fn test_download_rate_and_eta() {
+        let mut tracker = DownloadRateTracker::new();
+        let start = tokio::time::Instant::now();
+        
+        tracker.record(Some("sha256:a"), 0, 1_000_000, start);
+        tracker.record(Some("sha256:a"), 100_000, 1_000_000, start + std::time::Duration::from_secs(1));
+        let (rate, eta) = tracker.record(Some("sha256:a"), 200_000, 1_000_000, start + std::time::Duration::from_secs(2));
+        
+        assert!((rate.unwrap() - 100_000.0).abs() < 1.0);
+        assert_eq!(eta, Some(8));
+    }
+
+    #[test]
+    fn test_download_rate_is_averaged_over_window() {
+        let mut tracker = DownloadRateTracker::new();
+        let start = tokio::time::Instant::now();
+        
+        // Steady 100 KB/s for 20 seconds, then a single fast second
+        for second in 0..=20u64 {
+            tracker.record(Some("sha256:a"), second * 100_000, 10_000_000, start + std::time::Duration::from_secs(second));
+        }
+        let (rate, _) = tracker.record(Some("sha256:a"), 3_000_000, 10_000_000, start + std::time::Duration::from_secs(21));
+        let rate = rate.unwrap();
+        
+        // The spike is smoothed out rather than reported as 1 MB/s
+        assert!(rate > 100_000.0 && rate < 200_000.0, "rate was {}", rate);
+    }
+
+    #[test]
+    fn test_download_rate_resets_on_new_layer() {
+        let mut tracker = DownloadRateTracker::new();
+        let start = tokio::time::Instant::now();
+        
+        tracker.record(Some("sha256:a"), 0, 1_000, start);
+        tracker.record(Some("sha256:a"), 1_000, 1_000, start + std::time::Duration::from_secs(3));
+        
+        // The next layer starts from zero; old samples mustn't skew its rate
+        let (rate, eta) = tracker.record(Some("sha256:b"), 0, 5_000_000, start + std::time::Duration::from_secs(4));
+        assert!(rate.is_none());
+        assert!(eta.is_none());
+    }
+
+    #[test]
+    fn test_parse_ollama_version() {
+        assert_eq!(parse_ollama_version("0.1.32").unwrap().core, (0, 1, 32));
+        assert_eq!(parse_ollama_version(" v0.3.0 ").unwrap().core, (0, 3, 0));
+        assert_eq!(parse_ollama_version("0.2").unwrap().core, (0, 2, 0));
+        assert_eq!(parse_ollama_version("1.10.2+build5").unwrap().core, (1, 10, 2));
+        
+        let prerelease = parse_ollama_version("0.5.0-rc1").unwrap();
+        assert_eq!(prerelease.core, (0, 5, 0));
+        assert!(prerelease.prerelease);
+        
+        assert!(parse_ollama_version("unknown").is_none());
+        assert!(parse_ollama_version("").is_none());
+    }
+
+    #[test]
+    fn test_ollama_version_compatible() {
+        assert_eq!(ollama_version_compatible("0.1.14"), Some(true));
+        assert_eq!(ollama_version_compatible("0.1.32"), Some(true));
+        assert_eq!(ollama_version_compatible("0.10.0"), Some(true));
+        assert_eq!(ollama_version_compatible("1.0.0-rc2"), Some(true));
+        assert_eq!(ollama_version_compatible("0.0.0"), Some(true));
+        
+        assert_eq!(ollama_version_compatible("0.1.13"), Some(false));
+        assert_eq!(ollama_version_compatible("0.1.14-rc1"), Some(false));
+        assert_eq!(ollama_version_compatible("0.1"), Some(false));
+        
+        assert_eq!(ollama_version_compatible("unknown"), None);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_available_rejects_old_ollama() {
+        let (mut manager, mut server) = create_test_manager().await;
+        
+        let _tags_mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"models":[]}"#)
+            .create_async()
+            .await;
+        let _version_mock = server.mock("GET", "/api/version")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"version":"0.1.10"}"#)
+            .create_async()
+            .await;
+        
+        let result = manager.ensure_available().await;
+        
+        match result {
+            Err(AppError::OllamaError(message)) => {
+                assert!(message.contains("0.1.10"));
+                assert!(message.contains("update"));
+            }
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ensure_available_accepts_current_ollama() {
+        let (mut manager, mut server) = create_test_manager().await;
+        
+        let _tags_mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"models":[]}"#)
+            .create_async()
+            .await;
+        let _version_mock = server.mock("GET", "/api/version")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"version":"0.3.12"}"#)
+            .create_async()
+            .await;
+        
+        assert!(manager.ensure_available().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_set_endpoint_applies_to_all_api_calls() {
+        let (mut manager, _old_server) = create_test_manager().await;
+        let mut server = Server::new_async().await;
+        
+        let tags_mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"models":[]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let version_mock = server.mock("GET", "/api/version")
+            .with_status(200)
+            .with_body(r#"{"version":"0.3.12"}"#)
+            .create_async()
+            .await;
+        let generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(r#"{"response":"Hello","done":true}"#)
+            .create_async()
+            .await;
+        let pull_mock = server.mock("POST", "/api/pull")
+            .with_status(200)
+            .with_body(r#"{"status":"success"}"#)
+            .create_async()
+            .await;
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        manager.set_endpoint(host.to_string(), port.parse().unwrap());
+        
+        assert!(manager.check_health().await.is_ok());
+        assert!(manager.list_models().await.unwrap().is_empty());
+        assert!(manager.check_version_compatibility().await.is_ok());
+        assert_eq!(manager.generate_response("Hi").await.unwrap(), "Hello");
+        assert!(manager.download_model("phi3:mini").await.is_ok());
+        
+        tags_mock.assert_async().await;
+        version_mock.assert_async().await;
+        generate_mock.assert_async().await;
+        pull_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_done_is_incomplete() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        // The connection ends after two tokens, without the final `done: true` object
+        let _mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body("{\"response\":\"Knap a \",\"done\":false}\n{\"response\":\"flint\",\"done\":false}\n")
+            .create_async()
+            .await;
+        
+        let mut tokens = Vec::new();
+        let err = manager.generate_response_stream("How do I make a knife?", |token| tokens.push(token.to_string()))
+            .await
+            .unwrap_err();
+        
+        assert_eq!(err.code(), "INCOMPLETE");
+        assert_eq!(err.details().unwrap()["partial_response"], "Knap a flint");
+        assert_eq!(tokens, vec!["Knap a ", "flint"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_done_is_complete() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let _mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body("{\"response\":\"Knap a \",\"done\":false}\n{\"response\":\"flint\",\"done\":false}\n{\"response\":\"\",\"done\":true,\"eval_count\":2}")
+            .create_async()
+            .await;
+        
+        let response = manager.generate_response_stream("How do I make a knife?", |_| {}).await.unwrap();
+        assert_eq!(response, "Knap a flint");
+    }
+
+    #[tokio::test]
+    async fn test_downloads_fail_fast_in_offline_mode() {
+        let (mut manager, mut server) = create_test_manager().await;
+        let pull_mock = server.mock("POST", "/api/pull")
+            .expect(0)
+            .create_async()
+            .await;
+        manager.set_offline(true);
+        
+        let err = manager.download_model("phi3:mini").await.unwrap_err();
+        assert_eq!(err.code(), "OFFLINE_MODE");
+        let err = manager.download_model_with_progress("phi3:mini", |_| {}).await.unwrap_err();
+        assert_eq!(err.code(), "OFFLINE_MODE");
+        let err = manager.install_ollama().await.unwrap_err();
+        assert_eq!(err.code(), "OFFLINE_MODE");
+        
+        pull_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_local_ollama_is_used_in_offline_mode() {
+        let (mut manager, mut server) = create_test_manager().await;
+        let _generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(r#"{"response":"Hello","done":true}"#)
+            .create_async()
+            .await;
+        manager.set_offline(true);
+        
+        assert_eq!(manager.generate_response("Hi").await.unwrap(), "Hello");
+    }
+
+    fn test_aliases() -> HashMap<String, String> {
+        HashMap::from([
+            ("Fast (Phi-3)".to_string(), "phi3:mini".to_string()),
+            ("Smart".to_string(), "llama3.2:3b".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_resolve_model_alias() {
+        let aliases = test_aliases();
+        assert_eq!(resolve_model_alias(&aliases, "Fast (Phi-3)"), "phi3:mini");
+        assert_eq!(resolve_model_alias(&aliases, " smart "), "llama3.2:3b");
+        // Raw tags and unknown names resolve to themselves
+        assert_eq!(resolve_model_alias(&aliases, "phi3:mini"), "phi3:mini");
+        assert_eq!(resolve_model_alias(&aliases, "mistral"), "mistral");
+        
+        assert_eq!(alias_for_model(&aliases, "llama3.2:3b").as_deref(), Some("Smart"));
+        assert_eq!(alias_for_model(&aliases, "mistral:latest"), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_model_accepts_alias_and_list_models_reports_it() {
+        let (mut manager, mut server) = create_test_manager().await;
+        manager.set_model_aliases(test_aliases());
+        
+        manager.set_model("Smart".to_string());
+        assert_eq!(manager.config.model_name, "llama3.2:3b");
+        
+        let _mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(json!({
+                "models": [
+                    { "name": "phi3:mini", "size": 1, "digest": "a", "details": { "parameter_size": "3.8B", "quantization_level": "Q4_0", "family": "phi3" } },
+                    { "name": "mistral:latest", "size": 1, "digest": "b", "details": { "parameter_size": "7B", "quantization_level": "Q4_0", "family": "llama" } }
+                ]
+            }).to_string())
+            .create_async()
+            .await;
+        
+        let models = manager.list_models().await.unwrap();
+        assert_eq!(models[0].name, "phi3:mini");
+        assert_eq!(models[0].alias.as_deref(), Some("Fast (Phi-3)"));
+        assert_eq!(models[1].alias, None);
+    }
+
+    async fn create_breaker_test_manager(threshold: u32, cooldown_secs: u64) -> (OllamaManager, ServerGuard) {
+        let server = Server::new_async().await;
+        let url = server.url();
+        let parts: Vec<&str> = url.trim_start_matches("http://").split(':').collect();
+        let manager = OllamaManager::with_config(OllamaConfig {
+            host: parts[0].to_string(),
+            port: parts[1].parse().unwrap(),
+            breaker_failure_threshold: threshold,
+            breaker_cooldown_secs: cooldown_secs,
+            ..OllamaConfig::default()
+        }).await;
+        
+        (manager, server)
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast_after_repeated_failures() {
+        let (manager, mut server) = create_breaker_test_manager(2, 600).await;
+        
+        // Only the two requests before the breaker opens should reach the server
+        let generate_mock = server.mock("POST", "/api/generate")
+            .with_status(500)
+            .with_body("model runner crashed")
+            .expect(2)
+            .create_async()
+            .await;
+        
+        for _ in 0..2 {
+            let err = manager.generate_response("Hi").await.unwrap_err();
+            assert_eq!(err.code(), "OLLAMA_ERROR");
+        }
+        
+        let started = std::time::Instant::now();
+        let err = manager.generate_response_stream("Hi", |_| {}).await.unwrap_err();
+        assert_eq!(err.code(), "SERVICE_UNAVAILABLE");
+        assert!(err.details().unwrap()["retry_after_secs"].as_u64().unwrap() > 0);
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+        generate_mock.assert_async().await;
+        
+        let breaker = manager.breaker.status();
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.consecutive_failures, 2);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_probe_success_closes_it() {
+        let (manager, mut server) = create_breaker_test_manager(1, 0).await;
+        
+        let failing = server.mock("POST", "/api/generate")
+            .with_status(503)
+            .create_async()
+            .await;
+        manager.generate_response("Hi").await.unwrap_err();
+        failing.remove_async().await;
+        
+        // With no cooldown the breaker is already half-open, so the next request probes Ollama
+        assert_eq!(manager.breaker.status().state, BreakerState::HalfOpen);
+        let _ok = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "Hello", "done": true }).to_string())
+            .create_async()
+            .await;
+        assert_eq!(manager.generate_response("Hi").await.unwrap(), "Hello");
+        
+        let breaker = manager.breaker.status();
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_half_open_circuit_breaker_lets_one_probe_through() {
+        let breaker = CircuitBreaker::new(1, std::time::Duration::from_millis(50));
+        breaker.record_failure();
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+        
+        let results: Vec<AppResult<()>> = std::thread::scope(|scope| {
+            let checks: Vec<_> = (0..2).map(|_| scope.spawn(|| breaker.check())).collect();
+            checks.into_iter().map(|check| check.join().unwrap()).collect()
+        });
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        let rejected = results.into_iter().find_map(Result::err).unwrap();
+        assert_eq!(rejected.code(), "SERVICE_UNAVAILABLE");
+        
+        // Once the probe succeeds everything goes through again
+        breaker.record_success();
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_setup_models_skips_installed_and_reports_failures() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let _tags = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(json!({
+                "models": [
+                    { "name": "nomic-embed-text:latest", "size": 1, "digest": "a", "details": { "parameter_size": "137M", "quantization_level": "F16", "family": "nomic-bert" } }
+                ]
+            }).to_string())
+            .create_async()
+            .await;
+        let _pull_ok = server.mock("POST", "/api/pull")
+            .match_body(Matcher::PartialJson(json!({ "name": "phi3:mini" })))
+            .with_status(200)
+            .with_body("{\"status\":\"downloading\",\"completed\":50,\"total\":100}\n{\"status\":\"success\"}\n")
+            .create_async()
+            .await;
+        let _pull_missing = server.mock("POST", "/api/pull")
+            .match_body(Matcher::PartialJson(json!({ "name": "no-such-model" })))
+            .with_status(200)
+            .with_body("{\"error\":\"pull model manifest: file does not exist\"}\n")
+            .create_async()
+            .await;
+        
+        let updates = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let updates_clone = updates.clone();
+        let models = vec!["phi3:mini".to_string(), "nomic-embed-text".to_string(), "no-such-model".to_string()];
+        let results = manager.setup_models(&models, move |progress| {
+            updates_clone.lock().unwrap().push((progress.model_index, progress.overall_fraction));
+        }).await.unwrap();
+        
+        let outcomes: Vec<ModelSetupOutcome> = results.iter().map(|r| r.outcome).collect();
+        assert_eq!(outcomes, vec![ModelSetupOutcome::Downloaded, ModelSetupOutcome::AlreadyInstalled, ModelSetupOutcome::Failed]);
+        assert!(results[2].error.as_deref().unwrap().contains("file does not exist"));
+        
+        // Halfway through the first of three models is a sixth of the way overall
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates[0].0, 0);
+        assert!((updates[0].1 - 0.5 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_installer_backoff_grows_and_is_capped() {
+        use std::time::Duration;
+        
+        // Without jitter each wait is half the exponential step
+        assert_eq!(installer_backoff(1, 0.0), Duration::from_millis(500));
+        assert_eq!(installer_backoff(2, 0.0), Duration::from_secs(1));
+        assert_eq!(installer_backoff(3, 1.0), Duration::from_secs(4));
+        assert_eq!(installer_backoff(20, 1.0), Duration::from_secs(30));
+        assert_eq!(installer_backoff(u32::MAX, 1.0), Duration::from_secs(30));
+        
+        // Jitter spreads the wait over the upper half of the step
+        let jittered = installer_backoff(3, 0.5);
+        assert!(jittered > installer_backoff(3, 0.0) && jittered < installer_backoff(3, 1.0));
+    }
+
+    #[test]
+    fn test_only_transient_installer_statuses_are_retried() {
+        use reqwest::StatusCode;
+        
+        assert!(is_retryable_installer_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable_installer_status(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable_installer_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_installer_status(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_retryable_installer_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_installer_status(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn test_parse_context_length_prefers_num_ctx() {
+        // Without num_ctx Ollama runs its default window, however long the model was trained for
+        let trained_only = json!({ "model_info": { "general.architecture": "llama", "llama.context_length": 131072 } });
+        assert_eq!(parse_context_length(&trained_only), Some(DEFAULT_CONTEXT_LENGTH));
+        let trained_short = json!({ "model_info": { "llama.context_length": 2048 } });
+        assert_eq!(parse_context_length(&trained_short), Some(2048));
+        
+        let with_num_ctx = json!({
+            "parameters": "stop                           \"<|eot_id|>\"\nnum_ctx                        8192",
+            "model_info": { "llama.context_length": 131072 }
+        });
+        assert_eq!(parse_context_length(&with_num_ctx), Some(8192));
+        
+        assert_eq!(parse_context_length(&json!({ "parameters": "temperature 0.7" })), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_context_length_is_remembered_until_model_changes() {
+        let (mut manager, mut server) = create_test_manager().await;
+        let _mock = server.mock("POST", "/api/show")
+            .with_status(200)
+            .with_body(json!({ "model_info": { "phi3.context_length": 4096 } }).to_string())
+            .create_async()
+            .await;
+        
+        assert_eq!(manager.fetch_context_length().await.unwrap(), Some(4096));
+        assert_eq!(manager.context_length(), Some(4096));
+        
+        manager.set_model("mistral:latest".to_string());
+        assert_eq!(manager.context_length(), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_endpoint_resets_breaker_and_context_length() {
+        let (mut manager, mut server) = create_breaker_test_manager(1, 600).await;
+        let _show = server.mock("POST", "/api/show")
+            .with_status(200)
+            .with_body(json!({ "model_info": { "phi3.context_length": 4096 } }).to_string())
+            .create_async()
+            .await;
+        let _failing = server.mock("POST", "/api/generate")
+            .with_status(500)
+            .create_async()
+            .await;
+        manager.fetch_context_length().await.unwrap();
+        manager.generate_response("Hi").await.unwrap_err();
+        assert_eq!(manager.breaker.status().state, BreakerState::Open);
+        
+        let mut new_server = Server::new_async().await;
+        let _ok = new_server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "Hello", "done": true }).to_string())
+            .create_async()
+            .await;
+        let address = new_server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        manager.set_endpoint(host.to_string(), port.parse().unwrap());
+        
+        assert_eq!(manager.context_length(), None);
+        assert_eq!(manager.breaker.status().state, BreakerState::Closed);
+        assert_eq!(manager.generate_response("Hi").await.unwrap(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_generate_json_sends_format_and_retries_invalid_output() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let invalid_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "Sure! Here is the recipe:", "done": true }).to_string())
+            .match_body(Matcher::PartialJsonString(r#"{"format":"json","stream":false}"#.to_string()))
+            .expect(1)
+            .create_async()
+            .await;
+        let valid_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "{\"output\": \"Anvil\", \"ingredients\": [\"Iron ingot\"]}", "done": true }).to_string())
+            .match_body(Matcher::PartialJsonString(r#"{"format":"json"}"#.to_string()))
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let value = manager.generate_json("Extract the anvil recipe").await.unwrap();
+        
+        invalid_mock.assert_async().await;
+        valid_mock.assert_async().await;
+        assert_eq!(value["output"], "Anvil");
+        assert_eq!(value["ingredients"][0], "Iron ingot");
+    }
+
+    #[tokio::test]
+    async fn test_generate_json_gives_up_after_retry() {
+        let (manager, mut server) = create_test_manager().await;
+        
+        let mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "not json", "done": true }).to_string())
+            .expect(2)
+            .create_async()
+            .await;
+        
+        let result = manager.generate_json("Extract the anvil recipe").await;
+        
+        mock.assert_async().await;
+        assert!(matches!(result, Err(AppError::OllamaError(message)) if message.contains("valid JSON")));
+    }
+
+    fn low_memory(_: &std::path::Path) -> crate::services::system_resources::SystemResources {
+        crate::services::system_resources::SystemResources {
+            available_memory: 4_000_000_000,
+            available_disk: Some(500_000_000_000),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_low_memory_blocks_a_large_pull() {
+        let (mut manager, mut server) = create_test_manager().await;
+        manager.resource_probe = low_memory;
+        let pull_mock = server.mock("POST", "/api/pull")
+            .match_body(Matcher::PartialJson(json!({ "name": "qwen2.5:0.5b" })))
+            .with_status(200)
+            .with_body("{\"status\":\"success\"}\n")
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let err = manager.download_model_with_progress("llama3.1:70b", |_| {}).await.unwrap_err();
+        assert_eq!(err.code(), "INSUFFICIENT_RESOURCES");
+        let shortfall = err.details().unwrap()["shortfall_bytes"].as_u64().unwrap();
+        assert!(shortfall > 30_000_000_000, "a 70B model needs far more than 4 GB, got a shortfall of {}", shortfall);
+        
+        // A small model still fits
+        manager.download_model_with_progress("qwen2.5:0.5b", |_| {}).await.unwrap();
+        pull_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resource_guard_can_be_overridden() {
+        let (mut manager, mut server) = create_test_manager().await;
+        manager.resource_probe = low_memory;
+        manager.set_check_system_resources(false);
+        let pull_mock = server.mock("POST", "/api/pull")
+            .with_status(200)
+            .with_body("{\"status\":\"success\"}\n")
+            .expect(1)
+            .create_async()
+            .await;
+        
+        manager.download_model_with_progress("llama3.1:70b", |_| {}).await.unwrap();
+        pull_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_low_memory_only_warns_before_loading_a_large_model() {
+        let (mut manager, mut server) = create_test_manager().await;
+        manager.resource_probe = low_memory;
+        // Checked once; the second request finds the model loaded from the first
+        let ps_mock = server.mock("GET", "/api/ps")
+            .with_status(200)
+            .with_body(r#"{"models":[]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let tags_mock = server.mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(json!({ "models": [{
+                "name": "llama3.1:70b",
+                "size": 42_000_000_000u64,
+                "digest": "abc",
+                "details": { "parameter_size": "70.6B", "quantization_level": "Q4_K_M", "family": "llama" }
+            }] }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let generate_mock = server.mock("POST", "/api/generate")
+            .with_status(200)
+            .with_body(json!({ "response": "Feed it bones.", "done": true }).to_string())
+            .expect(2)
+            .create_async()
+            .await;
+        
+        // Ollama may load it into GPU memory, so low RAM doesn't stop it
+        for _ in 0..2 {
+            let response = manager.generate_response_with_model("llama3.1:70b", "How do I tame a wolf?").await.unwrap();
+            assert_eq!(response, "Feed it bones.");
+        }
+        generate_mock.assert_async().await;
+        ps_mock.assert_async().await;
+        tags_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_reply_cut_at_max_tokens_is_truncated() {
+        let (mut manager, mut server) = create_test_manager().await;
+        manager.set_max_response_tokens(16);
+        
+        let _stream = server.mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJson(json!({ "stream": true, "options": { "num_predict": 16 } })))
+            .with_status(200)
+            .with_body("{\"response\":\"Iron is smelted\",\"done\":false}\n{\"response\":\"\",\"done\":true,\"done_reason\":\"length\"}\n")
+            .create_async()
+            .await;
+        let _single = server.mock("POST", "/api/generate")
+            .match_body(Matcher::PartialJson(json!({ "stream": false, "options": { "num_predict": 16 } })))
+            .with_status(200)
+            .with_body(json!({ "response": "Iron is smelted", "done": true, "done_reason": "length" }).to_string())
+            .create_async()
+            .await;
+        
+        let streamed = manager.generate_stream_with_model("phi3:mini", "How do I smelt iron?", |_| {}).await.unwrap();
+        assert!(streamed.truncated());
+        let generated = manager.generate_with_model("phi3:mini", "How do I smelt iron?").await.unwrap();
+        assert!(generated.truncated());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::AppConfig;
+use crate::errors::{AppError, AppResult};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Disk space one stored page is budgeted: wiki pages are around 100-150 KB of HTML, and sled
+/// can take a few times that while old versions are still around
+pub const ESTIMATED_BYTES_PER_PAGE: u64 = 500_000;
+
+/// A page's HTML as the wiki served it, kept so it can be parsed again without re-fetching
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredPage {
+    pub url: String,
+    pub html: String,
+    pub last_modified: Option<String>,
+    pub fetched_at: String,
+}
+
+/// Raw HTML of scraped pages keyed by URL, separate from the vector store so it can be
+/// dropped without touching the knowledge base
+pub struct PageStore {
+    db: Db,
+}
+
+impl PageStore {
+    pub fn new() -> AppResult<Self> {
+        Self::open_at(&AppConfig::get_data_dir().join("page_store"))
+    }
+    
+    pub fn open_at(path: &Path) -> AppResult<Self> {
+        std::fs::create_dir_all(path)
+            .map_err(|e| AppError::StorageError(format!("Failed to create page store directory: {}", e)))?;
+        info!("Opening page store at: {:?}", path);
+    
+        let db = sled::open(path)
+            .map_err(|e| AppError::StorageError(format!("Failed to open page store: {}", e)))?;
+        Ok(Self { db })
+    }
+    
+    pub fn new_temporary() -> Self {
+        let db = sled::Config::new().temporary(true).open()
+            .expect("Failed to create temporary page store");
+        Self { db }
+    }
+    
+    /// Stores `page`, replacing any earlier copy of the same URL
+    pub fn save(&self, page: &StoredPage) -> AppResult<()> {
+        let value = bincode::serialize(page)
+            .map_err(|e| AppError::StorageError(format!("Failed to serialize page: {}", e)))?;
+        self.db.insert(page.url.as_bytes(), value)
+            .map_err(|e| AppError::StorageError(format!("Failed to store page HTML: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Iterates over every stored page, skipping entries that fail to read or deserialize
+    pub fn iter(&self) -> impl Iterator<Item = StoredPage> + '_ {
+        self.db.iter().filter_map(|result| match result {
+            Ok((_, value)) => bincode::deserialize(&value).ok(),
+            Err(e) => {
+                error!("Error reading from page store: {}", e);
+                None
+            }
+        })
+    }
+    
+    /// URLs of every stored page, without reading the pages themselves
+    pub fn urls(&self) -> HashSet<String> {
+        self.db.iter().keys()
+            .filter_map(|key| key.ok())
+            .map(|key| String::from_utf8_lossy(&key).into_owned())
+            .collect()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+}
@@ -1,11 +1,12 @@
 #[cfg(test)]
 mod rag_integration_tests {
     use crate::services::{
-        wiki_service::{WikiService, WikiPage},
+        wiki_service::{PageKind, WikiService, WikiPage},
         embedding_service::EmbeddingService,
         chat_service::ChatService,
         ollama_manager::OllamaManager,
     };
+    use std::collections::HashMap;
     use std::sync::Arc;
     use tokio::sync::Mutex;
 
@@ -49,6 +50,8 @@ mod rag_integration_tests {
             "#.to_string(),
             last_modified: None,
             categories: vec!["Crafting".to_string(), "Tools".to_string()],
+            kind: PageKind::Article,
+            scraped_at: None,
         };
 
         // Process the wiki page (this will create embeddings)
@@ -136,7 +139,7 @@ mod rag_integration_tests {
                 source_url: "test://1".to_string(),
                 source_title: "Test 1".to_string(),
                 embedding: vec![1.0, 0.0, 0.0, 0.5], // Mock embedding
-                metadata: "{}".to_string(),
+                metadata: HashMap::new(),
             },
             VectorDocument {
                 id: "test2".to_string(),
@@ -144,7 +147,7 @@ mod rag_integration_tests {
                 source_url: "test://2".to_string(),
                 source_title: "Test 2".to_string(),
                 embedding: vec![0.0, 1.0, 0.0, 0.5], // Different mock embedding
-                metadata: "{}".to_string(),
+                metadata: HashMap::new(),
             },
         ];
 
@@ -175,4 +178,622 @@ mod rag_integration_tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_search_rejects_mismatched_embedding_dimension() {
+        use crate::errors::AppError;
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        
+        // Store built with 384-dimensional (mock) embeddings
+        let docs = vec![VectorDocument {
+            id: "mock1".to_string(),
+            content: "Clay forming requires a clay forming surface".to_string(),
+            source_url: "test://clay".to_string(),
+            source_title: "Clay forming".to_string(),
+            embedding: vec![0.1; 384],
+            metadata: HashMap::new(),
+        }];
+        db.insert_documents(docs).await.unwrap();
+        
+        // Query embedded with a real 768-dimensional model
+        let result = db.search_similar(vec![0.1; 768], 5).await;
+        
+        match result {
+            Err(e @ AppError::DimensionMismatch { .. }) => {
+                let message = e.to_string();
+                assert!(message.contains("768"));
+                assert!(message.contains("384"));
+                assert!(message.contains("re-embed"));
+            }
+            other => panic!("expected a dimension mismatch error, got {:?}", other.map(|r| r.len())),
+        }
+        
+        // Matching dimensions still search normally
+        assert_eq!(db.search_similar(vec![0.1; 384], 5).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_batch_matches_individual_searches() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        let docs: Vec<VectorDocument> = (0..20)
+            .map(|i| {
+                let angle = i as f32 * 0.3;
+                VectorDocument {
+                    id: format!("doc{}", i),
+                    content: format!("Document number {}", i),
+                    source_url: format!("test://{}", i),
+                    source_title: format!("Doc {}", i),
+                    embedding: vec![angle.cos(), angle.sin(), (i % 3) as f32 * 0.2],
+                    metadata: HashMap::new(),
+                }
+            })
+            .collect();
+        db.insert_documents(docs).await.unwrap();
+        
+        let queries = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.1],
+            vec![-0.5, 0.5, 0.4],
+        ];
+        
+        let batch = db.search_batch(queries.clone(), 5).await.unwrap();
+        assert_eq!(batch.len(), queries.len());
+        
+        for (query, batch_results) in queries.into_iter().zip(batch) {
+            let individual = db.search_similar(query, 5).await.unwrap();
+            assert_eq!(batch_results.len(), individual.len());
+            for ((batch_doc, batch_score), (doc, score)) in batch_results.iter().zip(individual.iter()) {
+                assert_eq!(batch_doc.id, doc.id);
+                assert_eq!(batch_score, score);
+            }
+        }
+        
+        assert!(db.search_batch(Vec::new(), 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compact_reports_size_and_keeps_documents() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        let docs: Vec<VectorDocument> = (0..50)
+            .map(|i| VectorDocument {
+                id: format!("doc{}", i),
+                content: "Charcoal is made in a charcoal pit. ".repeat(20),
+                source_url: format!("test://{}", i % 2),
+                source_title: format!("Doc {}", i),
+                embedding: vec![0.1; 384],
+                metadata: HashMap::new(),
+            })
+            .collect();
+        db.insert_documents(docs).await.unwrap();
+        db.delete_by_source("test://0").await.unwrap();
+        
+        let report = db.compact().await.unwrap();
+        
+        assert_eq!(report.document_count, 25);
+        assert_eq!(db.count_documents().await.unwrap(), 25);
+        assert!(report.size_before_bytes > 0);
+        assert_eq!(report.size_after_bytes, db.size_on_disk().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_document_by_id() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![VectorDocument {
+            id: "bloomery_2".to_string(),
+            content: "The bloomery smelts iron ore into iron blooms.".to_string(),
+            source_url: "test://bloomery".to_string(),
+            source_title: "Bloomery".to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            metadata: HashMap::from([("chunk_index".to_string(), "2".to_string())]),
+        }]).await.unwrap();
+        
+        let doc = db.get_document("bloomery_2").await.unwrap().expect("document should exist");
+        assert_eq!(doc.source_title, "Bloomery");
+        assert_eq!(doc.content, "The bloomery smelts iron ore into iron blooms.");
+        assert_eq!(doc.embedding, vec![0.1, 0.2, 0.3]);
+        
+        assert!(db.get_document("bloomery_3").await.unwrap().is_none());
+        assert!(db.get_document("").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_waits_for_transient_lock() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        let db_path = std::env::temp_dir().join(format!("vs_ai_lock_test_{}", uuid::Uuid::new_v4()));
+        
+        // Another "instance" holds the lock briefly, then exits
+        let holder = sled::open(&db_path).unwrap();
+        holder.insert("existing", "kept").unwrap();
+        holder.flush().unwrap();
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            drop(holder);
+        });
+        
+        let db = VectorDatabase::open_at(&db_path).await.unwrap();
+        release.await.unwrap();
+        
+        // The existing data survived instead of the directory being wiped
+        assert_eq!(db.count_documents().await.unwrap(), 1);
+        
+        drop(db);
+        let _ = std::fs::remove_dir_all(&db_path);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_json_metadata_is_migrated_on_open() {
+        use crate::services::vector_database::{LegacyVectorDocument, VectorDatabase};
+        
+        let db_path = std::env::temp_dir().join(format!("vs_ai_metadata_test_{}", uuid::Uuid::new_v4()));
+        {
+            // Write a record the way older versions did, with metadata as a JSON string
+            let raw = sled::open(&db_path).unwrap();
+            let legacy = LegacyVectorDocument {
+                id: "anvil_0".to_string(),
+                content: "Anvils are used for smithing.".to_string(),
+                source_url: "test://anvil".to_string(),
+                source_title: "Anvil".to_string(),
+                embedding: vec![1.0, 0.0, 0.0],
+                metadata: r#"{"source_type":"wiki","chunk_index":"0"}"#.to_string(),
+            };
+            raw.insert("anvil_0", bincode::serialize(&legacy).unwrap()).unwrap();
+            raw.flush().unwrap();
+        }
+        
+        let db = VectorDatabase::open_at(&db_path).await.unwrap();
+        let doc = db.get_document("anvil_0").await.unwrap().expect("document should exist");
+        assert_eq!(doc.metadata.get("source_type").map(String::as_str), Some("wiki"));
+        assert_eq!(doc.metadata.get("chunk_index").map(String::as_str), Some("0"));
+        assert_eq!(db.find_ids_by_metadata("source_type", "wiki").await.unwrap(), vec!["anvil_0"]);
+        
+        drop(db);
+        std::fs::remove_dir_all(&db_path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_metadata_migration_runs_once() {
+        use crate::services::vector_database::{LegacyVectorDocument, VectorDatabase};
+        
+        let db_path = std::env::temp_dir().join(format!("vs_ai_migration_marker_test_{}", uuid::Uuid::new_v4()));
+        let write_legacy = |id: &str, metadata: &str| {
+            // sled lets go of the directory lock shortly after the last handle is dropped
+            let raw = (0..50).find_map(|_| sled::open(&db_path).ok().or_else(|| {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                None
+            })).unwrap();
+            let legacy = LegacyVectorDocument {
+                id: id.to_string(),
+                content: format!("{} content", id),
+                source_url: format!("test://{}", id),
+                source_title: id.to_string(),
+                embedding: vec![1.0, 0.0, 0.0],
+                metadata: metadata.to_string(),
+            };
+            raw.insert(id, bincode::serialize(&legacy).unwrap()).unwrap();
+            raw.flush().unwrap();
+        };
+        
+        // Nothing in this store is indexed, so the index is still empty after migrating
+        write_legacy("anvil_0", "{}");
+        drop(VectorDatabase::open_at(&db_path).await.unwrap());
+        
+        // The next start doesn't scan again, so a record slipped in meanwhile isn't picked up
+        write_legacy("quern_0", r#"{"source_type":"wiki"}"#);
+        let db = VectorDatabase::open_at(&db_path).await.unwrap();
+        assert!(db.find_ids_by_metadata("source_type", "wiki").await.unwrap().is_empty());
+        assert_eq!(db.count_documents().await.unwrap(), 2);
+        
+        drop(db);
+        std::fs::remove_dir_all(&db_path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_search_filtered_by_indexed_metadata() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let document = |id: &str, section: &str, embedding: Vec<f32>| VectorDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            source_url: format!("test://{}", id),
+            source_title: id.to_string(),
+            embedding,
+            metadata: HashMap::from([
+                ("source_type".to_string(), "wiki".to_string()),
+                ("section".to_string(), section.to_string()),
+            ]),
+        };
+        
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![
+            document("smelting", "Crafting", vec![1.0, 0.0, 0.0]),
+            document("bloomery", "Crafting", vec![0.9, 0.1, 0.0]),
+            document("wolf", "Creatures", vec![1.0, 0.0, 0.0]),
+        ]).await.unwrap();
+        
+        let results = db.search_similar_where(vec![1.0, 0.0, 0.0], 5, "section", "Crafting").await.unwrap();
+        let ids: Vec<&str> = results.iter().map(|(doc, _)| doc.id.as_str()).collect();
+        assert_eq!(ids, vec!["smelting", "bloomery"]);
+        
+        // Re-inserting a document with new metadata moves it in the index
+        db.insert_documents(vec![document("wolf", "Crafting", vec![0.0, 1.0, 0.0])]).await.unwrap();
+        assert!(db.find_ids_by_metadata("section", "Creatures").await.unwrap().is_empty());
+        
+        db.delete_by_source("test://smelting").await.unwrap();
+        let mut crafting = db.find_ids_by_metadata("section", "Crafting").await.unwrap();
+        crafting.sort();
+        assert_eq!(crafting, vec!["bloomery", "wolf"]);
+        
+        // Keys without an index fall back to scanning
+        assert_eq!(db.find_ids_by_metadata("source_url", "none").await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_find_related_excludes_seed_and_same_page() {
+        use crate::errors::AppError;
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let document = |id: &str, page: &str, embedding: Vec<f32>| VectorDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            source_url: format!("test://{}", page),
+            source_title: page.to_string(),
+            embedding,
+            metadata: HashMap::new(),
+        };
+        
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![
+            document("bloomery_0", "bloomery", vec![1.0, 0.0, 0.0]),
+            document("bloomery_1", "bloomery", vec![1.0, 0.0, 0.0]),
+            document("iron_0", "iron", vec![0.9, 0.1, 0.0]),
+            document("charcoal_0", "charcoal", vec![0.5, 0.5, 0.0]),
+            document("wolf_0", "wolf", vec![0.0, 0.0, 1.0]),
+        ]).await.unwrap();
+        
+        let related = db.find_related("bloomery_0", 2).await.unwrap();
+        let ids: Vec<&str> = related.iter().map(|(doc, _)| doc.id.as_str()).collect();
+        assert_eq!(ids, vec!["iron_0", "charcoal_0"]);
+        
+        let all = db.find_related("bloomery_0", 10).await.unwrap();
+        assert!(all.iter().all(|(doc, _)| doc.source_url != "test://bloomery"));
+        assert_eq!(all.len(), 3);
+        
+        assert!(matches!(db.find_related("missing", 5).await, Err(AppError::StorageError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_insert_reports_rejected_documents_and_keeps_the_rest() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let document = |id: &str, embedding: Vec<f32>| VectorDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            source_url: "test://page".to_string(),
+            source_title: "Page".to_string(),
+            embedding,
+            metadata: HashMap::new(),
+        };
+        
+        let db = VectorDatabase::new_fallback();
+        let summary = db.insert_documents(vec![
+            document("page_0", vec![1.0, 0.0]),
+            document("", vec![0.5, 0.5]),
+            document("page_2", vec![f32::NAN, 0.0]),
+            document("page_3", vec![]),
+            document("page_4", vec![0.0, 1.0]),
+        ]).await.unwrap();
+        
+        assert_eq!(summary.inserted, 2);
+        let failed: Vec<&str> = summary.failed.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(failed, vec!["", "page_2", "page_3"]);
+        assert!(summary.failed[1].1.contains("NaN"));
+        
+        assert_eq!(db.count_documents().await.unwrap(), 2);
+        assert!(db.get_document("page_0").await.unwrap().is_some());
+        assert!(db.get_document("page_4").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_database_metrics() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        let empty = db.metrics(true).await.unwrap();
+        assert_eq!(empty.document_count, 0);
+        assert_eq!(empty.average_embedding_dimension, 0.0);
+        assert!(empty.sampled_search_ms.is_none());
+        
+        db.insert_documents((0..4).map(|i| VectorDocument {
+            id: format!("doc_{}", i),
+            content: "content".to_string(),
+            source_url: "test://page".to_string(),
+            source_title: "Page".to_string(),
+            embedding: vec![i as f32, 1.0, 0.0],
+            metadata: HashMap::new(),
+        }).collect()).await.unwrap();
+        
+        let unsampled = db.metrics(false).await.unwrap();
+        assert_eq!(unsampled.document_count, 4);
+        assert_eq!(unsampled.average_embedding_dimension, 3.0);
+        assert!(unsampled.sampled_search_ms.is_none());
+        
+        let sampled = db.metrics(true).await.unwrap();
+        assert!(sampled.sampled_search_ms.unwrap() >= 0.0);
+    }
+
+    fn page_documents(page: usize, count: usize) -> Vec<crate::services::vector_database::VectorDocument> {
+        (0..count).map(|i| crate::services::vector_database::VectorDocument {
+            id: format!("page{}_{}", page, i),
+            content: format!("Chunk {} of page {} about smithing and smelting.", i, page),
+            source_url: format!("test://page{}", page),
+            source_title: format!("Page {}", page),
+            embedding: vec![i as f32, page as f32, 1.0],
+            metadata: HashMap::new(),
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_delete_sources_removes_several_pages_at_once() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        let db = VectorDatabase::new_fallback();
+        for (page, count) in [(0, 3), (1, 2), (2, 4)] {
+            db.insert_documents(page_documents(page, count)).await.unwrap();
+        }
+        
+        let removed = db.delete_sources(&[
+            "test://page0".to_string(),
+            "test://page2".to_string(),
+            "test://never-scraped".to_string(),
+        ]).await.unwrap();
+        
+        assert_eq!(removed, HashMap::from([
+            ("test://page0".to_string(), 3),
+            ("test://page2".to_string(), 4),
+            ("test://never-scraped".to_string(), 0),
+        ]));
+        assert_eq!(db.count_documents().await.unwrap(), 2);
+        assert_eq!(db.documents_for_source("test://page1").await.unwrap().len(), 2);
+        assert!(db.documents_for_source("test://page2").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_policy_batches_writes_and_survives_reopen() {
+        use crate::services::vector_database::{FlushPolicy, VectorDatabase};
+        
+        let db_path = std::env::temp_dir().join(format!("vs_ai_flush_test_{}", uuid::Uuid::new_v4()));
+        let mut db = VectorDatabase::open_at(&db_path).await.unwrap();
+        db.set_flush_policy(FlushPolicy { every_documents: 5, interval: std::time::Duration::from_secs(3600) });
+        
+        db.insert_documents(page_documents(0, 3)).await.unwrap();
+        assert_eq!(db.unflushed_documents(), 3);
+        db.insert_documents(page_documents(1, 2)).await.unwrap();
+        assert_eq!(db.unflushed_documents(), 0, "reaching the document threshold should flush");
+        
+        db.insert_documents(page_documents(2, 2)).await.unwrap();
+        assert_eq!(db.unflushed_documents(), 2);
+        db.flush().await.unwrap();
+        assert_eq!(db.unflushed_documents(), 0);
+        
+        drop(db);
+        let reopened = VectorDatabase::open_at(&db_path).await.unwrap();
+        assert_eq!(reopened.count_documents().await.unwrap(), 7);
+        
+        drop(reopened);
+        std::fs::remove_dir_all(&db_path).ok();
+    }
+
+    /// Ingestion throughput with a flush after every batch versus the default policy.
+    /// Run with `cargo test flush_benchmark -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_flush_benchmark() {
+        use crate::config::EmbeddingConfig;
+        use crate::services::vector_database::{FlushPolicy, VectorDatabase};
+        
+        const PAGES: usize = 200;
+        const CHUNKS_PER_PAGE: usize = 10;
+        let defaults = EmbeddingConfig::default();
+        let policies = [
+            ("every batch", FlushPolicy::ALWAYS),
+            ("batched", FlushPolicy {
+                every_documents: defaults.flush_every_documents,
+                interval: std::time::Duration::from_secs(defaults.flush_interval_secs),
+            }),
+        ];
+        
+        for (name, policy) in policies {
+            let db_path = std::env::temp_dir().join(format!("vs_ai_flush_bench_{}", uuid::Uuid::new_v4()));
+            let mut db = VectorDatabase::open_at(&db_path).await.unwrap();
+            db.set_flush_policy(policy);
+            
+            let started = std::time::Instant::now();
+            for page in 0..PAGES {
+                db.insert_documents(page_documents(page, CHUNKS_PER_PAGE)).await.unwrap();
+            }
+            db.flush().await.unwrap();
+            let elapsed = started.elapsed();
+            
+            println!("{}: {} documents in {:?} ({:.0} documents/s)", name, PAGES * CHUNKS_PER_PAGE, elapsed,
+                     (PAGES * CHUNKS_PER_PAGE) as f64 / elapsed.as_secs_f64());
+            drop(db);
+            std::fs::remove_dir_all(&db_path).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_size_estimate_is_conservative() {
+        use crate::services::vector_database::{estimate_document_bytes, VectorDatabase, VectorDocument};
+        
+        let db_path = std::env::temp_dir().join(format!("vs_ai_size_test_{}", uuid::Uuid::new_v4()));
+        let db = VectorDatabase::open_at(&db_path).await.unwrap();
+        let content = "Smithing on an anvil shapes hot metal into tools. ".repeat(60);
+        let documents: Vec<VectorDocument> = (0..500).map(|i| VectorDocument {
+            id: format!("page{}_{}", i / 10, i % 10),
+            content: content.clone(),
+            source_url: format!("https://wiki.vintagestory.at/index.php?title=Page_{}", i / 10),
+            source_title: format!("Page {}", i / 10),
+            embedding: vec![0.25; 768],
+            metadata: HashMap::from([
+                ("source_type".to_string(), "wiki".to_string()),
+                ("chunk_index".to_string(), (i % 10).to_string()),
+            ]),
+        }).collect();
+        for batch in documents.chunks(50) {
+            db.insert_documents(batch.to_vec()).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        
+        let estimate = estimate_document_bytes(768, content.chars().count()) * 500;
+        assert!(db.size_on_disk().unwrap() <= estimate,
+                "{} bytes on disk, estimated {}", db.size_on_disk().unwrap(), estimate);
+        
+        drop(db);
+        std::fs::remove_dir_all(&db_path).ok();
+    }
+
+    fn unit_test_document(id: &str, embedding: Vec<f32>) -> crate::services::vector_database::VectorDocument {
+        crate::services::vector_database::VectorDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            source_url: format!("test://{}", id),
+            source_title: id.to_string(),
+            embedding,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unit_length_embeddings_use_the_dot_product() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![unit_test_document("doc1", vec![0.6, 0.8, 0.0]), unit_test_document("doc2", vec![0.0, 0.0, 1.0])]).await.unwrap();
+        db.set_unit_length_embeddings(true).await;
+        assert!(db.uses_unit_length_embeddings());
+        
+        // The query is normalized, so scores are still cosine similarities
+        let results = db.search_similar(vec![3.0, 4.0, 0.0], 2).await.unwrap();
+        assert_eq!(results[0].0.id, "doc1");
+        assert!((results[0].1 - 1.0).abs() < 1e-6);
+        
+        // A vector that isn't unit length means scores need the full cosine again
+        db.insert_documents(vec![unit_test_document("doc3", vec![2.0, 0.0, 0.0])]).await.unwrap();
+        assert!(!db.uses_unit_length_embeddings());
+        let results = db.search_similar(vec![1.0, 0.0, 0.0], 1).await.unwrap();
+        assert_eq!(results[0].0.id, "doc3");
+        assert!((results[0].1 - 1.0).abs() < 1e-6);
+        
+        // Nor is the shortcut turned on over a store that already holds such vectors
+        let unnormalized = VectorDatabase::new_fallback();
+        unnormalized.insert_documents(vec![unit_test_document("doc1", vec![3.0, 4.0, 0.0])]).await.unwrap();
+        unnormalized.set_unit_length_embeddings(true).await;
+        assert!(!unnormalized.uses_unit_length_embeddings());
+    }
+
+    #[tokio::test]
+    async fn test_unit_length_check_covers_every_stored_embedding() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        // Only a later document breaks unit length
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(vec![unit_test_document("doc1", vec![1.0, 0.0, 0.0])]).await.unwrap();
+        db.insert_documents(vec![unit_test_document("doc2", vec![3.0, 4.0, 0.0])]).await.unwrap();
+        db.set_unit_length_embeddings(true).await;
+        assert!(!db.uses_unit_length_embeddings());
+        
+        // A store written before the check was recorded is scanned in full
+        let db_path = std::env::temp_dir().join(format!("vs_ai_unit_length_test_{}", uuid::Uuid::new_v4()));
+        {
+            let raw = sled::open(&db_path).unwrap();
+            for doc in [unit_test_document("doc1", vec![1.0, 0.0, 0.0]), unit_test_document("doc2", vec![3.0, 4.0, 0.0])] {
+                raw.insert(doc.id.as_bytes(), bincode::serialize(&doc).unwrap()).unwrap();
+            }
+            raw.flush().unwrap();
+        }
+        let db = VectorDatabase::open_at(&db_path).await.unwrap();
+        db.set_unit_length_embeddings(true).await;
+        assert!(!db.uses_unit_length_embeddings());
+        
+        // Clearing the store lifts the restriction
+        db.clear().await.unwrap();
+        db.set_unit_length_embeddings(true).await;
+        assert!(db.uses_unit_length_embeddings());
+        
+        drop(db);
+        std::fs::remove_dir_all(&db_path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index_from_populated_store() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let db = VectorDatabase::new_fallback();
+        db.insert_documents(page_documents(0, 3)).await.unwrap();
+        db.insert_documents(["quern", "anvil"].iter().map(|id| VectorDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            source_url: format!("test://{}", id),
+            source_title: id.to_string(),
+            embedding: vec![1.0, 0.0, 0.0],
+            metadata: HashMap::from([
+                ("section".to_string(), "Crafting".to_string()),
+                ("categories".to_string(), "Tools|Blocks".to_string()),
+            ]),
+        }).collect()).await.unwrap();
+        
+        let report = db.rebuild_index().await.unwrap();
+        
+        assert_eq!(report.documents_indexed, 5);
+        assert_eq!(report.index_entries, 6);
+        let mut crafting = db.find_ids_by_metadata("section", "Crafting").await.unwrap();
+        crafting.sort();
+        assert_eq!(crafting, vec!["anvil", "quern"]);
+        assert_eq!(db.find_ids_by_metadata("categories", "Blocks").await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prune_knowledge_base_to_allowlist() {
+        use crate::services::embedding_service::PruneCriteria;
+        use crate::services::vector_database::VectorDatabase;
+        
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        for (page, count) in [(0, 3), (1, 2), (2, 4), (3, 1)] {
+            db.lock().await.insert_documents(page_documents(page, count)).await.unwrap();
+        }
+        let mut service = EmbeddingService::new().await;
+        service.set_vector_database(db.clone());
+        
+        // Sources are kept by URL or by title ignoring case
+        let keep = PruneCriteria::Allowlist {
+            sources: vec!["test://page0".to_string(), "page 2".to_string(), "Not Stored".to_string()],
+        };
+        let report = service.prune_knowledge_base(&keep, &HashMap::new()).await.unwrap();
+        
+        assert_eq!(report.sources_kept, 2);
+        assert_eq!(report.chunks_removed, 3);
+        let removed: Vec<(&str, usize)> = report.removed.iter()
+            .map(|source| (source.source_url.as_str(), source.chunk_count))
+            .collect();
+        assert_eq!(removed, vec![("test://page1", 2), ("test://page3", 1)]);
+        
+        let remaining: Vec<String> = db.lock().await.stored_sources().await.unwrap().into_iter()
+            .map(|source| source.source_url)
+            .collect();
+        assert_eq!(remaining, vec!["test://page0", "test://page2"]);
+        
+        // An allowlist matching nothing would empty the knowledge base, so it's refused
+        let keep = PruneCriteria::Allowlist { sources: vec!["Not Stored".to_string()] };
+        assert!(service.prune_knowledge_base(&keep, &HashMap::new()).await.is_err());
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 7);
+    }
+}
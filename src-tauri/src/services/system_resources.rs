@@ -0,0 +1,215 @@
+use crate::errors::{AppError, AppResult};
+use std::path::{Path, PathBuf};
+use sysinfo::{Disks, System};
+
+/// Bits per weight assumed when a model's quantization isn't known; Ollama's default tags are
+/// 4-bit quantized
+const DEFAULT_BITS_PER_WEIGHT: f64 = 4.5;
+
+/// Extra memory a loaded model needs beyond its weights, for the context cache and activations
+const MEMORY_OVERHEAD_FACTOR: f64 = 1.2;
+const RUNTIME_OVERHEAD_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Disk space left free after a download, so a pull never fills the disk completely
+const DISK_HEADROOM_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Free memory and disk space when a model operation is about to start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemResources {
+    pub available_memory: u64,
+    /// Free space on the disk Ollama keeps its models on, if it could be read
+    pub available_disk: Option<u64>,
+}
+
+/// What a model needs to be downloaded and loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelRequirement {
+    pub memory: u64,
+    pub disk: u64,
+}
+
+impl SystemResources {
+    /// Reads the free memory and the free space on the disk holding `models_dir`
+    pub fn probe(models_dir: &Path) -> Self {
+        let mut system = System::new();
+        system.refresh_memory();
+        Self {
+            available_memory: system.available_memory(),
+            available_disk: available_disk_space(models_dir),
+        }
+    }
+}
+
+/// Free bytes on the disk holding `path`, or `None` if no mounted disk contains it
+pub fn available_disk_space(path: &Path) -> Option<u64> {
+    // The path may not exist yet; ask about the nearest folder that does
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let existing = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+    
+    let disks = Disks::new_with_refreshed_list();
+    disks.list().iter()
+        .filter(|disk| existing.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Where Ollama keeps downloaded models: `OLLAMA_MODELS` if set, otherwise `~/.ollama/models`
+pub fn ollama_models_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("OLLAMA_MODELS") {
+        return PathBuf::from(dir);
+    }
+    dirs::home_dir().unwrap_or_default().join(".ollama").join("models")
+}
+
+/// Parameter count from an Ollama `parameter_size` such as `3.8B`, `270M` or `8x7B`
+pub fn parse_parameter_size(size: &str) -> Option<f64> {
+    let size = size.trim().to_ascii_lowercase();
+    let (number, scale) = if let Some(number) = size.strip_suffix('b') {
+        (number, 1e9)
+    } else if let Some(number) = size.strip_suffix('m') {
+        (number, 1e6)
+    } else {
+        return None;
+    };
+    
+    // Mixture-of-experts sizes are written as experts x parameters per expert
+    let (experts, number) = match number.split_once('x') {
+        Some((experts, number)) => (experts.parse::<f64>().ok()?, number),
+        None => (1.0, number),
+    };
+    let parameters = number.parse::<f64>().ok()?;
+    (parameters > 0.0).then_some(experts * parameters * scale)
+}
+
+/// Parameter count and quantization named in a model tag, e.g. `llama3.1:8b-instruct-q4_0`.
+/// Tags like `phi3:mini` don't say, so their size is unknown until the model is installed.
+pub fn model_tag_size(model_name: &str) -> (Option<f64>, Option<&str>) {
+    let Some((_, tag)) = model_name.split_once(':') else {
+        return (None, None);
+    };
+    let parameters = tag.split('-').find_map(parse_parameter_size);
+    let quantization = tag.split('-').find(|part| {
+        let part = part.to_ascii_lowercase();
+        part.starts_with('q') || part.starts_with("iq") || part.starts_with("fp") || part.starts_with("bf")
+    });
+    (parameters, quantization)
+}
+
+/// Bits each weight takes with a quantization such as `Q4_K_M`, `Q8_0` or `F16`
+pub fn bits_per_weight(quantization: Option<&str>) -> f64 {
+    let Some(quantization) = quantization else {
+        return DEFAULT_BITS_PER_WEIGHT;
+    };
+    let quantization = quantization.trim().to_ascii_uppercase();
+    if quantization.contains("32") {
+        return 32.0;
+    }
+    if quantization.contains("16") {
+        return 16.0;
+    }
+    
+    // Quantized formats store a little more than their nominal bits for the block scales
+    let nominal = quantization.trim_start_matches('I').strip_prefix('Q')
+        .and_then(|rest| rest.chars().next())
+        .and_then(|digit| digit.to_digit(10));
+    match nominal {
+        Some(bits) => bits as f64 + 0.5,
+        None => DEFAULT_BITS_PER_WEIGHT,
+    }
+}
+
+/// Memory and disk a model with `parameters` weights needs at the given quantization
+pub fn model_requirement(parameters: f64, quantization: Option<&str>) -> ModelRequirement {
+    let weight_bytes = parameters * bits_per_weight(quantization) / 8.0;
+    ModelRequirement {
+        memory: (weight_bytes * MEMORY_OVERHEAD_FACTOR) as u64 + RUNTIME_OVERHEAD_BYTES,
+        disk: weight_bytes as u64 + DISK_HEADROOM_BYTES,
+    }
+}
+
+/// Refuses with `InsufficientResources` if `resources` fall short of what `model` needs.
+/// The disk is only checked when `downloading`; disk space that can't be read isn't held against it.
+pub fn ensure_enough_resources(model: &str, requirement: ModelRequirement, resources: SystemResources, downloading: bool) -> AppResult<()> {
+    if resources.available_memory < requirement.memory {
+        return Err(AppError::InsufficientResources {
+            message: format!(
+                "{} needs about {} MB of memory, but only {} MB is free",
+                model, requirement.memory / 1_000_000, resources.available_memory / 1_000_000
+            ),
+            shortfall_bytes: requirement.memory - resources.available_memory,
+        });
+    }
+    
+    if let Some(available_disk) = resources.available_disk.filter(|_| downloading) {
+        if available_disk < requirement.disk {
+            return Err(AppError::InsufficientResources {
+                message: format!(
+                    "{} needs about {} MB of disk space, but only {} MB is free",
+                    model, requirement.disk / 1_000_000, available_disk / 1_000_000
+                ),
+                shortfall_bytes: requirement.disk - available_disk,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const GB: u64 = 1_000_000_000;
+    
+    #[test]
+    fn test_parse_parameter_size() {
+        assert_eq!(parse_parameter_size("3.8B"), Some(3.8e9));
+        assert_eq!(parse_parameter_size("270M"), Some(2.7e8));
+        assert_eq!(parse_parameter_size("8x7B"), Some(56e9));
+        assert_eq!(parse_parameter_size("mini"), None);
+        assert_eq!(parse_parameter_size(""), None);
+    }
+    
+    #[test]
+    fn test_model_tag_size() {
+        assert_eq!(model_tag_size("llama3.1:70b-instruct-q8_0"), (Some(70e9), Some("q8_0")));
+        assert_eq!(model_tag_size("qwen2.5:0.5b"), (Some(0.5e9), None));
+        assert_eq!(model_tag_size("phi3:mini"), (None, None));
+        assert_eq!(model_tag_size("nomic-embed-text"), (None, None));
+    }
+    
+    #[test]
+    fn test_requirement_grows_with_quantization() {
+        let q4 = model_requirement(7e9, Some("Q4_K_M"));
+        let q8 = model_requirement(7e9, Some("Q8_0"));
+        let f16 = model_requirement(7e9, Some("F16"));
+        assert!(q4.memory > 4 * GB && q4.memory < 6 * GB);
+        assert!(q4.memory < q8.memory && q8.memory < f16.memory);
+        assert!(f16.disk > 14 * GB);
+        assert_eq!(model_requirement(7e9, None), q4);
+    }
+    
+    #[test]
+    fn test_shortfall_is_reported() {
+        let requirement = ModelRequirement { memory: 8 * GB, disk: 5 * GB };
+        let plenty = SystemResources { available_memory: 16 * GB, available_disk: Some(100 * GB) };
+        assert!(ensure_enough_resources("llama3:8b", requirement, plenty, true).is_ok());
+        
+        let low_memory = SystemResources { available_memory: 2 * GB, ..plenty };
+        match ensure_enough_resources("llama3:8b", requirement, low_memory, false) {
+            Err(AppError::InsufficientResources { shortfall_bytes, .. }) => assert_eq!(shortfall_bytes, 6 * GB),
+            other => panic!("Expected insufficient resources, got {:?}", other),
+        }
+        
+        // A full disk only matters when the model still has to be downloaded
+        let full_disk = SystemResources { available_disk: Some(GB), ..plenty };
+        assert!(ensure_enough_resources("llama3:8b", requirement, full_disk, false).is_ok());
+        let err = ensure_enough_resources("llama3:8b", requirement, full_disk, true).unwrap_err();
+        assert_eq!(err.code(), "INSUFFICIENT_RESOURCES");
+    }
+    
+    #[test]
+    fn test_available_disk_space_of_temp_dir() {
+        let missing = std::env::temp_dir().join(format!("disk-space-{}", uuid::Uuid::new_v4())).join("nested");
+        assert!(available_disk_space(&missing).unwrap_or(0) > 0);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config::AppConfig;
+use crate::errors::{AppError, AppResult};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Most-cited sources returned in a summary
+const TOP_SOURCES_LIMIT: usize = 10;
+
+const MESSAGES_KEY: &[u8] = b"messages";
+const RESPONSE_MS_KEY: &[u8] = b"response_ms";
+
+/// One answered chat message, as recorded in the usage stats
+#[derive(Debug, Clone)]
+pub struct ChatTurnUsage {
+    pub response_ms: u64,
+    /// The model that answered, or `None` for a canned fallback reply
+    pub model: Option<String>,
+    /// Titles of the wiki pages the answer drew on
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceUsage {
+    pub source: String,
+    pub citations: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub messages: u64,
+}
+
+/// Aggregated usage, most-used first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageStatsSummary {
+    pub total_messages: u64,
+    pub average_response_ms: f64,
+    pub top_sources: Vec<SourceUsage>,
+    pub model_usage: Vec<ModelUsage>,
+}
+
+/// Local-only counters of how the assistant has been used. Nothing here is ever sent anywhere.
+pub struct UsageStats {
+    totals: Db,
+    sources: Tree,
+    models: Tree,
+}
+
+impl UsageStats {
+    pub fn new() -> AppResult<Self> {
+        Self::open_at(&AppConfig::get_data_dir().join("usage_stats"))
+    }
+    
+    pub fn open_at(path: &Path) -> AppResult<Self> {
+        std::fs::create_dir_all(path)
+            .map_err(|e| AppError::StorageError(format!("Failed to create usage stats directory: {}", e)))?;
+        info!("Opening usage stats at: {:?}", path);
+        
+        let db = sled::open(path)
+            .map_err(|e| AppError::StorageError(format!("Failed to open usage stats: {}", e)))?;
+        Self::from_db(db)
+    }
+    
+    pub fn new_temporary() -> Self {
+        let db = sled::Config::new().temporary(true).open()
+            .expect("Failed to create temporary usage stats");
+        Self::from_db(db).expect("Failed to create temporary usage stats trees")
+    }
+    
+    fn from_db(totals: Db) -> AppResult<Self> {
+        let open_tree = |name: &str| totals.open_tree(name)
+            .map_err(|e| AppError::StorageError(format!("Failed to open usage stats tree {}: {}", name, e)));
+        let sources = open_tree("sources")?;
+        let models = open_tree("models")?;
+        Ok(Self { totals, sources, models })
+    }
+    
+    /// Adds one answered message to the counters. Each counter is a single in-memory sled
+    /// update; sled flushes them to disk in the background.
+    pub fn record_turn(&self, turn: &ChatTurnUsage) -> AppResult<()> {
+        increment(&self.totals, MESSAGES_KEY, 1)?;
+        increment(&self.totals, RESPONSE_MS_KEY, turn.response_ms)?;
+        if let Some(model) = &turn.model {
+            increment(&self.models, model.as_bytes(), 1)?;
+        }
+        for source in &turn.sources {
+            increment(&self.sources, source.as_bytes(), 1)?;
+        }
+        Ok(())
+    }
+    
+    pub fn summary(&self) -> AppResult<UsageStatsSummary> {
+        let total_messages = read_counter(&self.totals, MESSAGES_KEY)?;
+        let response_ms = read_counter(&self.totals, RESPONSE_MS_KEY)?;
+        let average_response_ms = if total_messages == 0 {
+            0.0
+        } else {
+            response_ms as f64 / total_messages as f64
+        };
+        
+        let mut top_sources: Vec<SourceUsage> = counters(&self.sources)
+            .map(|(source, citations)| SourceUsage { source, citations })
+            .collect();
+        top_sources.sort_by(|a, b| b.citations.cmp(&a.citations).then_with(|| a.source.cmp(&b.source)));
+        top_sources.truncate(TOP_SOURCES_LIMIT);
+        
+        let mut model_usage: Vec<ModelUsage> = counters(&self.models)
+            .map(|(model, messages)| ModelUsage { model, messages })
+            .collect();
+        model_usage.sort_by(|a, b| b.messages.cmp(&a.messages).then_with(|| a.model.cmp(&b.model)));
+        
+        Ok(UsageStatsSummary {
+            total_messages,
+            average_response_ms,
+            top_sources,
+            model_usage,
+        })
+    }
+    
+    /// How often each page title has been cited, for every page cited at least once
+    pub fn source_citations(&self) -> AppResult<HashMap<String, u64>> {
+        Ok(counters(&self.sources).collect())
+    }
+    
+    /// Clears every counter
+    pub fn reset(&self) -> AppResult<()> {
+        for tree in [&*self.totals, &self.sources, &self.models] {
+            tree.clear()
+                .map_err(|e| AppError::StorageError(format!("Failed to reset usage stats: {}", e)))?;
+        }
+        info!("Usage stats reset");
+        Ok(())
+    }
+}
+
+fn increment(tree: &Tree, key: &[u8], amount: u64) -> AppResult<()> {
+    tree.update_and_fetch(key, |old| {
+        let current = old.and_then(decode_counter).unwrap_or(0);
+        Some(current.saturating_add(amount).to_be_bytes().to_vec())
+    })
+    .map_err(|e| AppError::StorageError(format!("Failed to update usage stats: {}", e)))?;
+    Ok(())
+}
+
+fn read_counter(tree: &Tree, key: &[u8]) -> AppResult<u64> {
+    let value = tree.get(key)
+        .map_err(|e| AppError::StorageError(format!("Failed to read usage stats: {}", e)))?;
+    Ok(value.as_deref().and_then(decode_counter).unwrap_or(0))
+}
+
+/// Every counter in `tree` with its key as text, skipping entries that fail to read
+fn counters(tree: &Tree) -> impl Iterator<Item = (String, u64)> + '_ {
+    tree.iter().filter_map(|result| match result {
+        Ok((key, value)) => Some((String::from_utf8_lossy(&key).into_owned(), decode_counter(&value)?)),
+        Err(e) => {
+            error!("Error reading from usage stats: {}", e);
+            None
+        }
+    })
+}
+
+fn decode_counter(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn turn(response_ms: u64, model: Option<&str>, sources: &[&str]) -> ChatTurnUsage {
+        ChatTurnUsage {
+            response_ms,
+            model: model.map(str::to_string),
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+        }
+    }
+    
+    #[test]
+    fn test_summary_aggregates_recorded_turns() {
+        let stats = UsageStats::new_temporary();
+        stats.record_turn(&turn(1000, Some("phi3:mini"), &["Anvil", "Bloomery"])).unwrap();
+        stats.record_turn(&turn(3000, Some("phi3:mini"), &["Bloomery"])).unwrap();
+        stats.record_turn(&turn(2000, Some("llama3.2:3b"), &[])).unwrap();
+        stats.record_turn(&turn(2000, None, &[])).unwrap();
+        
+        let summary = stats.summary().unwrap();
+        
+        assert_eq!(summary.total_messages, 4);
+        assert_eq!(summary.average_response_ms, 2000.0);
+        assert_eq!(summary.top_sources[0].source, "Bloomery");
+        assert_eq!(summary.top_sources[0].citations, 2);
+        assert_eq!(summary.top_sources[1].source, "Anvil");
+        let models: Vec<(&str, u64)> = summary.model_usage.iter().map(|usage| (usage.model.as_str(), usage.messages)).collect();
+        assert_eq!(models, vec![("phi3:mini", 2), ("llama3.2:3b", 1)]);
+    }
+    
+    #[test]
+    fn test_reset_clears_all_counters() {
+        let stats = UsageStats::new_temporary();
+        stats.record_turn(&turn(1000, Some("phi3:mini"), &["Anvil"])).unwrap();
+        
+        stats.reset().unwrap();
+        
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.total_messages, 0);
+        assert_eq!(summary.average_response_ms, 0.0);
+        assert!(summary.top_sources.is_empty());
+        assert!(summary.model_usage.is_empty());
+    }
+}
@@ -1,12 +1,28 @@
 use crate::errors::{AppError, AppResult};
 use crate::config::AppConfig;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
 use log::{info, warn, error};
 use sled::Db;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorDocument {
+    pub id: String,
+    pub content: String,
+    pub source_url: String,
+    pub source_title: String,
+    pub embedding: Vec<f32>,
+    pub metadata: HashMap<String, String>,
+}
+
+/// Record layout from before metadata was stored as a map, when it was a JSON-encoded string
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyVectorDocument {
     pub id: String,
     pub content: String,
     pub source_url: String,
@@ -15,67 +31,456 @@ pub struct VectorDocument {
     pub metadata: String,
 }
 
+impl From<LegacyVectorDocument> for VectorDocument {
+    fn from(legacy: LegacyVectorDocument) -> Self {
+        let metadata = serde_json::from_str(&legacy.metadata).unwrap_or_else(|e| {
+            warn!("Discarding unreadable metadata on document {}: {}", legacy.id, e);
+            HashMap::new()
+        });
+        Self {
+            id: legacy.id,
+            content: legacy.content,
+            source_url: legacy.source_url,
+            source_title: legacy.source_title,
+            embedding: legacy.embedding,
+            metadata,
+        }
+    }
+}
+
+/// Decodes a stored document, converting records written with JSON-string metadata.
+/// Returns the document and whether it was in the legacy layout.
+fn decode_document(bytes: &[u8]) -> bincode::Result<(VectorDocument, bool)> {
+    match bincode::deserialize::<VectorDocument>(bytes) {
+        Ok(doc) => Ok((doc, false)),
+        Err(e) => match bincode::deserialize::<LegacyVectorDocument>(bytes) {
+            Ok(legacy) => Ok((legacy.into(), true)),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+/// Metadata keys with a secondary index, so filtering on them doesn't scan every document
+pub const INDEXED_METADATA_KEYS: &[&str] = &["source_type", "section", "categories"];
+
+/// Metadata keys holding a list joined with `METADATA_LIST_SEPARATOR`. Each entry is indexed
+/// on its own, so filtering matches any one of them.
+pub const LIST_METADATA_KEYS: &[&str] = &["categories"];
+pub const METADATA_LIST_SEPARATOR: char = '|';
+
+/// The values a metadata entry is indexed and filtered under
+fn metadata_values<'a>(key: &str, value: &'a str) -> Vec<&'a str> {
+    if LIST_METADATA_KEYS.contains(&key) {
+        value.split(METADATA_LIST_SEPARATOR).filter(|item| !item.is_empty()).collect()
+    } else {
+        vec![value]
+    }
+}
+
+/// Index entries are `key \0 value \0 document id`, so all ids for a key/value pair share a prefix
+fn metadata_index_prefix(key: &str, value: &str) -> Vec<u8> {
+    format!("{}\0{}\0", key, value).into_bytes()
+}
+
+/// A `scraped_at` timestamp for comparing, which sorts missing or unreadable ones first
+pub(crate) fn scraped_time(scraped_at: Option<&str>) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    scraped_at.and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+}
+
+fn metadata_index_keys(doc: &VectorDocument) -> Vec<Vec<u8>> {
+    INDEXED_METADATA_KEYS.iter()
+        .filter_map(|key| doc.metadata.get(*key).map(|value| (*key, value)))
+        .flat_map(|(key, value)| metadata_values(key, value).into_iter().map(move |value| {
+            let mut entry = metadata_index_prefix(key, value);
+            entry.extend_from_slice(doc.id.as_bytes());
+            entry
+        }))
+        .collect()
+}
+
+/// Vectors of different sizes can't be compared, so scoring them would return meaningless zeros
+fn dimension_mismatch_error(query_dimension: usize, stored_dimension: usize) -> AppError {
+    AppError::DimensionMismatch { query_dimension, stored_dimension }
+}
+
+/// Result of a maintenance pass, so callers can see how much space was reclaimed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactionReport {
+    pub size_before_bytes: u64,
+    pub size_after_bytes: u64,
+    pub document_count: usize,
+}
+
+/// Result of `rebuild_index`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexRebuildReport {
+    pub documents_indexed: usize,
+    /// One per indexed metadata value of each document
+    pub index_entries: usize,
+    pub elapsed_ms: u64,
+}
+
+/// A page with documents in the store that isn't one of the known sources
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanedSource {
+    pub source_url: String,
+    pub source_title: String,
+    pub chunk_count: usize,
+}
+
+/// A page with documents in the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredSource {
+    pub source_url: String,
+    pub source_title: String,
+    pub chunk_count: usize,
+    /// Latest `scraped_at` of its documents; missing if none recorded one
+    pub scraped_at: Option<String>,
+}
+
+/// A chunk whose embedding failed during ingestion, kept so it can be embedded again later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedEmbedding {
+    pub id: String,
+    pub content: String,
+    pub source_url: String,
+    pub source_title: String,
+    pub metadata: HashMap<String, String>,
+    /// Why the last attempt failed
+    pub error: String,
+}
+
+/// Store statistics for diagnosing slow searches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseMetrics {
+    pub document_count: usize,
+    pub size_on_disk_bytes: u64,
+    /// Mean embedding length across documents; differs from the stored dimension only if the store is mixed
+    pub average_embedding_dimension: f64,
+    /// Mean time of a full similarity search, if sampling was requested and the store isn't empty
+    pub sampled_search_ms: Option<f64>,
+}
+
+/// How far an embedding's length may be from 1 and still count as unit length, allowing for
+/// rounding in the model's output
+const UNIT_LENGTH_TOLERANCE: f32 = 1e-3;
+
+/// Whether `embedding` has unit length, so its dot product with another unit vector is their
+/// cosine similarity
+/// Key in the markers tree recording whether every stored embedding has unit length. Written
+/// `[0]` whenever one that doesn't is stored; missing until a full scan has checked the store.
+const UNIT_LENGTH_MARKER: &[u8] = b"unit_length_embeddings";
+
+/// Key in the markers tree set once the store's documents are in the current layout and indexed
+const METADATA_MIGRATED_MARKER: &[u8] = b"metadata_migrated";
+
+pub fn is_unit_length(embedding: &[f32]) -> bool {
+    let magnitude = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    (magnitude - 1.0).abs() <= UNIT_LENGTH_TOLERANCE
+}
+
+/// Scales `embedding` to unit length in place; all-zero vectors are left as they are
+pub fn normalize_embedding(embedding: &mut [f32]) {
+    let magnitude = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if magnitude > 0.0 {
+        for value in embedding.iter_mut() {
+            *value /= magnitude;
+        }
+    }
+}
+
+/// Euclidean distance between `a` and `b` after scaling both to unit length, so it doesn't depend on
+/// the embedding model's vector magnitudes: 0 for the same direction, up to 2 for opposite ones
+pub(crate) fn normalized_euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    let magnitude_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let magnitude_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if magnitude_a == 0.0 || magnitude_b == 0.0 {
+        return if magnitude_a == magnitude_b { 0.0 } else { 1.0 };
+    }
+    
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x / magnitude_a - y / magnitude_b).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Searches timed when sampling search speed for `metrics`
+const METRICS_SAMPLE_SEARCHES: usize = 3;
+
+/// Bytes of a stored document besides its embedding and text: id, URL, title, metadata and
+/// metadata index entries, with room to spare
+const DOCUMENT_FIXED_BYTES: usize = 1024;
+
+/// Disk space sled takes per byte of live data. It's log-structured and leaves old versions
+/// around until segments are rewritten, so it can use several times the raw size.
+const SLED_SPACE_AMPLIFICATION: f64 = 3.0;
+
+/// Conservative estimate of the disk space one document with a `dimension`-long embedding and
+/// `content_chars` characters of text takes in the store
+pub(crate) fn estimate_document_bytes(dimension: usize, content_chars: usize) -> u64 {
+    // Embeddings are f32s; text is budgeted at two bytes a character for non-ASCII content
+    let record_bytes = dimension * 4 + content_chars * 2 + DOCUMENT_FIXED_BYTES;
+    (record_bytes as f64 * SLED_SPACE_AMPLIFICATION).ceil() as u64
+}
+
+/// Outcome of `insert_documents`: how many documents were stored, and the id and reason for each
+/// document that was rejected. Rejected documents don't stop the rest of the batch.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InsertSummary {
+    pub inserted: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Checks a document is storable and serializes it
+fn encode_document(doc: &VectorDocument) -> Result<Vec<u8>, String> {
+    if doc.id.is_empty() {
+        return Err("document id is empty".to_string());
+    }
+    if doc.embedding.is_empty() {
+        return Err("embedding is empty".to_string());
+    }
+    if doc.embedding.iter().any(|value| !value.is_finite()) {
+        return Err("embedding contains NaN or infinite values".to_string());
+    }
+    bincode::serialize(doc).map_err(|e| format!("failed to serialize document: {}", e))
+}
+
+/// How many times to try opening a locked database before giving up, and the first wait between tries
+const OPEN_ATTEMPTS: u32 = 5;
+const OPEN_RETRY_INITIAL_DELAY: Duration = Duration::from_millis(200);
+
+fn is_lock_error(error: &sled::Error) -> bool {
+    let message = error.to_string();
+    message.contains("lock") || message.contains("locked")
+}
+
+/// Run maintenance automatically once this many documents have been deleted since the last pass
+const COMPACTION_DELETE_THRESHOLD: usize = 1000;
+
+/// When writes are flushed to disk. Each flush is an fsync, which dominates a large scrape if
+/// it happens after every batch. Unflushed writes are lost on a crash, but sled's log keeps
+/// the store itself consistent, so the pages behind them are just embedded again next update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushPolicy {
+    /// Flush once this many documents have been written or deleted since the last flush
+    pub every_documents: usize,
+    /// Flush on the next write once this long has passed since the last flush
+    pub interval: Duration,
+}
+
+impl FlushPolicy {
+    /// Flush after every write
+    pub const ALWAYS: FlushPolicy = FlushPolicy { every_documents: 1, interval: Duration::ZERO };
+}
+
 pub struct VectorDatabase {
     db: Arc<Db>,
+    /// Secondary index over `INDEXED_METADATA_KEYS`
+    metadata_index: sled::Tree,
+    /// Chunks that couldn't be embedded, by chunk id
+    failed_embeddings: sled::Tree,
+    /// Facts about the whole store that would otherwise take a full scan to find out
+    markers: sled::Tree,
+    /// Documents removed since the last maintenance pass
+    deletes_since_compaction: AtomicUsize,
+    flush_policy: FlushPolicy,
+    /// Documents written or deleted since the last flush
+    unflushed_documents: AtomicUsize,
+    last_flush: Mutex<Instant>,
+    /// Every stored embedding has unit length, so similarity is just the dot product with the
+    /// (normalized) query instead of a full cosine
+    unit_length_embeddings: AtomicBool,
 }
 
 impl VectorDatabase {
     pub async fn new() -> AppResult<Self> {
         let data_dir = AppConfig::get_data_dir();
-        let db_path = data_dir.join("vector_db");
-        
+        Self::open_at(&data_dir.join("vector_db")).await
+    }
+    
+    /// Opens the database at `db_path`, waiting out a lock held by another instance.
+    ///
+    /// sled guards its files with an OS file lock, which is released as soon as the holding
+    /// process exits - even if it crashed. A lock that persists through every retry therefore
+    /// belongs to a live instance, so the directory is never deleted; the caller gets an error
+    /// and can fall back to a temporary database instead.
+    pub async fn open_at(db_path: &Path) -> AppResult<Self> {
         // Create directory if it doesn't exist
-        std::fs::create_dir_all(&db_path)
+        std::fs::create_dir_all(db_path)
             .map_err(|e| AppError::StorageError(format!("Failed to create vector DB directory: {}", e)))?;
         
         info!("Opening sled database at: {:?}", db_path);
         
-        // Try to open the database with retry logic for lock issues
-        let db = match sled::open(&db_path) {
-            Ok(db) => db,
-            Err(e) => {
-                error!("Failed to open sled database: {}", e);
-                
-                // If it's a lock error, try to clean up and retry
-                if e.to_string().contains("lock") || e.to_string().contains("locked") {
-                    warn!("Database appears to be locked, attempting cleanup...");
-                    
-                    // Try to remove the entire database directory to clear locks
-                    if db_path.exists() {
-                        if let Err(cleanup_err) = std::fs::remove_dir_all(&db_path) {
-                            warn!("Failed to cleanup database directory: {}", cleanup_err);
-                        } else {
-                            info!("Cleaned up locked database directory");
-                        }
-                        
-                        // Recreate the directory
-                        if let Err(create_err) = std::fs::create_dir_all(&db_path) {
-                            warn!("Failed to recreate database directory: {}", create_err);
-                        }
-                    }
-                    
-                    // Try opening again with a fresh database
-                    sled::open(&db_path)
-                        .map_err(|e2| AppError::StorageError(format!("Failed to open sled database after cleanup: {}", e2)))?
-                } else {
+        let mut delay = OPEN_RETRY_INITIAL_DELAY;
+        let mut attempt = 1;
+        let db = loop {
+            match sled::open(db_path) {
+                Ok(db) => break db,
+                Err(e) if is_lock_error(&e) && attempt < OPEN_ATTEMPTS => {
+                    warn!("Vector database is locked (attempt {}/{}), retrying in {:?}", attempt, OPEN_ATTEMPTS, delay);
+                    sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) if is_lock_error(&e) => {
+                    error!("Vector database is still locked after {} attempts: {}", OPEN_ATTEMPTS, e);
+                    return Err(AppError::StorageError(format!(
+                        "The knowledge base is in use by another running instance of the app: {}", e
+                    )));
+                }
+                Err(e) => {
+                    error!("Failed to open sled database: {}", e);
                     return Err(AppError::StorageError(format!("Failed to open sled database: {}", e)));
                 }
             }
         };
         
+        let database = Self::from_db(db)?;
+        database.migrate_metadata()?;
+        Ok(database)
+    }
+    
+    fn from_db(db: Db) -> AppResult<Self> {
+        let metadata_index = db.open_tree("metadata_index")
+            .map_err(|e| AppError::StorageError(format!("Failed to open metadata index: {}", e)))?;
+        let failed_embeddings = db.open_tree("failed_embeddings")
+            .map_err(|e| AppError::StorageError(format!("Failed to open failed embeddings: {}", e)))?;
+        let markers = db.open_tree("markers")
+            .map_err(|e| AppError::StorageError(format!("Failed to open markers: {}", e)))?;
         Ok(Self {
             db: Arc::new(db),
+            metadata_index,
+            failed_embeddings,
+            markers,
+            deletes_since_compaction: AtomicUsize::new(0),
+            flush_policy: FlushPolicy::ALWAYS,
+            unflushed_documents: AtomicUsize::new(0),
+            last_flush: Mutex::new(Instant::now()),
+            unit_length_embeddings: AtomicBool::new(false),
         })
     }
     
+    pub fn set_flush_policy(&mut self, flush_policy: FlushPolicy) {
+        self.flush_policy = flush_policy;
+    }
+    
+    /// Scores searches with a plain dot product when every embedding written will have unit
+    /// length. Refused if the store already holds embeddings that don't, e.g. ones stored before
+    /// normalization was turned on.
+    pub async fn set_unit_length_embeddings(&self, enabled: bool) {
+        let stored_unit_length = self.stored_unit_length();
+        if enabled && !stored_unit_length {
+            warn!("Stored embeddings aren't unit length; using full cosine similarity until the knowledge base is re-embedded");
+        }
+        self.unit_length_embeddings.store(enabled && stored_unit_length, Ordering::SeqCst);
+    }
+    
+    pub fn uses_unit_length_embeddings(&self) -> bool {
+        self.unit_length_embeddings.load(Ordering::SeqCst)
+    }
+    
+    /// Whether every stored embedding has unit length, from the marker if there is one. Stores
+    /// written before the marker existed are scanned once and the answer recorded.
+    fn stored_unit_length(&self) -> bool {
+        match self.markers.get(UNIT_LENGTH_MARKER) {
+            Ok(Some(marker)) => return marker.as_ref() == [1],
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read the unit-length marker: {}", e),
+        }
+        
+        let unit_length = self.iter_documents().all(|doc| is_unit_length(&doc.embedding));
+        self.set_unit_length_marker(unit_length);
+        unit_length
+    }
+    
+    fn set_unit_length_marker(&self, unit_length: bool) {
+        if let Err(e) = self.markers.insert(UNIT_LENGTH_MARKER, &[unit_length as u8]) {
+            warn!("Failed to record whether stored embeddings are unit length: {}", e);
+        }
+    }
+    
+    /// Writes everything stored so far to disk. Ingestion calls this when it finishes, since
+    /// the flush policy may have left its last writes in memory.
+    pub async fn flush(&self) -> AppResult<()> {
+        self.db.flush_async().await
+            .map_err(|e| AppError::StorageError(format!("Failed to flush database: {}", e)))?;
+        self.unflushed_documents.store(0, Ordering::SeqCst);
+        *self.last_flush.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        Ok(())
+    }
+    
+    /// Documents written or deleted that aren't on disk yet
+    pub fn unflushed_documents(&self) -> usize {
+        self.unflushed_documents.load(Ordering::SeqCst)
+    }
+    
+    /// Counts `documents` towards the flush policy and flushes if it's due
+    async fn record_writes(&self, documents: usize) -> AppResult<()> {
+        let unflushed = self.unflushed_documents.fetch_add(documents, Ordering::SeqCst) + documents;
+        let elapsed = self.last_flush.lock().unwrap_or_else(|e| e.into_inner()).elapsed();
+        if unflushed >= self.flush_policy.every_documents || elapsed >= self.flush_policy.interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+    
+    /// Rewrites documents stored with JSON-string metadata in the current layout and builds
+    /// the metadata index if it's missing. Runs once per store: a marker records that it's done,
+    /// since documents without any indexed metadata leave the index empty.
+    fn migrate_metadata(&self) -> AppResult<()> {
+        let migrated = self.markers.contains_key(METADATA_MIGRATED_MARKER)
+            .map_err(|e| AppError::StorageError(format!("Failed to read markers: {}", e)))?;
+        if migrated {
+            return Ok(());
+        }
+        if !self.metadata_index.is_empty() || self.db.is_empty() {
+            return self.mark_metadata_migrated();
+        }
+        
+        let mut documents = sled::Batch::default();
+        let mut index = sled::Batch::default();
+        let mut converted = 0;
+        for (key, value) in self.db.iter().flatten() {
+            let Ok((doc, legacy)) = decode_document(&value) else {
+                continue;
+            };
+            if legacy {
+                let value = bincode::serialize(&doc)
+                    .map_err(|e| AppError::StorageError(format!("Failed to serialize document: {}", e)))?;
+                documents.insert(key, value);
+                converted += 1;
+            }
+            for entry in metadata_index_keys(&doc) {
+                index.insert(entry, &[]);
+            }
+        }
+        
+        self.db.apply_batch(documents)
+            .map_err(|e| AppError::StorageError(format!("Failed to migrate documents: {}", e)))?;
+        self.metadata_index.apply_batch(index)
+            .map_err(|e| AppError::StorageError(format!("Failed to build metadata index: {}", e)))?;
+        self.db.flush()
+            .map_err(|e| AppError::StorageError(format!("Failed to flush database: {}", e)))?;
+        
+        info!("Built metadata index; converted {} documents from JSON-string metadata", converted);
+        self.mark_metadata_migrated()
+    }
+    
+    fn mark_metadata_migrated(&self) -> AppResult<()> {
+        self.markers.insert(METADATA_MIGRATED_MARKER, &[])
+            .map_err(|e| AppError::StorageError(format!("Failed to record the metadata migration: {}", e)))?;
+        self.markers.flush()
+            .map_err(|e| AppError::StorageError(format!("Failed to flush markers: {}", e)))?;
+        Ok(())
+    }
+    
     pub fn new_fallback() -> Self {
         // Create an in-memory database as fallback
         let db = sled::Config::new().temporary(true).open()
             .expect("Failed to create temporary database");
         
-        Self {
-            db: Arc::new(db),
-        }
+        Self::from_db(db).expect("Failed to create temporary metadata index")
     }
     
     pub async fn initialize(&self) -> AppResult<()> {
@@ -83,39 +488,72 @@ impl VectorDatabase {
         Ok(())
     }
     
-    pub async fn insert_documents(&self, documents: Vec<VectorDocument>) -> AppResult<()> {
+    /// Stores `documents`, replacing any with the same id. Documents that can't be stored are
+    /// reported in the summary instead of failing the batch; an `Err` means the write itself failed.
+    pub async fn insert_documents(&self, documents: Vec<VectorDocument>) -> AppResult<InsertSummary> {
+        let mut summary = InsertSummary::default();
         if documents.is_empty() {
-            return Ok(());
+            return Ok(summary);
+        }
+        
+        // An embedding from elsewhere (e.g. an import) breaks the dot-product shortcut for every search
+        if documents.iter().any(|doc| !is_unit_length(&doc.embedding)) {
+            self.set_unit_length_marker(false);
+            if self.uses_unit_length_embeddings() {
+                warn!("Storing embeddings that aren't unit length; switching to full cosine similarity");
+                self.unit_length_embeddings.store(false, Ordering::SeqCst);
+            }
         }
         
         let mut batch = sled::Batch::default();
+        let mut index = sled::Batch::default();
         
         for doc in &documents {
-            let key = doc.id.as_bytes();
-            let value = bincode::serialize(&doc)
-                .map_err(|e| AppError::StorageError(format!("Failed to serialize document: {}", e)))?;
+            let value = match encode_document(doc) {
+                Ok(value) => value,
+                Err(reason) => {
+                    warn!("Skipping document {:?}: {}", doc.id, reason);
+                    summary.failed.push((doc.id.clone(), reason));
+                    continue;
+                }
+            };
             
-            batch.insert(key, value);
+            // A document replacing one with the same id may have different metadata
+            if let Some(previous) = self.get_document(&doc.id).await? {
+                for entry in metadata_index_keys(&previous) {
+                    index.remove(entry);
+                }
+            }
+            for entry in metadata_index_keys(doc) {
+                index.insert(entry, &[]);
+            }
+            batch.insert(doc.id.as_bytes(), value);
+            summary.inserted += 1;
         }
         
         self.db.apply_batch(batch)
             .map_err(|e| AppError::StorageError(format!("Failed to insert batch: {}", e)))?;
+        self.metadata_index.apply_batch(index)
+            .map_err(|e| AppError::StorageError(format!("Failed to update metadata index: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| AppError::StorageError(format!("Failed to flush database: {}", e)))?;
+        self.record_writes(summary.inserted).await?;
         
-        info!("Inserted {} documents into vector database", documents.len());
-        Ok(())
+        info!("Inserted {} documents into vector database ({} rejected)", summary.inserted, summary.failed.len());
+        Ok(summary)
     }
     
     pub async fn search_similar(&self, embedding: Vec<f32>, limit: usize) -> AppResult<Vec<(VectorDocument, f32)>> {
+        let embedding = self.prepare_query(embedding);
         let mut results = Vec::new();
         
         // Iterate through all documents and calculate similarity
         for result in self.db.iter() {
             match result {
                 Ok((_, value)) => {
-                    if let Ok(doc) = bincode::deserialize::<VectorDocument>(&value) {
+                    if let Ok((doc, _)) = decode_document(&value) {
+                        if doc.embedding.len() != embedding.len() {
+                            return Err(dimension_mismatch_error(embedding.len(), doc.embedding.len()));
+                        }
                         let similarity = self.cosine_similarity(&embedding, &doc.embedding);
                         results.push((doc, similarity));
                     }
@@ -134,15 +572,153 @@ impl VectorDatabase {
         Ok(results)
     }
     
+    /// Scores several query vectors in a single pass over the store, returning the top `limit`
+    /// matches for each query in the same order as `embeddings`. Results match calling
+    /// `search_similar` once per query, but each document is only read and deserialized once.
+    pub async fn search_batch(&self, embeddings: Vec<Vec<f32>>, limit: usize) -> AppResult<Vec<Vec<(VectorDocument, f32)>>> {
+        if embeddings.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let embeddings: Vec<Vec<f32>> = embeddings.into_iter().map(|embedding| self.prepare_query(embedding)).collect();
+        let mut documents = Vec::new();
+        let mut scores: Vec<Vec<(usize, f32)>> = vec![Vec::new(); embeddings.len()];
+        
+        for result in self.db.iter() {
+            match result {
+                Ok((_, value)) => {
+                    if let Ok((doc, _)) = decode_document(&value) {
+                        let doc_index = documents.len();
+                        for (query, query_scores) in embeddings.iter().zip(scores.iter_mut()) {
+                            if doc.embedding.len() != query.len() {
+                                return Err(dimension_mismatch_error(query.len(), doc.embedding.len()));
+                            }
+                            query_scores.push((doc_index, self.cosine_similarity(query, &doc.embedding)));
+                        }
+                        documents.push(doc);
+                    }
+                }
+                Err(e) => {
+                    error!("Error reading from database: {}", e);
+                }
+            }
+        }
+        
+        Ok(scores.into_iter()
+            .map(|mut query_scores| {
+                query_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+                query_scores.truncate(limit);
+                query_scores.into_iter()
+                    .map(|(doc_index, score)| (documents[doc_index].clone(), score))
+                    .collect()
+            })
+            .collect())
+    }
+    
+    /// The closest stored document to each of `embeddings` by `normalized_euclidean_distance`, in a
+    /// single pass over the store. `None` for every query when the store is empty.
+    pub async fn nearest_by_distance(&self, embeddings: &[Vec<f32>]) -> AppResult<Vec<Option<(VectorDocument, f32)>>> {
+        let mut nearest: Vec<Option<(VectorDocument, f32)>> = vec![None; embeddings.len()];
+        if embeddings.is_empty() {
+            return Ok(nearest);
+        }
+        
+        for doc in self.iter_documents() {
+            for (query, best) in embeddings.iter().zip(nearest.iter_mut()) {
+                if doc.embedding.len() != query.len() {
+                    return Err(dimension_mismatch_error(query.len(), doc.embedding.len()));
+                }
+                let distance = normalized_euclidean_distance(query, &doc.embedding);
+                if best.as_ref().is_none_or(|(_, best_distance)| distance < *best_distance) {
+                    *best = Some((doc.clone(), distance));
+                }
+            }
+        }
+        
+        Ok(nearest)
+    }
+    
+    /// Deletes every document of `source_url`, along with the chunks recorded as failed to embed
     pub async fn delete_by_source(&self, source_url: &str) -> AppResult<()> {
+        self.delete_by_source_except(source_url, &HashSet::new()).await?;
+        self.replace_failed_embeddings(source_url, &[])
+    }
+    
+    /// Deletes the documents of `source_url` whose ids aren't in `keep_ids`, returning how many
+    /// were removed. Used to drop chunks left over from an earlier version of a page.
+    pub async fn delete_by_source_except(&self, source_url: &str, keep_ids: &HashSet<String>) -> AppResult<usize> {
+        let deleted = self.delete_matching(|doc| doc.source_url == source_url && !keep_ids.contains(&doc.id)).await?;
+        info!("Deleted {} documents from source: {}", deleted, source_url);
+        Ok(deleted)
+    }
+    
+    /// Deletes every document of each of `source_urls` in a single pass over the store, returning
+    /// how many were removed per source (0 for sources with nothing stored)
+    pub async fn delete_sources(&self, source_urls: &[String]) -> AppResult<HashMap<String, usize>> {
+        let mut removed: HashMap<String, usize> = source_urls.iter().map(|url| (url.clone(), 0)).collect();
+        let deleted = self.delete_matching(|doc| match removed.get_mut(&doc.source_url) {
+            Some(count) => {
+                *count += 1;
+                true
+            }
+            None => false,
+        }).await?;
+        for url in source_urls {
+            self.replace_failed_embeddings(url, &[])?;
+        }
+        
+        info!("Deleted {} documents from {} sources", deleted, source_urls.len());
+        Ok(removed)
+    }
+    
+    /// Replaces the failed chunks recorded for `source_url` with `failures`, e.g. after the page
+    /// was embedded again. They're flushed right away so a crash doesn't lose track of them.
+    pub fn replace_failed_embeddings(&self, source_url: &str, failures: &[FailedEmbedding]) -> AppResult<()> {
+        let mut batch = sled::Batch::default();
+        for failure in self.failed_embeddings()?.into_iter().filter(|failure| failure.source_url == source_url) {
+            batch.remove(failure.id.as_bytes());
+        }
+        for failure in failures {
+            let value = bincode::serialize(failure)
+                .map_err(|e| AppError::StorageError(format!("Failed to serialize failed embedding: {}", e)))?;
+            batch.insert(failure.id.as_bytes(), value);
+        }
+        
+        self.failed_embeddings.apply_batch(batch)
+            .map_err(|e| AppError::StorageError(format!("Failed to record failed embeddings: {}", e)))?;
+        self.failed_embeddings.flush()
+            .map_err(|e| AppError::StorageError(format!("Failed to flush failed embeddings: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Every chunk whose embedding failed and hasn't been retried successfully, by chunk id
+    pub fn failed_embeddings(&self) -> AppResult<Vec<FailedEmbedding>> {
+        self.failed_embeddings.iter()
+            .map(|entry| {
+                let (_, value) = entry
+                    .map_err(|e| AppError::StorageError(format!("Failed to read failed embeddings: {}", e)))?;
+                bincode::deserialize(&value)
+                    .map_err(|e| AppError::StorageError(format!("Failed to decode failed embedding: {}", e)))
+            })
+            .collect()
+    }
+    
+    /// Deletes every document `matches` accepts with one batch, returning how many were removed
+    async fn delete_matching<F>(&self, mut matches: F) -> AppResult<usize>
+    where
+        F: FnMut(&VectorDocument) -> bool,
+    {
         let mut keys_to_delete = Vec::new();
+        let mut index = sled::Batch::default();
         
-        // Find all documents with matching source_url
         for result in self.db.iter() {
             match result {
                 Ok((key, value)) => {
-                    if let Ok(doc) = bincode::deserialize::<VectorDocument>(&value) {
-                        if doc.source_url == source_url {
+                    if let Ok((doc, _)) = decode_document(&value) {
+                        if matches(&doc) {
+                            for entry in metadata_index_keys(&doc) {
+                                index.remove(entry);
+                            }
                             keys_to_delete.push(key);
                         }
                     }
@@ -154,6 +730,7 @@ impl VectorDatabase {
         }
         
         // Delete the documents
+        let deleted = keys_to_delete.len();
         let mut batch = sled::Batch::default();
         for key in keys_to_delete {
             batch.remove(key);
@@ -161,11 +738,130 @@ impl VectorDatabase {
         
         self.db.apply_batch(batch)
             .map_err(|e| AppError::StorageError(format!("Failed to delete documents: {}", e)))?;
+        self.metadata_index.apply_batch(index)
+            .map_err(|e| AppError::StorageError(format!("Failed to update metadata index: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| AppError::StorageError(format!("Failed to flush database: {}", e)))?;
+        self.record_writes(deleted).await?;
+        
+        // Many small deletes add up; reclaim space once enough have accumulated
+        let pending = self.deletes_since_compaction.fetch_add(deleted, Ordering::SeqCst) + deleted;
+        if pending >= COMPACTION_DELETE_THRESHOLD {
+            info!("{} documents deleted since last maintenance, compacting database", pending);
+            if let Err(e) = self.compact().await {
+                warn!("Database maintenance after deletes failed: {}", e);
+            }
+        }
+        
+        Ok(deleted)
+    }
+    
+    /// Bytes the database currently occupies on disk
+    pub fn size_on_disk(&self) -> AppResult<u64> {
+        self.db.size_on_disk()
+            .map_err(|e| AppError::StorageError(format!("Failed to read database size: {}", e)))
+    }
+    
+    /// Flushes pending writes and gives sled a chance to reclaim space from deleted documents.
+    ///
+    /// sled has no explicit compaction call: it rewrites fragmented segments in the background
+    /// as they're flushed, so the on-disk size shrinks gradually rather than immediately after
+    /// a delete. Forcing a flush here makes that happen now for everything written so far; the
+    /// report shows the size before and after so the effect is visible.
+    pub async fn compact(&self) -> AppResult<CompactionReport> {
+        let size_before_bytes = self.size_on_disk()?;
+        
+        self.flush().await?;
+        self.deletes_since_compaction.store(0, Ordering::SeqCst);
+        
+        let report = CompactionReport {
+            size_before_bytes,
+            size_after_bytes: self.size_on_disk()?,
+            document_count: self.db.len(),
+        };
+        info!("Database maintenance complete: {} -> {} bytes, {} documents", 
+              report.size_before_bytes, report.size_after_bytes, report.document_count);
+        Ok(report)
+    }
+    
+    /// Discards the metadata index and rebuilds it from the stored documents. Documents and their
+    /// index entries are written separately, so a crash in between can leave the index out of
+    /// step with the documents; this is the repair.
+    pub async fn rebuild_index(&self) -> AppResult<IndexRebuildReport> {
+        let started = Instant::now();
+        self.metadata_index.clear()
+            .map_err(|e| AppError::StorageError(format!("Failed to clear metadata index: {}", e)))?;
+        
+        let mut index = sled::Batch::default();
+        let mut documents_indexed = 0;
+        let mut index_entries = 0;
+        for doc in self.iter_documents() {
+            for entry in metadata_index_keys(&doc) {
+                index.insert(entry, &[]);
+                index_entries += 1;
+            }
+            documents_indexed += 1;
+        }
+        self.metadata_index.apply_batch(index)
+            .map_err(|e| AppError::StorageError(format!("Failed to rebuild metadata index: {}", e)))?;
+        self.flush().await?;
+        
+        let report = IndexRebuildReport {
+            documents_indexed,
+            index_entries,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+        info!("Rebuilt metadata index from {} documents ({} entries) in {} ms",
+              report.documents_indexed, report.index_entries, report.elapsed_ms);
+        Ok(report)
+    }
+    
+    /// Looks a document up by id, returning `None` if there's no such document
+    pub async fn get_document(&self, id: &str) -> AppResult<Option<VectorDocument>> {
+        let Some(value) = self.db.get(id.as_bytes())
+            .map_err(|e| AppError::StorageError(format!("Failed to read document {}: {}", id, e)))? else {
+            return Ok(None);
+        };
+        
+        decode_document(&value)
+            .map(|(doc, _)| Some(doc))
+            .map_err(|e| AppError::StorageError(format!("Failed to deserialize document {}: {}", id, e)))
+    }
+    
+    /// Nearest neighbours of a stored document, seeded with its own embedding. The document
+    /// itself and other chunks of the same page are left out, so every result points elsewhere.
+    pub async fn find_related(&self, document_id: &str, limit: usize) -> AppResult<Vec<(VectorDocument, f32)>> {
+        let seed = self.get_document(document_id).await?
+            .ok_or_else(|| AppError::StorageError(format!("Document {} not found", document_id)))?;
+        
+        let mut results = Vec::new();
+        for doc in self.iter_documents() {
+            if doc.id == seed.id || doc.source_url == seed.source_url {
+                continue;
+            }
+            if doc.embedding.len() != seed.embedding.len() {
+                return Err(dimension_mismatch_error(seed.embedding.len(), doc.embedding.len()));
+            }
+            let similarity = self.cosine_similarity(&seed.embedding, &doc.embedding);
+            results.push((doc, similarity));
+        }
         
-        info!("Deleted documents from source: {}", source_url);
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        results.truncate(limit);
+        Ok(results)
+    }
+    
+    /// Removes every document, e.g. before re-embedding with a model of a different dimension
+    pub async fn clear(&self) -> AppResult<()> {
+        self.db.clear()
+            .map_err(|e| AppError::StorageError(format!("Failed to clear database: {}", e)))?;
+        self.metadata_index.clear()
+            .map_err(|e| AppError::StorageError(format!("Failed to clear metadata index: {}", e)))?;
+        self.failed_embeddings.clear()
+            .map_err(|e| AppError::StorageError(format!("Failed to clear failed embeddings: {}", e)))?;
+        self.set_unit_length_marker(true);
+        self.db.flush_async().await
+            .map_err(|e| AppError::StorageError(format!("Failed to flush database: {}", e)))?;
+        info!("Cleared vector database");
         Ok(())
     }
     
@@ -173,12 +869,194 @@ impl VectorDatabase {
         Ok(self.db.len())
     }
     
+    /// Number of distinct pages with documents in the store
+    pub async fn count_sources(&self) -> AppResult<usize> {
+        let sources: HashSet<String> = self.iter_documents().map(|doc| doc.source_url).collect();
+        Ok(sources.len())
+    }
+    
+    /// Every page with documents in the store, sorted by URL
+    pub async fn stored_sources(&self) -> AppResult<Vec<StoredSource>> {
+        let mut sources: HashMap<String, StoredSource> = HashMap::new();
+        for doc in self.iter_documents() {
+            let scraped_at = doc.metadata.get("scraped_at").cloned();
+            let source = sources.entry(doc.source_url.clone())
+                .or_insert_with(|| StoredSource {
+                    source_url: doc.source_url,
+                    source_title: doc.source_title,
+                    chunk_count: 0,
+                    scraped_at: None,
+                });
+            source.chunk_count += 1;
+            if scraped_time(scraped_at.as_deref()) > scraped_time(source.scraped_at.as_deref()) {
+                source.scraped_at = scraped_at;
+            }
+        }
+        
+        let mut sources: Vec<StoredSource> = sources.into_values().collect();
+        sources.sort_by(|a, b| a.source_url.cmp(&b.source_url));
+        Ok(sources)
+    }
+    
+    /// Pages with documents whose source URL isn't in `known_sources`, sorted by URL
+    pub async fn sources_not_in(&self, known_sources: &HashSet<String>) -> AppResult<Vec<OrphanedSource>> {
+        let mut orphans: HashMap<String, OrphanedSource> = HashMap::new();
+        for doc in self.iter_documents().filter(|doc| !known_sources.contains(&doc.source_url)) {
+            orphans.entry(doc.source_url.clone())
+                .or_insert_with(|| OrphanedSource {
+                    source_url: doc.source_url,
+                    source_title: doc.source_title,
+                    chunk_count: 0,
+                })
+                .chunk_count += 1;
+        }
+        
+        let mut orphans: Vec<OrphanedSource> = orphans.into_values().collect();
+        orphans.sort_by(|a, b| a.source_url.cmp(&b.source_url));
+        Ok(orphans)
+    }
+    
+    /// Iterates over every stored document, skipping entries that fail to read or deserialize
+    pub fn iter_documents(&self) -> impl Iterator<Item = VectorDocument> + '_ {
+        self.db.iter().filter_map(|result| match result {
+            Ok((_, value)) => decode_document(&value).ok().map(|(doc, _)| doc),
+            Err(e) => {
+                error!("Error reading from database: {}", e);
+                None
+            }
+        })
+    }
+    
+    /// Every document of `source_url` in page order, by their `chunk_index` metadata
+    pub async fn documents_for_source(&self, source_url: &str) -> AppResult<Vec<VectorDocument>> {
+        let mut documents: Vec<VectorDocument> = self.iter_documents()
+            .filter(|doc| doc.source_url == source_url)
+            .collect();
+        documents.sort_by_key(|doc| {
+            let chunk_index = doc.metadata.get("chunk_index").and_then(|index| index.parse::<usize>().ok());
+            (chunk_index.unwrap_or(usize::MAX), doc.id.clone())
+        });
+        Ok(documents)
+    }
+    
+    /// Ids of the documents whose metadata has `key` set to `value` (or, for `LIST_METADATA_KEYS`, listing it).
+    /// Uses the index for `INDEXED_METADATA_KEYS` and falls back to a full scan for any other key.
+    pub async fn find_ids_by_metadata(&self, key: &str, value: &str) -> AppResult<Vec<String>> {
+        if !INDEXED_METADATA_KEYS.contains(&key) {
+            return Ok(self.iter_documents()
+                .filter(|doc| doc.metadata.get(key).is_some_and(|stored| metadata_values(key, stored).contains(&value)))
+                .map(|doc| doc.id)
+                .collect());
+        }
+        
+        let prefix = metadata_index_prefix(key, value);
+        self.metadata_index.scan_prefix(&prefix)
+            .keys()
+            .map(|entry| {
+                let entry = entry.map_err(|e| AppError::StorageError(format!("Failed to read metadata index: {}", e)))?;
+                Ok(String::from_utf8_lossy(&entry[prefix.len()..]).into_owned())
+            })
+            .collect()
+    }
+    
+    /// Like `search_similar`, but only scores documents from the given pages. Each entry of
+    /// `sources` matches a document's source URL exactly or its page title ignoring case.
+    pub async fn search_similar_filtered(&self, embedding: Vec<f32>, limit: usize, sources: &[String]) -> AppResult<Vec<(VectorDocument, f32)>> {
+        let embedding = self.prepare_query(embedding);
+        let mut results = Vec::new();
+        for doc in self.iter_documents() {
+            let from_source = sources.iter()
+                .any(|source| *source == doc.source_url || source.eq_ignore_ascii_case(&doc.source_title));
+            if !from_source {
+                continue;
+            }
+            if doc.embedding.len() != embedding.len() {
+                return Err(dimension_mismatch_error(embedding.len(), doc.embedding.len()));
+            }
+            let similarity = self.cosine_similarity(&embedding, &doc.embedding);
+            results.push((doc, similarity));
+        }
+        
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        results.truncate(limit);
+        Ok(results)
+    }
+    
+    /// Like `search_similar`, but only scores documents whose metadata has `key` set to `value`
+    pub async fn search_similar_where(&self, embedding: Vec<f32>, limit: usize, key: &str, value: &str) -> AppResult<Vec<(VectorDocument, f32)>> {
+        let embedding = self.prepare_query(embedding);
+        let mut results = Vec::new();
+        for id in self.find_ids_by_metadata(key, value).await? {
+            let Some(doc) = self.get_document(&id).await? else {
+                continue;
+            };
+            if doc.embedding.len() != embedding.len() {
+                return Err(dimension_mismatch_error(embedding.len(), doc.embedding.len()));
+            }
+            let similarity = self.cosine_similarity(&embedding, &doc.embedding);
+            results.push((doc, similarity));
+        }
+        
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        results.truncate(limit);
+        Ok(results)
+    }
+    
+    /// Counts, size and embedding statistics for the store. With `sample_search`, also times a few
+    /// full searches using the first stored embedding as the query, which costs one scan per sample.
+    pub async fn metrics(&self, sample_search: bool) -> AppResult<DatabaseMetrics> {
+        let mut document_count = 0;
+        let mut total_dimension = 0;
+        let mut sample_query = None;
+        for doc in self.iter_documents() {
+            document_count += 1;
+            total_dimension += doc.embedding.len();
+            if sample_query.is_none() {
+                sample_query = Some(doc.embedding);
+            }
+        }
+        
+        let sampled_search_ms = match sample_query {
+            Some(query) if sample_search => {
+                let started = std::time::Instant::now();
+                for _ in 0..METRICS_SAMPLE_SEARCHES {
+                    self.search_similar(query.clone(), 5).await?;
+                }
+                Some(started.elapsed().as_secs_f64() * 1000.0 / METRICS_SAMPLE_SEARCHES as f64)
+            }
+            _ => None,
+        };
+        
+        Ok(DatabaseMetrics {
+            document_count,
+            size_on_disk_bytes: self.size_on_disk()?,
+            average_embedding_dimension: if document_count == 0 { 0.0 } else { total_dimension as f64 / document_count as f64 },
+            sampled_search_ms,
+        })
+    }
+    
+    /// Embedding dimension of the stored documents, taken from the first readable one
+    pub async fn stored_dimension(&self) -> Option<usize> {
+        self.iter_documents().next().map(|doc| doc.embedding.len())
+    }
+    
+    /// Normalizes a query for the dot-product shortcut; unchanged when full cosine similarity is used
+    fn prepare_query(&self, mut embedding: Vec<f32>) -> Vec<f32> {
+        if self.uses_unit_length_embeddings() {
+            normalize_embedding(&mut embedding);
+        }
+        embedding
+    }
+    
     fn cosine_similarity(&self, vec_a: &[f32], vec_b: &[f32]) -> f32 {
         if vec_a.len() != vec_b.len() {
             return 0.0;
         }
         
         let dot_product: f32 = vec_a.iter().zip(vec_b.iter()).map(|(a, b)| a * b).sum();
+        if self.uses_unit_length_embeddings() {
+            return dot_product;
+        }
         let magnitude_a: f32 = vec_a.iter().map(|x| x * x).sum::<f32>().sqrt();
         let magnitude_b: f32 = vec_b.iter().map(|x| x * x).sum::<f32>().sqrt();
         
@@ -27,7 +27,7 @@ mod tests {
                 source_url: "test://wiki/crafting".to_string(),
                 source_title: "Crafting Guide".to_string(),
                 embedding: vec![1.0, 0.0, 0.0],
-                metadata: "{}".to_string(),
+                metadata: HashMap::new(),
             },
             VectorDocument {
                 id: "doc2".to_string(),
@@ -35,7 +35,7 @@ mod tests {
                 source_url: "test://wiki/mining".to_string(),
                 source_title: "Mining Guide".to_string(),
                 embedding: vec![0.8, 0.6, 0.0],
-                metadata: "{}".to_string(),
+                metadata: HashMap::new(),
             },
             VectorDocument {
                 id: "doc3".to_string(),
@@ -43,7 +43,7 @@ mod tests {
                 source_url: "test://wiki/food".to_string(),
                 source_title: "Food Guide".to_string(),
                 embedding: vec![0.0, 0.0, 1.0],
-                metadata: "{}".to_string(),
+                metadata: HashMap::new(),
             },
         ];
         
@@ -1,10 +1,15 @@
-use crate::config::WikiConfig;
+use crate::config::{AppConfig, WikiConfig, DEFAULT_WIKI_CONTACT, DEFAULT_WIKI_USER_AGENT};
 use crate::errors::{AppError, AppResult};
-use crate::services::embedding_service::EmbeddingService;
+use crate::services::cancellation::CancelFlag;
+use crate::services::embedding_service::{EmbeddingProgressTracker, EmbeddingService, CODE_FENCE};
+use crate::services::page_store::{PageStore, StoredPage};
+use crate::services::vector_database::OrphanedSource;
 use serde::{Deserialize, Serialize};
 use scraper::{Html, Selector};
-use reqwest::Client;
-use std::collections::HashSet;
+use reqwest::{Client, Url};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::time::Duration;
@@ -18,8 +23,42 @@ pub struct WikiStatus {
     pub is_updating: bool,
     pub pages_scraped: u32,
     pub errors_encountered: u32,
+    /// Deepest link level actually reached during the last crawl
+    pub max_depth_reached: u32,
+    /// Pages skipped because the wiki reported them unchanged since the previous crawl
+    pub pages_unchanged: u32,
+    /// Disambiguation and stub pages that were crawled for links but not embedded
+    pub low_value_pages_skipped: u32,
 }
 
+/// Fails fast when a crawl is already running, so callers don't queue a second one behind the service lock
+pub fn ensure_no_update_running(update_in_progress: &AtomicBool) -> AppResult<()> {
+    if update_in_progress.load(Ordering::SeqCst) {
+        return Err(AppError::WikiUpdateInProgress);
+    }
+    Ok(())
+}
+
+/// Marks a crawl as running until dropped
+pub struct UpdateClaim(Arc<AtomicBool>);
+
+impl Drop for UpdateClaim {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Marks a crawl as running, or fails if one already is. Commands claim before waiting for the
+/// service lock, so a second request is rejected rather than run once the first finishes.
+pub fn claim_update(update_in_progress: &Arc<AtomicBool>) -> AppResult<UpdateClaim> {
+    update_in_progress.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .map_err(|_| AppError::WikiUpdateInProgress)?;
+    Ok(UpdateClaim(update_in_progress.clone()))
+}
+
+/// Upper bound for the configurable crawl depth; deeper crawls fan out to most of the wiki
+pub const MAX_CRAWL_DEPTH: u32 = 6;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WikiPage {
     pub title: String,
@@ -27,6 +66,438 @@ pub struct WikiPage {
     pub content: String,
     pub last_modified: Option<String>,
     pub categories: Vec<String>,
+    #[serde(default)]
+    pub kind: PageKind,
+    /// When the HTML was fetched (RFC 3339); `None` means now
+    #[serde(default)]
+    pub scraped_at: Option<String>,
+}
+
+impl WikiPage {
+    /// When the page was fetched, defaulting to now for pages that don't say
+    pub fn scraped_at_or_now(&self) -> String {
+        self.scraped_at.clone().unwrap_or_else(|| chrono::Utc::now().to_rfc3339())
+    }
+}
+
+/// Whether a page is worth embedding. Disambiguation and stub pages are still crawled for
+/// their links but add little but noise to the knowledge base.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageKind {
+    #[default]
+    Article,
+    Disambiguation,
+    Stub,
+}
+
+/// Pages whose main content has less text than this are treated as stubs
+const MIN_ARTICLE_CHARS: usize = 200;
+
+const DISAMBIGUATION_MARKERS: &str = ".dmbox, #disambigbox, .disambigbox, .disambiguation";
+const STUB_MARKERS: &str = ".stub, .ambox-stub, .stub-notice, #stub";
+
+/// Text elements containing any of these are left out of a page's extracted text
+const REMOVED_CONTENT_SELECTORS: &str = ".mw-editsection, .navbox, .infobox, .toc, #toc, .thumb, .mbox, script, style, .reference, .noprint";
+
+/// Text of `element`, leaving out the parts inside elements matching `removed`
+fn text_without(element: scraper::ElementRef, removed: &Selector) -> String {
+    let removed_ids: HashSet<_> = element.select(removed).map(|removed_el| removed_el.id()).collect();
+    element.descendants()
+        .filter(|node| !node.ancestors().any(|ancestor| removed_ids.contains(&ancestor.id())))
+        .filter_map(|node| node.value().as_text().map(|text| &**text))
+        .collect()
+}
+
+/// Pause between fetching linked pages during a crawl
+const CRAWL_DELAY: Duration = Duration::from_millis(200);
+
+/// Timeout for each request made while validating the wiki config
+const CONFIG_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fetch attempts beyond this mostly prolong outages instead of riding them out
+const MAX_SENSIBLE_FETCH_ATTEMPTS: u32 = 10;
+
+/// Retry backoffs shorter than this hit a struggling wiki again almost immediately
+const MIN_SENSIBLE_RETRY_BACKOFF_MS: u64 = 100;
+
+/// How long an unused pooled connection to the wiki stays open. Comfortably longer than the
+/// pauses between pages and entry points, so a whole crawl can run over the same connections.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// TCP keep-alive interval, so pooled connections dropped by a router are noticed
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// The MediaWiki API, relative to the wiki's base URL
+const MEDIAWIKI_API_PATH: &str = "/api.php";
+
+/// MediaWiki namespace of category pages
+const CATEGORY_NAMESPACE: i64 = 14;
+
+/// Longest pause honored from a `Retry-After` header, so a misbehaving server can't stall the crawl for hours
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+
+/// Times a page is re-requested after HTTP 429 before it counts as an error. These don't use up
+/// `fetch_attempts`, which are for failures.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigCheckStatus {
+    Pass,
+    /// Works, but is likely to cause trouble
+    Warn,
+    Fail,
+    /// Not run, e.g. network checks in offline mode or when an earlier check failed
+    Skipped,
+}
+
+/// Result of one sanity check from `validate_config`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigCheck {
+    pub name: String,
+    pub status: ConfigCheckStatus,
+    pub message: String,
+}
+
+impl ConfigCheck {
+    fn new(name: impl Into<String>, status: ConfigCheckStatus, message: impl Into<String>) -> Self {
+        Self { name: name.into(), status, message: message.into() }
+    }
+}
+
+/// Outcome of `validate_config`; `ok` is false if any check failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WikiConfigReport {
+    pub ok: bool,
+    pub checks: Vec<ConfigCheck>,
+}
+
+/// The parts of a robots.txt that apply to one crawler
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct RobotsRules {
+    allow: Vec<String>,
+    disallow: Vec<String>,
+    pub(crate) crawl_delay: Option<f64>,
+}
+
+impl RobotsRules {
+    /// Whether `path` (including any query string) may be crawled. The longest matching rule
+    /// wins and `Allow` wins ties. Rules are matched as plain prefixes; `*` and `$` patterns are
+    /// cut off at the first wildcard.
+    pub(crate) fn allows(&self, path: &str) -> bool {
+        let longest_match = |rules: &[String]| rules.iter()
+            .map(|rule| rule.split(['*', '$']).next().unwrap_or(""))
+            .filter(|prefix| path.starts_with(prefix))
+            .map(str::len)
+            .max();
+        
+        match (longest_match(&self.allow), longest_match(&self.disallow)) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(allowed), Some(disallowed)) => allowed >= disallowed,
+        }
+    }
+}
+
+/// Rules from `robots_txt` for `user_agent`: those of groups naming its product token (the part
+/// before `/`) if there are any, otherwise those of the `*` group
+pub(crate) fn parse_robots_txt(robots_txt: &str, user_agent: &str) -> RobotsRules {
+    let token = user_agent.split('/').next().unwrap_or("").trim().to_lowercase();
+    let mut specific = RobotsRules::default();
+    let mut wildcard = RobotsRules::default();
+    let mut found_specific = false;
+    
+    let mut group_agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+    for line in robots_txt.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        
+        if key == "user-agent" {
+            // A user-agent line after rules starts a new group
+            if in_rules {
+                group_agents.clear();
+                in_rules = false;
+            }
+            group_agents.push(value.to_lowercase());
+            continue;
+        }
+        in_rules = true;
+        
+        let applies_to_us = !token.is_empty() && group_agents.iter().any(|agent| agent != "*" && !agent.is_empty() && token.contains(agent.as_str()));
+        let applies_to_all = group_agents.iter().any(|agent| agent == "*");
+        let mut targets = Vec::new();
+        if applies_to_us {
+            found_specific = true;
+            targets.push(&mut specific);
+        }
+        if applies_to_all {
+            targets.push(&mut wildcard);
+        }
+        
+        for rules in targets {
+            match key.as_str() {
+                "allow" if !value.is_empty() => rules.allow.push(value.to_string()),
+                // An empty Disallow allows everything
+                "disallow" if !value.is_empty() => rules.disallow.push(value.to_string()),
+                "crawl-delay" => rules.crawl_delay = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+    
+    if found_specific { specific } else { wildcard }
+}
+
+/// Whether `link` matches an exclude pattern: a glob (`*` for any run of characters, `?` for
+/// one) if the pattern has wildcards, otherwise a substring. Case is ignored.
+pub(crate) fn matches_exclude_pattern(link: &str, pattern: &str) -> bool {
+    let link = link.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if !pattern.contains(['*', '?']) {
+        return link.contains(&pattern);
+    }
+    
+    // Greedy wildcard matching, backtracking to the last `*` on a mismatch
+    let link: Vec<char> = link.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let (mut l, mut p) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while l < link.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == link[l]) {
+            l += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, l));
+            p += 1;
+        } else if let Some((star_p, star_l)) = last_star {
+            p = star_p + 1;
+            l = star_l + 1;
+            last_star = Some((star_p, star_l + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// How long a `Retry-After` header asks to wait: either a number of seconds or an HTTP date
+pub(crate) fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let until = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date already in the past means the wait is over
+    Some((until.with_timezone(&chrono::Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Path and query of `url`, the part robots.txt rules are matched against
+fn robots_path(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+/// Outcome of `reparse_all`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReparseSummary {
+    pub pages_reparsed: usize,
+    /// Chunks in the knowledge base for the re-parsed pages afterwards
+    pub chunks: usize,
+    /// Stored pages that couldn't be parsed or embedded; their previous chunks are kept
+    pub errors: usize,
+}
+
+/// Outcome of `scrape_category`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryScrapeSummary {
+    /// The category as queried, e.g. `Category:Mechanics`
+    pub category: String,
+    /// Pages in the category and its subcategories
+    pub pages_found: usize,
+    pub pages_scraped: u32,
+    /// Pages skipped because the wiki reported them unchanged since they were last fetched
+    pub pages_unchanged: u32,
+    pub errors: u32,
+}
+
+/// Response of the MediaWiki API's `list=categorymembers` query
+#[derive(Debug, Deserialize)]
+struct CategoryMembersResponse {
+    query: Option<CategoryMembersQuery>,
+    /// Present when there are more members than fit in one response
+    #[serde(rename = "continue")]
+    continuation: Option<CategoryMembersContinuation>,
+    error: Option<MediaWikiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryMembersQuery {
+    categorymembers: Vec<CategoryMember>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryMember {
+    ns: i64,
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryMembersContinuation {
+    cmcontinue: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaWikiError {
+    info: String,
+}
+
+/// What `test_scrape` extracted from a single page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapeProbe {
+    /// The probed URL, resolved against the wiki's base URL
+    pub url: String,
+    pub title: String,
+    pub kind: PageKind,
+    /// Characters of text extracted from the page's main content
+    pub content_length: usize,
+    pub category_count: usize,
+    /// Wiki links a crawl would follow from the page
+    pub link_count: usize,
+}
+
+/// What was learned about a page on its last successful fetch, used to skip unchanged pages
+#[derive(Debug, Clone)]
+struct PageRecord {
+    last_modified: Option<String>,
+    links: Vec<String>,
+}
+
+enum PageFetch {
+    Fetched { page: WikiPage, links: Vec<String> },
+    NotModified,
+}
+
+/// Fills in `{version}` and `{contact}` in a user-agent template and checks the result is a usable header.
+pub fn render_user_agent(template: &str, contact: &str) -> AppResult<String> {
+    let contact = contact.trim();
+    if template.contains("{contact}") && contact.is_empty() {
+        return Err(AppError::ConfigError(
+            "User-agent template uses {contact} but no contact is configured".to_string()
+        ));
+    }
+    
+    let user_agent = template
+        .replace("{version}", env!("CARGO_PKG_VERSION"))
+        .replace("{contact}", contact)
+        .trim()
+        .to_string();
+    
+    if user_agent.is_empty() {
+        return Err(AppError::ConfigError("User-agent cannot be empty".to_string()));
+    }
+    
+    if reqwest::header::HeaderValue::from_str(&user_agent).is_err() {
+        return Err(AppError::ConfigError(
+            "User-agent contains characters that aren't allowed in an HTTP header".to_string()
+        ));
+    }
+    
+    if contact.is_empty() || !user_agent.contains(contact) {
+        warn!("Scraper user-agent has no contact information; wiki operators may block anonymous crawlers");
+    }
+    
+    Ok(user_agent)
+}
+
+/// Checks the configured custom headers and turns them into a header map. Values are marked
+/// sensitive since they're often API keys. `User-Agent` and `Host` are refused; the user-agent
+/// has its own setting and the host comes from `base_url`.
+pub fn custom_header_map(headers: &HashMap<String, String>) -> AppResult<reqwest::header::HeaderMap> {
+    let mut header_map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        let header_name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| {
+            AppError::ConfigError(format!("\"{}\" is not a valid HTTP header name", name))
+        })?;
+        if header_name == reqwest::header::USER_AGENT || header_name == reqwest::header::HOST {
+            return Err(AppError::ConfigError(format!("The {} header can't be set as a custom header", header_name)));
+        }
+        let mut header_value = reqwest::header::HeaderValue::from_str(value.trim()).map_err(|_| {
+            AppError::ConfigError(format!("The value of header {} contains characters that aren't allowed in an HTTP header", header_name))
+        })?;
+        header_value.set_sensitive(true);
+        header_map.insert(header_name, header_value);
+    }
+    Ok(header_map)
+}
+
+/// Turns a configured entry point into an absolute URL on the wiki's host.
+/// Accepts paths relative to `base_url` (`/index.php?title=Main_Page`) or full URLs with the same
+/// origin - scheme, host and port.
+pub fn resolve_entry_point(entry_point: &str, base_url: &str) -> AppResult<String> {
+    let entry_point = entry_point.trim();
+    if entry_point.is_empty() {
+        return Err(AppError::ConfigError("Entry point cannot be empty".to_string()));
+    }
+    
+    let base = Url::parse(base_url)
+        .map_err(|e| AppError::ConfigError(format!("Invalid wiki base URL {}: {}", base_url, e)))?;
+    
+    if entry_point.starts_with("//") {
+        return Err(AppError::ConfigError(
+            format!("Entry point {} must be a relative path or a URL on {}", entry_point, base.host_str().unwrap_or(base_url))
+        ));
+    }
+    
+    if entry_point.starts_with('/') {
+        return Ok(format!("{}{}", base_url.trim_end_matches('/'), entry_point));
+    }
+    
+    let url = Url::parse(entry_point)
+        .map_err(|_| AppError::ConfigError(
+            format!("Entry point {} must start with '/' or be a full URL", entry_point)
+        ))?;
+    
+    if !matches!(url.scheme(), "http" | "https") || url.origin() != base.origin() {
+        return Err(AppError::ConfigError(
+            format!("Entry point {} is not on the wiki {}", entry_point, base.origin().ascii_serialization())
+        ));
+    }
+    
+    Ok(url.to_string())
+}
+
+/// `Category:Name` for a category given with or without its prefix
+pub(crate) fn category_title(category: &str) -> AppResult<String> {
+    let name = category.trim();
+    let name = name.strip_prefix("Category:").unwrap_or(name).trim().replace('_', " ");
+    if name.is_empty() {
+        return Err(AppError::ConfigError("Category cannot be empty".to_string()));
+    }
+    Ok(format!("Category:{}", name))
+}
+
+/// The `/index.php?title=` link MediaWiki writes for a page title, so pages listed by the API get
+/// the same URLs as pages found by following links
+pub(crate) fn title_link(title: &str) -> String {
+    let mut link = String::from("/index.php?title=");
+    for byte in title.trim().replace(' ', "_").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+            | b'-' | b'_' | b'.' | b'~' | b';' | b':' | b'@' | b'$' | b'!' | b'*' | b'(' | b')' | b',' | b'/' => {
+                link.push(byte as char);
+            }
+            _ => link.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    link
 }
 
 pub struct WikiService {
@@ -34,24 +505,62 @@ pub struct WikiService {
     client: Client,
     status: WikiStatus,
     visited_urls: HashSet<String>,
+    page_records: HashMap<String, PageRecord>,
     embedding_service: Option<Arc<Mutex<EmbeddingService>>>,
+    /// Where fetched HTML is kept when `store_raw_html` is enabled
+    page_store: Option<Arc<PageStore>>,
+    /// Told about every page the crawl queues, so embedding progress has a total to estimate from
+    embedding_progress: Arc<EmbeddingProgressTracker>,
+    /// Set while a crawl runs; shared so callers can reject a second update without waiting on the lock
+    update_in_progress: Arc<AtomicBool>,
+    /// Stops a running crawl or re-parse after the page being embedded
+    crawl_cancelled: Arc<CancelFlag>,
+    /// Air-gapped mode: the wiki is never fetched
+    offline: bool,
+    /// Config file the last-update time is written back to; `None` keeps it in memory only
+    config_path: Option<PathBuf>,
 }
 
 impl WikiService {
     pub async fn new() -> Self {
-        let config = WikiConfig::default();
+        Self::with_config(WikiConfig::default()).await
+    }
+    
+    pub async fn with_config(config: WikiConfig) -> Self {
+        let user_agent = render_user_agent(&config.user_agent, &config.contact).unwrap_or_else(|e| {
+            warn!("Invalid scraper user-agent, using the default: {}", e);
+            render_user_agent(DEFAULT_WIKI_USER_AGENT, DEFAULT_WIKI_CONTACT)
+                .expect("default user-agent is valid")
+        });
+        info!("Scraper user-agent: {}", user_agent);
+        
+        let custom_headers = custom_header_map(&config.custom_headers).unwrap_or_else(|e| {
+            warn!("Invalid custom wiki headers, sending none: {}", e);
+            reqwest::header::HeaderMap::new()
+        });
+        
+        // HTTP/2 is negotiated during the TLS handshake when the wiki supports it; otherwise
+        // HTTP/1.1 connections are kept alive and reused from the pool
         let client = Client::builder()
             .timeout(Duration::from_secs(30))
-            .user_agent("VintageStoryAI/1.0 (Educational)")
+            .user_agent(user_agent)
+            .default_headers(custom_headers)
+            .pool_max_idle_per_host(config.max_idle_connections)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .tcp_keepalive(TCP_KEEPALIVE)
+            .http2_adaptive_window(true)
             .build()
             .expect("Failed to create HTTP client");
         
         let status = WikiStatus {
-            last_update: None,
+            last_update: config.last_update.map(|time| time.to_rfc3339()),
             total_pages: 0,
             is_updating: false,
             pages_scraped: 0,
             errors_encountered: 0,
+            max_depth_reached: 0,
+            pages_unchanged: 0,
+            low_value_pages_skipped: 0,
         };
         
         Self {
@@ -59,38 +568,160 @@ impl WikiService {
             client,
             status,
             visited_urls: HashSet::new(),
+            page_records: HashMap::new(),
             embedding_service: None,
+            page_store: None,
+            embedding_progress: Arc::new(EmbeddingProgressTracker::new()),
+            update_in_progress: Arc::new(AtomicBool::new(false)),
+            crawl_cancelled: Arc::new(CancelFlag::default()),
+            offline: false,
+            config_path: None,
+        }
+    }
+    
+    /// In offline mode updates fail immediately and only the existing knowledge base is used
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+    
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+    
+    /// Forgets what earlier crawls saw, so the next update re-fetches and re-embeds every page
+    /// instead of skipping the ones the wiki reports unchanged
+    pub fn forget_page_records(&mut self) {
+        self.page_records.clear();
+    }
+    
+    /// Forgets what earlier crawls saw of `urls`, so pages deleted from the knowledge base are
+    /// fetched and embedded again by the next update
+    pub fn forget_pages(&mut self, urls: &[String]) {
+        for url in urls {
+            self.page_records.remove(url);
         }
     }
     
+    /// Flag that is set for as long as `update_content` is running
+    pub fn update_in_progress_handle(&self) -> Arc<AtomicBool> {
+        self.update_in_progress.clone()
+    }
+    
+    /// Flag that stops a running update or re-parse, settable without the service lock
+    pub fn crawl_cancel_handle(&self) -> Arc<CancelFlag> {
+        self.crawl_cancelled.clone()
+    }
+    
+    /// Persists the last-update time to this config file after each update
+    pub fn set_config_path(&mut self, config_path: PathBuf) {
+        self.config_path = Some(config_path);
+    }
+    
     pub fn set_embedding_service(&mut self, embedding_service: Arc<Mutex<EmbeddingService>>) {
         self.embedding_service = Some(embedding_service);
     }
     
+    /// Whether fetched pages' HTML is kept in a page store
+    pub fn stores_raw_html(&self) -> bool {
+        self.page_store.is_some()
+    }
+    
+    /// Where raw HTML is saved when `store_raw_html` is on, and read back by `reparse_all`
+    pub fn set_page_store(&mut self, page_store: Arc<PageStore>) {
+        self.page_store = Some(page_store);
+    }
+    
+    /// Shares the embedding service's progress counter; see `EmbeddingService::progress_handle`
+    pub fn set_embedding_progress(&mut self, embedding_progress: Arc<EmbeddingProgressTracker>) {
+        self.embedding_progress = embedding_progress;
+    }
+    
+    pub fn get_entry_points(&self) -> Vec<String> {
+        self.config.entry_points.clone()
+    }
+    
+    pub fn set_entry_points(&mut self, entry_points: Vec<String>) {
+        self.config.entry_points = entry_points;
+    }
+    
+    pub fn get_exclude_patterns(&self) -> Vec<String> {
+        self.config.exclude_patterns.clone()
+    }
+    
+    pub fn set_exclude_patterns(&mut self, exclude_patterns: Vec<String>) {
+        self.config.exclude_patterns = exclude_patterns;
+    }
+    
+    pub fn get_max_depth(&self) -> u32 {
+        self.config.max_depth
+    }
+    
+    pub fn set_max_depth(&mut self, max_depth: u32) {
+        self.config.max_depth = max_depth;
+    }
+    
+    pub fn get_pinned_pages(&self) -> Vec<String> {
+        self.config.pinned_pages.clone()
+    }
+    
+    pub fn set_pinned_pages(&mut self, pinned_pages: Vec<String>) {
+        self.config.pinned_pages = pinned_pages;
+    }
+    
+    fn is_pinned(&self, url: &str) -> bool {
+        self.config.pinned_pages.iter().any(|pinned| pinned == url)
+    }
+    
+    pub fn base_url(&self) -> &str {
+        &self.config.base_url
+    }
+    
+    /// The settings in use, including entry point and crawl changes made since startup
+    pub fn config(&self) -> &WikiConfig {
+        &self.config
+    }
+    
     pub async fn get_status(&self) -> AppResult<WikiStatus> {
         Ok(self.status.clone())
     }
     
     pub async fn update_content(&mut self) -> AppResult<()> {
+        // A second crawl would reset visited_urls and the counters of the one already running
+        let claim = claim_update(&self.update_in_progress).inspect_err(|_| {
+            warn!("Wiki update requested while another is running");
+        })?;
+        self.update_content_claimed(claim).await
+    }
+    
+    /// `update_content` for a caller that claimed the update flag before taking the service lock
+    pub async fn update_content_claimed(&mut self, _claim: UpdateClaim) -> AppResult<()> {
+        if self.offline {
+            return Err(AppError::OfflineMode("can't update wiki content without internet access".to_string()));
+        }
+        
         info!("Starting Vintage Story wiki content update");
-        self.status.is_updating = true;
-        self.status.pages_scraped = 0;
-        self.status.errors_encountered = 0;
+        let _running = self.crawl_cancelled.start();
+        self.reset_run_status();
+        let max_depth = self.config.max_depth;
         
-        // Start with the main wiki page and key entry points
-        let entry_points = vec![
-            "/index.php?title=Main_Page",
-            "/index.php?title=Blocks",
-            "/index.php?title=Items", 
-            "/index.php?title=Crafting",
-            "/index.php?title=Getting_started",
-            "/index.php?title=Knapping",
-            "/index.php?title=Clay_forming",
-        ];
+        // Start with the configured entry points (main page and key sections by default)
+        let entry_points = self.config.entry_points.clone();
+        self.embedding_progress.start(entry_points.len());
         
         for entry_point in entry_points {
-            let url = format!("{}{}", self.config.base_url, entry_point);
-            if let Err(e) = self.scrape_page_recursive(&url, 0, 3).await {
+            if self.crawl_cancelled.is_cancelled() {
+                break;
+            }
+            let url = match resolve_entry_point(&entry_point, &self.config.base_url) {
+                Ok(url) => url,
+                Err(e) => {
+                    error!("Skipping invalid entry point {}: {}", entry_point, e);
+                    self.status.errors_encountered += 1;
+                    self.embedding_progress.page_skipped();
+                    continue;
+                }
+            };
+            if let Err(e) = self.scrape_page_recursive(&url, 0, max_depth).await {
                 error!("Failed to scrape entry point {}: {}", url, e);
                 self.status.errors_encountered += 1;
             }
@@ -99,78 +730,739 @@ impl WikiService {
             sleep(Duration::from_millis(500)).await;
         }
         
+        // Pinned pages are always fetched, even when no entry point leads to them
+        let pinned_pages = self.config.pinned_pages.clone();
+        for url in pinned_pages {
+            if self.crawl_cancelled.is_cancelled() {
+                break;
+            }
+            if self.visited_urls.contains(&url) {
+                continue;
+            }
+            self.embedding_progress.pages_discovered(1);
+            if let Err(e) = self.scrape_page_recursive(&url, 0, max_depth).await {
+                error!("Failed to scrape pinned page {}: {}", url, e);
+                self.status.errors_encountered += 1;
+            }
+        }
+        
+        self.flush_knowledge_base().await;
+        
+        let finished_at = chrono::Utc::now();
+        self.embedding_progress.finish();
         self.status.is_updating = false;
-        self.status.last_update = Some(chrono::Utc::now().to_rfc3339());
+        
+        // Pages embedded before the cancel are kept, but the wiki wasn't fully checked
+        if self.crawl_cancelled.is_cancelled() {
+            info!("Wiki update cancelled after {} pages", self.status.pages_scraped);
+            return Err(AppError::Cancelled("wiki update".to_string()));
+        }
+        
+        // Unchanged pages count as checked, but a crawl that got nothing at all is broken
+        if self.status.pages_scraped == 0 && self.status.pages_unchanged == 0 {
+            error!("Wiki update scraped no pages ({} errors)", self.status.errors_encountered);
+            return Err(AppError::NoPagesScraped { errors: self.status.errors_encountered });
+        }
+        
+        self.status.last_update = Some(finished_at.to_rfc3339());
         self.status.total_pages = self.status.pages_scraped;
+        self.config.last_update = Some(finished_at);
+        self.persist_last_update(finished_at);
         
-        info!("Wiki update completed. Pages scraped: {}, Errors: {}", 
-               self.status.pages_scraped, self.status.errors_encountered);
+        info!("Wiki update completed. Pages scraped: {}, Unchanged: {}, Errors: {}", 
+               self.status.pages_scraped, self.status.pages_unchanged, self.status.errors_encountered);
         
         Ok(())
     }
     
-    fn scrape_page_recursive<'a>(&'a mut self, url: &'a str, depth: u32, max_depth: u32) -> std::pin::Pin<Box<dyn std::future::Future<Output = AppResult<()>> + Send + 'a>> {
-        Box::pin(async move {
-            if depth > max_depth || self.visited_urls.contains(url) {
-                return Ok(());
-            }
-            
-            self.visited_urls.insert(url.to_string());
-            
-            info!("Scraping page: {} (depth: {})", url, depth);
-            
-            match self.scrape_single_page(url).await {
-                Ok(page) => {
-                    self.status.pages_scraped += 1;
-                    self.save_page_content(&page).await?;
-                    
-                    // Extract and follow wiki links for deeper scraping
-                    if depth < max_depth {
-                        let links = self.extract_wiki_links(&page.content);
-                        for link in links.iter().take(5) { // Limit to prevent infinite recursion
-                            let full_url = if link.starts_with("/") {
-                                format!("{}{}", self.config.base_url, link)
-                            } else if link.starts_with("http") {
-                                link.clone()
-                            } else {
-                                continue;
-                            };
-                            
-                            sleep(Duration::from_millis(200)).await; // Rate limiting
-                            if let Err(e) = self.scrape_page_recursive(&full_url, depth + 1, max_depth).await {
-                                warn!("Failed to scrape linked page {}: {}", full_url, e);
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to scrape page {}: {}", url, e);
-                    self.status.errors_encountered += 1;
-                }
-            }
-            
-            Ok(())
-        })
+    /// Clears the counters and visited pages of the previous crawl
+    fn reset_run_status(&mut self) {
+        self.status.is_updating = true;
+        self.status.pages_scraped = 0;
+        self.status.errors_encountered = 0;
+        self.status.max_depth_reached = 0;
+        self.status.pages_unchanged = 0;
+        self.status.low_value_pages_skipped = 0;
+        self.visited_urls.clear();
+    }
+    
+    /// Scrapes the pages in `category` and its subcategories, as listed by the MediaWiki API,
+    /// without following any other links. `category` may be given with or without `Category:`.
+    /// Like a full update, pages the wiki reports unchanged aren't fetched again.
+    pub async fn scrape_category(&mut self, category: &str) -> AppResult<CategoryScrapeSummary> {
+        let claim = claim_update(&self.update_in_progress).inspect_err(|_| {
+            warn!("Category scrape requested while a wiki update is running");
+        })?;
+        self.scrape_category_claimed(category, claim).await
     }
     
-    async fn scrape_single_page(&self, url: &str) -> AppResult<WikiPage> {
-        let response = self.client.get(url).send().await
-            .map_err(|e| AppError::WikiError(format!("Failed to fetch {}: {}", url, e)))?;
+    /// `scrape_category` for a caller that claimed the update flag before taking the service lock
+    pub async fn scrape_category_claimed(&mut self, category: &str, _claim: UpdateClaim) -> AppResult<CategoryScrapeSummary> {
+        if self.offline {
+            return Err(AppError::OfflineMode("can't scrape a wiki category without internet access".to_string()));
+        }
+        let category = category_title(category)?;
         
-        if !response.status().is_success() {
-            return Err(AppError::WikiError(format!("HTTP {} for {}", response.status(), url)));
+        info!("Scraping wiki category: {}", category);
+        let _running = self.crawl_cancelled.start();
+        self.reset_run_status();
+        self.embedding_progress.start(0);
+        
+        let result = self.scrape_category_pages(&category).await;
+        
+        self.flush_knowledge_base().await;
+        self.embedding_progress.finish();
+        self.status.is_updating = false;
+        let pages_found = result?;
+        
+        if self.crawl_cancelled.is_cancelled() {
+            info!("Category scrape cancelled after {} pages", self.status.pages_scraped);
+            return Err(AppError::Cancelled("category scrape".to_string()));
         }
         
-        let html_content = response.text().await
-            .map_err(|e| AppError::WikiError(format!("Failed to read response for {}: {}", url, e)))?;
+        info!("Category scrape of {} completed. Pages scraped: {}, Unchanged: {}, Errors: {}",
+              category, self.status.pages_scraped, self.status.pages_unchanged, self.status.errors_encountered);
+        Ok(CategoryScrapeSummary {
+            category,
+            pages_found,
+            pages_scraped: self.status.pages_scraped,
+            pages_unchanged: self.status.pages_unchanged,
+            errors: self.status.errors_encountered,
+        })
+    }
+    
+    async fn scrape_category_pages(&mut self, category: &str) -> AppResult<usize> {
+        let pages = self.category_pages(category).await?;
+        if pages.is_empty() {
+            return Err(AppError::WikiError(format!("{} has no pages", category)));
+        }
+        self.embedding_progress.pages_discovered(pages.len());
         
-        self.parse_wiki_page(url, &html_content)
+        for url in &pages {
+            if self.crawl_cancelled.is_cancelled() {
+                break;
+            }
+            // Depth 0 of 0: the page itself, none of its links
+            if let Err(e) = self.scrape_page_recursive(url, 0, 0).await {
+                error!("Failed to scrape category page {}: {}", url, e);
+                self.status.errors_encountered += 1;
+            }
+            sleep(CRAWL_DELAY).await;
+        }
+        Ok(pages.len())
     }
     
-    fn parse_wiki_page(&self, url: &str, html_content: &str) -> AppResult<WikiPage> {
-        let document = Html::parse_document(html_content);
+    /// URLs of the pages in `category` and, recursively, its subcategories, leaving out pages
+    /// matching the exclude patterns
+    async fn category_pages(&self, category: &str) -> AppResult<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut seen_pages = HashSet::new();
+        let mut seen_categories = HashSet::from([category.to_string()]);
+        let mut queue = VecDeque::from([category.to_string()]);
         
-        // Extract title - MediaWiki specific
+        while let Some(current) = queue.pop_front() {
+            for member in self.category_members(&current).await? {
+                if member.ns == CATEGORY_NAMESPACE {
+                    // Categories can contain each other
+                    if seen_categories.insert(member.title.clone()) {
+                        queue.push_back(member.title);
+                    }
+                    continue;
+                }
+                
+                let link = title_link(&member.title);
+                if self.config.exclude_patterns.iter().any(|pattern| matches_exclude_pattern(&link, pattern)) {
+                    continue;
+                }
+                let url = format!("{}{}", self.config.base_url, link);
+                if seen_pages.insert(url.clone()) {
+                    pages.push(url);
+                }
+            }
+        }
+        Ok(pages)
+    }
+    
+    /// The pages and subcategories directly in `category`, following the API's continuation
+    /// until the list is complete
+    async fn category_members(&self, category: &str) -> AppResult<Vec<CategoryMember>> {
+        let api_url = format!("{}{}", self.config.base_url, MEDIAWIKI_API_PATH);
+        let mut members = Vec::new();
+        let mut continue_from: Option<String> = None;
+        
+        loop {
+            let mut url = Url::parse(&api_url)
+                .map_err(|e| AppError::ConfigError(format!("Invalid wiki API URL {}: {}", api_url, e)))?;
+            url.query_pairs_mut()
+                .append_pair("action", "query")
+                .append_pair("list", "categorymembers")
+                .append_pair("cmtitle", category)
+                .append_pair("cmtype", "page|subcat")
+                .append_pair("cmlimit", "500")
+                .append_pair("format", "json");
+            if let Some(continue_from) = &continue_from {
+                url.query_pairs_mut().append_pair("cmcontinue", continue_from);
+            }
+            
+            let response = self.send_with_retries(url.as_str(), None).await?;
+            if !response.status().is_success() {
+                return Err(AppError::WikiError(format!("HTTP {} listing {}", response.status(), category)));
+            }
+            let listing: CategoryMembersResponse = response.json().await
+                .map_err(|e| AppError::WikiError(format!("Unexpected response listing {}: {}", category, e)))?;
+            if let Some(error) = listing.error {
+                return Err(AppError::WikiError(format!("Wiki API error listing {}: {}", category, error.info)));
+            }
+            
+            members.extend(listing.query.map(|query| query.categorymembers).unwrap_or_default());
+            continue_from = listing.continuation.and_then(|continuation| continuation.cmcontinue);
+            if continue_from.is_none() {
+                return Ok(members);
+            }
+        }
+    }
+    
+    /// Re-fetches a single page and replaces its chunks, leaving the rest of the knowledge base
+    /// alone. `url` may be a path on the wiki or a URL on the wiki host. Returns how many chunks
+    /// the page has now; pages that aren't articles end up with none.
+    pub async fn rescrape_page(&mut self, url: &str) -> AppResult<usize> {
+        // Don't interleave with a crawl that may be embedding the same page
+        let claim = claim_update(&self.update_in_progress).inspect_err(|_| {
+            warn!("Page re-scrape requested while a wiki update is running");
+        })?;
+        self.rescrape_page_claimed(url, claim).await
+    }
+    
+    /// `rescrape_page` for a caller that claimed the update flag before taking the service lock
+    pub async fn rescrape_page_claimed(&mut self, url: &str, _claim: UpdateClaim) -> AppResult<usize> {
+        if self.offline {
+            return Err(AppError::OfflineMode("can't re-scrape a wiki page without internet access".to_string()));
+        }
+        let url = resolve_entry_point(url, &self.config.base_url)?;
+        let Some(embedding_service) = self.embedding_service.clone() else {
+            return Err(AppError::EmbeddingError("No embedding service is available to re-embed the page".to_string()));
+        };
+        
+        let result = self.replace_page_chunks(&url, &embedding_service).await;
+        self.flush_knowledge_base().await;
+        result
+    }
+    
+    /// Fetches and parses one page without storing or embedding anything, to check that a
+    /// candidate entry point yields content. `url` may be a path on the wiki or a URL on the wiki host.
+    pub async fn test_scrape(&self, url: &str) -> AppResult<ScrapeProbe> {
+        if self.offline {
+            return Err(AppError::OfflineMode("can't fetch a wiki page without internet access".to_string()));
+        }
+        let url = resolve_entry_point(url, &self.config.base_url)?;
+        
+        let response = self.send_with_retries(&url, None).await?;
+        if !response.status().is_success() {
+            return Err(AppError::WikiError(format!("HTTP {} for {}", response.status(), url)));
+        }
+        let html_content = response.text().await
+            .map_err(|e| AppError::WikiError(format!("Failed to read response for {}: {}", url, e)))?;
+        
+        let page = self.parse_wiki_page(&url, &html_content)?;
+        let links = self.extract_wiki_links(&html_content);
+        Ok(ScrapeProbe {
+            url,
+            title: page.title,
+            kind: page.kind,
+            content_length: page.content.chars().count(),
+            category_count: page.categories.len(),
+            link_count: links.len(),
+        })
+    }
+    
+    async fn replace_page_chunks(&mut self, url: &str, embedding_service: &Mutex<EmbeddingService>) -> AppResult<usize> {
+        info!("Re-scraping page: {}", url);
+        let PageFetch::Fetched { page, links } = self.scrape_single_page(url, None).await? else {
+            return Err(AppError::WikiError(format!("Unexpected 304 Not Modified for {}", url)));
+        };
+        self.page_records.insert(url.to_string(), PageRecord {
+            last_modified: page.last_modified.clone(),
+            links,
+        });
+        
+        Self::embed_replacing(url, &page, embedding_service).await
+    }
+    
+    /// Parses every page in the page store again and replaces its chunks, without touching the
+    /// network. Used after the parser changes; needs `store_raw_html` to have been on while crawling.
+    pub async fn reparse_all(&mut self) -> AppResult<ReparseSummary> {
+        let claim = claim_update(&self.update_in_progress).inspect_err(|_| {
+            warn!("Re-parse requested while a wiki update is running");
+        })?;
+        self.reparse_all_claimed(claim).await
+    }
+    
+    /// `reparse_all` for a caller that claimed the update flag before taking the service lock
+    pub async fn reparse_all_claimed(&mut self, _claim: UpdateClaim) -> AppResult<ReparseSummary> {
+        let Some(page_store) = self.page_store.clone() else {
+            return Err(AppError::ConfigError(
+                "Raw HTML isn't being stored; enable wiki.store_raw_html and update the wiki first".to_string()
+            ));
+        };
+        let Some(embedding_service) = self.embedding_service.clone() else {
+            return Err(AppError::EmbeddingError("No embedding service is available to re-embed pages".to_string()));
+        };
+        
+        info!("Re-parsing {} stored pages", page_store.len());
+        let _running = self.crawl_cancelled.start();
+        let mut summary = ReparseSummary { pages_reparsed: 0, chunks: 0, errors: 0 };
+        for stored in page_store.iter() {
+            if self.crawl_cancelled.is_cancelled() {
+                break;
+            }
+            let result = match self.parse_wiki_page(&stored.url, &stored.html) {
+                Ok(mut page) => {
+                    page.last_modified = stored.last_modified.clone();
+                    page.scraped_at = Some(stored.fetched_at.clone());
+                    self.page_records.insert(stored.url.clone(), PageRecord {
+                        last_modified: stored.last_modified,
+                        links: self.extract_wiki_links(&stored.html),
+                    });
+                    Self::embed_replacing(&stored.url, &page, &embedding_service).await
+                }
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Ok(chunks) => {
+                    summary.pages_reparsed += 1;
+                    summary.chunks += chunks;
+                }
+                Err(e) => {
+                    error!("Failed to re-parse {}: {}", stored.url, e);
+                    summary.errors += 1;
+                }
+            }
+        }
+        
+        self.flush_knowledge_base().await;
+        if self.crawl_cancelled.is_cancelled() {
+            info!("Re-parse cancelled after {} pages", summary.pages_reparsed);
+            return Err(AppError::Cancelled("re-parse".to_string()));
+        }
+        info!("Re-parsed {} pages into {} chunks ({} errors)", summary.pages_reparsed, summary.chunks, summary.errors);
+        Ok(summary)
+    }
+    
+    /// Pages with chunks in the knowledge base but no copy in the page store, e.g. left behind when
+    /// a re-embed failed partway. Needs `store_raw_html` to have been on while the knowledge base was
+    /// built; pages embedded before that, or imported from another install, show up as orphans too.
+    pub async fn find_orphaned_chunks(&self) -> AppResult<Vec<OrphanedSource>> {
+        let known_sources = self.known_sources()?;
+        let Some(embedding_service) = &self.embedding_service else {
+            return Err(AppError::EmbeddingError("No embedding service is available to check".to_string()));
+        };
+        embedding_service.lock().await.orphaned_sources(&known_sources).await
+    }
+    
+    /// Deletes the chunks `find_orphaned_chunks` reports, returning what was removed
+    pub async fn clean_orphaned_chunks(&self) -> AppResult<Vec<OrphanedSource>> {
+        let known_sources = self.known_sources()?;
+        let Some(embedding_service) = self.embedding_service.clone() else {
+            return Err(AppError::EmbeddingError("No embedding service is available to clean".to_string()));
+        };
+        
+        let mut embedding_service = embedding_service.lock().await;
+        let mut orphans = embedding_service.orphaned_sources(&known_sources).await?;
+        if orphans.is_empty() {
+            return Ok(orphans);
+        }
+        let urls: Vec<String> = orphans.iter().map(|orphan| orphan.source_url.clone()).collect();
+        let removed = embedding_service.remove_sources(&urls).await?;
+        for orphan in &mut orphans {
+            orphan.chunk_count = removed.get(&orphan.source_url).copied().unwrap_or(0);
+        }
+        
+        info!("Removed {} orphaned chunks from {} pages", orphans.iter().map(|orphan| orphan.chunk_count).sum::<usize>(), orphans.len());
+        Ok(orphans)
+    }
+    
+    /// Pages the knowledge base should have chunks for: those in the page store, plus any crawled
+    /// since the app started
+    fn known_sources(&self) -> AppResult<HashSet<String>> {
+        let Some(page_store) = self.page_store.as_ref() else {
+            return Err(AppError::ConfigError(
+                "Raw HTML isn't being stored, so there's no record of which pages exist; enable wiki.store_raw_html and update the wiki first".to_string()
+            ));
+        };
+        if page_store.is_empty() {
+            // Every chunk would count as an orphan
+            return Err(AppError::ConfigError("The page store is empty; update the wiki before looking for orphaned chunks".to_string()));
+        }
+        
+        let mut known_sources = page_store.urls();
+        known_sources.extend(self.page_records.keys().cloned());
+        Ok(known_sources)
+    }
+    
+    /// Writes out chunks the knowledge base's flush policy hasn't flushed yet, at the end of ingestion
+    async fn flush_knowledge_base(&self) {
+        if let Some(embedding_service) = &self.embedding_service {
+            if let Err(e) = embedding_service.lock().await.flush_database().await {
+                error!("Failed to flush the knowledge base: {}", e);
+            }
+        }
+    }
+    
+    /// Replaces the chunks stored for `url` with ones made from `page`; non-articles end up with none.
+    /// An article's previous chunks are only dropped once all of its new chunks embedded.
+    async fn embed_replacing(url: &str, page: &WikiPage, embedding_service: &Mutex<EmbeddingService>) -> AppResult<usize> {
+        let mut service = embedding_service.lock().await;
+        if page.kind == PageKind::Article {
+            service.process_wiki_page_scraped_at(&page.title, url, &page.content, &page.categories, &page.scraped_at_or_now()).await?;
+        } else {
+            info!("Not embedding {:?} page: {}", page.kind, url);
+            service.remove_source(url).await?;
+        }
+        
+        let chunk_count = service.get_chunks_for_source(url).len();
+        info!("Replaced chunks for {} ({} now)", url, chunk_count);
+        Ok(chunk_count)
+    }
+    
+    /// Writes the last-update time into the config file, leaving the rest of it untouched
+    fn persist_last_update(&self, last_update: chrono::DateTime<chrono::Utc>) {
+        let Some(config_path) = &self.config_path else {
+            return;
+        };
+        
+        let result = AppConfig::load_from(config_path).and_then(|mut app_config| {
+            app_config.wiki.last_update = Some(last_update);
+            app_config.save_to(config_path)
+        });
+        
+        // The crawl itself succeeded, so a failed write is only worth a warning
+        if let Err(e) = result {
+            warn!("Failed to persist wiki last-update time: {}", e);
+        }
+    }
+    
+    fn scrape_page_recursive<'a>(&'a mut self, url: &'a str, depth: u32, max_depth: u32) -> std::pin::Pin<Box<dyn std::future::Future<Output = AppResult<()>> + Send + 'a>> {
+        Box::pin(async move {
+            if depth > max_depth || self.visited_urls.contains(url) || self.crawl_cancelled.is_cancelled() {
+                self.embedding_progress.page_skipped();
+                return Ok(());
+            }
+            
+            self.visited_urls.insert(url.to_string());
+            
+            info!("Scraping page: {} (depth: {})", url, depth);
+            
+            // Pinned pages are always re-fetched; others are skipped if the wiki says they're unchanged
+            let known_last_modified = if self.is_pinned(url) {
+                None
+            } else {
+                self.page_records.get(url).and_then(|record| record.last_modified.clone())
+            };
+            
+            let links = match self.scrape_single_page(url, known_last_modified.as_deref()).await {
+                Ok(PageFetch::Fetched { page, links }) => {
+                    self.status.pages_scraped += 1;
+                    self.page_records.insert(url.to_string(), PageRecord {
+                        last_modified: page.last_modified.clone(),
+                        links: links.clone(),
+                    });
+                    if page.kind == PageKind::Article {
+                        self.save_page_content(&page).await?;
+                    } else {
+                        info!("Not embedding {:?} page: {}", page.kind, url);
+                        self.status.low_value_pages_skipped += 1;
+                        self.embedding_progress.page_skipped();
+                    }
+                    links
+                }
+                Ok(PageFetch::NotModified) => {
+                    info!("Page unchanged since last crawl, skipping: {}", url);
+                    self.status.pages_unchanged += 1;
+                    self.embedding_progress.page_skipped();
+                    self.page_records.get(url).map(|record| record.links.clone()).unwrap_or_default()
+                }
+                Err(e) => {
+                    error!("Failed to scrape page {}: {}", url, e);
+                    self.status.errors_encountered += 1;
+                    self.embedding_progress.page_skipped();
+                    return Ok(());
+                }
+            };
+            self.status.max_depth_reached = self.status.max_depth_reached.max(depth);
+            
+            // Follow wiki links for deeper scraping; unchanged pages may still link to changed ones
+            if depth < max_depth {
+                for link in links.iter().take(5) { // Limit to prevent infinite recursion
+                    let full_url = if link.starts_with("/") {
+                        format!("{}{}", self.config.base_url, link)
+                    } else if link.starts_with("http") {
+                        link.clone()
+                    } else {
+                        continue;
+                    };
+                    self.embedding_progress.pages_discovered(1);
+                    
+                    sleep(CRAWL_DELAY).await; // Rate limiting
+                    if let Err(e) = self.scrape_page_recursive(&full_url, depth + 1, max_depth).await {
+                        warn!("Failed to scrape linked page {}: {}", full_url, e);
+                    }
+                }
+            }
+            
+            Ok(())
+        })
+    }
+    
+    /// Fetches and parses a page along with the wiki links found in its HTML.
+    /// With `if_modified_since`, a page the server reports as unchanged isn't downloaded again.
+    async fn scrape_single_page(&self, url: &str, if_modified_since: Option<&str>) -> AppResult<PageFetch> {
+        let response = self.send_with_retries(url, if_modified_since).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(PageFetch::NotModified);
+        }
+        
+        if !response.status().is_success() {
+            return Err(AppError::WikiError(format!("HTTP {} for {}", response.status(), url)));
+        }
+        
+        let last_modified = response.headers()
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        
+        let html_content = response.text().await
+            .map_err(|e| AppError::WikiError(format!("Failed to read response for {}: {}", url, e)))?;
+        self.store_raw_html(url, &html_content, last_modified.as_deref());
+        
+        let mut page = self.parse_wiki_page(url, &html_content)?;
+        page.last_modified = last_modified;
+        let links = self.extract_wiki_links(&html_content);
+        Ok(PageFetch::Fetched { page, links })
+    }
+    
+    /// Keeps the fetched HTML for `reparse_all` when `store_raw_html` is on. The page itself was
+    /// fetched fine, so a failed write is only logged.
+    fn store_raw_html(&self, url: &str, html: &str, last_modified: Option<&str>) {
+        let Some(page_store) = self.page_store.as_ref().filter(|_| self.config.store_raw_html) else {
+            return;
+        };
+        
+        let stored = StoredPage {
+            url: url.to_string(),
+            html: html.to_string(),
+            last_modified: last_modified.map(str::to_string),
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        };
+        if let Err(e) = page_store.save(&stored) {
+            warn!("Failed to store raw HTML for {}: {}", url, e);
+        }
+    }
+    
+    /// Sanity-checks the wiki config before a crawl: the base URL and every entry point respond,
+    /// robots.txt lets us crawl them, and the user-agent and retry settings are sensible.
+    /// Each request has a short timeout; network checks are skipped in offline mode.
+    pub async fn validate_config(&self) -> WikiConfigReport {
+        let mut checks = Vec::new();
+        
+        let user_agent = match render_user_agent(&self.config.user_agent, &self.config.contact) {
+            Ok(user_agent) => {
+                checks.push(ConfigCheck::new("user_agent", ConfigCheckStatus::Pass, format!("Crawling as {}", user_agent)));
+                user_agent
+            }
+            Err(e) => {
+                checks.push(ConfigCheck::new("user_agent", ConfigCheckStatus::Fail, e.to_string()));
+                render_user_agent(DEFAULT_WIKI_USER_AGENT, DEFAULT_WIKI_CONTACT).expect("default user-agent is valid")
+            }
+        };
+        if !self.config.custom_headers.is_empty() {
+            checks.push(match custom_header_map(&self.config.custom_headers) {
+                Ok(headers) => ConfigCheck::new("custom_headers", ConfigCheckStatus::Pass, format!("Sending {} custom header(s)", headers.len())),
+                Err(e) => ConfigCheck::new("custom_headers", ConfigCheckStatus::Fail, e.to_string()),
+            });
+        }
+        checks.push(self.check_rate_limit());
+        checks.push(if self.config.max_depth > MAX_CRAWL_DEPTH {
+            ConfigCheck::new("max_depth", ConfigCheckStatus::Fail, format!("Crawl depth {} is above the maximum of {}", self.config.max_depth, MAX_CRAWL_DEPTH))
+        } else {
+            ConfigCheck::new("max_depth", ConfigCheckStatus::Pass, format!("Following links {} levels deep", self.config.max_depth))
+        });
+        
+        let entry_points: Vec<(&String, AppResult<String>)> = self.config.entry_points.iter()
+            .map(|entry_point| (entry_point, resolve_entry_point(entry_point, &self.config.base_url)))
+            .collect();
+        if entry_points.is_empty() {
+            checks.push(ConfigCheck::new("entry_points", ConfigCheckStatus::Fail, "No entry points are configured"));
+        }
+        
+        let base = match Url::parse(&self.config.base_url) {
+            Ok(base) if matches!(base.scheme(), "http" | "https") => base,
+            _ => {
+                checks.push(ConfigCheck::new("base_url", ConfigCheckStatus::Fail, format!("{} is not an http(s) URL", self.config.base_url)));
+                return WikiConfigReport { ok: false, checks };
+            }
+        };
+        
+        if self.offline {
+            checks.push(ConfigCheck::new("base_url", ConfigCheckStatus::Skipped, "Offline mode is on"));
+            checks.push(ConfigCheck::new("robots_txt", ConfigCheckStatus::Skipped, "Offline mode is on"));
+            for (entry_point, resolved) in entry_points {
+                let name = format!("entry_point:{}", entry_point);
+                checks.push(match resolved {
+                    Ok(_) => ConfigCheck::new(name, ConfigCheckStatus::Skipped, "Offline mode is on"),
+                    Err(e) => ConfigCheck::new(name, ConfigCheckStatus::Fail, e.to_string()),
+                });
+            }
+        } else {
+            let base_status = self.check_url(&self.config.base_url).await;
+            checks.push(match base_status {
+                Ok(status) if status.is_success() => ConfigCheck::new("base_url", ConfigCheckStatus::Pass, format!("{} responded", self.config.base_url)),
+                Ok(status) => ConfigCheck::new("base_url", ConfigCheckStatus::Fail, format!("{} responded with HTTP {}", self.config.base_url, status)),
+                Err(e) => ConfigCheck::new("base_url", ConfigCheckStatus::Fail, format!("{} is unreachable: {}", self.config.base_url, e)),
+            });
+            
+            let (robots_check, robots) = self.check_robots_txt(&base, &user_agent).await;
+            checks.push(robots_check);
+            
+            for (entry_point, resolved) in entry_points {
+                checks.push(self.check_entry_point(entry_point, resolved, robots.as_ref()).await);
+            }
+        }
+        
+        let ok = checks.iter().all(|check| check.status != ConfigCheckStatus::Fail);
+        WikiConfigReport { ok, checks }
+    }
+    
+    fn check_rate_limit(&self) -> ConfigCheck {
+        let attempts = self.config.fetch_attempts;
+        let backoff_ms = self.config.retry_backoff_ms;
+        if attempts == 0 {
+            ConfigCheck::new("rate_limit", ConfigCheckStatus::Warn, "fetch_attempts is 0; each page will be tried once")
+        } else if attempts > MAX_SENSIBLE_FETCH_ATTEMPTS {
+            ConfigCheck::new("rate_limit", ConfigCheckStatus::Warn, format!("{} fetch attempts per page is more than the wiki should need; {} or fewer is plenty", attempts, MAX_SENSIBLE_FETCH_ATTEMPTS))
+        } else if attempts > 1 && backoff_ms < MIN_SENSIBLE_RETRY_BACKOFF_MS {
+            ConfigCheck::new("rate_limit", ConfigCheckStatus::Warn, format!("A {}ms retry backoff retries failing pages almost immediately; use at least {}ms", backoff_ms, MIN_SENSIBLE_RETRY_BACKOFF_MS))
+        } else {
+            ConfigCheck::new("rate_limit", ConfigCheckStatus::Pass, format!("{}ms between pages, up to {} attempts per page starting at {}ms backoff", CRAWL_DELAY.as_millis(), attempts, backoff_ms))
+        }
+    }
+    
+    /// Fetches robots.txt from the wiki's origin. A missing file allows everything.
+    async fn check_robots_txt(&self, base: &Url, user_agent: &str) -> (ConfigCheck, Option<RobotsRules>) {
+        let robots_url = match base.join("/robots.txt") {
+            Ok(url) => url,
+            Err(e) => return (ConfigCheck::new("robots_txt", ConfigCheckStatus::Fail, format!("Can't build the robots.txt URL: {}", e)), None),
+        };
+        
+        let response = match self.client.get(robots_url.clone()).timeout(CONFIG_CHECK_TIMEOUT).send().await {
+            Ok(response) => response,
+            Err(e) => return (ConfigCheck::new("robots_txt", ConfigCheckStatus::Warn, format!("Couldn't fetch {}: {}", robots_url, e)), None),
+        };
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return (ConfigCheck::new("robots_txt", ConfigCheckStatus::Pass, "The wiki has no robots.txt"), Some(RobotsRules::default()));
+        }
+        if !response.status().is_success() {
+            return (ConfigCheck::new("robots_txt", ConfigCheckStatus::Warn, format!("{} responded with HTTP {}", robots_url, response.status())), None);
+        }
+        
+        let robots = match response.text().await {
+            Ok(text) => parse_robots_txt(&text, user_agent),
+            Err(e) => return (ConfigCheck::new("robots_txt", ConfigCheckStatus::Warn, format!("Couldn't read {}: {}", robots_url, e)), None),
+        };
+        
+        let check = if !robots.allows("/") && robots.allow.is_empty() {
+            ConfigCheck::new("robots_txt", ConfigCheckStatus::Fail, "robots.txt disallows crawling the whole wiki")
+        } else if let Some(delay) = robots.crawl_delay.filter(|delay| *delay > CRAWL_DELAY.as_secs_f64()) {
+            ConfigCheck::new(
+                "robots_txt",
+                ConfigCheckStatus::Warn,
+                format!("robots.txt asks for {}s between requests, longer than the crawler's {}ms", delay, CRAWL_DELAY.as_millis()),
+            )
+        } else {
+            ConfigCheck::new("robots_txt", ConfigCheckStatus::Pass, "robots.txt permits crawling")
+        };
+        (check, Some(robots))
+    }
+    
+    async fn check_entry_point(&self, entry_point: &str, resolved: AppResult<String>, robots: Option<&RobotsRules>) -> ConfigCheck {
+        let name = format!("entry_point:{}", entry_point);
+        let url = match resolved.and_then(|url| Url::parse(&url).map_err(|e| AppError::ConfigError(format!("Invalid entry point URL {}: {}", url, e)))) {
+            Ok(url) => url,
+            Err(e) => return ConfigCheck::new(name, ConfigCheckStatus::Fail, e.to_string()),
+        };
+        
+        if let Some(robots) = robots {
+            if !robots.allows(&robots_path(&url)) {
+                return ConfigCheck::new(name, ConfigCheckStatus::Fail, format!("robots.txt disallows {}", url));
+            }
+        }
+        
+        match self.check_url(url.as_str()).await {
+            Ok(status) if status.is_success() => ConfigCheck::new(name, ConfigCheckStatus::Pass, format!("{} responded", url)),
+            Ok(status) => ConfigCheck::new(name, ConfigCheckStatus::Fail, format!("{} responded with HTTP {}", url, status)),
+            Err(e) => ConfigCheck::new(name, ConfigCheckStatus::Fail, format!("{} is unreachable: {}", url, e)),
+        }
+    }
+    
+    async fn check_url(&self, url: &str) -> Result<reqwest::StatusCode, reqwest::Error> {
+        let response = self.client.get(url).timeout(CONFIG_CHECK_TIMEOUT).send().await?;
+        Ok(response.status())
+    }
+    
+    /// Sends the page request, retrying network errors and 5xx responses with exponential backoff.
+    /// Other responses, including 4xx, are returned as-is on the first attempt.
+    async fn send_with_retries(&self, url: &str, if_modified_since: Option<&str>) -> AppResult<reqwest::Response> {
+        let attempts = self.config.fetch_attempts.max(1);
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        
+        let mut attempt = 1;
+        let mut rate_limited = 0;
+        loop {
+            let mut request = self.client.get(url);
+            if let Some(timestamp) = if_modified_since {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, timestamp);
+            }
+            
+            let failure = match request.send().await {
+                Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && rate_limited < MAX_RATE_LIMIT_RETRIES => {
+                    rate_limited += 1;
+                    let wait = response.headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| parse_retry_after(value, chrono::Utc::now()))
+                        .unwrap_or(backoff)
+                        .min(MAX_RATE_LIMIT_WAIT);
+                    warn!("Rate limited by the wiki fetching {}, pausing the crawl for {:?}", url, wait);
+                    
+                    // The crawl is sequential, so waiting here pauses all of it
+                    tokio::select! {
+                        _ = sleep(wait) => continue,
+                        _ = self.crawl_cancelled.cancelled() => {
+                            return Err(AppError::Cancelled("wiki update".to_string()));
+                        }
+                    }
+                }
+                Ok(response) if !response.status().is_server_error() => return Ok(response),
+                Ok(response) if attempt == attempts => return Ok(response),
+                Ok(response) => format!("HTTP {}", response.status()),
+                Err(e) if attempt == attempts => {
+                    return Err(AppError::WikiError(format!("Failed to fetch {}: {}", url, e)));
+                }
+                Err(e) => e.to_string(),
+            };
+            
+            warn!("Fetching {} failed (attempt {}/{}): {}, retrying in {:?}", url, attempt, attempts, failure, backoff);
+            sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+    
+    fn parse_wiki_page(&self, url: &str, html_content: &str) -> AppResult<WikiPage> {
+        let document = Html::parse_document(html_content);
+        
+        // Extract title - MediaWiki specific
         let title_selector = Selector::parse("h1#firstHeading, h1.firstHeading, .mw-page-title-main")
             .map_err(|_| AppError::WikiError("Invalid title selector".to_string()))?;
         let title = document.select(&title_selector)
@@ -188,8 +1480,10 @@ impl WikiService {
             .map_err(|_| AppError::WikiError("Invalid content selector".to_string()))?;
         
         let mut content = String::new();
+        let mut parser_output_chars = None;
         if let Some(content_el) = document.select(&content_selector).next() {
             content = self.extract_clean_text(content_el);
+            parser_output_chars = Some(content_el.text().map(|text| text.trim().len()).sum::<usize>());
         } else {
             // Fallback to broader selector
             if let Ok(fallback_selector) = Selector::parse("#bodyContent") {
@@ -206,6 +1500,7 @@ impl WikiService {
         
         // Extract categories
         let categories = self.extract_categories(&document);
+        let kind = self.classify_page(&document, &categories, parser_output_chars);
         
         Ok(WikiPage {
             title,
@@ -213,63 +1508,69 @@ impl WikiService {
             content,
             last_modified: None,
             categories,
+            kind,
+            scraped_at: None,
         })
     }
     
-    fn extract_clean_text(&self, element: scraper::ElementRef) -> String {
-        // First, remove elements we don't want
-        let remove_selectors = [
-            ".mw-editsection",
-            ".navbox",
-            ".infobox",
-            ".toc",
-            "#toc",
-            ".thumb",
-            ".mbox",
-            "script",
-            "style",
-            ".reference",
-            ".noprint",
-        ];
-        
-        // Clone the HTML to work with
-        let html = element.html();
-        let mut document = Html::parse_fragment(&html);
+    /// Recognizes disambiguation and stub pages from MediaWiki template markers, their
+    /// categories, or a near-empty `mw-parser-output`
+    fn classify_page(&self, document: &Html, categories: &[String], parser_output_chars: Option<usize>) -> PageKind {
+        let has_marker = |markers: &str| {
+            Selector::parse(markers)
+                .map(|selector| document.select(&selector).next().is_some())
+                .unwrap_or(false)
+        };
+        let in_category = |needle: &str| {
+            categories.iter().any(|category| category.to_lowercase().contains(needle))
+        };
         
+        if has_marker(DISAMBIGUATION_MARKERS) || in_category("disambiguation") {
+            PageKind::Disambiguation
+        } else if has_marker(STUB_MARKERS)
+            || in_category("stub")
+            || parser_output_chars.is_some_and(|chars| chars < MIN_ARTICLE_CHARS)
+        {
+            PageKind::Stub
+        } else {
+            PageKind::Article
+        }
+    }
+    
+    fn extract_clean_text(&self, element: scraper::ElementRef) -> String {
+        // Parsed once per page rather than once per element
+        let removed = Selector::parse(REMOVED_CONTENT_SELECTORS).expect("Valid removed content selector");
+        // One selector, so elements come out in page order and each heading precedes its section
+        let text_elements = Selector::parse("p, h2, h3, h4, ul, ol, blockquote, pre").expect("Valid text selector");
         let mut clean_text = Vec::new();
         
-        // Extract text from important elements
-        let text_selectors = ["p", "h2", "h3", "h4", "ul", "ol", "blockquote"];
-        
-        for selector_str in &text_selectors {
-            if let Ok(selector) = Selector::parse(selector_str) {
-                for text_el in element.select(&selector) {
-                    // Check if this element is within a removed section
-                    let mut should_skip = false;
-                    for remove_sel in &remove_selectors {
-                        if let Ok(rem_selector) = Selector::parse(remove_sel) {
-                            if text_el.select(&rem_selector).next().is_some() {
-                                should_skip = true;
-                                break;
-                            }
-                        }
-                    }
-                    
-                    if !should_skip {
-                        let text = text_el.text().collect::<String>();
-                        let cleaned = text.trim();
-                        if !cleaned.is_empty() && cleaned.len() > 20 {
-                            // Add formatting based on element type
-                            let formatted = match *selector_str {
-                                "h2" => format!("\n## {}\n", cleaned),
-                                "h3" => format!("\n### {}\n", cleaned),
-                                "h4" => format!("\n#### {}\n", cleaned),
-                                _ => cleaned.to_string(),
-                            };
-                            clean_text.push(formatted);
-                        }
-                    }
-                }
+        for text_el in element.select(&text_elements) {
+            let tag = text_el.value().name();
+            let is_heading = matches!(tag, "h2" | "h3" | "h4");
+            let text = if is_heading {
+                // MediaWiki puts an edit link in every heading; drop just its text
+                text_without(text_el, &removed)
+            } else if text_el.select(&removed).next().is_some() {
+                // Skip elements holding navboxes, references and the like
+                continue;
+            } else {
+                text_el.text().collect::<String>()
+            };
+            
+            let cleaned = text.trim();
+            // Short paragraphs and headings are kept; chunks that end up too short
+            // are dropped at embedding time (`EmbeddingConfig::min_chunk_chars`)
+            if !cleaned.is_empty() {
+                // Add formatting based on element type
+                let formatted = match tag {
+                    "h2" => format!("\n## {}\n", cleaned),
+                    "h3" => format!("\n### {}\n", cleaned),
+                    "h4" => format!("\n#### {}\n", cleaned),
+                    // Fenced, keeping its line breaks and indentation, so the chunker keeps it whole
+                    "pre" => format!("{}\n{}\n{}", CODE_FENCE, text.trim_matches(['\r', '\n']).trim_end(), CODE_FENCE),
+                    _ => cleaned.to_string(),
+                };
+                clean_text.push(formatted);
             }
         }
         
@@ -318,6 +1619,11 @@ impl WikiService {
                             continue;
                         }
                         
+                        // Skip sections the user excluded, like talk pages
+                        if self.config.exclude_patterns.iter().any(|pattern| matches_exclude_pattern(href, pattern)) {
+                            continue;
+                        }
+                        
                         // Skip anchor links
                         if href.contains("#") {
                             continue;
@@ -348,7 +1654,7 @@ impl WikiService {
             let mut service = embedding_service.lock().await;
             
             // Process the page content for embeddings
-            match service.process_wiki_page(&page.title, &page.url, &page.content).await {
+            match service.process_wiki_page_scraped_at(&page.title, &page.url, &page.content, &page.categories, &page.scraped_at_or_now()).await {
                 Ok(_) => {
                     info!("Successfully processed embeddings for page: {}", page.title);
                 }
@@ -359,6 +1665,7 @@ impl WikiService {
             }
         } else {
             warn!("No embedding service available, skipping embedding generation for: {}", page.title);
+            self.embedding_progress.page_skipped();
         }
         
         Ok(())
@@ -437,6 +1744,80 @@ mod tests {
         assert!(!clean_text.contains("Navigation box to remove"));
     }
 
+    #[tokio::test]
+    async fn test_extract_clean_text_fences_code_blocks() {
+        let wiki_service = WikiService::new().await;
+        let html = "<div class=\"mw-parser-output\"><p>Register the block in your mod:</p>\
+                    <div class=\"mw-highlight\"><pre>\nvoid Start(ICoreAPI api)\n{\n    api.RegisterBlockClass(\"Bloomery\", typeof(BlockBloomery));\n}\n</pre></div></div>";
+        
+        let document = Html::parse_fragment(html);
+        let clean_text = wiki_service.extract_clean_text(document.root_element());
+        
+        assert!(clean_text.ends_with("```\nvoid Start(ICoreAPI api)\n{\n    api.RegisterBlockClass(\"Bloomery\", typeof(BlockBloomery));\n}\n```"));
+    }
+
+    #[tokio::test]
+    async fn test_chunks_of_parsed_page_record_their_section() {
+        let wiki_service = WikiService::new().await;
+        // Both heading markups MediaWiki uses, each with an edit link
+        let html = r#"<html><body><h1 id="firstHeading">Bloomery</h1><div id="mw-content-text"><div class="mw-parser-output">
+            <p>The bloomery smelts ore into iron blooms for later smithing.</p>
+            <h2><span class="mw-headline" id="Construction">Construction</span><span class="mw-editsection"><span class="mw-editsection-bracket">[</span><a href="/index.php?title=Bloomery&action=edit&section=1">edit</a><span class="mw-editsection-bracket">]</span></span></h2>
+            <p>Build it from fire bricks around a chimney.</p>
+            <div class="mw-heading mw-heading2"><h2 id="Usage">Usage</h2><span class="mw-editsection">[<a href="/index.php?title=Bloomery&action=edit&section=2">edit</a>]</span></div>
+            <p>Fill it with ore and charcoal, then ignite.</p>
+            </div></div></body></html>"#;
+        let page = wiki_service.parse_wiki_page("https://wiki.vintagestory.at/Bloomery", html).unwrap();
+        
+        let embedding_service = EmbeddingService::with_config(crate::config::EmbeddingConfig {
+            chunk_size: 10,
+            chunk_overlap: 0,
+            ..crate::config::EmbeddingConfig::default()
+        }, &crate::config::OllamaConfig::default()).await;
+        let chunks = embedding_service.split_into_sectioned_chunks(&page.content);
+        
+        let sections: Vec<(&str, Option<&str>)> = chunks.iter()
+            .map(|(chunk, section)| (chunk.as_str(), section.as_deref()))
+            .collect();
+        assert_eq!(sections, vec![
+            ("The bloomery smelts ore into iron blooms for later smithing.", None),
+            ("## Construction Build it from fire bricks around a chimney.", Some("Construction")),
+            ("## Usage Fill it with ore and charcoal, then ignite.", Some("Usage")),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_extract_clean_text_of_large_page() {
+        let wiki_service = WikiService::new().await;
+        const SECTIONS: usize = 2000;
+        
+        let mut html = String::from(r#"<div class="mw-parser-output">"#);
+        for i in 0..SECTIONS {
+            html.push_str(&format!(
+                r#"<h2>Section {i}<span class="mw-editsection">edit</span></h2>
+                <h3>Topic {i}</h3>
+                <p>Paragraph {i} about knapping flint into tools.</p>
+                <p>See the <span class="reference">[{i}]</span> note.</p>
+                <ul><li>Item {i}</li></ul>"#
+            ));
+        }
+        html.push_str("</div>");
+        
+        let document = Html::parse_fragment(&html);
+        let clean_text = wiki_service.extract_clean_text(document.root_element());
+        
+        // Text stays in page order, headings lose their edit links, and paragraphs holding references are dropped
+        let expected: Vec<String> = (0..SECTIONS)
+            .flat_map(|i| [
+                format!("\n## Section {}\n", i),
+                format!("\n### Topic {}\n", i),
+                format!("Paragraph {} about knapping flint into tools.", i),
+                format!("Item {}", i),
+            ])
+            .collect();
+        assert_eq!(clean_text, expected.join("\n\n"));
+    }
+
     #[tokio::test]
     async fn test_extract_wiki_links() {
         let wiki_service = WikiService::new().await;
@@ -462,23 +1843,1233 @@ mod tests {
         assert!(!links.iter().any(|l| l.contains("#")));
     }
 
-    #[tokio::test]
-    async fn test_wiki_status() {
-        let wiki_service = WikiService::new().await;
-        let status = wiki_service.get_status().await.unwrap();
+    #[test]
+    fn test_resolve_entry_point() {
+        let base = "https://wiki.vintagestory.at";
         
-        assert_eq!(status.total_pages, 0);
-        assert!(!status.is_updating);
-        assert_eq!(status.pages_scraped, 0);
-        assert_eq!(status.errors_encountered, 0);
+        assert_eq!(
+            resolve_entry_point("/index.php?title=Smithing", base).unwrap(),
+            "https://wiki.vintagestory.at/index.php?title=Smithing"
+        );
+        assert_eq!(
+            resolve_entry_point("https://wiki.vintagestory.at/index.php?title=Smithing", base).unwrap(),
+            "https://wiki.vintagestory.at/index.php?title=Smithing"
+        );
+        
+        // Other hosts, protocol-relative URLs and bare page names are rejected
+        assert!(resolve_entry_point("https://example.com/index.php?title=Smithing", base).is_err());
+        assert!(resolve_entry_point("//example.com/Smithing", base).is_err());
+        assert!(resolve_entry_point("Smithing", base).is_err());
+        assert!(resolve_entry_point("ftp://wiki.vintagestory.at/Smithing", base).is_err());
+        assert!(resolve_entry_point("  ", base).is_err());
+        
+        // As are other ports and schemes on the same host
+        assert!(resolve_entry_point("https://wiki.vintagestory.at:8443/index.php?title=Smithing", base).is_err());
+        assert!(resolve_entry_point("http://wiki.vintagestory.at/index.php?title=Smithing", base).is_err());
+        assert!(resolve_entry_point("https://wiki.vintagestory.at:443/index.php?title=Smithing", base).is_ok());
     }
 
     #[tokio::test]
-    async fn test_embedding_service_integration() {
-        let mut wiki_service = WikiService::new().await;
-        let embedding_service = Arc::new(Mutex::new(EmbeddingService::new().await));
+    async fn test_custom_entry_points_are_crawled() {
+        let mut server = mockito::Server::new_async().await;
+        let page = r#"<html><body>
+            <h1 id="firstHeading">Custom Mod</h1>
+            <div id="mw-content-text"><div class="mw-parser-output">
+                <p>This page documents a custom mod that adds new pottery recipes.</p>
+            </div></div>
+        </body></html>"#;
         
-        wiki_service.set_embedding_service(embedding_service.clone());
+        let custom_mock = server.mock("GET", "/index.php?title=Custom_Mod")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(page)
+            .expect(1)
+            .create_async()
+            .await;
+        let absolute_mock = server.mock("GET", "/index.php?title=Other_Mod")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(page)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec![
+                "/index.php?title=Custom_Mod".to_string(),
+                format!("{}/index.php?title=Other_Mod", server.url()),
+            ],
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        
+        wiki_service.update_content().await.unwrap();
+        
+        custom_mock.assert_async().await;
+        absolute_mock.assert_async().await;
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 2);
+        assert_eq!(status.errors_encountered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_category_fetches_only_its_pages() {
+        use mockito::Matcher;
+        
+        let mut server = mockito::Server::new_async().await;
+        let listing = |category: &str, continue_from: Option<&str>| {
+            let mut params = vec![
+                Matcher::UrlEncoded("list".into(), "categorymembers".into()),
+                Matcher::UrlEncoded("cmtitle".into(), category.into()),
+            ];
+            if let Some(continue_from) = continue_from {
+                params.push(Matcher::UrlEncoded("cmcontinue".into(), continue_from.into()));
+            }
+            Matcher::AllOf(params)
+        };
+        // The second batch is registered first: the request without `cmcontinue` doesn't match it
+        server.mock("GET", "/api.php")
+            .match_query(listing("Category:Mechanics", Some("page|BLOOMERY")))
+            .with_body(r#"{"query":{"categorymembers":[{"pageid":3,"ns":0,"title":"Bloomery"}]}}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/api.php")
+            .match_query(listing("Category:Mechanics", None))
+            .with_body(r#"{"continue":{"cmcontinue":"page|BLOOMERY","continue":"-||"},"query":{"categorymembers":[
+                {"pageid":1,"ns":0,"title":"Anvil"},
+                {"pageid":2,"ns":14,"title":"Category:Smithing"}
+            ]}}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/api.php")
+            .match_query(listing("Category:Smithing", None))
+            .with_body(r#"{"query":{"categorymembers":[
+                {"pageid":1,"ns":0,"title":"Anvil"},
+                {"pageid":4,"ns":0,"title":"Iron ingot"},
+                {"pageid":5,"ns":14,"title":"Category:Mechanics"}
+            ]}}"#)
+            .create_async()
+            .await;
+        
+        let linking_page = r#"<html><body>
+            <h1 id="firstHeading">Anvil</h1>
+            <div id="mw-content-text"><div class="mw-parser-output">
+                <p>Heat the metal in a <a href="/wiki/Firepit">firepit</a> first.</p>
+            </div></div>
+        </body></html>"#;
+        let mut page_mocks = Vec::new();
+        for (path, body) in [
+            ("/index.php?title=Anvil", linking_page),
+            ("/index.php?title=Bloomery", SIMPLE_PAGE),
+            ("/index.php?title=Iron_ingot", SIMPLE_PAGE),
+        ] {
+            page_mocks.push(server.mock("GET", path)
+                .with_status(200)
+                .with_header("content-type", "text/html")
+                .with_body(body)
+                .expect(1)
+                .create_async()
+                .await);
+        }
+        let unlisted_mock = server.mock("GET", "/index.php?title=Firepit")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        let summary = wiki_service.scrape_category("Mechanics").await.unwrap();
+        
+        for page_mock in page_mocks {
+            page_mock.assert_async().await;
+        }
+        unlisted_mock.assert_async().await;
+        assert_eq!(summary.category, "Category:Mechanics");
+        assert_eq!(summary.pages_found, 3);
+        assert_eq!(summary.pages_scraped, 3);
+        assert_eq!(summary.errors, 0);
+        assert!(!wiki_service.update_in_progress_handle().load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_category_title_and_title_link() {
+        assert_eq!(category_title("Mechanics").unwrap(), "Category:Mechanics");
+        assert_eq!(category_title(" Category:Game_mechanics ").unwrap(), "Category:Game mechanics");
+        assert!(category_title("Category:").is_err());
+        
+        assert_eq!(title_link("Iron ingot"), "/index.php?title=Iron_ingot");
+        assert_eq!(title_link("Guide:Smithing (Beginner)"), "/index.php?title=Guide:Smithing_(Beginner)");
+        assert_eq!(title_link("Fish & Chips"), "/index.php?title=Fish_%26_Chips");
+    }
+
+    /// Serves `page` to every request over HTTP/1.1 keep-alive, counting accepted connections
+    async fn start_connection_counting_server(page: &'static str) -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    loop {
+                        let Ok(n) = socket.read(&mut buf).await else { return };
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                        // GET requests have no body, so each one ends with a blank line
+                        while let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                            request.drain(..end + 4);
+                            let response = format!(
+                                "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: {}\r\n\r\n{}",
+                                page.len(), page
+                            );
+                            if socket.write_all(response.as_bytes()).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (addr, connections)
+    }
+
+    #[tokio::test]
+    async fn test_sequential_fetches_reuse_connections() {
+        let page = r#"<html><body>
+            <h1 id="firstHeading">Pottery</h1>
+            <div id="mw-content-text"><div class="mw-parser-output">
+                <p>Clay is shaped into pots, bowls and crocks on the pottery table.</p>
+            </div></div>
+        </body></html>"#;
+        let entry_points: Vec<String> = ["Pottery", "Clay", "Crock"].iter()
+            .map(|title| format!("/index.php?title={}", title))
+            .collect();
+        
+        for (max_idle_connections, expected_connections) in [(8, 1), (0, 3)] {
+            let (addr, connections) = start_connection_counting_server(page).await;
+            let config = WikiConfig {
+                base_url: format!("http://{}", addr),
+                entry_points: entry_points.clone(),
+                max_depth: 0,
+                max_idle_connections,
+                ..WikiConfig::default()
+            };
+            let mut wiki_service = WikiService::with_config(config).await;
+            
+            wiki_service.update_content().await.unwrap();
+            
+            assert_eq!(wiki_service.get_status().await.unwrap().pages_scraped, 3);
+            assert_eq!(connections.load(Ordering::SeqCst), expected_connections,
+                       "max_idle_connections = {}", max_idle_connections);
+        }
+    }
+
+    #[test]
+    fn test_matches_exclude_pattern() {
+        assert!(matches_exclude_pattern("/index.php?title=Talk:Anvil", "talk:"));
+        assert!(matches_exclude_pattern("/index.php?title=Anvil&oldid=1234", "oldid="));
+        assert!(!matches_exclude_pattern("/index.php?title=Anvil", "talk:"));
+        
+        assert!(matches_exclude_pattern("/index.php?title=Mod:Pottery_Plus", "*title=mod:*"));
+        assert!(matches_exclude_pattern("/index.php?title=Anvil/de", "*/??"));
+        assert!(!matches_exclude_pattern("/index.php?title=Anvil/deu", "*/??"));
+        assert!(!matches_exclude_pattern("/index.php?title=Anvil", "*title=mod:*"));
+    }
+
+    #[tokio::test]
+    async fn test_excluded_links_are_never_fetched() {
+        let mut server = mockito::Server::new_async().await;
+        let page = |title: &str, links: &str| format!(r#"<html><body>
+            <h1 id="firstHeading">{}</h1>
+            <div id="mw-content-text"><div class="mw-parser-output">
+                <p>This page covers smithing metal tools on an anvil in detail.</p>
+                {}
+            </div></div>
+        </body></html>"#, title, links);
+        
+        let entry_mock = server.mock("GET", "/index.php?title=Smithing")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(page("Smithing", r#"
+                <a href="/index.php?title=Anvil">Anvil</a>
+                <a href="/index.php?title=Talk:Anvil">Discussion</a>
+                <a href="/index.php?title=Anvil/de">Deutsch</a>
+            "#))
+            .expect(1)
+            .create_async()
+            .await;
+        let anvil_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(page("Anvil", ""))
+            .expect(1)
+            .create_async()
+            .await;
+        let talk_mock = server.mock("GET", "/index.php?title=Talk:Anvil")
+            .with_status(200)
+            .with_body(page("Talk:Anvil", ""))
+            .expect(0)
+            .create_async()
+            .await;
+        let translation_mock = server.mock("GET", "/index.php?title=Anvil/de")
+            .with_status(200)
+            .with_body(page("Anvil/de", ""))
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Smithing".to_string()],
+            max_depth: 1,
+            exclude_patterns: vec!["talk:".to_string(), "*/??".to_string()],
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        
+        wiki_service.update_content().await.unwrap();
+        
+        entry_mock.assert_async().await;
+        anvil_mock.assert_async().await;
+        talk_mock.assert_async().await;
+        translation_mock.assert_async().await;
+        assert_eq!(wiki_service.get_status().await.unwrap().pages_scraped, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rescrape_page_replaces_its_chunks() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let mut server = mockito::Server::new_async().await;
+        let article = format!(r#"<html><body>
+            <h1 id="firstHeading">Anvil</h1>
+            <div id="mw-content-text"><div class="mw-parser-output"><p>{}</p></div></div>
+        </body></html>"#, "The anvil is used to smith metal tools and plates from heated ingots. ".repeat(4));
+        let page_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(article)
+            .expect(1)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.3,0.4,0.5]}"#)
+            .create_async()
+            .await;
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_ollama_endpoint(&crate::config::OllamaConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            ..crate::config::OllamaConfig::default()
+        });
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        embedding_service.set_vector_database(db.clone());
+        
+        // A chunk from an older, longer version of the page
+        let url = format!("{}/index.php?title=Anvil", server.url());
+        db.lock().await.insert_documents(vec![VectorDocument {
+            id: "anvil_7".to_string(),
+            content: "Outdated anvil text".to_string(),
+            source_url: url.clone(),
+            source_title: "Anvil".to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            metadata: HashMap::new(),
+        }]).await.unwrap();
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        let chunk_count = wiki_service.rescrape_page("/index.php?title=Anvil").await.unwrap();
+        
+        page_mock.assert_async().await;
+        assert_eq!(chunk_count, 1);
+        let db = db.lock().await;
+        assert!(db.get_document("anvil_7").await.unwrap().is_none());
+        assert_eq!(db.count_documents().await.unwrap(), 1);
+        assert!(db.iter_documents().all(|doc| doc.id.starts_with("anvil_")));
+        assert!(!wiki_service.update_in_progress_handle().load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_rescrape_page_that_fails_to_embed_keeps_its_chunks() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let mut server = mockito::Server::new_async().await;
+        let article = format!(r#"<html><body>
+            <h1 id="firstHeading">Anvil</h1>
+            <div id="mw-content-text"><div class="mw-parser-output"><p>{}</p></div></div>
+        </body></html>"#, "The anvil is used to smith metal tools and plates from heated ingots. ".repeat(4));
+        server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(article)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("dimension probe".to_string()))
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.3,0.4,0.5]}"#)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("anvil is used".to_string()))
+            .with_status(500)
+            .create_async()
+            .await;
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_ollama_endpoint(&crate::config::OllamaConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            ..crate::config::OllamaConfig::default()
+        });
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        embedding_service.set_vector_database(db.clone());
+        embedding_service.probe_dimension().await.unwrap();
+        
+        let url = format!("{}/index.php?title=Anvil", server.url());
+        db.lock().await.insert_documents(vec![VectorDocument {
+            id: "anvil_7".to_string(),
+            content: "Earlier anvil text".to_string(),
+            source_url: url.clone(),
+            source_title: "Anvil".to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            metadata: HashMap::new(),
+        }]).await.unwrap();
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        wiki_service.rescrape_page("/index.php?title=Anvil").await.unwrap();
+        
+        let db = db.lock().await;
+        assert!(db.get_document("anvil_7").await.unwrap().is_some());
+        assert_eq!(db.failed_embeddings().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rescrape_page_rejects_other_hosts() {
+        let server = mockito::Server::new_async().await;
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(EmbeddingService::new().await)));
+        
+        let result = wiki_service.rescrape_page("https://example.com/index.php?title=Anvil").await;
+        assert!(matches!(result, Err(AppError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_reports_the_page_without_storing_it() {
+        let mut server = mockito::Server::new_async().await;
+        let page = r#"<html><body>
+            <h1 id="firstHeading">Anvil</h1>
+            <div id="mw-content-text"><div class="mw-parser-output">
+                <p>The anvil is used to smith <a href="/wiki/Iron_ingot">iron ingots</a> into <a href="/index.php?title=Tools">tools</a>.</p>
+            </div></div>
+            <div id="catlinks"><a href="/index.php?title=Category:Blocks">Category:Blocks</a></div>
+        </body></html>"#;
+        server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(page)
+            .create_async()
+            .await;
+        
+        let page_store = Arc::new(PageStore::new_temporary());
+        let mut wiki_service = WikiService::with_config(WikiConfig {
+            store_raw_html: true,
+            ..retry_test_config(&server)
+        }).await;
+        wiki_service.set_page_store(page_store.clone());
+        
+        let probe = wiki_service.test_scrape("/index.php?title=Anvil").await.unwrap();
+        assert_eq!(probe.url, format!("{}/index.php?title=Anvil", server.url()));
+        assert_eq!(probe.title, "Anvil");
+        assert_eq!(probe.content_length, "The anvil is used to smith iron ingots into tools.".len());
+        assert_eq!(probe.category_count, 1);
+        assert_eq!(probe.link_count, 2);
+        assert!(page_store.is_empty());
+        
+        let result = wiki_service.test_scrape("https://example.com/index.php?title=Anvil").await;
+        assert!(matches!(result, Err(AppError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reparse_all_uses_stored_html_without_fetching() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        let mut server = mockito::Server::new_async().await;
+        let article = format!(r#"<html><body>
+            <h1 id="firstHeading">Anvil</h1>
+            <div id="mw-content-text"><div class="mw-parser-output"><p>{}</p></div></div>
+        </body></html>"#, "The anvil is used to smith metal tools and plates from heated ingots. ".repeat(4));
+        let page_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(article)
+            .expect(1)
+            .create_async()
+            .await;
+        server.mock("POST", "/api/embeddings")
+            .with_status(200)
+            .with_body(r#"{"embedding":[0.3,0.4,0.5]}"#)
+            .create_async()
+            .await;
+        
+        // Crawl without embedding so the only copy of the page is its stored HTML
+        let page_store = Arc::new(PageStore::new_temporary());
+        let mut wiki_service = WikiService::with_config(WikiConfig {
+            store_raw_html: true,
+            ..retry_test_config(&server)
+        }).await;
+        wiki_service.set_page_store(page_store.clone());
+        wiki_service.update_content().await.unwrap();
+        assert_eq!(page_store.len(), 1);
+        
+        let address = server.host_with_port();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_ollama_endpoint(&crate::config::OllamaConfig {
+            host: host.to_string(),
+            port: port.parse().unwrap(),
+            ..crate::config::OllamaConfig::default()
+        });
+        let db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        embedding_service.set_vector_database(db.clone());
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        let summary = wiki_service.reparse_all().await.unwrap();
+        
+        page_mock.assert_async().await;
+        assert_eq!(summary.pages_reparsed, 1);
+        assert_eq!(summary.chunks, 1);
+        assert_eq!(summary.errors, 0);
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_chunks_are_found_and_cleaned() {
+        use crate::services::vector_database::{VectorDatabase, VectorDocument};
+        
+        let page_store = Arc::new(PageStore::new_temporary());
+        let anvil_url = "https://wiki.vintagestory.at/index.php?title=Anvil";
+        let removed_url = "https://wiki.vintagestory.at/index.php?title=Removed_Page";
+        page_store.save(&StoredPage {
+            url: anvil_url.to_string(),
+            html: SIMPLE_PAGE.to_string(),
+            last_modified: None,
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        }).unwrap();
+        
+        let db = VectorDatabase::new_fallback();
+        let documents = [(anvil_url, "Anvil", 2), (removed_url, "Removed Page", 3)].iter()
+            .flat_map(|&(url, title, count)| (0..count).map(move |i| VectorDocument {
+                id: format!("{}_{}", title, i),
+                content: format!("{} chunk {}", title, i),
+                source_url: url.to_string(),
+                source_title: title.to_string(),
+                embedding: vec![0.5, 0.5, 0.1],
+                metadata: HashMap::new(),
+            }))
+            .collect();
+        db.insert_documents(documents).await.unwrap();
+        let db = Arc::new(Mutex::new(db));
+        
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_vector_database(db.clone());
+        let mut wiki_service = WikiService::new().await;
+        wiki_service.set_page_store(page_store);
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        
+        let orphans = wiki_service.find_orphaned_chunks().await.unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].source_url, removed_url);
+        assert_eq!(orphans[0].chunk_count, 3);
+        
+        let removed = wiki_service.clean_orphaned_chunks().await.unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_count, 3);
+        assert!(wiki_service.find_orphaned_chunks().await.unwrap().is_empty());
+        assert_eq!(db.lock().await.count_documents().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_orphan_check_needs_the_page_store() {
+        let wiki_service = WikiService::new().await;
+        assert!(matches!(wiki_service.find_orphaned_chunks().await, Err(AppError::ConfigError(_))));
+        
+        let mut wiki_service = WikiService::new().await;
+        wiki_service.set_page_store(Arc::new(PageStore::new_temporary()));
+        assert!(matches!(wiki_service.clean_orphaned_chunks().await, Err(AppError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_raw_html_not_stored_unless_enabled() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(SIMPLE_PAGE)
+            .create_async()
+            .await;
+        
+        let page_store = Arc::new(PageStore::new_temporary());
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.set_page_store(page_store.clone());
+        wiki_service.update_content().await.unwrap();
+        
+        assert!(page_store.is_empty());
+    }
+
+    const SIMPLE_PAGE: &str = r#"<html><body>
+        <h1 id="firstHeading">Anvil</h1>
+        <div id="mw-content-text"><div class="mw-parser-output">
+            <p>The anvil is used to smith metal tools and plates.</p>
+        </div></div>
+    </body></html>"#;
+
+    fn retry_test_config(server: &mockito::ServerGuard) -> WikiConfig {
+        WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Anvil".to_string()],
+            fetch_attempts: 3,
+            retry_backoff_ms: 10,
+            ..WikiConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+        let success_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(SIMPLE_PAGE)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.update_content().await.unwrap();
+        
+        failed_mock.assert_async().await;
+        success_mock.assert_async().await;
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 1);
+        assert_eq!(status.errors_encountered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_pauses_and_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let limited_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let success_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(SIMPLE_PAGE)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        // A single attempt: the rate-limited request must not use it up
+        let config = WikiConfig { fetch_attempts: 1, ..retry_test_config(&server) };
+        let mut wiki_service = WikiService::with_config(config).await;
+        let started = std::time::Instant::now();
+        wiki_service.update_content().await.unwrap();
+        
+        assert!(started.elapsed() >= Duration::from_millis(950), "crawl resumed after {:?}", started.elapsed());
+        limited_mock.assert_async().await;
+        success_mock.assert_async().await;
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 1);
+        assert_eq!(status.errors_encountered, 0);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2026 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2026 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_client_error_is_not_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let missing_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(404)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        // The only page failed, so the update as a whole does too
+        wiki_service.update_content().await.unwrap_err();
+        
+        missing_mock.assert_async().await;
+        assert_eq!(wiki_service.get_status().await.unwrap().errors_encountered, 1);
+    }
+
+    #[tokio::test]
+    async fn test_error_counted_once_after_retries_are_exhausted() {
+        let mut server = mockito::Server::new_async().await;
+        let failed_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(503)
+            .expect(3)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        // The only page failed, so the update as a whole does too
+        wiki_service.update_content().await.unwrap_err();
+        
+        failed_mock.assert_async().await;
+        assert_eq!(wiki_service.get_status().await.unwrap().errors_encountered, 1);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_progress_accounts_for_every_page() {
+        let mut server = mockito::Server::new_async().await;
+        let _mocks = create_linked_wiki(&mut server).await;
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Root".to_string()],
+            max_depth: 2,
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        let tracker = Arc::new(EmbeddingProgressTracker::new());
+        wiki_service.set_embedding_progress(tracker.clone());
+        
+        wiki_service.update_content().await.unwrap();
+        
+        let progress = tracker.snapshot();
+        assert!(!progress.is_running);
+        assert_eq!(progress.pages_discovered, 3);
+        assert_eq!(progress.pages_accounted, 3);
+        assert_eq!(progress.fraction, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_update_is_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let page_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(SIMPLE_PAGE)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        let update_in_progress = wiki_service.update_in_progress_handle();
+        let wiki_service = Arc::new(Mutex::new(wiki_service));
+        
+        // What `update_wiki_content` does: claim the flag, then wait for the service lock
+        let update = || {
+            let wiki_service = wiki_service.clone();
+            let update_in_progress = update_in_progress.clone();
+            tokio::spawn(async move {
+                let claim = claim_update(&update_in_progress)?;
+                wiki_service.lock().await.update_content_claimed(claim).await
+            })
+        };
+        let (first, second) = (update(), update());
+        let mut results = [first.await.unwrap(), second.await.unwrap()];
+        results.sort_by_key(Result::is_err);
+        
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().code(), "WIKI_UPDATE_IN_PROGRESS");
+        page_mock.assert_async().await;
+        assert!(ensure_no_update_running(&update_in_progress).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_update_stops_following_links() {
+        let mut server = mockito::Server::new_async().await;
+        let root_mock = server.mock("GET", "/index.php?title=Root")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_chunked_body(|writer| {
+                // Keep the crawl on this page long enough to cancel it
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                writer.write_all(br#"<html><body><h1 id="firstHeading">Root</h1><div id="mw-content-text"><div class="mw-parser-output">
+                <p>This is the Root page with enough text to be kept.</p>
+                <p>Continue reading about <a href="/index.php?title=Level_1">Level_1</a> here.</p></div></div></body></html>"#)
+            })
+            .expect(1)
+            .create_async()
+            .await;
+        let linked_mock = server.mock("GET", "/index.php?title=Level_1")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Root".to_string()],
+            max_depth: 2,
+            ..WikiConfig::default()
+        };
+        let wiki_service = WikiService::with_config(config).await;
+        let cancel_flag = wiki_service.crawl_cancel_handle();
+        let wiki_service = Arc::new(Mutex::new(wiki_service));
+        
+        let update = {
+            let wiki_service = wiki_service.clone();
+            tokio::spawn(async move { wiki_service.lock().await.update_content().await })
+        };
+        while !cancel_flag.is_running() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(cancel_flag.cancel());
+        
+        let err = update.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), "CANCELLED");
+        root_mock.assert_async().await;
+        linked_mock.assert_async().await;
+        
+        let wiki_service = wiki_service.lock().await;
+        let status = wiki_service.get_status().await.unwrap();
+        assert!(!status.is_updating);
+        assert!(status.last_update.is_none(), "a cancelled update isn't recorded as completed");
+        assert!(!wiki_service.update_in_progress_handle().load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_update_content_rejects_overlapping_run() {
+        let mut server = mockito::Server::new_async().await;
+        let page_mock = server.mock("GET", "/index.php?title=Anvil")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        // Another caller holding the shared flag means a crawl is already underway
+        wiki_service.update_in_progress_handle().store(true, Ordering::SeqCst);
+        
+        let err = wiki_service.update_content().await.unwrap_err();
+        assert_eq!(err.code(), "WIKI_UPDATE_IN_PROGRESS");
+        page_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_update_fails_fast_in_offline_mode() {
+        let mut server = mockito::Server::new_async().await;
+        let page_mock = server.mock("GET", "/index.php?title=Anvil")
+            .expect(0)
+            .create_async()
+            .await;
+        
+        let mut wiki_service = WikiService::with_config(retry_test_config(&server)).await;
+        wiki_service.set_offline(true);
+        
+        let err = wiki_service.update_content().await.unwrap_err();
+        assert_eq!(err.code(), "OFFLINE_MODE");
+        page_mock.assert_async().await;
+        assert!(!wiki_service.get_status().await.unwrap().is_updating);
+    }
+
+    async fn create_linked_wiki(server: &mut mockito::ServerGuard) -> Vec<mockito::Mock> {
+        // Root -> Level_1 -> Level_2, each page linking only to the next
+        let pages = [("Root", Some("Level_1")), ("Level_1", Some("Level_2")), ("Level_2", None)];
+        let mut mocks = Vec::new();
+        for (title, next) in pages {
+            let link = next
+                .map(|next| format!(r#"<p>Continue reading about <a href="/index.php?title={0}">{0}</a> here.</p>"#, next))
+                .unwrap_or_default();
+            let body = format!(
+                r#"<html><body><h1 id="firstHeading">{0}</h1><div id="mw-content-text"><div class="mw-parser-output">
+                <p>This is the {0} page with enough text to be kept.</p>{1}</div></div></body></html>"#,
+                title, link
+            );
+            mocks.push(server.mock("GET", format!("/index.php?title={}", title).as_str())
+                .with_status(200)
+                .with_header("content-type", "text/html")
+                .with_body(body)
+                .create_async()
+                .await);
+        }
+        mocks
+    }
+
+    #[tokio::test]
+    async fn test_crawl_honors_configured_max_depth() {
+        for (max_depth, expected_pages) in [(0, 1), (1, 2), (3, 3)] {
+            let mut server = mockito::Server::new_async().await;
+            let _mocks = create_linked_wiki(&mut server).await;
+            
+            let config = WikiConfig {
+                base_url: server.url(),
+                entry_points: vec!["/index.php?title=Root".to_string()],
+                max_depth,
+                ..WikiConfig::default()
+            };
+            let mut wiki_service = WikiService::with_config(config).await;
+            wiki_service.update_content().await.unwrap();
+            
+            let status = wiki_service.get_status().await.unwrap();
+            assert_eq!(status.pages_scraped, expected_pages, "max_depth {}", max_depth);
+            // Only two link levels exist, so a deeper limit reports what was actually reached
+            assert_eq!(status.max_depth_reached, max_depth.min(2), "max_depth {}", max_depth);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinned_page_is_rescraped_when_unchanged() {
+        let mut server = mockito::Server::new_async().await;
+        let last_modified = "Tue, 01 Oct 2024 12:00:00 GMT";
+        let page = |title: &str| format!(
+            r#"<html><body><h1 id="firstHeading">{0}</h1><div id="mw-content-text"><div class="mw-parser-output">
+            <p>The {0} page has some content that stays the same between crawls.</p></div></div></body></html>"#,
+            title
+        );
+        
+        // The unpinned page is downloaded once, then answered with 304 when revalidated
+        let stable_full = server.mock("GET", "/index.php?title=Stable")
+            .match_header("if-modified-since", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("last-modified", last_modified)
+            .with_body(page("Stable"))
+            .expect(1)
+            .create_async()
+            .await;
+        let stable_revalidated = server.mock("GET", "/index.php?title=Stable")
+            .match_header("if-modified-since", last_modified)
+            .with_status(304)
+            .expect(1)
+            .create_async()
+            .await;
+        // The pinned page must be fetched in full both times
+        let pinned_full = server.mock("GET", "/index.php?title=Patch_notes")
+            .match_header("if-modified-since", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("last-modified", last_modified)
+            .with_body(page("Patch notes"))
+            .expect(2)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec![
+                "/index.php?title=Stable".to_string(),
+                "/index.php?title=Patch_notes".to_string(),
+            ],
+            pinned_pages: vec![format!("{}/index.php?title=Patch_notes", server.url())],
+            max_depth: 0,
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        
+        wiki_service.update_content().await.unwrap();
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 2);
+        assert_eq!(status.pages_unchanged, 0);
+        
+        wiki_service.update_content().await.unwrap();
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 1);
+        assert_eq!(status.pages_unchanged, 1);
+        assert_eq!(status.errors_encountered, 0);
+        
+        stable_full.assert_async().await;
+        stable_revalidated.assert_async().await;
+        pinned_full.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_pinned_page_outside_entry_points_is_crawled() {
+        let mut server = mockito::Server::new_async().await;
+        let pinned = server.mock("GET", "/index.php?title=Patch_notes")
+            .with_status(200)
+            .with_body(r#"<html><body><h1 id="firstHeading">Patch notes</h1><div id="mw-content-text"><div class="mw-parser-output">
+                <p>Version 1.20 adds a new set of recipes and rebalances metalworking.</p></div></div></body></html>"#)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: Vec::new(),
+            pinned_pages: vec![format!("{}/index.php?title=Patch_notes", server.url())],
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        wiki_service.update_content().await.unwrap();
+        
+        pinned.assert_async().await;
+        assert_eq!(wiki_service.get_status().await.unwrap().pages_scraped, 1);
+    }
+
+    #[test]
+    fn test_render_user_agent() {
+        let user_agent = render_user_agent(DEFAULT_WIKI_USER_AGENT, "admin@example.com").unwrap();
+        assert_eq!(
+            user_agent,
+            format!("VintageStoryAI/{} (Educational; +admin@example.com)", env!("CARGO_PKG_VERSION"))
+        );
+        
+        // Templates don't have to use every placeholder
+        assert_eq!(render_user_agent("MyCrawler/2.0", "").unwrap(), "MyCrawler/2.0");
+        
+        assert!(render_user_agent("", "admin@example.com").is_err());
+        assert!(render_user_agent("   ", "").is_err());
+        assert!(render_user_agent("Crawler (+{contact})", " ").is_err());
+        assert!(render_user_agent("Crawler\n/1.0", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_configured_user_agent_is_sent() {
+        let mut server = mockito::Server::new_async().await;
+        let expected = format!("TestCrawler/{} (+ops@example.com)", env!("CARGO_PKG_VERSION"));
+        let mock = server.mock("GET", "/index.php?title=Main_Page")
+            .match_header("user-agent", expected.as_str())
+            .with_status(200)
+            .with_body(r#"<html><body><h1 id="firstHeading">Main Page</h1></body></html>"#)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Main_Page".to_string()],
+            max_depth: 0,
+            user_agent: "TestCrawler/{version} (+{contact})".to_string(),
+            contact: "ops@example.com".to_string(),
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        wiki_service.update_content().await.unwrap();
+        
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_custom_headers_are_sent() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/index.php?title=Main_Page")
+            .match_header("x-api-key", "secret-key")
+            .match_header("cf-access-token", "bypass")
+            .with_status(200)
+            .with_body(r#"<html><body><h1 id="firstHeading">Main Page</h1></body></html>"#)
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Main_Page".to_string()],
+            max_depth: 0,
+            custom_headers: HashMap::from([
+                ("X-Api-Key".to_string(), "secret-key".to_string()),
+                ("CF-Access-Token".to_string(), " bypass ".to_string()),
+            ]),
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        wiki_service.update_content().await.unwrap();
+        
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_invalid_custom_headers_are_refused() {
+        let headers = |name: &str, value: &str| HashMap::from([(name.to_string(), value.to_string())]);
+        assert_eq!(custom_header_map(&headers("X-Api-Key", "abc")).unwrap().len(), 1);
+        assert!(custom_header_map(&headers("Bad Header", "abc")).is_err());
+        assert!(custom_header_map(&headers("", "abc")).is_err());
+        assert!(custom_header_map(&headers("X-Api-Key", "line\nbreak")).is_err());
+        assert!(custom_header_map(&headers("user-agent", "Other/1.0")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_service_reflects_persisted_last_update() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server.mock("GET", "/index.php?title=Main_Page")
+            .with_status(200)
+            .with_body(r#"<html><body><h1 id="firstHeading">Main Page</h1></body></html>"#)
+            .create_async()
+            .await;
+        let config_path = std::env::temp_dir()
+            .join(format!("wiki-config-{}", uuid::Uuid::new_v4()))
+            .join("config.json");
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Main_Page".to_string()],
+            max_depth: 0,
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        wiki_service.set_config_path(config_path.clone());
+        wiki_service.update_content().await.unwrap();
+        let last_update = wiki_service.get_status().await.unwrap().last_update;
+        assert!(last_update.is_some());
+        
+        // A fresh service built from the saved config (as on app startup) shows the same time
+        let saved = AppConfig::load_from(&config_path).unwrap();
+        assert_eq!(saved.wiki.last_update.map(|time| time.to_rfc3339()), last_update);
+        let restarted = WikiService::with_config(saved.wiki).await;
+        assert_eq!(restarted.get_status().await.unwrap().last_update, last_update);
+        
+        let _ = std::fs::remove_dir_all(config_path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_scraping_no_pages_fails() {
+        let mut server = mockito::Server::new_async().await;
+        let _missing_mock = server.mock("GET", "/index.php?title=Anvil")
+            .with_status(404)
+            .create_async()
+            .await;
+        let _failing_mock = server.mock("GET", "/index.php?title=Forge")
+            .with_status(503)
+            .create_async()
+            .await;
+        
+        let last_update = chrono::Utc::now() - chrono::Duration::days(3);
+        let config = WikiConfig {
+            entry_points: vec!["/index.php?title=Anvil".to_string(), "/index.php?title=Forge".to_string()],
+            last_update: Some(last_update),
+            ..retry_test_config(&server)
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        
+        let err = wiki_service.update_content().await.unwrap_err();
+        
+        assert!(matches!(err, AppError::NoPagesScraped { errors: 2 }), "got {:?}", err);
+        assert_eq!(err.code(), "NO_PAGES_SCRAPED");
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.last_update, Some(last_update.to_rfc3339()));
+        assert!(!status.is_updating);
+        assert_eq!(wiki_service.config().last_update, Some(last_update));
+        assert!(!wiki_service.update_in_progress_handle().load(Ordering::SeqCst));
+    }
+
+    fn article_body(text: &str) -> String {
+        // Long enough that length alone doesn't mark the page as a stub
+        format!("<p>{}</p>", text.repeat(10))
+    }
+
+    #[tokio::test]
+    async fn test_classify_page() {
+        let wiki_service = WikiService::new().await;
+        let page_html = |body: &str| format!(
+            r#"<html><body><h1 id="firstHeading">Page</h1><div id="mw-content-text"><div class="mw-parser-output">{}</div></div></body></html>"#,
+            body
+        );
+        let url = "https://wiki.vintagestory.at/index.php?title=Page";
+        
+        let article = article_body("Copper can be smelted in a crucible over a firepit. ");
+        assert_eq!(wiki_service.parse_wiki_page(url, &page_html(&article)).unwrap().kind, PageKind::Article);
+        
+        let disambiguation = format!(r#"<table class="dmbox"><tr><td>This page may refer to:</td></tr></table>{}"#, article);
+        assert_eq!(wiki_service.parse_wiki_page(url, &page_html(&disambiguation)).unwrap().kind, PageKind::Disambiguation);
+        
+        let flagged_stub = format!(r#"<div class="stub">This article is a stub.</div>{}"#, article);
+        assert_eq!(wiki_service.parse_wiki_page(url, &page_html(&flagged_stub)).unwrap().kind, PageKind::Stub);
+        
+        let short = "<p>Flax is a plant.</p>";
+        assert_eq!(wiki_service.parse_wiki_page(url, &page_html(short)).unwrap().kind, PageKind::Stub);
+        
+        let stub_category = format!(r#"{}</div></div><div id="catlinks"><a href="/Category:Stubs">Category:Stubs</a></div><div><div>"#, article);
+        assert_eq!(wiki_service.parse_wiki_page(url, &page_html(&stub_category)).unwrap().kind, PageKind::Stub);
+    }
+
+    #[tokio::test]
+    async fn test_stub_page_is_not_embedded_but_links_are_followed() {
+        use crate::services::vector_database::VectorDatabase;
+        
+        let mut server = mockito::Server::new_async().await;
+        let stub_mock = server.mock("GET", "/index.php?title=Flax")
+            .with_status(200)
+            .with_body(r#"<html><body><h1 id="firstHeading">Flax</h1><div id="mw-content-text"><div class="mw-parser-output">
+                <div class="stub">This article is a stub. You can help by expanding it.</div>
+                <p>Flax is a crop. See <a href="/index.php?title=Linen">Linen</a> for more.</p>
+            </div></div></body></html>"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let article_mock = server.mock("GET", "/index.php?title=Linen")
+            .with_status(200)
+            .with_body(format!(
+                r#"<html><body><h1 id="firstHeading">Linen</h1><div id="mw-content-text"><div class="mw-parser-output">{}</div></div></body></html>"#,
+                article_body("Linen is woven from flax fibers on a loom and used for clothing and sails. ")
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        
+        let vector_db = Arc::new(Mutex::new(VectorDatabase::new_fallback()));
+        let mut embedding_service = EmbeddingService::new().await;
+        embedding_service.set_vector_database(vector_db.clone());
+        
+        let config = WikiConfig {
+            base_url: server.url(),
+            entry_points: vec!["/index.php?title=Flax".to_string()],
+            max_depth: 1,
+            ..WikiConfig::default()
+        };
+        let mut wiki_service = WikiService::with_config(config).await;
+        wiki_service.set_embedding_service(Arc::new(Mutex::new(embedding_service)));
+        wiki_service.update_content().await.unwrap();
+        
+        stub_mock.assert_async().await;
+        article_mock.assert_async().await;
+        
+        let status = wiki_service.get_status().await.unwrap();
+        assert_eq!(status.pages_scraped, 2);
+        assert_eq!(status.low_value_pages_skipped, 1);
+        
+        // Only the linked article made it into the knowledge base
+        let db = vector_db.lock().await;
+        let sources: HashSet<String> = db.iter_documents().map(|doc| doc.source_title).collect();
+        assert_eq!(sources, HashSet::from(["Linen".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_wiki_status() {
+        let wiki_service = WikiService::new().await;
+        let status = wiki_service.get_status().await.unwrap();
+        
+        assert_eq!(status.total_pages, 0);
+        assert!(!status.is_updating);
+        assert_eq!(status.pages_scraped, 0);
+        assert_eq!(status.errors_encountered, 0);
+        assert_eq!(status.max_depth_reached, 0);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_service_integration() {
+        let mut wiki_service = WikiService::new().await;
+        let embedding_service = Arc::new(Mutex::new(EmbeddingService::new().await));
+        
+        wiki_service.set_embedding_service(embedding_service.clone());
         
         // Create a test page
         let test_page = WikiPage {
@@ -487,6 +3078,8 @@ mod tests {
             content: "This is test content for the wiki page. It contains information about crafting and tools.".to_string(),
             last_modified: None,
             categories: vec!["Crafting".to_string()],
+            kind: PageKind::Article,
+            scraped_at: None,
         };
         
         // Test saving page content (this will try to create embeddings)
@@ -495,4 +3088,60 @@ mod tests {
         // Should succeed even if embedding creation fails (graceful degradation)
         assert!(result.is_ok() || result.is_err()); // Either outcome is acceptable for this test
     }
+
+    #[test]
+    fn test_parse_robots_txt_prefers_our_group() {
+        let robots = "User-agent: *\nDisallow: /\n\n# The assistant may read articles\nUser-agent: VintageStoryAI\nUser-agent: OtherBot\nDisallow: /index.php?title=Special:\nAllow: /index.php?title=Special:Categories\nCrawl-delay: 2\n";
+        
+        let rules = parse_robots_txt(robots, "VintageStoryAI/0.1.0 (Educational)");
+        assert!(rules.allows("/index.php?title=Anvil"));
+        assert!(!rules.allows("/index.php?title=Special:Random"));
+        assert!(rules.allows("/index.php?title=Special:Categories"));
+        assert_eq!(rules.crawl_delay, Some(2.0));
+        
+        let rules = parse_robots_txt(robots, "SomeoneElse/1.0");
+        assert!(!rules.allows("/index.php?title=Anvil"));
+        assert!(parse_robots_txt("", "VintageStoryAI/0.1.0").allows("/anything"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_config_reports_each_check() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_status(200).create_async().await;
+        server.mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_body("User-agent: *\nDisallow: /index.php?title=Special:\n")
+            .create_async()
+            .await;
+        server.mock("GET", "/index.php?title=Anvil").with_status(200).create_async().await;
+        server.mock("GET", "/index.php?title=Missing").with_status(404).create_async().await;
+        let special_mock = server.mock("GET", "/index.php?title=Special:Random").expect(0).create_async().await;
+        
+        let wiki_service = WikiService::with_config(WikiConfig {
+            entry_points: vec![
+                "/index.php?title=Anvil".to_string(),
+                "/index.php?title=Special:Random".to_string(),
+                "/index.php?title=Missing".to_string(),
+                "https://elsewhere.example/index.php?title=Anvil".to_string(),
+            ],
+            retry_backoff_ms: 5,
+            ..retry_test_config(&server)
+        }).await;
+        
+        let report = wiki_service.validate_config().await;
+        
+        special_mock.assert_async().await;
+        let status = |name: &str| report.checks.iter()
+            .find(|check| check.name == name)
+            .unwrap_or_else(|| panic!("missing check {}", name))
+            .status;
+        assert!(!report.ok);
+        assert_eq!(status("base_url"), ConfigCheckStatus::Pass);
+        assert_eq!(status("robots_txt"), ConfigCheckStatus::Pass);
+        assert_eq!(status("rate_limit"), ConfigCheckStatus::Warn);
+        assert_eq!(status("entry_point:/index.php?title=Anvil"), ConfigCheckStatus::Pass);
+        assert_eq!(status("entry_point:/index.php?title=Special:Random"), ConfigCheckStatus::Fail);
+        assert_eq!(status("entry_point:/index.php?title=Missing"), ConfigCheckStatus::Fail);
+        assert_eq!(status("entry_point:https://elsewhere.example/index.php?title=Anvil"), ConfigCheckStatus::Fail);
+    }
 }
@@ -10,7 +10,7 @@ import { ChatHistory } from "./components/ChatHistory";
 import { ErrorTester } from "./components/ErrorTester";
 import { 
   ChatMessage, ChatSession, ChatResponse, OllamaStatus, 
-  WikiStatus, SystemStatus 
+  WikiStatus, SystemStatus, formatCommandError 
 } from "./types";
 
 
@@ -65,7 +65,7 @@ function App() {
       setSystemStatus({
         ollama_ready: false,
         wiki_ready: false,
-        error_message: String(error),
+        error_message: formatCommandError(error),
       });
     } finally {
       setIsInitializing(false);
@@ -127,7 +127,7 @@ function App() {
       console.error("Failed to send message:", error);
       const errorMessage: ChatMessage = {
         id: Date.now().toString(),
-        content: `Error: ${error}`,
+        content: `Error: ${formatCommandError(error)}`,
         role: "error",
         timestamp: new Date().toISOString(),
       };
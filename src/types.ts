@@ -15,13 +15,251 @@ export interface ChatSession {
 export interface ChatResponse {
   message: ChatMessage;
   context_used: string[];
+  /** The reply stopped at the model's token limit; `continue_response` extends it */
+  truncated: boolean;
+  /** How well the wiki context backs the answer; null for continuations */
+  confidence: AnswerConfidence | null;
+}
+
+/** Low confidence means little wiki context matched; suggest running a wiki update */
+export type ConfidenceLevel = 'high' | 'medium' | 'low';
+
+export interface AnswerConfidence {
+  /** 0.0 - 1.0 */
+  score: number;
+  level: ConfidenceLevel;
+}
+
+/** Payload of the retrieval-started, retrieval-complete, generation-started and chat-token events */
+export type ChatPipelineEvent =
+  | { stage: 'retrieval_started' }
+  | { stage: 'retrieval_complete'; sources: string[] }
+  | { stage: 'generation_started' }
+  | { stage: 'token'; text: string };
+
+export interface HighlightedSnippet {
+  /** HTML-escaped text with matched terms wrapped in <mark> tags */
+  text: string;
+  match_count: number;
+}
+
+export interface KnowledgeSearchResult {
+  chunk_id: string;
+  source_title: string;
+  source_url: string;
+  similarity_score: number;
+  snippet: HighlightedSnippet;
+  /** When the page was fetched from the wiki (RFC 3339) */
+  scraped_at?: string;
+}
+
+export interface TextChunk {
+  id: string;
+  content: string;
+  source_url: string;
+  source_title: string;
+  /** Omitted from chunks read back out of the knowledge base */
+  embedding: number[] | null;
+  metadata: Record<string, string>;
+}
+
+export interface SimilarityResult {
+  chunk: TextChunk;
+  similarity_score: number;
+}
+
+export type CoverageLevel = "good" | "partial" | "none";
+
+export interface CoverageReport {
+  topic: string;
+  level: CoverageLevel;
+  best_score: number;
+  mean_score: number;
+  top_sources: KnowledgeSearchResult[];
+}
+
+export interface RetrievalExplanation {
+  rank: number;
+  chunk_id: string;
+  source_title: string;
+  source_url: string;
+  similarity_score: number;
+  matched_terms: string[];
+  missing_terms: string[];
+  /** Fraction of the query's terms that appear in the chunk */
+  term_overlap: number;
+  content: string;
+}
+
+/** Result of the embed command */
+export interface TextEmbedding {
+  model_name: string;
+  dimension: number;
+  /** Probed from the model if it has been, otherwise its published dimension */
+  expected_dimension: number | null;
+  /** Made by the development fallback, not the model; don't compare it with real embeddings */
+  mock: boolean;
+  embedding: number[];
+}
+
+export interface EmbeddingRetryReport {
+  retried: number;
+  /** Embedded and stored this time */
+  recovered: number;
+  /** Still failing; kept for the next retry */
+  still_failing: number;
+}
+
+export interface EmbeddingProgress {
+  is_running: boolean;
+  pages_discovered: number;
+  pages_accounted: number;
+  chunks_processed: number;
+  /** Grows and settles as the crawl discovers and embeds pages */
+  chunks_estimated: number;
+  /** 0.0 - 1.0 */
+  fraction: number;
+}
+
+export interface ImportProgress {
+  documents_processed: number;
+  documents_total: number;
+}
+
+export interface DatabaseMetrics {
+  document_count: number;
+  size_on_disk_bytes: number;
+  average_embedding_dimension: number;
+  /** Only present when search sampling was requested and the store isn't empty */
+  sampled_search_ms: number | null;
+}
+
+export interface IndexRebuildReport {
+  documents_indexed: number;
+  index_entries: number;
+  elapsed_ms: number;
+}
+
+/** Which pages `prune_knowledge_base` keeps; allowlist entries match a URL or a title */
+export type PruneCriteria =
+  | { by: 'most_cited'; count: number }
+  | { by: 'newest'; count: number }
+  | { by: 'allowlist'; sources: string[] };
+
+export interface StoredSource {
+  source_url: string;
+  source_title: string;
+  chunk_count: number;
+  /** When the page was last fetched from the wiki (RFC 3339) */
+  scraped_at: string | null;
+}
+
+export interface PruneReport {
+  sources_kept: number;
+  chunks_removed: number;
+  removed: StoredSource[];
+}
+
+export interface QueryTiming {
+  embedding_ms: number;
+  retrieval_ms: number;
+  generation_ms: number;
+  total_ms: number;
+  used_mock_embedding: boolean;
+  context_chunks: number;
+  response_chars: number;
+}
+
+export interface ModelAnswer {
+  model: string;
+  /** Missing when the model was skipped or failed; `note` says why */
+  response?: string;
+  generation_ms: number;
+  note?: string;
+}
+
+/** Result of compare_models: each model's answer from the same wiki context */
+export interface ModelComparison {
+  query: string;
+  context_used: string[];
+  retrieval_ms: number;
+  answers: ModelAnswer[];
+}
+
+export interface StorageEstimate {
+  estimated_pages: number;
+  chunks_per_page: number;
+  estimated_chunks: number;
+  embedding_dimension: number;
+  bytes_per_chunk: number;
+  estimated_bytes: number;
+}
+
+export interface ScrapeSizeEstimate {
+  knowledge_base: StorageEstimate;
+  raw_html_bytes: number;
+  total_bytes: number;
+  disk_space_available?: number;
+  warning?: string;
+}
+
+export interface SourceSummary {
+  source_url: string;
+  source_title: string;
+  summary: string;
+  chunks_summarized: number;
+  passes: number;
+}
+
+export interface SourceUsage {
+  source: string;
+  citations: number;
+}
+
+export interface ModelUsage {
+  model: string;
+  messages: number;
+}
+
+/** Local-only usage totals from get_usage_stats, most-used first */
+export interface UsageStatsSummary {
+  total_messages: number;
+  average_response_ms: number;
+  top_sources: SourceUsage[];
+  model_usage: ModelUsage[];
+}
+
+export interface EmbeddingModelInfo {
+  model_name: string;
+  dimension?: number;
+  stored_dimension?: number;
+  chunk_count: number;
+  /** The knowledge base can't be searched with the active model until it's re-embedded */
+  reindex_required: boolean;
 }
 
 export interface OllamaStatus {
   is_running: boolean;
   is_installed: boolean;
   version?: string;
+  compatible?: boolean;
   models: ModelInfo[];
+  circuit_breaker: CircuitBreakerStatus;
+}
+
+export interface CircuitBreakerStatus {
+  state: "closed" | "open" | "half_open";
+  consecutive_failures: number;
+  /** Seconds until a request is let through to probe Ollama, while open */
+  retry_after_secs: number | null;
+}
+
+export interface ModelContextLength {
+  model_name: string;
+  context_length: number;
+  /** False when Ollama didn't report it and a default is assumed */
+  known: boolean;
+  context_token_budget: number;
 }
 
 export interface ModelInfo {
@@ -33,6 +271,32 @@ export interface ModelInfo {
     quantization_level: string;
     family: string;
   };
+  /** Friendly name configured for this model, if any */
+  alias: string | null;
+}
+
+export interface DownloadProgress {
+  fraction: number;
+  status: string;
+  completed_bytes?: number;
+  total_bytes?: number;
+  bytes_per_second?: number;
+  eta_seconds?: number;
+}
+
+export interface ModelSetupProgress {
+  model_name: string;
+  model_index: number;
+  model_count: number;
+  /** 0.0 - 1.0 across all models */
+  overall_fraction: number;
+  download: DownloadProgress;
+}
+
+export interface ModelSetupResult {
+  model_name: string;
+  outcome: "downloaded" | "already_installed" | "failed" | "cancelled";
+  error: string | null;
 }
 
 export interface WikiStatus {
@@ -41,10 +305,93 @@ export interface WikiStatus {
   is_updating: boolean;
   pages_scraped: number;
   errors_encountered: number;
+  max_depth_reached: number;
+  pages_unchanged: number;
+  low_value_pages_skipped: number;
+}
+
+export interface ReparseSummary {
+  pages_reparsed: number;
+  chunks: number;
+  /** Stored pages that couldn't be re-parsed; they keep their previous chunks */
+  errors: number;
+}
+
+export interface CategoryScrapeSummary {
+  /** e.g. `Category:Mechanics` */
+  category: string;
+  /** Pages in the category and its subcategories */
+  pages_found: number;
+  pages_scraped: number;
+  pages_unchanged: number;
+  errors: number;
+}
+
+/** What `test_scrape` extracted from a page, without storing it */
+export interface ScrapeProbe {
+  url: string;
+  title: string;
+  kind: 'article' | 'disambiguation' | 'stub';
+  content_length: number;
+  category_count: number;
+  /** Wiki links a crawl would follow from the page */
+  link_count: number;
+}
+
+export type ConfigCheckStatus = 'pass' | 'warn' | 'fail' | 'skipped';
+
+export interface ConfigCheck {
+  /** e.g. `base_url`, `robots_txt` or `entry_point:/index.php?title=Blocks` */
+  name: string;
+  status: ConfigCheckStatus;
+  message: string;
+}
+
+export interface WikiConfigReport {
+  /** False if any check failed */
+  ok: boolean;
+  checks: ConfigCheck[];
 }
 
 export interface SystemStatus {
   ollama_ready: boolean;
   wiki_ready: boolean;
   error_message?: string;
-}
\ No newline at end of file
+}
+
+export interface CancelReport {
+  generation: boolean;
+  wiki_update: boolean;
+  knowledge_import: boolean;
+  model_download: boolean;
+  ollama_install: boolean;
+}
+
+export interface CommandError {
+  code: string;
+  message: string;
+  details?: Record<string, unknown>;
+}
+
+/** `details` of an `EMPTY_RESPONSE` error: what the model was sent when it answered with nothing */
+export interface EmptyResponseDiagnostic {
+  model: string;
+  prompt_chars: number;
+  /** Estimated at about four characters per token */
+  prompt_tokens: number;
+  /** Prompt tokens Ollama says it read */
+  prompt_eval_count: number | null;
+  context_length: number | null;
+  /** Wiki chunks in the prompt; only known for chat messages */
+  context_chunks: number | null;
+  done_reason: string | null;
+  /** Plain-language explanations to show the user, most likely first */
+  likely_causes: string[];
+}
+
+export function formatCommandError(error: unknown): string {
+  if (error && typeof error === "object" && "message" in error) {
+    return String((error as CommandError).message);
+  }
+  return String(error);
+}